@@ -0,0 +1,54 @@
+//! Delivery benchmarks: what cloning large content for every subscriber
+//! costs next to sharing it behind an `Arc`, with and without
+//! `CloneStrategy::MoveToLast`, and what `Publisher::recommend_strategies()`
+//! makes of each run. Shared content is sized as the pointer it is, since
+//! that is all a clone of it copies.
+//!
+//! Run with `cargo bench --bench delivery`. As with the routing benchmarks,
+//! these are plain wall-clock timings, so compare runs on the same machine.
+
+extern crate alewife;
+
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use alewife::{CloneStrategy, Publisher, TopicOptions};
+
+const SUBSCRIBERS: usize = 4;
+const PUBLISHES: u32 = 100_000;
+const SIZES: [usize; 3] = [64, 4096, 65536];
+
+fn run<Content, F>(label: &str, size: usize, strategy: CloneStrategy, sizer: fn(&Content) -> usize, make: F)
+    where Content: Clone + Send + Sync + 'static, F: Fn() -> Content
+{
+    let mut builder = Publisher::<&str, Content>::new();
+    builder.track_topic_stats(Duration::from_secs(3600), sizer);
+    builder.declare_topic("frame", TopicOptions::new().clone_strategy(strategy));
+    let subscribers: Vec<_> = (0..SUBSCRIBERS).map(|_| builder.add_subscriber(&["frame"])).collect();
+    let publisher = builder.build();
+
+    let started = Instant::now();
+    for n in 0..PUBLISHES {
+        publisher.publish("frame", make());
+        if n % 256 == 0 {
+            for subscriber in &subscribers { subscriber.fetch(); }
+        }
+    }
+    for subscriber in &subscribers { subscriber.fetch(); }
+    let elapsed = started.elapsed();
+
+    let advice = &publisher.recommend_strategies()["frame"];
+    println!("{}, {} bytes, {:?}: {:.0} ns each, {:.1} clones each, advice: {}",
+             label, size, strategy, elapsed.as_nanos() as f64 / PUBLISHES as f64,
+             advice.clones_per_message, advice.strategy);
+}
+
+fn main() {
+    for &size in &SIZES {
+        for &strategy in &[CloneStrategy::PerSubscriber, CloneStrategy::MoveToLast] {
+            run("Vec<u8>", size, strategy, |content: &Vec<u8>| content.len(), || vec![0u8; size]);
+            run("Arc<Vec<u8>>", size, strategy, |_: &Arc<Vec<u8>>| mem::size_of::<Arc<Vec<u8>>>(), || Arc::new(vec![0u8; size]));
+        }
+    }
+}
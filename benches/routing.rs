@@ -0,0 +1,136 @@
+//! Routing table benchmarks: how much memory a network with many topics and
+//! few subscribers holds on to, how fast it publishes and resubscribes, and
+//! how a `StaticRouter` compares on enum topics, and what the choice of
+//! `TopicHasher` does to publishing on string topics.
+//!
+//! Run with `cargo bench --bench routing`. There is no benchmark framework
+//! here, just wall-clock timings and a counting allocator, so compare runs on
+//! the same machine.
+
+extern crate alewife;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::time::Instant;
+
+use alewife::{EnumTopic, Publisher, StaticRouter, TopicHasher};
+
+/// Counts the bytes currently allocated, so a network's footprint can be
+/// read off as the difference before and after building it.
+struct Counting;
+
+static HELD: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        HELD.fetch_add(layout.size() as isize, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        HELD.fetch_sub(layout.size() as isize, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const TOPICS: u32 = 10_000;
+const SUBSCRIBERS: usize = 4;
+const PUBLISHES: u32 = 1_000_000;
+const SCOPED: u32 = 1_000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Stage { Decode, Validate, Apply, Log }
+
+impl EnumTopic for Stage {
+    const COUNT: usize = 4;
+
+    fn index(&self) -> usize {
+        match *self { Stage::Decode => 0, Stage::Validate => 1, Stage::Apply => 2, Stage::Log => 3 }
+    }
+}
+
+const STAGES: [Stage; 4] = [Stage::Decode, Stage::Validate, Stage::Apply, Stage::Log];
+
+fn main() {
+    let topics: Vec<u32> = (0..TOPICS).collect();
+
+    let before = HELD.load(Ordering::Relaxed);
+    let started = Instant::now();
+    let mut builder = Publisher::<u32, u64>::new();
+    let subscribers: Vec<_> = (0..SUBSCRIBERS).map(|_| builder.add_subscriber(&topics)).collect();
+    let publisher = builder.build();
+    let setup = started.elapsed();
+    let held = HELD.load(Ordering::Relaxed) - before;
+    println!("setup, {} topics x {} subscribers: {:?}, {} KiB held",
+             TOPICS, SUBSCRIBERS, setup, held / 1024);
+
+    let started = Instant::now();
+    for n in 0..PUBLISHES {
+        publisher.publish(n % TOPICS, n as u64);
+        if n % 1024 == 0 {
+            for subscriber in &subscribers { subscriber.fetch(); }
+        }
+    }
+    for subscriber in &subscribers { subscriber.fetch(); }
+    let elapsed = started.elapsed();
+    println!("publish: {:?} for {}, {:.0} ns each",
+             elapsed, PUBLISHES, elapsed.as_nanos() as f64 / PUBLISHES as f64);
+
+    let started = Instant::now();
+    for _ in 0..SCOPED {
+        let (_subscriber, _guard) = publisher.subscribe_scoped(&topics[..100]);
+    }
+    let elapsed = started.elapsed();
+    println!("subscribe and drop, 100 topics: {:?} for {}, {:.0} ns each",
+             elapsed, SCOPED, elapsed.as_nanos() as f64 / SCOPED as f64);
+
+    let mut builder = Publisher::<Stage, u64>::new();
+    let dynamic: Vec<_> = (0..SUBSCRIBERS).map(|_| builder.add_subscriber(&STAGES)).collect();
+    let publisher = builder.build();
+    let mut builder = StaticRouter::<Stage, u64>::new();
+    let fixed: Vec<_> = (0..SUBSCRIBERS).map(|_| builder.add_subscriber(&STAGES)).collect();
+    let router = builder.build();
+
+    let started = Instant::now();
+    for n in 0..PUBLISHES {
+        publisher.publish(STAGES[n as usize % 4], n as u64);
+        if n % 1024 == 0 {
+            for subscriber in &dynamic { subscriber.fetch(); }
+        }
+    }
+    let elapsed = started.elapsed();
+    println!("publish, enum topics: {:.0} ns each", elapsed.as_nanos() as f64 / PUBLISHES as f64);
+
+    let started = Instant::now();
+    for n in 0..PUBLISHES {
+        router.publish(STAGES[n as usize % 4], n as u64);
+        if n % 1024 == 0 {
+            for subscriber in &fixed { subscriber.fetch(); }
+        }
+    }
+    let elapsed = started.elapsed();
+    println!("publish, enum topics, StaticRouter: {:.0} ns each",
+             elapsed.as_nanos() as f64 / PUBLISHES as f64);
+
+    let names: Vec<String> = (0..TOPICS).map(|n| format!("plant/line-{}/sensor-{}", n / 100, n % 100)).collect();
+    for &hasher in &[TopicHasher::SipHash, TopicHasher::Fx] {
+        let mut builder = Publisher::<String, u64>::new();
+        builder.topic_hasher(hasher);
+        let subscribers: Vec<_> = (0..SUBSCRIBERS).map(|_| builder.add_subscriber(&names)).collect();
+        let publisher = builder.build();
+
+        let started = Instant::now();
+        for n in 0..PUBLISHES {
+            publisher.publish_ref(&names[(n % TOPICS) as usize], n as u64);
+            if n % 1024 == 0 {
+                for subscriber in &subscribers { subscriber.fetch(); }
+            }
+        }
+        let elapsed = started.elapsed();
+        println!("publish, string topics, {:?}: {:.0} ns each",
+                 hasher, elapsed.as_nanos() as f64 / PUBLISHES as f64);
+    }
+}
@@ -0,0 +1,47 @@
+//! Single-consumer benchmarks: what a point-to-point topic costs through the
+//! generic route, next to the same topic declared with
+//! `Builder::declare_single_consumer()`, for content that is cheap to clone
+//! and content that isn't. Other topics with subscribers of their own share
+//! the network, so the generic path has a real routing table to look through.
+//!
+//! Run with `cargo bench --bench single_consumer`. As with the other
+//! benchmarks, these are plain wall-clock timings, so compare runs on the
+//! same machine.
+
+extern crate alewife;
+
+use std::time::Instant;
+
+use alewife::Publisher;
+
+const OTHER_TOPICS: u32 = 64;
+const PUBLISHES: u32 = 100_000;
+
+fn run<Content, F>(label: &str, single: bool, make: F)
+    where Content: Clone + Send + 'static, F: Fn() -> Content
+{
+    let mut builder = Publisher::<u32, Content>::new();
+    if single { builder.declare_single_consumer(0); }
+    let consumer = builder.add_subscriber(&[0]);
+    let others: Vec<_> = (1 ..= OTHER_TOPICS).map(|topic| builder.add_subscriber(&[topic])).collect();
+    let publisher = builder.build();
+
+    let started = Instant::now();
+    for n in 0 .. PUBLISHES {
+        publisher.publish(0, make());
+        if n % 256 == 0 { consumer.fetch(); }
+    }
+    consumer.fetch();
+    let elapsed = started.elapsed();
+    drop(others);
+
+    println!("{}, {}: {:.0} ns each", label, if single { "single-consumer" } else { "generic" },
+             elapsed.as_nanos() as f64 / PUBLISHES as f64);
+}
+
+fn main() {
+    for &single in &[false, true] {
+        run("u64", single, || 7u64);
+        run("Vec<u8>, 4096 bytes", single, || vec![0u8; 4096]);
+    }
+}
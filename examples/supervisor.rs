@@ -0,0 +1,122 @@
+//! A supervisor keeping a crash-prone worker process running.
+//!
+//! ```text
+//! cargo run --example supervisor
+//! ```
+//!
+//! The supervising process listens on a local TCP port and starts a copy of
+//! itself as a worker, which connects back and bridges its heartbeats over.
+//! The worker "crashes" after a while by exiting, the supervisor notices the
+//! silence and starts a new one, and after two restarts it gives up.
+
+extern crate alewife;
+
+use std::env;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::process::{self, Child, Command};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use alewife::{Publisher, Unmatched};
+use alewife::bridge::{self, Framed};
+use alewife::codec::{Codec, CodecError};
+use alewife::heartbeat::Heartbeat;
+use alewife::supervisor::{PeerEvent, RestartPolicy, Supervision};
+
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sends heartbeats as their component's name.
+struct Beats;
+
+impl Codec<&'static str, Heartbeat> for Beats {
+    fn encode(&self, _: &&'static str, beat: &Heartbeat) -> Result<Vec<u8>, CodecError> {
+        Ok(beat.component.as_bytes().to_vec())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<(&'static str, Heartbeat), CodecError> {
+        let component = String::from_utf8(bytes.to_vec()).map_err(CodecError::new)?;
+        Ok(("heartbeats", Heartbeat { component }))
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let result = match args.get(1).map(|arg| &arg[..]) {
+        Some("worker") if args.len() == 4 => work(&args[2], &args[3]),
+        _ => supervise(),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn supervise() -> io::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?.to_string();
+
+    let mut builder = Publisher::new();
+    let beats = builder.add_subscriber(&["heartbeats"]);
+    let heartbeats = builder.build();
+    thread::spawn(move || {
+        let mut links = vec![];
+        for stream in listener.incoming().flatten() {
+            if stream.set_read_timeout(Some(INTERVAL)).is_err() { continue; }
+            links.push(bridge::import(Framed::new(stream), Beats, heartbeats.clone()));
+        }
+    });
+
+    let children: Arc<Mutex<Vec<Child>>> = Arc::new(Mutex::new(vec![]));
+    let start = {
+        let children = children.clone();
+        move || {
+            let exe = env::current_exe().expect("the example knows where it is");
+            match Command::new(exe).args(["worker", "worker", &address]).spawn() {
+                Ok(child) => children.lock().unwrap().push(child),
+                Err(e) => eprintln!("couldn't start a worker: {}", e),
+            }
+        }
+    };
+    start();
+
+    let mut builder = Publisher::new();
+    let events = builder.add_subscriber(&["peers"]);
+    let supervisor = Supervision::new(INTERVAL, 3)
+        .peer("worker", RestartPolicy::UpTo(2), move |_| start())
+        .spawn(beats, builder.build(), "peers");
+
+    loop {
+        let Some((_, event)) = events.wait_for(|_, _| true, Duration::from_secs(10), Unmatched::Keep) else {
+            return Err(io::Error::other("the supervisor went quiet"));
+        };
+        println!("{:?}", event);
+        if let PeerEvent::GaveUp { .. } = event { break; }
+    }
+
+    supervisor.stop();
+    for mut child in children.lock().unwrap().drain(..) { child.wait()?; }
+    Ok(())
+}
+
+fn work(name: &str, address: &str) -> io::Result<()> {
+    let stream = TcpStream::connect(address)?;
+    let mut builder = Publisher::new();
+    let outgoing = builder.add_subscriber(&["heartbeats"]);
+    let heartbeats = builder.build();
+    let _link = bridge::export(outgoing, Beats, Framed::new(stream));
+
+    let mut builder = Publisher::<&str, u32>::new();
+    let mut jobs = builder.add_subscriber(&["jobs"]);
+    jobs.heartbeat(name, INTERVAL, heartbeats, "heartbeats");
+    let _jobs = builder.build();
+
+    let crash_at = Instant::now() + INTERVAL * 10;
+    while Instant::now() < crash_at {
+        jobs.process_for(INTERVAL / 2, |_, _| ());
+        thread::sleep(INTERVAL / 2);
+    }
+    eprintln!("{} crashing", name);
+    process::exit(1);
+}
@@ -0,0 +1,152 @@
+//! Procedural macros for alewife. Use them through the `macros` feature of
+//! the `alewife` crate, which re-exports them.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, FnArg, ImplItem, ItemImpl, Type};
+
+/// Implements `alewife::Handlers` for the type of an `impl` block, from the
+/// methods in it that take `&self` or `&mut self` and one argument by
+/// reference. Each such method handles messages whose content holds its
+/// argument's type, which must implement `alewife::Event`; the methods'
+/// names don't matter. Other methods are left alone.
+///
+/// ```ignore
+/// #[alewife::handler]
+/// impl Hud {
+///     fn on_damage(&mut self, damage: &Damage) { self.health -= damage.amount; }
+///     fn on_heal(&mut self, heal: &Heal) { self.health += heal.amount; }
+/// }
+///
+/// let subscriber = builder.add_handlers::<Hud>();
+/// // later, on the HUD's thread:
+/// subscriber.dispatch(&mut hud);
+/// ```
+#[proc_macro_attribute]
+pub fn handler(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        let args = proc_macro2::TokenStream::from(args);
+        return syn::Error::new_spanned(args, "#[handler] takes no arguments")
+            .to_compile_error().into();
+    }
+
+    let item = parse_macro_input!(input as ItemImpl);
+    if let Some((_, path, _)) = &item.trait_ {
+        return syn::Error::new_spanned(path, "#[handler] goes on an inherent impl block")
+            .to_compile_error().into();
+    }
+
+    let mut methods = vec![];
+    let mut events = vec![];
+    for member in &item.items {
+        let method = match *member {
+            ImplItem::Fn(ref method) => method,
+            _ => continue,
+        };
+        let inputs: Vec<&FnArg> = method.sig.inputs.iter().collect();
+        let event = match inputs[..] {
+            [FnArg::Receiver(receiver), FnArg::Typed(arg)] if receiver.reference.is_some() => {
+                match *arg.ty {
+                    Type::Reference(ref reference) if reference.mutability.is_none() => &reference.elem,
+                    _ => continue,
+                }
+            },
+            _ => continue,
+        };
+        methods.push(&method.sig.ident);
+        events.push(event);
+    }
+
+    if methods.is_empty() {
+        return syn::Error::new_spanned(&item.self_ty,
+            "#[handler] found no methods taking `&self` or `&mut self` and one `&Event`")
+            .to_compile_error().into();
+    }
+
+    let mut generics = item.generics.clone();
+    generics.params.push(parse_quote!(__AlewifeTopic));
+    generics.params.push(parse_quote!(__AlewifeContent));
+    {
+        let clause = generics.make_where_clause();
+        for event in &events {
+            clause.predicates.push(parse_quote!(
+                #event: ::alewife::Event<__AlewifeTopic, __AlewifeContent>
+            ));
+        }
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let self_ty = &item.self_ty;
+
+    let expanded = quote! {
+        #item
+
+        impl #impl_generics ::alewife::Handlers<__AlewifeTopic, __AlewifeContent> for #self_ty
+            #where_clause
+        {
+            fn topics() -> ::std::vec::Vec<__AlewifeTopic> {
+                vec![#(<#events as ::alewife::Event<__AlewifeTopic, __AlewifeContent>>::topic()),*]
+            }
+
+            fn handle(&mut self, content: &__AlewifeContent) -> bool {
+                #(
+                    let event = <#events as ::alewife::Event<__AlewifeTopic, __AlewifeContent>>
+                        ::from_content(content);
+                    if let ::std::option::Option::Some(event) = event {
+                        self.#methods(event);
+                        return true;
+                    }
+                )*
+                false
+            }
+        }
+    };
+    expanded.into()
+}
+
+/// Implements `alewife::EnumTopic` for a fieldless enum, numbering its
+/// variants in the order they are declared. The `index()` it writes is a
+/// `match`, which the compiler turns into a jump table or plain arithmetic.
+///
+/// ```ignore
+/// #[derive(Copy, Clone, Debug, alewife::EnumTopic)]
+/// enum Stage { Decode, Validate, Apply }
+/// ```
+#[proc_macro_derive(EnumTopic)]
+pub fn derive_enum_topic(input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as DeriveInput);
+    let variants = match item.data {
+        Data::Enum(ref data) => &data.variants,
+        _ => {
+            return syn::Error::new_spanned(&item.ident, "EnumTopic can only be derived for an enum")
+                .to_compile_error().into();
+        },
+    };
+    if let Some(variant) = variants.iter().find(|variant| !matches!(variant.fields, Fields::Unit)) {
+        return syn::Error::new_spanned(variant, "EnumTopic variants can't have fields")
+            .to_compile_error().into();
+    }
+
+    let name = &item.ident;
+    let (impl_generics, type_generics, where_clause) = item.generics.split_for_impl();
+    let count = variants.len();
+    let arms = variants.iter().enumerate().map(|(index, variant)| {
+        let variant = &variant.ident;
+        quote! { #name::#variant => #index }
+    });
+
+    let expanded = quote! {
+        impl #impl_generics ::alewife::EnumTopic for #name #type_generics #where_clause {
+            const COUNT: usize = #count;
+
+            fn index(&self) -> usize {
+                match *self { #(#arms,)* }
+            }
+        }
+    };
+    expanded.into()
+}
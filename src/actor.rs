@@ -0,0 +1,177 @@
+//! Point-to-point messaging between addressable mailboxes.
+//!
+//! An `ActorSystem` is a network whose topics are addresses. Each `Mailbox`
+//! gets an address of its own, and `ActorSystem::send()` delivers a message to
+//! whichever mailbox owns the address, if any. Mailboxes can also be listed
+//! under a name in the system's directory, so others can find them without
+//! being handed the address directly; every listing is also published on a
+//! directory topic, for anyone who wants to follow changes as they happen.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::{Publisher, Subscriber, SubscriptionGuard, Unmatched};
+use super::sync::{AtomicU64, Ordering};
+
+/// Identifies one mailbox within an `ActorSystem`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address(u64);
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Topics of an actor system's underlying network.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ActorTopic {
+    /// Messages for the mailbox with this address.
+    To(Address),
+    /// Names added to the directory.
+    Directory,
+}
+
+/// Content of an actor system's underlying network.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Letter<M> {
+    /// A message for a mailbox.
+    Message(M),
+    /// A mailbox was listed in the directory under this name.
+    Listed(Arc<str>, Address),
+}
+
+/// A network of mailboxes exchanging messages of type `M`. Clones share the
+/// same mailboxes and directory.
+#[derive(Clone)]
+pub struct ActorSystem<M: Clone> {
+    publisher: Publisher<ActorTopic, Letter<M>>,
+    next_address: Arc<AtomicU64>,
+    directory: Arc<RwLock<HashMap<Arc<str>, Address>>>,
+}
+
+impl<M: Clone> Default for ActorSystem<M> {
+    fn default() -> Self {
+        ActorSystem::new()
+    }
+}
+
+impl<M: Clone> ActorSystem<M> {
+    /// Creates an empty system.
+    pub fn new() -> Self {
+        ActorSystem {
+            publisher: Publisher::new().build(),
+            next_address: Arc::new(AtomicU64::new(0)),
+            directory: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Creates a mailbox with a fresh address. Addresses are never reused, so
+    /// messages sent to a dropped mailbox are discarded rather than reaching
+    /// a newer one.
+    pub fn mailbox(&self) -> Mailbox<M> {
+        let address = Address(self.next_address.fetch_add(1, Ordering::Relaxed));
+        let (subscriber, guard) = self.publisher.subscribe_scoped(&[ActorTopic::To(address)]);
+        Mailbox { address, subscriber, _guard: guard }
+    }
+
+    /// Sends a message to the mailbox at `to`. Nothing happens if there is no
+    /// such mailbox.
+    pub fn send(&self, to: Address, message: M) {
+        self.publisher.publish(ActorTopic::To(to), Letter::Message(message));
+    }
+
+    /// Lists `address` in the directory under `name`, replacing any earlier
+    /// listing of that name, and announces it on the directory topic.
+    pub fn register(&self, name: &str, address: Address) {
+        let name: Arc<str> = name.into();
+        self.directory.write().unwrap_or_else(|e| e.into_inner())
+            .insert(name.clone(), address);
+        self.publisher.publish(ActorTopic::Directory, Letter::Listed(name, address));
+    }
+
+    /// Looks up the address listed under `name`.
+    pub fn lookup(&self, name: &str) -> Option<Address> {
+        self.directory.read().unwrap_or_else(|e| e.into_inner()).get(name).cloned()
+    }
+
+    /// Subscribes to the directory topic, which receives a `Letter::Listed`
+    /// for every later call to `register()`.
+    pub fn watch_directory(&self)
+        -> (Subscriber<ActorTopic, Letter<M>>, SubscriptionGuard<ActorTopic, Letter<M>>)
+    {
+        self.publisher.subscribe_scoped(&[ActorTopic::Directory])
+    }
+
+    /// Returns the underlying network's publisher.
+    pub fn publisher(&self) -> &Publisher<ActorTopic, Letter<M>> {
+        &self.publisher
+    }
+}
+
+/// An endpoint that receives the messages sent to its address.
+pub struct Mailbox<M: Clone> {
+    address: Address,
+    subscriber: Subscriber<ActorTopic, Letter<M>>,
+    _guard: SubscriptionGuard<ActorTopic, Letter<M>>,
+}
+
+impl<M: Clone> Mailbox<M> {
+    /// Returns the address other actors send to.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Takes the next message, if one has arrived.
+    pub fn try_recv(&self) -> Option<M> {
+        while let Some(letter) = self.subscriber.next() {
+            if let Some(message) = open(letter) { return Some(message); }
+        }
+        None
+    }
+
+    /// Waits up to `timeout` for the next message.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<M> {
+        let letter = self.subscriber.wait_for(|_, letter| matches!(*letter, Letter::Message(_)),
+                                              timeout, Unmatched::Discard)?;
+        open(letter)
+    }
+
+    /// Takes every message that has arrived so far.
+    pub fn fetch(&self) -> Vec<M> {
+        self.subscriber.fetch().into_iter().filter_map(open).collect()
+    }
+}
+
+fn open<M>((_, letter): (ActorTopic, Letter<M>)) -> Option<M> {
+    match letter {
+        Letter::Message(message) => Some(message),
+        Letter::Listed(..) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages_reach_only_their_mailbox() {
+        let system = ActorSystem::new();
+        let alice = system.mailbox();
+        let bob = system.mailbox();
+        let (directory, _guard) = system.watch_directory();
+
+        system.register("bob", bob.address());
+        let to_bob = system.lookup("bob").unwrap();
+        system.send(to_bob, "hello");
+        system.send(alice.address(), "hi");
+
+        assert_eq!(bob.fetch(), vec!["hello"]);
+        assert_eq!(alice.recv_timeout(Duration::from_secs(1)), Some("hi"));
+        assert_eq!(system.lookup("carol"), None);
+        assert_eq!(directory.fetch(),
+                   vec![(ActorTopic::Directory, Letter::Listed("bob".into(), bob.address()))]);
+    }
+}
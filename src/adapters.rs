@@ -0,0 +1,243 @@
+//! Wrappers that change how messages are read from an endpoint.
+//!
+//! Each adapter wraps anything that implements `Subscribe`, and implements
+//! it in turn, so they stack like iterator adapters:
+//!
+//! ```ignore
+//! let readings = subscriber
+//!     .filtered(|_, reading| reading.valid)
+//!     .mapped(|topic, reading| (topic, reading.celsius))
+//!     .throttled(Duration::from_millis(100))
+//!     .metered();
+//! ```
+//!
+//! Messages an adapter passes over are gone, as if read; messages it holds
+//! back stay queued in the endpoint underneath.
+
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::Subscribe;
+
+/// Passes over messages that don't match. Made by `Subscribe::filtered()`.
+pub struct Filtered<S, F> {
+    inner: S,
+    matching: F,
+}
+
+impl<S, F> Filtered<S, F> {
+    pub(crate) fn new(inner: S, matching: F) -> Self {
+        Filtered { inner, matching }
+    }
+
+    /// Unwraps the endpoint underneath.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<Topic, Content, S, F> Subscribe<Topic, Content> for Filtered<S, F>
+    where S: Subscribe<Topic, Content>,
+          F: Fn(&Topic, &Content) -> bool,
+{
+    fn next(&self) -> Option<(Topic, Content)> {
+        while let Some((topic, content)) = self.inner.next() {
+            if (self.matching)(&topic, &content) { return Some((topic, content)); }
+        }
+        None
+    }
+
+    fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let (topic, content) = self.inner.next_timeout(left)?;
+            if (self.matching)(&topic, &content) { return Some((topic, content)); }
+        }
+    }
+
+    /// At most this many; some may be passed over.
+    fn pending(&self) -> usize {
+        self.inner.pending()
+    }
+}
+
+/// Changes each message as it is read. Made by `Subscribe::mapped()`.
+pub struct Mapped<S, F, Topic, Content> {
+    inner: S,
+    map: F,
+    marker: PhantomData<fn(Topic, Content)>,
+}
+
+impl<S, F, Topic, Content> Mapped<S, F, Topic, Content> {
+    pub(crate) fn new(inner: S, map: F) -> Self {
+        Mapped { inner, map, marker: PhantomData }
+    }
+
+    /// Unwraps the endpoint underneath.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<Topic, Content, ToTopic, ToContent, S, F> Subscribe<ToTopic, ToContent> for Mapped<S, F, Topic, Content>
+    where S: Subscribe<Topic, Content>,
+          F: Fn(Topic, Content) -> (ToTopic, ToContent),
+{
+    fn next(&self) -> Option<(ToTopic, ToContent)> {
+        self.inner.next().map(|(topic, content)| (self.map)(topic, content))
+    }
+
+    fn next_timeout(&self, timeout: Duration) -> Option<(ToTopic, ToContent)> {
+        self.inner.next_timeout(timeout).map(|(topic, content)| (self.map)(topic, content))
+    }
+
+    fn pending(&self) -> usize {
+        self.inner.pending()
+    }
+}
+
+/// Hands over at most one message per interval, leaving the rest queued.
+/// Made by `Subscribe::throttled()`.
+pub struct Throttled<S> {
+    inner: S,
+    interval: Duration,
+    last: Cell<Option<Instant>>,
+}
+
+impl<S> Throttled<S> {
+    pub(crate) fn new(inner: S, interval: Duration) -> Self {
+        Throttled { inner, interval, last: Cell::new(None) }
+    }
+
+    /// Unwraps the endpoint underneath.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// When the next message may be handed over.
+    fn allowed_at(&self) -> Option<Instant> {
+        self.last.get().map(|last| last + self.interval)
+    }
+
+    fn handed<M>(&self, message: Option<M>) -> Option<M> {
+        if message.is_some() { self.last.set(Some(Instant::now())); }
+        message
+    }
+}
+
+impl<Topic, Content, S> Subscribe<Topic, Content> for Throttled<S>
+    where S: Subscribe<Topic, Content>,
+{
+    /// Returns nothing until the interval since the last message is up.
+    fn next(&self) -> Option<(Topic, Content)> {
+        if self.allowed_at().is_some_and(|at| Instant::now() < at) { return None; }
+        self.handed(self.inner.next())
+    }
+
+    /// Waits out what is left of the interval first, if the timeout allows.
+    fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        let deadline = Instant::now() + timeout;
+        if let Some(at) = self.allowed_at() {
+            if at > deadline { return None; }
+            thread::sleep(at.saturating_duration_since(Instant::now()));
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        self.handed(self.inner.next_timeout(left))
+    }
+
+    fn pending(&self) -> usize {
+        self.inner.pending()
+    }
+}
+
+/// Counts the messages read through it. Made by `Subscribe::metered()`.
+pub struct Metered<S> {
+    inner: S,
+    started: Instant,
+    messages: Cell<u64>,
+    last: Cell<Option<Instant>>,
+}
+
+impl<S> Metered<S> {
+    pub(crate) fn new(inner: S) -> Self {
+        Metered { inner, started: Instant::now(), messages: Cell::new(0), last: Cell::new(None) }
+    }
+
+    /// Unwraps the endpoint underneath.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// How many messages have been read.
+    pub fn messages(&self) -> u64 {
+        self.messages.get()
+    }
+
+    /// When the latest message was read.
+    pub fn last_read(&self) -> Option<Instant> {
+        self.last.get()
+    }
+
+    /// Messages read per second, on average, since the meter was fitted.
+    pub fn rate(&self) -> f64 {
+        self.messages.get() as f64 / self.started.elapsed().as_secs_f64().max(f64::EPSILON)
+    }
+
+    fn count<M>(&self, message: Option<M>) -> Option<M> {
+        if message.is_some() {
+            self.messages.set(self.messages.get() + 1);
+            self.last.set(Some(Instant::now()));
+        }
+        message
+    }
+}
+
+impl<Topic, Content, S> Subscribe<Topic, Content> for Metered<S>
+    where S: Subscribe<Topic, Content>,
+{
+    fn next(&self) -> Option<(Topic, Content)> {
+        self.count(self.inner.next())
+    }
+
+    fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        self.count(self.inner.next_timeout(timeout))
+    }
+
+    fn pending(&self) -> usize {
+        self.inner.pending()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Publisher;
+
+    #[test]
+    fn adapters_stack() {
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["reading"]);
+        let publisher = builder.build();
+        for n in 0 .. 6 { publisher.publish("reading", n); }
+
+        let readings = subscriber
+            .filtered(|_, &n| n % 2 == 0)
+            .mapped(|topic, n| (topic, n * 10))
+            .throttled(Duration::from_millis(30))
+            .metered();
+
+        assert_eq!(readings.next(), Some(("reading", 0)));
+        assert_eq!(readings.next(), None);
+        let started = Instant::now();
+        assert_eq!(readings.next_timeout(Duration::from_secs(5)), Some(("reading", 20)));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(readings.next_timeout(Duration::from_millis(1)), None);
+        assert_eq!(readings.messages(), 2);
+
+        let subscriber = readings.into_inner().into_inner().into_inner().into_inner();
+        assert_eq!(subscriber.fetch(), vec![("reading", 3), ("reading", 4), ("reading", 5)]);
+    }
+}
@@ -0,0 +1,162 @@
+//! Subscribers that periodically summarize their traffic.
+//!
+//! An aggregator owns a `Subscriber` and a background thread. Every time a
+//! window closes, the messages that fell inside it are folded into a single
+//! summary, which is published under a topic of your choosing. The output
+//! publisher may belong to the same network as the input or a different one.
+
+use std::hash::Hash;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use super::{Publisher, Subscriber};
+use super::worker::Worker;
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn tumbling_count() {
+        let mut builder = Publisher::new();
+        let input = builder.add_subscriber(&["clicks"]);
+        let summaries = builder.add_subscriber(&["clicks/per-window"]);
+        let publisher = builder.build();
+
+        let window = Window::Tumbling(Duration::from_millis(20));
+        let aggregator = spawn(input, window, 0, |n, _, _| n + 1,
+                               publisher.clone(), "clicks/per-window");
+
+        for _ in 0 .. 3 { publisher.publish("clicks", 1); }
+
+        let mut total = 0;
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while total < 3 && Instant::now() < deadline {
+            total += summaries.fetch().into_iter().map(|(_, n)| n).sum::<i32>();
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        aggregator.stop();
+        assert_eq!(total, 3);
+    }
+
+    #[test]
+    fn zero_windows_are_stretched() {
+        assert_eq!(Window::Tumbling(Duration::ZERO).every(), SHORTEST_WINDOW);
+        assert_eq!(Window::Tumbling(Duration::ZERO).length(), SHORTEST_WINDOW);
+        let sliding = Window::Sliding { length: Duration::ZERO, every: Duration::ZERO };
+        assert_eq!(sliding.every(), SHORTEST_WINDOW);
+        assert_eq!(sliding.length(), Duration::ZERO);
+    }
+}
+
+/// How often an aggregator emits and how much history each summary covers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Window {
+    /// Back-to-back windows of the given length. Each message is counted in
+    /// exactly one summary.
+    Tumbling(Duration),
+    /// A summary is emitted `every` interval, covering the most recent
+    /// `length` of traffic. Windows overlap when `length` exceeds `every`.
+    Sliding {
+        /// How much history each summary covers.
+        length: Duration,
+        /// How often a summary is emitted.
+        every: Duration,
+    },
+}
+
+impl Window {
+    fn length(&self) -> Duration {
+        match *self {
+            Window::Tumbling(_) => self.every(),
+            Window::Sliding { length, .. } => length,
+        }
+    }
+
+    /// Never zero, or the worker would emit in a busy loop.
+    fn every(&self) -> Duration {
+        match *self {
+            Window::Tumbling(d) => d,
+            Window::Sliding { every, .. } => every,
+        }.max(SHORTEST_WINDOW)
+    }
+}
+
+/// Windows shorter than this, including zero, are taken to be this long.
+const SHORTEST_WINDOW: Duration = Duration::from_millis(1);
+
+/// How long the worker may block before checking whether it was stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Handle to a running aggregator. Dropping it stops the background thread.
+pub struct Aggregator {
+    worker: Worker,
+}
+
+impl Aggregator {
+    /// Stops the aggregator and waits for its thread to finish. Messages in
+    /// the current, unfinished window are discarded.
+    pub fn stop(self) {
+        self.worker.stop();
+    }
+}
+
+/// Starts aggregating the traffic received by `source`. When each window
+/// closes, its messages are passed through `fold` in arrival order, starting
+/// from a copy of `init`, and the result is published to `output` under
+/// `topic`. Windows with no traffic still produce a summary (equal to `init`).
+/// A tumbling window, or a sliding window's `every`, shorter than a
+/// millisecond is stretched to one.
+pub fn spawn<Topic, Content, OutTopic, OutContent, F>(
+    source: Subscriber<Topic, Content>,
+    window: Window,
+    init: OutContent,
+    mut fold: F,
+    output: Publisher<OutTopic, OutContent>,
+    topic: OutTopic,
+) -> Aggregator
+    where Topic: Send + 'static,
+          Content: Send + 'static,
+          OutTopic: Hash + Eq + Clone + Send + Sync + 'static,
+          OutContent: Clone + Send + Sync + 'static,
+          F: FnMut(OutContent, &Topic, &Content) -> OutContent + Send + 'static,
+{
+    let worker = Worker::spawn(move |stopped| {
+        let mut history = Vec::new();
+        let mut next_emit = Instant::now() + window.every();
+
+        while !stopped.load(Ordering::Acquire) {
+            let now = Instant::now();
+
+            if now >= next_emit {
+                // Window bounds follow the schedule rather than the clock, so
+                // a late wakeup doesn't shift messages between windows.
+                let end = next_emit;
+                let start = end.checked_sub(window.length());
+                let summary = history.iter()
+                    .filter(|&&(t, _)| t < end && start.is_none_or(|s| t >= s))
+                    .fold(init.clone(), |acc, &(_, (ref topic, ref content))| {
+                        fold(acc, topic, content)
+                    });
+                output.publish(topic.clone(), summary);
+
+                // Keep only what the next window will still cover.
+                let keep_from = (end + window.every()).checked_sub(window.length());
+                history.retain(|&(t, _)| keep_from.is_none_or(|k| t >= k));
+
+                next_emit += window.every();
+                continue;
+            }
+
+            let wait = (next_emit - now).min(POLL_INTERVAL);
+            if let Some(message) = source.next_timeout(wait) {
+                history.push((Instant::now(), message));
+            }
+        }
+    });
+
+    Aggregator { worker }
+}
@@ -0,0 +1,261 @@
+//! Receiving messages in async code.
+//!
+//! An `AsyncSubscriber` is woken by the publishing thread whenever a message
+//! arrives, so it works under any executor without polling. Deadlines are
+//! kept by one shared timer thread, started the first time one is needed,
+//! so `next_timeout()` doesn't need a runtime's timer either. Nothing here
+//! belongs to tokio, smol or async-std, so the same subscriber works under
+//! each of them. With the `futures` feature it is also a `Stream`, for use
+//! with the combinators in `futures` or `futures-lite`.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Outbox, Publisher, SubscriptionGuard};
+use super::sync::Mutex;
+
+/// What the publishing side and the subscriber share.
+struct Shared<Topic, Content> {
+    queue: Mutex<VecDeque<(Topic, Content)>>,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl<Topic, Content> Shared<Topic, Content> {
+    /// Takes the next message, or leaves `waker` to be woken by the next one.
+    fn poll_next(&self, waker: &Waker) -> Option<(Topic, Content)> {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(message) = queue.pop_front() { return Some(message); }
+        // Registered with the queue still locked, so a message pushed in
+        // between can't miss it.
+        *self.waker.lock().unwrap_or_else(|e| e.into_inner()) = Some(waker.clone());
+        None
+    }
+}
+
+/// A subscriber for async code, made by `Publisher::subscribe_async()`. It
+/// stays subscribed until it is dropped. Unlike a plain subscriber, it is
+/// fed through a forwarding route, so its queue isn't charged to the
+/// network's memory budget.
+pub struct AsyncSubscriber<Topic: Hash + Eq + Clone, Content: Clone> {
+    shared: Arc<Shared<Topic, Content>>,
+    _guard: SubscriptionGuard<Topic, Content>,
+}
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + 'static,
+          Content: Clone + Send + 'static,
+{
+    /// Adds a subscriber to `topics` for use from async code.
+    pub fn subscribe_async(&self, topics: &[Topic]) -> AsyncSubscriber<Topic, Content> {
+        let shared = Arc::new(Shared { queue: Mutex::new(VecDeque::new()), waker: Mutex::new(None) });
+        let sender = shared.clone();
+        let outbox = Outbox::Forward(Arc::new(move |topic: Topic, content: Content| {
+            let mut queue = sender.queue.lock().unwrap_or_else(|e| e.into_inner());
+            queue.push_back((topic, content));
+            let waker = sender.waker.lock().unwrap_or_else(|e| e.into_inner()).take();
+            drop(queue);
+            if let Some(waker) = waker { waker.wake(); }
+        }));
+
+        let bus = &self.handle.bus;
+        let id = bus.subscribe(topics, outbox, None);
+        AsyncSubscriber { shared, _guard: SubscriptionGuard { bus: bus.clone(), id } }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> AsyncSubscriber<Topic, Content> {
+    /// Takes the next pending message without waiting.
+    pub fn try_next(&self) -> Option<(Topic, Content)> {
+        self.shared.queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front()
+    }
+
+    /// Waits for the next message.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Next<'_, Topic, Content> {
+        Next { shared: &self.shared }
+    }
+
+    /// Waits up to `timeout` for the next message, returning `None` if none
+    /// came in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> NextUntil<'_, Topic, Content> {
+        self.next_until(Instant::now() + timeout)
+    }
+
+    /// Waits until `deadline` for the next message, returning `None` if none
+    /// came in time.
+    pub fn next_until(&mut self, deadline: Instant) -> NextUntil<'_, Topic, Content> {
+        NextUntil { shared: &self.shared, deadline, armed: false }
+    }
+
+    /// Number of messages waiting to be read.
+    pub fn pending(&self) -> usize {
+        self.shared.queue.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+/// Yields every message, and never ends.
+#[cfg(feature = "futures")]
+impl<Topic: Hash + Eq + Clone, Content: Clone> ::futures_core::Stream for AsyncSubscriber<Topic, Content> {
+    type Item = (Topic, Content);
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<(Topic, Content)>> {
+        match self.shared.poll_next(context.waker()) {
+            Some(message) => Poll::Ready(Some(message)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Future returned by `AsyncSubscriber::next()`.
+pub struct Next<'a, Topic, Content> {
+    shared: &'a Shared<Topic, Content>,
+}
+
+impl<'a, Topic, Content> Future for Next<'a, Topic, Content> {
+    type Output = (Topic, Content);
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<(Topic, Content)> {
+        match self.shared.poll_next(context.waker()) {
+            Some(message) => Poll::Ready(message),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Future returned by `AsyncSubscriber::next_timeout()` and `next_until()`.
+pub struct NextUntil<'a, Topic, Content> {
+    shared: &'a Shared<Topic, Content>,
+    deadline: Instant,
+    /// Whether the timer thread has been asked to wake us at the deadline.
+    armed: bool,
+}
+
+impl<'a, Topic, Content> Future for NextUntil<'a, Topic, Content> {
+    type Output = Option<(Topic, Content)>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>)
+        -> Poll<Option<(Topic, Content)>>
+    {
+        if let Some(message) = self.shared.poll_next(context.waker()) {
+            return Poll::Ready(Some(message));
+        }
+        if Instant::now() >= self.deadline { return Poll::Ready(None); }
+        if !self.armed {
+            wake_at(self.deadline, context.waker().clone());
+            self.armed = true;
+        }
+        Poll::Pending
+    }
+}
+
+/// Wakers waiting for a deadline, served by one thread for the process.
+struct Alarms {
+    waiting: std::sync::Mutex<Vec<(Instant, Waker)>>,
+    changed: Condvar,
+}
+
+/// Arranges for `waker` to be woken at `deadline`.
+fn wake_at(deadline: Instant, waker: Waker) {
+    static ALARMS: OnceLock<Arc<Alarms>> = OnceLock::new();
+    let alarms = ALARMS.get_or_init(|| {
+        let alarms = Arc::new(Alarms {
+            waiting: std::sync::Mutex::new(Vec::new()),
+            changed: Condvar::new(),
+        });
+        let serving = alarms.clone();
+        thread::Builder::new()
+            .name("alewife-alarms".to_owned())
+            .spawn(move || serving.run())
+            .expect("failed to start the alarm thread");
+        alarms
+    });
+
+    alarms.waiting.lock().unwrap_or_else(|e| e.into_inner()).push((deadline, waker));
+    alarms.changed.notify_one();
+}
+
+impl Alarms {
+    fn run(&self) {
+        let mut waiting = self.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let now = Instant::now();
+            let (due, later): (Vec<_>, Vec<_>) = waiting.drain(..).partition(|&(at, _)| at <= now);
+            *waiting = later;
+            for (_, waker) in due { waker.wake(); }
+
+            waiting = match waiting.iter().map(|&(at, _)| at).min() {
+                Some(next) => {
+                    self.changed.wait_timeout(waiting, next - now)
+                        .unwrap_or_else(|e| e.into_inner()).0
+                },
+                None => self.changed.wait(waiting).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::task::Wake;
+
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut context = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) { return output; }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn async_reads_wait_for_messages_or_deadlines() {
+        let publisher = Publisher::new().build();
+        let mut subscriber = publisher.subscribe_async(&["beat"]);
+
+        let sender = publisher.clone();
+        let beat = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            sender.publish("beat", 1);
+        });
+        assert_eq!(block_on(subscriber.next()), ("beat", 1));
+        beat.join().unwrap();
+
+        let started = Instant::now();
+        assert_eq!(block_on(subscriber.next_timeout(Duration::from_millis(30))), None);
+        assert!(started.elapsed() >= Duration::from_millis(30));
+
+        publisher.publish("beat", 2);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert_eq!(block_on(subscriber.next_until(deadline)), Some(("beat", 2)));
+        assert_eq!(subscriber.try_next(), None);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn subscribers_are_streams() {
+        use futures_core::Stream;
+
+        let publisher = Publisher::new().build();
+        let mut subscriber = publisher.subscribe_async(&["beat"]);
+        publisher.publish("beat", 3);
+
+        let next = ::std::future::poll_fn(|context| Pin::new(&mut subscriber).poll_next(context));
+        assert_eq!(block_on(next), Some(("beat", 3)));
+    }
+}
@@ -0,0 +1,127 @@
+//! Using a network from a Bevy ECS world.
+//!
+//! The bus appears in the world as two resources and two kinds of event.
+//! Insert a `BusInbox` holding a subscriber and `drain_inbox` turns every
+//! message it receives into a `BusEvent`, once per run of the schedule. Insert
+//! a `BusPublisher` and `publish_events` sends every `PublishEvent` written
+//! during the frame out to the network. With `bevy_app`, that looks like:
+//!
+//! ```ignore
+//! app.add_event::<BusEvent<Topic, Content>>()
+//!    .add_event::<PublishEvent<Topic, Content>>()
+//!    .insert_resource(BusInbox::new(subscriber))
+//!    .insert_resource(BusPublisher(publisher))
+//!    .add_systems(PreUpdate, drain_inbox::<Topic, Content>)
+//!    .add_systems(PostUpdate, publish_events::<Topic, Content>);
+//! ```
+//!
+//! Each resource holds one subscriber or publisher per pair of topic and
+//! content types.
+
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use bevy_ecs::event::{Event, EventReader, EventWriter};
+use bevy_ecs::resource::Resource;
+use bevy_ecs::system::Res;
+
+use super::{Publisher, Subscriber};
+
+/// A message received from the network.
+#[derive(Event, Clone, Debug, PartialEq)]
+pub struct BusEvent<Topic: Send + Sync + 'static, Content: Send + Sync + 'static> {
+    /// The message's topic.
+    pub topic: Topic,
+    /// The message's content.
+    pub content: Content,
+}
+
+/// A message to publish to the network.
+#[derive(Event, Clone, Debug, PartialEq)]
+pub struct PublishEvent<Topic: Send + Sync + 'static, Content: Send + Sync + 'static> {
+    /// The message's topic.
+    pub topic: Topic,
+    /// The message's content.
+    pub content: Content,
+}
+
+/// The subscriber `drain_inbox` reads from.
+#[derive(Resource)]
+pub struct BusInbox<Topic: Send + 'static, Content: Send + 'static> {
+    // Resources must be `Sync`, which a `Subscriber` isn't on its own.
+    subscriber: Mutex<Subscriber<Topic, Content>>,
+}
+
+impl<Topic: Send + 'static, Content: Send + 'static> BusInbox<Topic, Content> {
+    /// Wraps a subscriber for use as a resource.
+    pub fn new(subscriber: Subscriber<Topic, Content>) -> Self {
+        BusInbox { subscriber: Mutex::new(subscriber) }
+    }
+}
+
+/// The publisher `publish_events` sends with.
+#[derive(Resource)]
+pub struct BusPublisher<Topic, Content>(pub Publisher<Topic, Content>)
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static;
+
+/// System that writes a `BusEvent` for each message waiting in the
+/// `BusInbox`.
+pub fn drain_inbox<Topic, Content>(inbox: Res<BusInbox<Topic, Content>>,
+                                   mut events: EventWriter<BusEvent<Topic, Content>>)
+    where Topic: Send + Sync + 'static,
+          Content: Send + Sync + 'static,
+{
+    let subscriber = inbox.subscriber.lock().unwrap_or_else(|e| e.into_inner());
+    while let Some((topic, content)) = subscriber.next() {
+        events.write(BusEvent { topic, content });
+    }
+}
+
+/// System that publishes each `PublishEvent` with the `BusPublisher`.
+pub fn publish_events<Topic, Content>(publisher: Res<BusPublisher<Topic, Content>>,
+                                      mut events: EventReader<PublishEvent<Topic, Content>>)
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+{
+    for event in events.read() {
+        publisher.0.publish(event.topic.clone(), event.content.clone());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy_ecs::event::Events;
+    use bevy_ecs::schedule::{IntoScheduleConfigs, Schedule};
+    use bevy_ecs::world::World;
+
+    #[test]
+    fn events_cross_in_both_directions() {
+        let mut builder = Publisher::<&'static str, u32>::new();
+        let inbox = builder.add_subscriber(&["to-world"]);
+        let outside = builder.add_subscriber(&["from-world"]);
+        let publisher = builder.build();
+
+        let mut world = World::new();
+        world.init_resource::<Events<BusEvent<&'static str, u32>>>();
+        world.init_resource::<Events<PublishEvent<&'static str, u32>>>();
+        world.insert_resource(BusInbox::new(inbox));
+        world.insert_resource(BusPublisher(publisher.clone()));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((drain_inbox::<&'static str, u32>,
+                              publish_events::<&'static str, u32>).chain());
+
+        publisher.publish("to-world", 1);
+        world.send_event(PublishEvent { topic: "from-world", content: 2u32 });
+        schedule.run(&mut world);
+
+        let received: Vec<_> = world.resource::<Events<BusEvent<&'static str, u32>>>()
+            .iter_current_update_events()
+            .cloned()
+            .collect();
+        assert_eq!(received, vec![BusEvent { topic: "to-world", content: 1 }]);
+        assert_eq!(outside.fetch(), vec![("from-world", 2)]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Inspects a network from the outside, over a bridge.
+//!
+//! The network has to be bridged over TCP with the JSON codec, using `String`
+//! topics: accept a connection, then `export()` a subscriber to it and
+//! `import()` from a clone of the stream into a publisher. Anything the
+//! exported subscriber receives can then be watched, and anything published
+//! here arrives through the import.
+//!
+//! ```text
+//! alewife-ctl <address> list-topics [seconds]
+//! alewife-ctl <address> stats [seconds]
+//! alewife-ctl <address> tail <topic>
+//! alewife-ctl <address> publish <topic> <json>
+//! ```
+//!
+//! `list-topics` and `stats` watch the traffic for a few seconds, three by
+//! default, and report what they saw.
+
+extern crate alewife;
+extern crate serde_json;
+
+use std::env;
+use std::io;
+use std::net::TcpStream;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use alewife::{Publisher, Unmatched};
+use alewife::bridge::{self, Framed};
+use alewife::codec::Json;
+use serde_json::Value;
+
+const USAGE: &str = "usage: alewife-ctl <address> <command>
+
+commands:
+    list-topics [seconds]    list the topics seen while watching
+    stats [seconds]          count messages and bytes per topic while watching
+    tail <topic>             print each message on a topic as it arrives
+    publish <topic> <json>   publish one message";
+
+/// How long `list-topics` and `stats` watch by default.
+const DEFAULT_WATCH: u64 = 3;
+
+/// Lets the import thread notice when it is stopped.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let words: Vec<&str> = args.iter().map(|a| &a[..]).collect();
+
+    let result = match words[..] {
+        [address, "list-topics", ref rest @ ..] => watch_for(rest).and_then(|secs| {
+            list_topics(address, secs)
+        }),
+        [address, "stats", ref rest @ ..] => watch_for(rest).and_then(|secs| {
+            stats(address, secs)
+        }),
+        [address, "tail", topic] => tail(address, topic),
+        [address, "publish", topic, json] => publish(address, topic, json),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+    };
+
+    if let Err(e) = result {
+        eprintln!("alewife-ctl: {}", e);
+        process::exit(1);
+    }
+}
+
+fn watch_for(rest: &[&str]) -> io::Result<u64> {
+    match *rest {
+        [] => Ok(DEFAULT_WATCH),
+        [secs] => secs.parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("not a number of seconds: {}", secs))
+        }),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    }
+}
+
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Watches the traffic for `secs` seconds, returning a publisher that kept
+/// stats on it.
+fn watch(address: &str, secs: u64) -> io::Result<Publisher<String, Value>> {
+    let window = Duration::from_secs(secs.max(1));
+    let mut builder = Publisher::new();
+    builder.track_topic_stats(window, |content: &Value| content.to_string().len());
+    let publisher = builder.build();
+
+    let link = bridge::import(Framed::new(connect(address)?), Json::new(), publisher.clone());
+    let deadline = Instant::now() + Duration::from_secs(secs);
+    while Instant::now() < deadline && !link.is_finished() {
+        thread::sleep(READ_TIMEOUT);
+    }
+    link.stop()?;
+    Ok(publisher)
+}
+
+fn list_topics(address: &str, secs: u64) -> io::Result<()> {
+    let mut topics: Vec<String> = watch(address, secs)?.topic_stats().into_keys().collect();
+    topics.sort();
+    for topic in topics { println!("{}", topic); }
+    Ok(())
+}
+
+fn stats(address: &str, secs: u64) -> io::Result<()> {
+    let mut stats: Vec<_> = watch(address, secs)?.topic_stats().into_iter().collect();
+    stats.sort_by(|a, b| b.1.messages.cmp(&a.1.messages).then_with(|| a.0.cmp(&b.0)));
+
+    let secs = secs.max(1) as f64;
+    println!("{:<32} {:>10} {:>10} {:>12}", "topic", "messages", "per sec", "bytes");
+    for (topic, stats) in stats {
+        println!("{:<32} {:>10} {:>10.1} {:>12}",
+                 topic, stats.messages, stats.messages as f64 / secs, stats.bytes);
+    }
+    Ok(())
+}
+
+fn tail(address: &str, topic: &str) -> io::Result<()> {
+    let mut builder = Publisher::<String, Value>::new();
+    let subscriber = builder.add_subscriber(&[topic.to_owned()]);
+    let publisher = builder.build();
+
+    let link = bridge::import(Framed::new(connect(address)?), Json::new(), publisher);
+    while !link.is_finished() {
+        let next = subscriber.wait_for(|_, _| true, READ_TIMEOUT, Unmatched::Keep);
+        if let Some((_, content)) = next {
+            println!("{}", content);
+        }
+    }
+    link.stop()
+}
+
+fn publish(address: &str, topic: &str, json: &str) -> io::Result<()> {
+    let content: Value = serde_json::from_str(json)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut builder = Publisher::new();
+    let outgoing = builder.add_subscriber(&[topic.to_owned()]);
+    let publisher = builder.build();
+
+    let link = bridge::export(outgoing, Json::new(), Framed::new(connect(address)?));
+    publisher.publish(topic.to_owned(), content);
+
+    // Once the bridge has taken the message off the queue, stopping it
+    // still sends it.
+    while publisher.health().queued > 0 && !link.is_finished() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    link.stop()
+}
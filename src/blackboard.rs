@@ -0,0 +1,86 @@
+//! Shared state that announces its own changes.
+//!
+//! A blackboard is a table of current values that many components read and
+//! write, as robots and game AIs use to share what they know about the
+//! world. `Blackboard` keeps one on top of a network, with each key as a
+//! topic: `set()` records a value and publishes it, `get()` reads the latest,
+//! and `watch()` subscribes to the changes to one key, starting from its
+//! value at that moment.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::slice;
+use std::sync::{Arc, RwLock};
+
+use super::{Publisher, Subscriber, SubscriptionGuard};
+
+/// A table of values, each published on its key whenever it changes. Clones
+/// share the same table.
+pub struct Blackboard<Key: Hash + Eq + Clone, Value: Clone> {
+    publisher: Publisher<Key, Value>,
+    values: Arc<RwLock<HashMap<Key, Value>>>,
+}
+
+impl<Key: Hash + Eq + Clone, Value: Clone> Clone for Blackboard<Key, Value> {
+    fn clone(&self) -> Self {
+        Blackboard { publisher: self.publisher.clone(), values: self.values.clone() }
+    }
+}
+
+/// A key's value when `Blackboard::watch()` was called, and the changes
+/// since.
+pub struct Watch<Key: Hash + Eq + Clone, Value: Clone> {
+    /// The value when watching began, if the key had one.
+    pub current: Option<Value>,
+    /// Each value set from then on.
+    pub updates: Subscriber<Key, Value>,
+    _guard: SubscriptionGuard<Key, Value>,
+}
+
+impl<Key: Hash + Eq + Clone, Value: Clone> Blackboard<Key, Value> {
+    /// An empty blackboard that publishes through `publisher`.
+    pub fn new(publisher: Publisher<Key, Value>) -> Self {
+        Blackboard { publisher, values: Arc::new(RwLock::new(HashMap::new())) }
+    }
+
+    /// Makes `value` the current value of `key`, and publishes it on `key`.
+    pub fn set(&self, key: Key, value: Value) {
+        let mut values = self.values.write().unwrap_or_else(|e| e.into_inner());
+        values.insert(key.clone(), value.clone());
+        // Still locked, so a watcher sees either the old value and then this
+        // one, or this one as its starting value, never neither.
+        self.publisher.publish(key, value);
+    }
+
+    /// Returns the current value of `key`, if it has been set.
+    pub fn get(&self, key: &Key) -> Option<Value> {
+        self.values.read().unwrap_or_else(|e| e.into_inner()).get(key).cloned()
+    }
+
+    /// Watches `key` until the `Watch` is dropped.
+    pub fn watch(&self, key: &Key) -> Watch<Key, Value> {
+        let values = self.values.read().unwrap_or_else(|e| e.into_inner());
+        let (updates, guard) = self.publisher.subscribe_scoped(slice::from_ref(key));
+        Watch { current: values.get(key).cloned(), updates, _guard: guard }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn watchers_start_from_the_current_value() {
+        let board = Blackboard::new(Publisher::new().build());
+        assert_eq!(board.get(&"target"), None);
+        board.set("target", (3, 4));
+
+        let watch = board.watch(&"target");
+        assert_eq!(watch.current, Some((3, 4)));
+        board.clone().set("target", (5, 6));
+        board.set("threat", (0, 0));
+
+        assert_eq!(watch.updates.fetch(), vec![("target", (5, 6))]);
+        assert_eq!(board.get(&"target"), Some((5, 6)));
+    }
+}
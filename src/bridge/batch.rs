@@ -0,0 +1,101 @@
+//! Packing several encoded messages into one frame.
+//!
+//! Every frame a bridge sends is a batch: a sequence of messages, each
+//! prefixed with its length as a big-endian `u32`. Unbatched links just send
+//! batches of one.
+
+use std::io;
+use std::iter;
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// Messages waiting to be sent together.
+pub(crate) struct Batch {
+    frame: Vec<u8>,
+    len: usize,
+    started: Option<Instant>,
+}
+
+impl Batch {
+    pub(crate) fn new() -> Self {
+        Batch { frame: Vec::new(), len: 0, started: None }
+    }
+
+    pub(crate) fn push(&mut self, message: &[u8]) {
+        if self.started.is_none() { self.started = Some(Instant::now()); }
+        self.frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        self.frame.extend_from_slice(message);
+        self.len += 1;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How long the oldest message has been waiting.
+    pub(crate) fn age(&self) -> Duration {
+        self.started.map(|t| t.elapsed()).unwrap_or_default()
+    }
+
+    /// Returns the finished frame and starts a new batch.
+    pub(crate) fn take(&mut self) -> Vec<u8> {
+        self.len = 0;
+        self.started = None;
+        mem::take(&mut self.frame)
+    }
+}
+
+/// Splits a frame back into its messages. A truncated message is reported as
+/// an error, after any complete ones before it.
+pub(crate) fn unpack(mut frame: &[u8]) -> impl Iterator<Item=io::Result<&[u8]>> {
+    iter::from_fn(move || {
+        if frame.is_empty() { return None; }
+
+        let message = if frame.len() < 4 {
+            None
+        } else {
+            let mut prefix = [0; 4];
+            prefix.copy_from_slice(&frame[.. 4]);
+            let len = u32::from_be_bytes(prefix) as usize;
+            frame.get(4 .. 4 + len).map(|message| (message, 4 + len))
+        };
+
+        match message {
+            Some((message, used)) => {
+                frame = &frame[used ..];
+                Some(Ok(message))
+            },
+            None => {
+                frame = &[];
+                Some(Err(io::Error::new(io::ErrorKind::InvalidData, "truncated batch")))
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pack_and_unpack() {
+        let mut batch = Batch::new();
+        assert!(batch.is_empty());
+
+        batch.push(b"one");
+        batch.push(b"");
+        batch.push(b"three");
+        assert_eq!(batch.len(), 3);
+
+        let frame = batch.take();
+        assert!(batch.is_empty());
+        let messages: Vec<_> = unpack(&frame).collect::<io::Result<_>>().unwrap();
+        assert_eq!(messages, vec![&b"one"[..], b"", b"three"]);
+
+        assert!(unpack(&frame[.. frame.len() - 1]).any(|m| m.is_err()));
+    }
+}
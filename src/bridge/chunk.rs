@@ -0,0 +1,207 @@
+//! Splitting large frames into chunks, and putting them back together.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+
+use super::{FrameSink, FrameSource};
+
+/// Marks a frame sent whole.
+const WHOLE: u8 = 0;
+/// Marks one chunk of a larger frame.
+const CHUNK: u8 = 1;
+/// The kind byte, then the frame's id, the chunk's index and the number of
+/// chunks, each as a little-endian `u32`.
+const HEADER: usize = 13;
+
+/// A message still being put back together.
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    missing: usize,
+    bytes: usize,
+}
+
+/// A `FrameSink` or `FrameSource` that carries frames too large for its
+/// transport, or too large to send in one piece without holding everything
+/// behind them up. Frames larger than a chunk are split on the way out and
+/// reassembled on the way in, so both ends of the link must be wrapped.
+///
+/// Reassembly is bounded: a frame that would grow past
+/// `max_message_bytes()`, and the oldest partial frame once more than
+/// `max_partial()` are in progress, are thrown away, as are chunks that
+/// make no sense, so a misbehaving peer can't make the receiver hold on to
+/// unbounded memory.
+pub struct Chunked<T> {
+    inner: T,
+    chunk_bytes: usize,
+    max_message_bytes: usize,
+    max_partial: usize,
+    next_id: u32,
+    partial: HashMap<u32, Partial>,
+    /// Ids of partial frames, oldest first.
+    started: VecDeque<u32>,
+}
+
+impl<T> Chunked<T> {
+    /// Wraps `inner`, sending at most `chunk_bytes` of each frame at a time.
+    /// Reassembled frames may be up to 64MiB, and up to 16 may be in
+    /// progress at once.
+    ///
+    /// Panics if `chunk_bytes` is zero.
+    pub fn new(inner: T, chunk_bytes: usize) -> Self {
+        assert!(chunk_bytes > 0, "chunks must hold at least one byte");
+        Chunked {
+            inner,
+            chunk_bytes,
+            max_message_bytes: 64 << 20,
+            max_partial: 16,
+            next_id: 0,
+            partial: HashMap::new(),
+            started: VecDeque::new(),
+        }
+    }
+
+    /// Throws away incoming frames that would be larger than `bytes` once
+    /// reassembled.
+    pub fn max_message_bytes(mut self, bytes: usize) -> Self {
+        self.max_message_bytes = bytes;
+        self
+    }
+
+    /// Keeps at most `frames` incoming frames in progress, throwing away the
+    /// oldest to make room. Zero is treated as one.
+    pub fn max_partial(mut self, frames: usize) -> Self {
+        self.max_partial = frames.max(1);
+        self
+    }
+
+    /// Files one chunk, returning the whole frame if it was the last one
+    /// missing.
+    fn file(&mut self, id: u32, index: usize, count: usize, payload: &[u8]) -> Option<Vec<u8>> {
+        if count == 0 || index >= count { return None; }
+        if !self.partial.contains_key(&id) {
+            if self.started.len() >= self.max_partial {
+                if let Some(oldest) = self.started.pop_front() { self.partial.remove(&oldest); }
+            }
+            self.partial.insert(id, Partial { chunks: vec![None; count], missing: count, bytes: 0 });
+            self.started.push_back(id);
+        }
+
+        let partial = self.partial.get_mut(&id).unwrap();
+        let fits = partial.chunks.len() == count && partial.bytes + payload.len() <= self.max_message_bytes;
+        if !fits {
+            self.forget(id);
+            return None;
+        }
+        if partial.chunks[index].is_none() {
+            partial.chunks[index] = Some(payload.to_vec());
+            partial.missing -= 1;
+            partial.bytes += payload.len();
+        }
+        if partial.missing > 0 { return None; }
+
+        let partial = self.forget(id)?;
+        Some(partial.chunks.into_iter().flatten().flatten().collect())
+    }
+
+    fn forget(&mut self, id: u32) -> Option<Partial> {
+        self.started.retain(|&started| started != id);
+        self.partial.remove(&id)
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+impl<S: FrameSink> FrameSink for Chunked<S> {
+    fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        if frame.len() <= self.chunk_bytes {
+            let mut whole = Vec::with_capacity(frame.len() + 1);
+            whole.push(WHOLE);
+            whole.extend_from_slice(frame);
+            return self.inner.send_frame(&whole);
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let count = frame.len().div_ceil(self.chunk_bytes) as u32;
+        let mut chunk = Vec::with_capacity(HEADER + self.chunk_bytes);
+        for (index, payload) in frame.chunks(self.chunk_bytes).enumerate() {
+            chunk.clear();
+            chunk.push(CHUNK);
+            chunk.extend_from_slice(&id.to_le_bytes());
+            chunk.extend_from_slice(&(index as u32).to_le_bytes());
+            chunk.extend_from_slice(&count.to_le_bytes());
+            chunk.extend_from_slice(payload);
+            self.inner.send_frame(&chunk)?;
+        }
+        Ok(())
+    }
+
+    fn idle(&mut self) -> io::Result<()> {
+        self.inner.idle()
+    }
+
+    fn reconnected(&mut self) -> bool {
+        self.inner.reconnected()
+    }
+}
+
+impl<S: FrameSource> FrameSource for Chunked<S> {
+    fn recv_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let frame = match self.inner.recv_frame()? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            match frame.first() {
+                Some(&WHOLE) => return Ok(Some(frame[1 ..].to_vec())),
+                Some(&CHUNK) if frame.len() >= HEADER => {
+                    let id = read_u32(&frame[1 ..]);
+                    let index = read_u32(&frame[5 ..]) as usize;
+                    let count = read_u32(&frame[9 ..]) as usize;
+                    if let Some(whole) = self.file(id, index, count, &frame[HEADER ..]) {
+                        return Ok(Some(whole));
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Loopback(VecDeque<Vec<u8>>);
+
+    impl FrameSink for Loopback {
+        fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+            self.0.push_back(frame.to_vec());
+            Ok(())
+        }
+    }
+
+    impl FrameSource for Loopback {
+        fn recv_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.0.pop_front())
+        }
+    }
+
+    #[test]
+    fn large_frames_are_split_and_reassembled() {
+        let mut link = Chunked::new(Loopback(VecDeque::new()), 1000).max_message_bytes(4000);
+        let big: Vec<u8> = (0 .. 2500).map(|n| n as u8).collect();
+        link.send_frame(&big).unwrap();
+        link.send_frame(b"small").unwrap();
+        link.send_frame(&[7; 5000]).unwrap();
+        assert_eq!(link.inner.0.len(), 3 + 1 + 5);
+
+        assert_eq!(link.recv_frame().unwrap(), Some(big));
+        assert_eq!(link.recv_frame().unwrap(), Some(b"small".to_vec()));
+        // Too large to reassemble, so it is thrown away.
+        assert_eq!(link.recv_frame().unwrap(), None);
+        assert!(link.partial.is_empty() && link.started.is_empty());
+    }
+}
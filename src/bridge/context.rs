@@ -0,0 +1,98 @@
+//! Carrying trace context across a bridge.
+
+use super::super::codec::{Codec, CodecError};
+use super::super::envelope::{Envelope, Trace};
+
+/// A codec for envelopes that sends each one's `Trace` along with its body,
+/// as a W3C `traceparent` header, so a chain of messages can be followed from
+/// one process to the next. The body, and its topic, are encoded by the inner
+/// codec. Reply topics and deadlines are local to each process and don't
+/// cross.
+///
+/// A traced envelope arrives as the child of the one that was sent, in the
+/// same chain, with an id of the receiving process's own.
+pub struct TraceContext<K> {
+    codec: K,
+}
+
+impl<K> TraceContext<K> {
+    /// Wraps `codec`.
+    pub fn new(codec: K) -> Self {
+        TraceContext { codec }
+    }
+}
+
+impl<Topic, Body, K> Codec<Topic, Envelope<Topic, Body>> for TraceContext<K>
+    where K: Codec<Topic, Body>,
+{
+    fn encode(&self, topic: &Topic, envelope: &Envelope<Topic, Body>)
+        -> Result<Vec<u8>, CodecError>
+    {
+        let header = envelope.trace.map(|trace| trace.traceparent()).unwrap_or_default();
+        let body = self.codec.encode(topic, &envelope.body)?;
+        let mut frame = Vec::with_capacity(1 + header.len() + body.len());
+        frame.push(header.len() as u8);
+        frame.extend_from_slice(header.as_bytes());
+        frame.extend(body);
+        Ok(frame)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<(Topic, Envelope<Topic, Body>), CodecError> {
+        let (&len, rest) = bytes.split_first()
+            .ok_or_else(|| CodecError::new("empty frame"))?;
+        if rest.len() < len as usize { return Err(CodecError::new("truncated trace header")); }
+        let (header, body) = rest.split_at(len as usize);
+
+        let trace = match len {
+            0 => None,
+            _ => {
+                let header = std::str::from_utf8(header).map_err(CodecError::new)?;
+                let trace = Trace::continue_from(header)
+                    .ok_or_else(|| CodecError::new(format!("bad traceparent {:?}", header)))?;
+                Some(trace)
+            },
+        };
+        let (topic, body) = self.codec.decode(body)?;
+        let mut envelope = Envelope::new(body);
+        envelope.trace = trace;
+        Ok((topic, envelope))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Plain;
+
+    impl Codec<String, String> for Plain {
+        fn encode(&self, topic: &String, content: &String) -> Result<Vec<u8>, CodecError> {
+            Ok(format!("{}:{}", topic, content).into_bytes())
+        }
+
+        fn decode(&self, bytes: &[u8]) -> Result<(String, String), CodecError> {
+            let text = String::from_utf8_lossy(bytes);
+            let (topic, content) = text.split_once(':').ok_or_else(|| CodecError::new("no colon"))?;
+            Ok((topic.to_owned(), content.to_owned()))
+        }
+    }
+
+    #[test]
+    fn traces_continue_on_the_far_side() {
+        let codec = TraceContext::new(Plain);
+        let sent = Envelope::traced("order".to_owned());
+        let root = sent.trace.unwrap();
+
+        let frame = codec.encode(&"orders".to_owned(), &sent).unwrap();
+        let (topic, received) = codec.decode(&frame).unwrap();
+        let trace = received.trace.unwrap();
+        assert_eq!((topic, received.body), ("orders".to_owned(), "order".to_owned()));
+        assert_eq!(trace.correlation, root.correlation);
+        assert_eq!(trace.causation, Some(root.id));
+        assert!(trace.id != root.id);
+
+        let untraced = codec.encode(&"orders".to_owned(), &Envelope::new("x".to_owned())).unwrap();
+        assert_eq!(codec.decode(&untraced).unwrap().1.trace, None);
+        assert!(codec.decode(&[5, b'0', b'0']).is_err());
+    }
+}
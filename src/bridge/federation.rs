@@ -0,0 +1,338 @@
+//! Connecting several networks in a mesh.
+
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::super::{Publish, PublishError, Publisher, SubscriptionGuard, SubscriptionOptions};
+use super::super::codec::Codec;
+use super::{batch, Batch, FrameSink, FrameSource, Link, POLL_INTERVAL};
+
+/// How many recently seen messages each node remembers, to recognise copies
+/// arriving by a second path.
+const SEEN_CAPACITY: usize = 4096;
+
+/// Size of the stamp in front of each encoded message: origin id, sequence
+/// number and hop count.
+const STAMP_LEN: usize = 17;
+
+/// One network's membership of a federation: a mesh of networks, each
+/// connected by bridges to one or more of the others, where every network
+/// receives the traffic of all of them on the federated topics.
+///
+/// Each message is stamped with the id of the network it was first published
+/// on, a sequence number, and the number of bridges it has crossed. Sequence
+/// numbers start from the time the node was created, in nanoseconds, so a
+/// network that rejoins under its old id after restarting isn't mistaken for
+/// a repeat of what it sent before. A node
+/// passes messages from one peer on to its other peers, but drops any that
+/// started on its own network, any it has already seen, and any that have
+/// crossed too many bridges, so messages can't circle forever however the
+/// mesh is wired. Ids must be unique within the federation.
+///
+/// Messages arriving from peers are published on the local network, but not
+/// sent back out through this node's own subscription, which would otherwise
+/// pick them up.
+pub struct Federation<Topic: Hash + Eq + Clone, Content: Clone> {
+    node: Arc<Node<Topic, Content>>,
+    _pump: Link,
+    _guard: SubscriptionGuard<Topic, Content>,
+}
+
+struct Node<Topic: Hash + Eq + Clone, Content: Clone> {
+    id: u64,
+    codec: Box<dyn Codec<Topic, Content>>,
+    publisher: Publisher<Topic, Content>,
+    route: usize,
+    max_hops: AtomicUsize,
+    next_seq: AtomicU64,
+    next_peer: AtomicUsize,
+    peers: Mutex<Vec<(usize, Box<dyn FrameSink>)>>,
+    seen: Mutex<Seen>,
+}
+
+impl<Topic, Content> Federation<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+{
+    /// Joins `publisher`'s network to a federation under the given id,
+    /// sharing the listed topics. Messages published locally on those topics
+    /// go to every connected peer.
+    pub fn new<K>(id: u64, publisher: Publisher<Topic, Content>, topics: &[Topic], codec: K)
+        -> Self
+        where K: Codec<Topic, Content> + 'static
+    {
+        let bus = publisher.handle.bus.clone();
+        let (local, route) = bus.add_subscriber(topics, SubscriptionOptions::new());
+        let guard = SubscriptionGuard { bus, id: route };
+
+        let node = Arc::new(Node {
+            id,
+            codec: Box::new(codec),
+            publisher,
+            route,
+            max_hops: AtomicUsize::new(8),
+            next_seq: AtomicU64::new(first_seq()),
+            next_peer: AtomicUsize::new(0),
+            peers: Mutex::new(Vec::new()),
+            seen: Mutex::new(Seen::new()),
+        });
+
+        let pump = {
+            let node = node.clone();
+            Link::spawn(move |stopped| {
+                while !stopped.load(Ordering::Acquire) {
+                    if let Some((topic, content)) = local.next_timeout(POLL_INTERVAL) {
+                        node.originate(&topic, &content);
+                    }
+                }
+                Ok(())
+            })
+        };
+
+        Federation { node, _pump: pump, _guard: guard }
+    }
+
+    /// Limits how many bridges a message may cross. The default is eight,
+    /// which is plenty unless the mesh is a long chain.
+    pub fn max_hops(self, hops: usize) -> Self {
+        self.node.max_hops.store(hops, Ordering::Relaxed);
+        self
+    }
+
+    /// Returns this network's id within the federation.
+    pub fn id(&self) -> u64 {
+        self.node.id
+    }
+
+    /// Adds a bridge to a peer. Frames for the peer are written to `sink`, and
+    /// frames from it read from `source`; the returned link carries the
+    /// incoming half. The peer is dropped from the mesh as soon as either half
+    /// fails, or when the link stops.
+    pub fn connect<S, R>(&self, sink: S, mut source: R) -> Link
+        where S: FrameSink + 'static,
+              R: FrameSource + 'static,
+    {
+        let node = self.node.clone();
+        let key = node.next_peer.fetch_add(1, Ordering::Relaxed);
+        node.lock_peers().push((key, Box::new(sink)));
+
+        Link::spawn(move |stopped| {
+            let mut result = Ok(());
+            while !stopped.load(Ordering::Acquire) {
+                match source.recv_frame() {
+                    Ok(Some(frame)) => if let Err(e) = node.receive(&frame, key) {
+                        result = Err(e);
+                        break;
+                    },
+                    Ok(None) => (),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    },
+                }
+            }
+
+            node.lock_peers().retain(|&(k, _)| k != key);
+            result
+        })
+    }
+}
+
+impl<Topic, Content> Publish<Topic, Content> for Federation<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+{
+    /// Publishes on the local network, and so to every peer if the topic is
+    /// federated.
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        self.node.publisher.try_publish(topic, content)
+    }
+}
+
+impl<Topic, Content> Node<Topic, Content>
+    where Topic: Hash + Eq + Clone,
+          Content: Clone,
+{
+    fn lock_peers(&self) -> MutexGuard<'_, Vec<(usize, Box<dyn FrameSink>)>> {
+        self.peers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stamps a locally published message and sends it to every peer.
+    fn originate(&self, topic: &Topic, content: &Content) {
+        let payload = match self.codec.encode(topic, content) {
+            Ok(payload) => payload,
+            Err(_) => return,
+        };
+
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        self.seen.lock().unwrap_or_else(|e| e.into_inner()).insert((self.id, seq));
+        self.broadcast(&stamp(self.id, seq, 0, &payload), None);
+    }
+
+    /// Handles a frame from the peer with the given key.
+    fn receive(&self, frame: &[u8], from: usize) -> io::Result<()> {
+        for message in batch::unpack(frame) {
+            let message = message?;
+            if message.len() < STAMP_LEN {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "unstamped message"));
+            }
+
+            let (origin, seq, hops) = read_stamp(message);
+            if origin == self.id { continue; }
+            if !self.seen.lock().unwrap_or_else(|e| e.into_inner()).insert((origin, seq)) {
+                continue;
+            }
+
+            let payload = &message[STAMP_LEN ..];
+            if let Ok((topic, content)) = self.codec.decode(payload) {
+                let _ = self.publisher.publish_skipping(&topic, content, Some(self.route));
+            }
+
+            let hops = hops.saturating_add(1);
+            if (hops as usize) < self.max_hops.load(Ordering::Relaxed) {
+                self.broadcast(&stamp(origin, seq, hops, payload), Some(from));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a message to every peer except `except`. Peers whose sink fails
+    /// are dropped from the mesh.
+    fn broadcast(&self, message: &[u8], except: Option<usize>) {
+        let mut batch = Batch::new();
+        batch.push(message);
+        let frame = batch.take();
+
+        self.lock_peers().retain_mut(|&mut (key, ref mut sink)| {
+            Some(key) == except || sink.send_frame(&frame).is_ok()
+        });
+    }
+}
+
+/// Where a new node's sequence numbers start: later than any a previous run
+/// under the same id can have reached, unless the clock went back.
+fn first_seq() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos() as u64)
+}
+
+fn stamp(origin: u64, seq: u64, hops: u8, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(STAMP_LEN + payload.len());
+    message.extend_from_slice(&origin.to_be_bytes());
+    message.extend_from_slice(&seq.to_be_bytes());
+    message.push(hops);
+    message.extend_from_slice(payload);
+    message
+}
+
+fn read_stamp(message: &[u8]) -> (u64, u64, u8) {
+    let mut origin = [0; 8];
+    let mut seq = [0; 8];
+    origin.copy_from_slice(&message[0 .. 8]);
+    seq.copy_from_slice(&message[8 .. 16]);
+    (u64::from_be_bytes(origin), u64::from_be_bytes(seq), message[16])
+}
+
+/// A bounded set of message stamps, forgetting the oldest first.
+struct Seen {
+    set: HashSet<(u64, u64)>,
+    order: VecDeque<(u64, u64)>,
+}
+
+impl Seen {
+    fn new() -> Self {
+        Seen { set: HashSet::new(), order: VecDeque::new() }
+    }
+
+    /// Returns false if the stamp was already present.
+    fn insert(&mut self, stamp: (u64, u64)) -> bool {
+        if !self.set.insert(stamp) { return false; }
+        self.order.push_back(stamp);
+        if self.order.len() > SEEN_CAPACITY {
+            let oldest = self.order.pop_front().unwrap();
+            self.set.remove(&oldest);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::test::{Plain, wait_for_messages};
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::thread;
+    use std::time::Duration;
+
+    struct PipeSink(Sender<Vec<u8>>);
+    struct PipeSource(Receiver<Vec<u8>>);
+
+    impl FrameSink for PipeSink {
+        fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+            self.0.send(frame.to_vec()).map_err(|_| io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    impl FrameSource for PipeSource {
+        fn recv_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+            match self.0.recv_timeout(POLL_INTERVAL) {
+                Ok(frame) => Ok(Some(frame)),
+                Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+                Err(_) => Err(io::ErrorKind::UnexpectedEof.into()),
+            }
+        }
+    }
+
+    fn join(a: &Federation<&'static str, u32>, b: &Federation<&'static str, u32>)
+        -> (Link, Link)
+    {
+        let (a_tx, b_rx) = mpsc::channel();
+        let (b_tx, a_rx) = mpsc::channel();
+        (a.connect(PipeSink(a_tx), PipeSource(a_rx)),
+         b.connect(PipeSink(b_tx), PipeSource(b_rx)))
+    }
+
+    #[test]
+    fn triangle_delivers_once() {
+        let mut nodes = vec![];
+        let mut subscribers = vec![];
+        let mut publishers = vec![];
+        for id in 0 .. 3 {
+            let mut builder = Publisher::new();
+            subscribers.push(builder.add_subscriber(&["remote"]));
+            let publisher = builder.build();
+            publishers.push(publisher.clone());
+            nodes.push(Federation::new(id, publisher, &["local"], Plain));
+        }
+
+        let _links = [join(&nodes[0], &nodes[1]),
+                      join(&nodes[1], &nodes[2]),
+                      join(&nodes[2], &nodes[0])];
+
+        publishers[0].publish("local", 7);
+        thread::sleep(Duration::from_millis(100));
+
+        assert!(subscribers[0].fetch().is_empty());
+        for subscriber in &subscribers[1 ..] {
+            assert_eq!(wait_for_messages(subscriber, 1), vec![("remote", 7)]);
+        }
+    }
+
+    #[test]
+    fn restarted_nodes_are_heard_again() {
+        let mut builder = Publisher::new();
+        let heard = builder.add_subscriber(&["remote"]);
+        let listener = Federation::new(1, builder.build(), &["local"], Plain);
+
+        for n in 0 .. 2 {
+            let publisher = Publisher::new().build();
+            let node = Federation::new(0, publisher.clone(), &["local"], Plain);
+            let _links = join(&node, &listener);
+            publisher.publish("local", n);
+            assert_eq!(wait_for_messages(&heard, 1), vec![("remote", n)]);
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Carrying messages between processes.
+//!
+//! A bridge moves messages across a transport that only understands frames of
+//! bytes. `export()` reads from a local `Subscriber`, encodes each message with
+//! a `Codec`, and writes it to a `FrameSink`; `import()` does the reverse,
+//! publishing whatever arrives from a `FrameSource` to a local network. Wire
+//! the two ends of a transport up to networks in different processes and they
+//! behave like one.
+//!
+//! Two transports are included: `Framed`, which sends length-prefixed frames
+//! over any byte stream such as a `TcpStream`, and (with the `shm` feature) a
+//! shared-memory ring buffer for processes on the same machine.
+//!
+//! For chatty topics, `export_with()` can batch several messages into each
+//! frame, which saves a write (and on some transports a syscall) per message.
+//! Wrap a sink in `Reconnect` to ride out a peer going away: messages sent
+//! while it is gone are buffered, up to a limit, and replayed when it returns.
+//! To control which topics cross, and what they are called on arrival, wrap
+//! the codec in `Translated` with a set of `TopicRules`. A peer that joins
+//! late can be brought up to date with `export_with_snapshot()`, which sends
+//! the current state before any live traffic, and again each time a
+//! `Reconnect` sink gets its peer back. Networks carrying envelopes
+//! can send their traces along by wrapping the codec in `TraceContext`. Over
+//! a transport others can reach, wrap it in `Signed` too, so frames that were
+//! tampered with or forged are turned away. `Throttled` counts the bytes
+//! each peer's link carries and can cap them, so a busy peer can't crowd out
+//! the rest. For the occasional payload of many megabytes, wrap both ends in
+//! `Chunked`, which sends large frames in pieces and reassembles them, within
+//! limits, on arrival.
+//!
+//! Bridges between two networks are enough for most setups. To join three or
+//! more in a mesh, give each a `Federation` node and connect the nodes to each
+//! other; they take care of passing messages along without loops.
+
+use std::hash::Hash;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{Publisher, Subscriber};
+use super::health::Liveness;
+use super::codec::Codec;
+
+mod batch;
+mod chunk;
+mod context;
+mod federation;
+mod options;
+mod reconnect;
+mod rules;
+mod signing;
+mod stream;
+mod throttle;
+#[cfg(feature = "shm")]
+pub mod shm;
+
+pub use self::chunk::Chunked;
+pub use self::context::TraceContext;
+pub use self::federation::Federation;
+pub use self::options::BridgeOptions;
+pub use self::reconnect::{Reconnect, LinkEvent};
+pub use self::rules::{TopicRules, Translated};
+pub use self::signing::{Rejection, Signed};
+pub use self::stream::Framed;
+pub use self::throttle::{Throttled, Traffic};
+
+use self::batch::Batch;
+
+/// Somewhere to write frames of bytes.
+pub trait FrameSink: Send {
+    /// Writes one complete frame.
+    fn send_frame(&mut self, frame: &[u8]) -> io::Result<()>;
+
+    /// Called by the bridge thread when it has had nothing to send for a
+    /// while, roughly every 20ms. Sinks with work of their own to do, such as
+    /// retrying a connection, can do it here.
+    fn idle(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns true if the sink has opened a new connection since it was
+    /// last asked, as `Reconnect` does after losing one. Sinks that only ever
+    /// have the one connection keep the default, false.
+    fn reconnected(&mut self) -> bool {
+        false
+    }
+}
+
+/// Somewhere to read frames of bytes from.
+pub trait FrameSource: Send {
+    /// Reads the next frame, waiting for no longer than the transport's own
+    /// timeout. Returns `Ok(None)` if nothing arrived in time, so the caller
+    /// gets a chance to stop. A transport that has closed for good reports an
+    /// error of kind `UnexpectedEof`.
+    fn recv_frame(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// How long a bridge thread may block before checking whether it was stopped.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Handle to a running bridge thread. Dropping it stops the thread, though an
+/// import may not notice until its transport next returns.
+pub struct Link {
+    stopped: Arc<AtomicBool>,
+    liveness: Liveness,
+    worker: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Link {
+    fn spawn<F>(work: F) -> Self
+        where F: FnOnce(&AtomicBool) -> io::Result<()> + Send + 'static
+    {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = stopped.clone();
+        let (liveness, running) = Liveness::start();
+        let worker = thread::spawn(move || {
+            let _running = running;
+            work(&flag)
+        });
+        Link { stopped, liveness, worker: Some(worker) }
+    }
+
+    pub(crate) fn liveness(&self) -> Liveness {
+        self.liveness.clone()
+    }
+
+    /// Returns true once the thread has exited, whether it was stopped or its
+    /// transport failed.
+    pub fn is_finished(&self) -> bool {
+        self.worker.as_ref().is_none_or(|w| w.is_finished())
+    }
+
+    /// Stops the thread, waits for it, and reports the transport error that
+    /// ended it, if any.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.stopped.store(true, Ordering::Release);
+        match self.worker.take().map(|w| w.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("bridge thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+    }
+}
+
+/// Sends everything `source` receives over `sink`, one message per frame.
+/// Messages that fail to encode are skipped; a transport error ends the link.
+pub fn export<Topic, Content, K, S>(source: Subscriber<Topic, Content>, codec: K, sink: S)
+    -> Link
+    where Topic: Send + 'static,
+          Content: Send + 'static,
+          K: Codec<Topic, Content> + 'static,
+          S: FrameSink + 'static,
+{
+    export_with(source, codec, sink, BridgeOptions::new())
+}
+
+/// Like `export()`, with the given options.
+pub fn export_with<Topic, Content, K, S>(
+    source: Subscriber<Topic, Content>,
+    codec: K,
+    sink: S,
+    options: BridgeOptions,
+) -> Link
+    where Topic: Send + 'static,
+          Content: Send + 'static,
+          K: Codec<Topic, Content> + 'static,
+          S: FrameSink + 'static,
+{
+    export_with_snapshot(source, codec, sink, options, Vec::new)
+}
+
+/// Like `export_with()`, but first sends the messages returned by `snapshot`,
+/// so the far side starts from a complete picture of the current state before
+/// any live traffic arrives. They are sent in batches as set by `options`.
+/// Whenever the sink reports a new connection (see
+/// `FrameSink::reconnected()`), `snapshot` is called and sent again, after
+/// whatever the sink replays, so a peer that comes back is brought up to date
+/// too.
+///
+/// `snapshot` is called on the bridge thread, after `source` was created, so
+/// nothing published in between is missed; it may arrive both in the snapshot
+/// and live, though, so replaying a message should be harmless.
+pub fn export_with_snapshot<Topic, Content, K, S, F>(
+    source: Subscriber<Topic, Content>,
+    codec: K,
+    mut sink: S,
+    options: BridgeOptions,
+    mut snapshot: F,
+) -> Link
+    where Topic: Send + 'static,
+          Content: Send + 'static,
+          K: Codec<Topic, Content> + 'static,
+          S: FrameSink + 'static,
+          F: FnMut() -> Vec<(Topic, Content)> + Send + 'static,
+{
+    Link::spawn(move |stopped| {
+        let mut batch = Batch::new();
+        let mut send_snapshot = |sink: &mut S, batch: &mut Batch| -> io::Result<()> {
+            for (topic, content) in snapshot() {
+                if let Ok(message) = codec.encode(&topic, &content) {
+                    batch.push(&message);
+                }
+                if batch.len() >= options.batch_messages { sink.send_frame(&batch.take())?; }
+            }
+            if !batch.is_empty() { sink.send_frame(&batch.take())?; }
+            Ok(())
+        };
+
+        send_snapshot(&mut sink, &mut batch)?;
+        // The snapshot just went out on whatever connection that made.
+        sink.reconnected();
+
+        while !stopped.load(Ordering::Acquire) {
+            let wait = if batch.is_empty() {
+                POLL_INTERVAL
+            } else {
+                options.batch_interval.saturating_sub(batch.age()).min(POLL_INTERVAL)
+            };
+
+            match source.next_timeout(wait) {
+                Some((topic, content)) => if let Ok(message) = codec.encode(&topic, &content) {
+                    batch.push(&message);
+                },
+                None => if batch.is_empty() { sink.idle()?; },
+            }
+
+            if !batch.is_empty() && (batch.len() >= options.batch_messages
+                || batch.age() >= options.batch_interval)
+            {
+                sink.send_frame(&batch.take())?;
+            }
+            if sink.reconnected() {
+                if !batch.is_empty() { sink.send_frame(&batch.take())?; }
+                send_snapshot(&mut sink, &mut batch)?;
+            }
+        }
+
+        if !batch.is_empty() { sink.send_frame(&batch.take())?; }
+        Ok(())
+    })
+}
+
+/// Publishes every message that arrives from `source` to `publisher`.
+/// Messages that fail to decode are skipped; a transport error or a malformed
+/// frame ends the link.
+pub fn import<Topic, Content, K, S>(mut source: S, codec: K, publisher: Publisher<Topic, Content>)
+    -> Link
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+          K: Codec<Topic, Content> + 'static,
+          S: FrameSource + 'static,
+{
+    Link::spawn(move |stopped| {
+        while !stopped.load(Ordering::Acquire) {
+            let frame = match source.recv_frame()? {
+                Some(frame) => frame,
+                None => continue,
+            };
+
+            for message in batch::unpack(&frame) {
+                if let Ok((topic, content)) = codec.decode(message?) {
+                    publisher.publish(topic, content);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::CodecError;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    /// Encodes `u32` content as four bytes, with a fixed topic.
+    pub(crate) struct Plain;
+
+    impl Codec<&'static str, u32> for Plain {
+        fn encode(&self, _: &&'static str, content: &u32)
+            -> Result<Vec<u8>, CodecError>
+        {
+            Ok(content.to_be_bytes().to_vec())
+        }
+
+        fn decode(&self, bytes: &[u8])
+            -> Result<(&'static str, u32), CodecError>
+        {
+            let mut word = [0; 4];
+            if bytes.len() != 4 { return Err(CodecError::new("bad length")); }
+            word.copy_from_slice(bytes);
+            Ok(("remote", u32::from_be_bytes(word)))
+        }
+    }
+
+    pub(crate) fn wait_for_messages<T, C>(subscriber: &Subscriber<T, C>, count: usize)
+        -> Vec<(T, C)>
+    {
+        let mut messages = vec![];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while messages.len() < count && Instant::now() < deadline {
+            subscriber.fetch_into(&mut messages);
+            thread::sleep(Duration::from_millis(1));
+        }
+        messages
+    }
+
+    struct Recorder(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl FrameSink for Recorder {
+        fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+            self.0.lock().unwrap().push(frame.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn batches_flush_when_full_or_stale() {
+        let mut builder = Publisher::new();
+        let outgoing = builder.add_subscriber(&["local"]);
+        let sender = builder.build();
+
+        let frames = Arc::new(Mutex::new(vec![]));
+        let options = BridgeOptions::new().batch(2, Duration::from_millis(50));
+        let link = export_with(outgoing, Plain, Recorder(frames.clone()), options);
+
+        for i in 0 .. 5 { sender.publish("local", i); }
+        thread::sleep(Duration::from_millis(150));
+        link.stop().unwrap();
+
+        let sizes: Vec<usize> = frames.lock().unwrap().iter()
+            .map(|frame| batch::unpack(frame).count())
+            .collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn snapshot_precedes_live_traffic() {
+        let mut builder = Publisher::new();
+        let outgoing = builder.add_subscriber(&["local"]);
+        let sender = builder.build();
+        sender.publish("local", 3);
+
+        let frames = Arc::new(Mutex::new(vec![]));
+        let link = export_with_snapshot(outgoing, Plain, Recorder(frames.clone()),
+                                        BridgeOptions::new(),
+                                        || vec![("local", 1), ("local", 2)]);
+        sender.publish("local", 4);
+        thread::sleep(Duration::from_millis(50));
+        link.stop().unwrap();
+
+        let received: Vec<u32> = frames.lock().unwrap().iter()
+            .flat_map(|frame| batch::unpack(frame).map(|m| m.unwrap().to_vec()).collect::<Vec<_>>())
+            .map(|m| Plain.decode(&m).unwrap().1)
+            .collect();
+        assert_eq!(received, vec![1, 2, 3, 4]);
+    }
+
+    /// Accepts one frame, then fails.
+    struct Once(Arc<Mutex<Vec<Vec<u8>>>>, bool);
+
+    impl FrameSink for Once {
+        fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+            if self.1 { return Err(io::ErrorKind::BrokenPipe.into()); }
+            self.1 = true;
+            self.0.lock().unwrap().push(frame.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn snapshot_is_resent_after_reconnecting() {
+        let mut builder = Publisher::new();
+        let outgoing = builder.add_subscriber(&["local"]);
+        let sender = builder.build();
+
+        let frames = Arc::new(Mutex::new(vec![]));
+        let recorded = frames.clone();
+        let sink = Reconnect::new(move || Ok(Once(recorded.clone(), false)))
+            .retry_every(Duration::from_secs(0));
+        let link = export_with_snapshot(outgoing, Plain, sink, BridgeOptions::new(), || vec![("local", 1)]);
+        sender.publish("local", 2);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while frames.lock().unwrap().len() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        link.stop().unwrap();
+
+        let received: Vec<u32> = frames.lock().unwrap().iter()
+            .flat_map(|frame| batch::unpack(frame).map(|m| m.unwrap().to_vec()).collect::<Vec<_>>())
+            .map(|m| Plain.decode(&m).unwrap().1)
+            .collect();
+        assert_eq!(received[.. 2], [1, 1]);
+    }
+
+    #[test]
+    fn tcp_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut builder = Publisher::new();
+        let outgoing = builder.add_subscriber(&["local"]);
+        let sender = builder.build();
+        let _export = export(outgoing, Plain, Framed::new(TcpStream::connect(addr).unwrap()));
+
+        let mut builder = Publisher::new();
+        let incoming = builder.add_subscriber(&["remote"]);
+        let receiver = builder.build();
+        let (stream, _) = listener.accept().unwrap();
+        let _import = import(Framed::new(stream), Plain, receiver);
+
+        for i in 0 .. 3 { sender.publish("local", i); }
+        assert_eq!(wait_for_messages(&incoming, 3),
+                   vec![("remote", 0), ("remote", 1), ("remote", 2)]);
+    }
+}
@@ -0,0 +1,37 @@
+//! Settings for a bridge link.
+
+use std::time::Duration;
+
+/// Settings for `export_with()`.
+#[derive(Clone, Debug)]
+pub struct BridgeOptions {
+    pub(crate) batch_messages: usize,
+    pub(crate) batch_interval: Duration,
+}
+
+impl Default for BridgeOptions {
+    fn default() -> Self {
+        BridgeOptions {
+            batch_messages: 1,
+            batch_interval: Duration::from_secs(0),
+        }
+    }
+}
+
+impl BridgeOptions {
+    /// Options that behave exactly like `export()`: every message is sent in
+    /// a frame of its own, straight away.
+    pub fn new() -> Self {
+        BridgeOptions::default()
+    }
+
+    /// Collects messages into a single frame, sent once it holds `messages`
+    /// of them or once the oldest has waited for `interval`, whichever comes
+    /// first. Anything still waiting when the link stops is sent before it
+    /// exits. A limit of zero is treated as one.
+    pub fn batch(mut self, messages: usize, interval: Duration) -> Self {
+        self.batch_messages = messages.max(1);
+        self.batch_interval = interval;
+        self
+    }
+}
@@ -0,0 +1,264 @@
+//! A sink that survives its transport going away.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use super::FrameSink;
+use super::batch;
+
+/// Something that happened to the connection behind a `Reconnect` sink.
+#[derive(Debug)]
+pub enum LinkEvent {
+    /// A connection was made, and any buffered messages are being replayed.
+    Connected,
+    /// The connection failed with the given error, or couldn't be made.
+    Disconnected(io::Error),
+    /// This many messages were discarded to keep the buffer within its limit.
+    Dropped(usize),
+}
+
+type EventHandler = Box<dyn FnMut(LinkEvent) + Send>;
+
+/// A `FrameSink` that opens its transport on demand and opens it again after
+/// it fails. While it is disconnected, frames are kept in a buffer and
+/// replayed, in order, once a connection is made.
+///
+/// The buffer's limit is counted in messages, not frames. When it is full, the
+/// oldest buffered messages are discarded to make room. The default limit is
+/// zero, which discards everything sent while disconnected.
+///
+/// A frame whose write failed partway through is replayed in full, so the
+/// receiving end may see a message twice.
+pub struct Reconnect<S, F> {
+    connect: F,
+    sink: Option<S>,
+    buffer: VecDeque<(Vec<u8>, usize)>,
+    buffered: usize,
+    limit: usize,
+    retry: Duration,
+    last_attempt: Option<Instant>,
+    on_event: Option<EventHandler>,
+    /// Set by each connection, until `reconnected()` reports it.
+    fresh: bool,
+}
+
+impl<S, F> Reconnect<S, F>
+    where S: FrameSink,
+          F: FnMut() -> io::Result<S> + Send,
+{
+    /// Creates a sink that calls `connect` whenever it needs a transport. The
+    /// first attempt is made when the first frame is sent.
+    pub fn new(connect: F) -> Self {
+        Reconnect {
+            connect,
+            sink: None,
+            buffer: VecDeque::new(),
+            buffered: 0,
+            limit: 0,
+            retry: Duration::from_secs(1),
+            last_attempt: None,
+            on_event: None,
+            fresh: false,
+        }
+    }
+
+    /// Keeps up to `messages` messages while disconnected.
+    pub fn buffer(mut self, messages: usize) -> Self {
+        self.limit = messages;
+        self
+    }
+
+    /// Waits at least `interval` between connection attempts. The default is
+    /// one second.
+    pub fn retry_every(mut self, interval: Duration) -> Self {
+        self.retry = interval;
+        self
+    }
+
+    /// Calls `handler` from the bridge thread whenever the connection comes or
+    /// goes, and whenever buffered messages are discarded.
+    pub fn on_event<H>(mut self, handler: H) -> Self
+        where H: FnMut(LinkEvent) + Send + 'static
+    {
+        self.on_event = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns true if the transport is currently connected.
+    pub fn is_connected(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Number of messages waiting for a connection.
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    fn emit(&mut self, event: LinkEvent) {
+        if let Some(ref mut handler) = self.on_event { handler(event); }
+    }
+
+    fn enqueue(&mut self, frame: &[u8]) {
+        let messages = batch::unpack(frame).count();
+        self.buffer.push_back((frame.to_vec(), messages));
+        self.buffered += messages;
+    }
+
+    /// Discards whole frames from the front until the buffer is within its
+    /// limit. The frame at the front may be in the middle of being replayed,
+    /// but replay stops as soon as a write fails, so none is.
+    fn trim(&mut self) {
+        let mut dropped = 0;
+        while self.buffered > self.limit {
+            let (_, messages) = self.buffer.pop_front().unwrap();
+            self.buffered -= messages;
+            dropped += messages;
+        }
+        if dropped > 0 { self.emit(LinkEvent::Dropped(dropped)); }
+    }
+
+    fn try_connect(&mut self) {
+        if self.sink.is_some() { return; }
+        if self.last_attempt.is_some_and(|t| t.elapsed() < self.retry) { return; }
+
+        self.last_attempt = Some(Instant::now());
+        match (self.connect)() {
+            Ok(sink) => {
+                self.sink = Some(sink);
+                self.fresh = true;
+                self.emit(LinkEvent::Connected);
+            },
+            Err(e) => self.emit(LinkEvent::Disconnected(e)),
+        }
+    }
+
+    /// Sends buffered frames until the buffer is empty or a write fails.
+    fn replay(&mut self) {
+        self.try_connect();
+
+        while let Some(sink) = self.sink.as_mut() {
+            let result = match self.buffer.front() {
+                Some((frame, _)) => sink.send_frame(frame),
+                None => break,
+            };
+
+            match result {
+                Ok(()) => {
+                    let (_, messages) = self.buffer.pop_front().unwrap();
+                    self.buffered -= messages;
+                },
+                Err(e) => {
+                    self.sink = None;
+                    self.emit(LinkEvent::Disconnected(e));
+                },
+            }
+        }
+
+        self.trim();
+    }
+}
+
+impl<S, F> FrameSink for Reconnect<S, F>
+    where S: FrameSink,
+          F: FnMut() -> io::Result<S> + Send,
+{
+    /// Never fails; frames that can't be sent are buffered instead.
+    fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.enqueue(frame);
+        self.replay();
+        Ok(())
+    }
+
+    fn idle(&mut self) -> io::Result<()> {
+        if self.sink.is_none() || !self.buffer.is_empty() { self.replay(); }
+        Ok(())
+    }
+
+    fn reconnected(&mut self) -> bool {
+        let fresh = self.fresh && self.sink.is_some();
+        self.fresh = false;
+        fresh
+    }
+}
+
+impl<S, F> fmt::Debug for Reconnect<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Reconnect")
+            .field("connected", &self.sink.is_some())
+            .field("buffered", &self.buffered)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Accepts frames until told to fail.
+    struct Flaky {
+        frames: Arc<Mutex<Vec<Vec<u8>>>>,
+        healthy: Arc<Mutex<bool>>,
+    }
+
+    impl FrameSink for Flaky {
+        fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+            if !*self.healthy.lock().unwrap() {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.frames.lock().unwrap().push(frame.to_vec());
+            Ok(())
+        }
+    }
+
+    fn frame(message: &[u8]) -> Vec<u8> {
+        let mut batch = batch::Batch::new();
+        batch.push(message);
+        batch.take()
+    }
+
+    #[test]
+    fn buffers_and_replays() {
+        let frames = Arc::new(Mutex::new(vec![]));
+        let healthy = Arc::new(Mutex::new(true));
+        let events = Arc::new(Mutex::new(vec![]));
+
+        let (f, h, e) = (frames.clone(), healthy.clone(), events.clone());
+        let mut sink = Reconnect::new(move || {
+            if *h.lock().unwrap() {
+                Ok(Flaky { frames: f.clone(), healthy: h.clone() })
+            } else {
+                Err(io::ErrorKind::ConnectionRefused.into())
+            }
+        })
+            .buffer(2)
+            .retry_every(Duration::from_secs(0))
+            .on_event(move |event| e.lock().unwrap().push(format!("{:?}", event)));
+
+        sink.send_frame(&frame(b"a")).unwrap();
+        assert!(sink.is_connected());
+
+        *healthy.lock().unwrap() = false;
+        for message in [b"b", b"c", b"d"].iter() {
+            sink.send_frame(&frame(&message[..])).unwrap();
+        }
+        assert!(!sink.is_connected());
+        assert_eq!(sink.buffered(), 2);
+
+        *healthy.lock().unwrap() = true;
+        sink.idle().unwrap();
+        assert_eq!(sink.buffered(), 0);
+        assert_eq!(*frames.lock().unwrap(), vec![frame(b"a"), frame(b"c"), frame(b"d")]);
+
+        assert!(sink.reconnected());
+        assert!(!sink.reconnected());
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.first().map(String::as_str), Some("Connected"));
+        assert!(events.iter().any(|e| e == "Dropped(1)"));
+        assert_eq!(events.last().map(String::as_str), Some("Connected"));
+    }
+}
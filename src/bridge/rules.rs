@@ -0,0 +1,174 @@
+//! Renaming and filtering topics as they cross a bridge.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::super::codec::{Codec, CodecError};
+
+/// How many topics' translations `Translated` remembers. When it has this
+/// many, it forgets them all and starts again.
+const CACHE_CAPACITY: usize = 4096;
+
+/// Rules deciding which topics cross a bridge, and what they are called on
+/// the other side.
+///
+/// Patterns may contain one `*`, which matches any run of characters
+/// (including none). In a `forward()` target, `*` is replaced by whatever the
+/// source pattern matched, so `forward("local/telemetry/*",
+/// "site42/telemetry/*")` renames `local/telemetry/cpu` to
+/// `site42/telemetry/cpu`. A pattern without `*` matches only itself.
+///
+/// A topic matching any `exclude()` pattern never crosses. Otherwise the first
+/// matching `forward()` rule decides its new name; if there are forward rules
+/// but none match, the topic doesn't cross either. With no forward rules at
+/// all, topics that aren't excluded cross unchanged.
+#[derive(Clone, Debug, Default)]
+pub struct TopicRules {
+    forward: Vec<(Pattern, String)>,
+    exclude: Vec<Pattern>,
+}
+
+impl TopicRules {
+    /// An empty rule set, which lets every topic through unchanged.
+    pub fn new() -> Self {
+        TopicRules::default()
+    }
+
+    /// Lets topics matching `from` through, renamed according to `to`.
+    pub fn forward(mut self, from: &str, to: &str) -> Self {
+        self.forward.push((Pattern::new(from), to.to_owned()));
+        self
+    }
+
+    /// Stops topics matching `pattern`, whatever the forward rules say.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(Pattern::new(pattern));
+        self
+    }
+
+    /// Returns the name `topic` goes by on the far side of the bridge, or
+    /// `None` if it shouldn't cross.
+    pub fn apply(&self, topic: &str) -> Option<String> {
+        if self.exclude.iter().any(|p| p.capture(topic).is_some()) {
+            return None;
+        }
+
+        if self.forward.is_empty() { return Some(topic.to_owned()); }
+
+        self.forward.iter().find_map(|(pattern, target)| {
+            pattern.capture(topic).map(|captured| target.replacen('*', captured, 1))
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Pattern {
+    prefix: String,
+    suffix: Option<String>,
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Self {
+        match pattern.find('*') {
+            Some(star) => Pattern {
+                prefix: pattern[.. star].to_owned(),
+                suffix: Some(pattern[star + 1 ..].to_owned()),
+            },
+            None => Pattern { prefix: pattern.to_owned(), suffix: None },
+        }
+    }
+
+    /// Returns what the wildcard matched, or the empty string for a pattern
+    /// without one.
+    fn capture<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        match self.suffix {
+            None => if topic == self.prefix { Some("") } else { None },
+            Some(ref suffix) => {
+                let rest = topic.strip_prefix(self.prefix.as_str())?;
+                rest.strip_suffix(suffix.as_str())
+            },
+        }
+    }
+}
+
+/// A codec that applies `TopicRules` on top of another codec. When encoding,
+/// topics are renamed before the inner codec sees them, and topics that
+/// shouldn't cross fail to encode, which makes `export()` skip them. When
+/// decoding the rules are applied to whatever the inner codec returns, so the
+/// receiving side can rename or filter too.
+///
+/// Matching a topic against every rule costs time in proportion to the
+/// number of rules, so the outcome for each topic is remembered, and a topic
+/// seen before is translated with one lookup.
+pub struct Translated<K> {
+    rules: TopicRules,
+    codec: K,
+    cache: Mutex<HashMap<String, Option<String>>>,
+}
+
+impl<K> Translated<K> {
+    /// Wraps `codec`.
+    pub fn new(rules: TopicRules, codec: K) -> Self {
+        Translated { rules, codec, cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// Applies the rules to `topic`, or recalls what they said last time.
+    fn apply(&self, topic: &str) -> Option<String> {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(renamed) = cache.get(topic) { return renamed.clone(); }
+        if cache.len() >= CACHE_CAPACITY { cache.clear(); }
+        let renamed = self.rules.apply(topic);
+        cache.insert(topic.to_owned(), renamed.clone());
+        renamed
+    }
+
+    fn translate<Topic>(&self, topic: &Topic) -> Result<Topic, CodecError>
+        where Topic: AsRef<str> + From<String>
+    {
+        match self.apply(topic.as_ref()) {
+            Some(renamed) => Ok(Topic::from(renamed)),
+            None => Err(CodecError::new(format!("topic {:?} doesn't cross", topic.as_ref()))),
+        }
+    }
+}
+
+impl<Topic, Content, K> Codec<Topic, Content> for Translated<K>
+    where Topic: AsRef<str> + From<String>,
+          K: Codec<Topic, Content>,
+{
+    fn encode(&self, topic: &Topic, content: &Content) -> Result<Vec<u8>, CodecError> {
+        self.codec.encode(&self.translate(topic)?, content)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<(Topic, Content), CodecError> {
+        let (topic, content) = self.codec.decode(bytes)?;
+        Ok((self.translate(&topic)?, content))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rename_and_exclude() {
+        let rules = TopicRules::new()
+            .exclude("local/telemetry/debug*")
+            .forward("local/telemetry/*", "site42/telemetry/*")
+            .forward("local/status", "site42/status");
+
+        assert_eq!(rules.apply("local/telemetry/cpu"), Some("site42/telemetry/cpu".into()));
+        assert_eq!(rules.apply("local/status"), Some("site42/status".into()));
+        assert_eq!(rules.apply("local/telemetry/debug/heap"), None);
+        assert_eq!(rules.apply("local/secrets"), None);
+
+        assert_eq!(TopicRules::new().apply("anything"), Some("anything".into()));
+
+        let translated = Translated::new(rules, ());
+        for _ in 0 .. 2 {
+            assert_eq!(translated.apply("local/status"), Some("site42/status".into()));
+            assert_eq!(translated.apply("local/secrets"), None);
+        }
+        assert_eq!(translated.cache.lock().unwrap().len(), 2);
+    }
+}
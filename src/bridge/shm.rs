@@ -0,0 +1,386 @@
+//! A shared-memory ring buffer for bridging processes on the same machine.
+//!
+//! One process creates a segment with `ShmWriter::create()`, which backs it
+//! with a file (on Linux, a path under `/dev/shm` keeps it in memory). Other
+//! processes call `ShmReader::open()` on the same path. Every reader sees every
+//! frame written after it opened the segment.
+//!
+//! Frames are stored back to back, each behind a four-byte length, and padded
+//! to eight bytes. A frame that won't fit before the end of the buffer is moved
+//! to the start, leaving a marker that tells readers to skip the gap.
+//!
+//! Readers register themselves in a table in the segment's header, and the
+//! writer never overwrites data a registered reader hasn't read yet; when the
+//! buffer is full, it waits. A reader that exits without being dropped keeps
+//! its slot, and stalls the writer, until `ShmWriter::evict_readers()` is
+//! called.
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use memmap2::MmapMut;
+
+use super::{FrameSink, FrameSource};
+
+const MAGIC: u64 = 0x616c_6577_6966_6531; // "alewife1"
+
+/// Maximum number of readers attached to one segment at a time.
+pub const MAX_READERS: usize = 16;
+
+const CAPACITY_OFFSET: usize = 8;
+const WRITE_POS_OFFSET: usize = 16;
+const READERS_OFFSET: usize = 64;
+const DATA_OFFSET: usize = READERS_OFFSET + 8 * MAX_READERS;
+
+/// Length value marking the unused tail of the buffer before a wrap.
+const WRAP_MARKER: u32 = u32::MAX;
+
+/// How long readers and a blocked writer sleep between checks.
+const SPIN_INTERVAL: Duration = Duration::from_micros(200);
+
+/// A mapped segment. All positions are byte counts since the segment was
+/// created; a position's place in the buffer is `position % capacity`.
+struct Segment {
+    map: MmapMut,
+    capacity: u64,
+}
+
+impl Segment {
+    fn atomic(&self, offset: usize) -> &AtomicU64 {
+        // Header fields are eight-byte aligned within a page-aligned mapping,
+        // and are only ever accessed atomically.
+        unsafe { &*(self.map.as_ptr().add(offset) as *const AtomicU64) }
+    }
+
+    fn write_pos(&self) -> &AtomicU64 {
+        self.atomic(WRITE_POS_OFFSET)
+    }
+
+    /// A reader's slot holds its position plus one, or zero when free.
+    fn reader_slot(&self, slot: usize) -> &AtomicU64 {
+        self.atomic(READERS_OFFSET + 8 * slot)
+    }
+
+    fn data_ptr(&self, position: u64) -> *mut u8 {
+        let offset = (position % self.capacity) as usize;
+        unsafe { (self.map.as_ptr() as *mut u8).add(DATA_OFFSET + offset) }
+    }
+
+    fn read_len(&self, position: u64) -> u32 {
+        let mut bytes = [0; 4];
+        unsafe { ptr::copy_nonoverlapping(self.data_ptr(position), bytes.as_mut_ptr(), 4) };
+        u32::from_le_bytes(bytes)
+    }
+
+    fn write_bytes(&self, position: u64, bytes: &[u8]) {
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), self.data_ptr(position), bytes.len()) };
+    }
+
+    /// Position of the registered reader furthest behind, if any.
+    fn slowest_reader(&self) -> Option<u64> {
+        (0 .. MAX_READERS)
+            .map(|slot| self.reader_slot(slot).load(Ordering::Acquire))
+            .filter(|&value| value != 0)
+            .map(|value| value - 1)
+            .min()
+    }
+}
+
+fn padded(len: usize) -> u64 {
+    ((4 + len as u64) + 7) & !7
+}
+
+/// The writing end of a shared-memory segment. There must be only one.
+pub struct ShmWriter {
+    segment: Segment,
+    position: u64,
+    timeout: Option<Duration>,
+}
+
+impl ShmWriter {
+    /// Creates (or replaces) a segment at `path` able to hold `capacity`
+    /// bytes of frames, rounded up to a multiple of eight. Each frame uses its
+    /// length plus four bytes, rounded up the same way.
+    ///
+    /// An existing file at `path` is unlinked rather than resized, since
+    /// shrinking a file another process has mapped would crash it; readers of
+    /// the old segment keep it until they let go.
+    pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> io::Result<Self> {
+        let capacity = (capacity as u64 + 7) & !7;
+        if capacity == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty segment"));
+        }
+
+        let path = path.as_ref();
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != io::ErrorKind::NotFound { return Err(e); }
+        }
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(path)?;
+        file.set_len(DATA_OFFSET as u64 + capacity)?;
+
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        let segment = Segment { map, capacity };
+        segment.atomic(CAPACITY_OFFSET).store(capacity, Ordering::Relaxed);
+        segment.write_pos().store(0, Ordering::Relaxed);
+        segment.atomic(0).store(MAGIC, Ordering::Release);
+
+        Ok(ShmWriter { segment, position: 0, timeout: None })
+    }
+
+    /// Limits how long `send_frame()` waits for readers to make room. By
+    /// default it waits indefinitely.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Frees every reader slot. Readers that are still attached will misbehave
+    /// afterwards, so only use this after they have gone away.
+    pub fn evict_readers(&self) {
+        for slot in 0 .. MAX_READERS {
+            self.segment.reader_slot(slot).store(0, Ordering::Release);
+        }
+    }
+
+    /// Waits until no registered reader would lose data if the buffer were
+    /// filled up to `end`.
+    fn wait_for_room(&self, end: u64) -> io::Result<()> {
+        let started = Instant::now();
+        loop {
+            match self.segment.slowest_reader() {
+                Some(slowest) if end - slowest > self.segment.capacity => (),
+                _ => return Ok(()),
+            }
+
+            if self.timeout.is_some_and(|t| started.elapsed() >= t) {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "segment is full"));
+            }
+            thread::sleep(SPIN_INTERVAL);
+        }
+    }
+}
+
+impl FrameSink for ShmWriter {
+    fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        let size = padded(frame.len());
+        let capacity = self.segment.capacity;
+        if size > capacity || frame.len() >= WRAP_MARKER as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame too large"));
+        }
+
+        let mut position = self.position;
+        let offset = position % capacity;
+        let gap = if offset + size > capacity { capacity - offset } else { 0 };
+        self.wait_for_room(position + gap + size)?;
+
+        if gap > 0 {
+            // Gaps are at least eight bytes, since everything is padded.
+            self.segment.write_bytes(position, &WRAP_MARKER.to_le_bytes());
+            position += gap;
+        }
+
+        self.segment.write_bytes(position, &(frame.len() as u32).to_le_bytes());
+        self.segment.write_bytes(position + 4, frame);
+        self.position = position + size;
+        self.segment.write_pos().store(self.position, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// A reading end of a shared-memory segment.
+pub struct ShmReader {
+    segment: Segment,
+    slot: usize,
+    position: u64,
+    timeout: Duration,
+}
+
+impl ShmReader {
+    /// Attaches to the segment at `path` and registers as a reader. Only
+    /// frames written from now on will be received.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let map = unsafe { MmapMut::map_mut(&file)? };
+
+        if map.len() < DATA_OFFSET {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a segment"));
+        }
+
+        let mut segment = Segment { map, capacity: 1 };
+        if segment.atomic(0).load(Ordering::Acquire) != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a segment"));
+        }
+        segment.capacity = segment.atomic(CAPACITY_OFFSET).load(Ordering::Relaxed);
+        if segment.capacity == 0 || !segment.capacity.is_multiple_of(8) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt segment"));
+        }
+        if segment.map.len() as u64 != DATA_OFFSET as u64 + segment.capacity {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated segment"));
+        }
+
+        // Claim a free slot. The writer may overrun the position we claimed
+        // with before it notices the claim, so start from wherever it got to
+        // once the claim was visible.
+        for slot in 0 .. MAX_READERS {
+            let claimed = segment.write_pos().load(Ordering::Acquire);
+            if segment.reader_slot(slot)
+                .compare_exchange(0, claimed + 1, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                let mut reader = ShmReader {
+                    segment,
+                    slot,
+                    position: claimed,
+                    timeout: POLL_TIMEOUT,
+                };
+                let position = reader.segment.write_pos().load(Ordering::Acquire);
+                reader.advance(position);
+                return Ok(reader);
+            }
+        }
+
+        Err(io::Error::other("too many readers"))
+    }
+
+    /// Sets how long `recv_frame()` waits for a frame before returning
+    /// `Ok(None)`.
+    pub fn set_read_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn advance(&mut self, position: u64) {
+        self.position = position;
+        self.segment.reader_slot(self.slot).store(position + 1, Ordering::Release);
+    }
+}
+
+/// Default wait in `ShmReader::recv_frame()`.
+const POLL_TIMEOUT: Duration = Duration::from_millis(20);
+
+impl FrameSource for ShmReader {
+    fn recv_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let started = Instant::now();
+        loop {
+            let written = self.segment.write_pos().load(Ordering::Acquire);
+            if written == self.position {
+                if started.elapsed() >= self.timeout { return Ok(None); }
+                thread::sleep(SPIN_INTERVAL);
+                continue;
+            }
+
+            // Nothing the segment says is trusted until it is checked to lie
+            // within the buffer: another process may have corrupted it.
+            let capacity = self.segment.capacity;
+            let offset = self.position % capacity;
+            if offset + 4 > capacity {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt frame"));
+            }
+            let len = self.segment.read_len(self.position);
+            if len == WRAP_MARKER {
+                let next = self.position + capacity - self.position % capacity;
+                self.advance(next);
+                continue;
+            }
+
+            let len = len as usize;
+            if offset + padded(len) > capacity {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt frame"));
+            }
+
+            let start = self.segment.data_ptr(self.position + 4);
+            let frame = unsafe { slice::from_raw_parts(start, len) }.to_vec();
+            let next = self.position + padded(len);
+            self.advance(next);
+            return Ok(Some(frame));
+        }
+    }
+}
+
+impl Drop for ShmReader {
+    fn drop(&mut self) {
+        self.segment.reader_slot(self.slot).store(0, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn segment_path(name: &str) -> ::std::path::PathBuf {
+        env::temp_dir().join(format!("alewife-{}-{}", name, ::std::process::id()))
+    }
+
+    #[test]
+    fn frames_wrap_around() {
+        let path = segment_path("wrap");
+        let mut writer = ShmWriter::create(&path, 64).unwrap();
+        let mut reader = ShmReader::open(&path).unwrap();
+
+        for round in 0 .. 20u8 {
+            let frame = vec![round; (round % 5) as usize * 3 + 1];
+            writer.send_frame(&frame).unwrap();
+            assert_eq!(reader.recv_frame().unwrap(), Some(frame));
+        }
+
+        assert_eq!(reader.recv_frame().unwrap(), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writer_waits_for_slow_reader() {
+        let path = segment_path("backpressure");
+        let mut writer = ShmWriter::create(&path, 32).unwrap();
+        writer.set_write_timeout(Some(Duration::from_millis(10)));
+        let mut reader = ShmReader::open(&path).unwrap();
+
+        writer.send_frame(&[1; 12]).unwrap();
+        writer.send_frame(&[2; 12]).unwrap();
+        let full = writer.send_frame(&[3; 12]).unwrap_err();
+        assert_eq!(full.kind(), io::ErrorKind::TimedOut);
+
+        assert_eq!(reader.recv_frame().unwrap(), Some(vec![1; 12]));
+        writer.send_frame(&[3; 12]).unwrap();
+
+        drop(reader);
+        for _ in 0 .. 10 { writer.send_frame(&[4; 12]).unwrap(); }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn frames_running_past_the_buffer_are_refused() {
+        let path = segment_path("overrun");
+        let mut writer = ShmWriter::create(&path, 64).unwrap();
+        let mut reader = ShmReader::open(&path).unwrap();
+        writer.send_frame(&[1; 4]).unwrap();
+        assert_eq!(reader.recv_frame().unwrap(), Some(vec![1; 4]));
+
+        // A length that would fit an empty buffer, but not from here.
+        writer.segment.write_bytes(8, &56u32.to_le_bytes());
+        writer.segment.write_pos().store(72, Ordering::Release);
+        assert_eq!(reader.recv_frame().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replacing_a_segment_leaves_old_readers_mapped() {
+        let path = segment_path("replace");
+        let mut old_writer = ShmWriter::create(&path, 64).unwrap();
+        let mut old_reader = ShmReader::open(&path).unwrap();
+
+        let mut writer = ShmWriter::create(&path, 16).unwrap();
+        let mut reader = ShmReader::open(&path).unwrap();
+        old_writer.send_frame(&[1; 8]).unwrap();
+        writer.send_frame(&[2; 8]).unwrap();
+
+        assert_eq!(old_reader.recv_frame().unwrap(), Some(vec![1; 8]));
+        assert_eq!(reader.recv_frame().unwrap(), Some(vec![2; 8]));
+        fs::remove_file(&path).unwrap();
+    }
+}
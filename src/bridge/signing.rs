@@ -0,0 +1,253 @@
+//! Signing frames so tampered or forged ones are turned away.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::super::codec::{Codec, CodecError};
+
+/// Why `Signed` turned a frame away.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// The frame carried no signature, or was too short to.
+    Unsigned,
+    /// The frame was signed with a key id nobody trusted.
+    UnknownKey(String),
+    /// The signature didn't match the frame, which was changed on the way or
+    /// signed with the wrong key.
+    BadSignature(String),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rejection::Unsigned => write!(f, "unsigned frame"),
+            Rejection::UnknownKey(ref id) => write!(f, "frame signed with unknown key {:?}", id),
+            Rejection::BadSignature(ref id) => write!(f, "bad signature for key {:?}", id),
+        }
+    }
+}
+
+type Report = Box<dyn Fn(&Rejection) + Send + Sync>;
+
+/// A codec that signs every frame with HMAC-SHA256 and checks the signature
+/// on every frame it decodes, before the inner codec sees it.
+///
+/// Each side signs with a key of its own, which it names by an id sent along
+/// with the frame, and trusts the keys of its peers by their ids. A frame that
+/// is unsigned, signed with a key not trusted, or altered after signing fails
+/// to decode, so `import()` drops it, and is reported to the `on_rejected()`
+/// callback, if there is one, along with the reason.
+pub struct Signed<K> {
+    codec: K,
+    key_id: String,
+    key: Vec<u8>,
+    trusted: HashMap<String, Vec<u8>>,
+    on_rejected: Option<Report>,
+}
+
+impl<K> Signed<K> {
+    /// Wraps `codec`, signing with `key` under the name `key_id`, which must
+    /// be shorter than 256 bytes. Frames this side signed are trusted too.
+    pub fn new(codec: K, key_id: &str, key: &[u8]) -> Self {
+        assert!(key_id.len() <= u8::MAX as usize, "key id too long");
+        let mut trusted = HashMap::new();
+        trusted.insert(key_id.to_owned(), key.to_vec());
+        Signed { codec, key_id: key_id.to_owned(), key: key.to_vec(), trusted, on_rejected: None }
+    }
+
+    /// Accepts frames signed by the peer whose key is `key`, under the name
+    /// `key_id`.
+    pub fn trust(mut self, key_id: &str, key: &[u8]) -> Self {
+        self.trusted.insert(key_id.to_owned(), key.to_vec());
+        self
+    }
+
+    /// Calls `report` with the reason for each frame turned away.
+    pub fn on_rejected<F>(mut self, report: F) -> Self
+        where F: Fn(&Rejection) + Send + Sync + 'static
+    {
+        self.on_rejected = Some(Box::new(report));
+        self
+    }
+
+    fn reject(&self, rejection: Rejection) -> CodecError {
+        if let Some(ref report) = self.on_rejected { report(&rejection); }
+        CodecError::new(rejection.to_string())
+    }
+
+    fn verify<'a>(&self, bytes: &'a [u8]) -> Result<&'a [u8], Rejection> {
+        let (&len, rest) = bytes.split_first().ok_or(Rejection::Unsigned)?;
+        if len == 0 || rest.len() < len as usize + TAG_LEN { return Err(Rejection::Unsigned); }
+        let (key_id, rest) = rest.split_at(len as usize);
+        let (tag, body) = rest.split_at(TAG_LEN);
+
+        let key_id = String::from_utf8_lossy(key_id).into_owned();
+        let key = match self.trusted.get(&key_id) {
+            Some(key) => key,
+            None => return Err(Rejection::UnknownKey(key_id)),
+        };
+        let expected = hmac_sha256(key, &[&[len], key_id.as_bytes(), body]);
+        // Compare every byte, so the time taken says nothing about where the
+        // first difference is.
+        let difference = expected.iter().zip(tag).fold(0, |acc, (a, b)| acc | (a ^ b));
+        if difference != 0 { return Err(Rejection::BadSignature(key_id)); }
+        Ok(body)
+    }
+}
+
+impl<Topic, Content, K> Codec<Topic, Content> for Signed<K>
+    where K: Codec<Topic, Content>,
+{
+    fn encode(&self, topic: &Topic, content: &Content) -> Result<Vec<u8>, CodecError> {
+        let body = self.codec.encode(topic, content)?;
+        let len = self.key_id.len() as u8;
+        let tag = hmac_sha256(&self.key, &[&[len], self.key_id.as_bytes(), &body]);
+        let mut frame = Vec::with_capacity(1 + self.key_id.len() + TAG_LEN + body.len());
+        frame.push(len);
+        frame.extend_from_slice(self.key_id.as_bytes());
+        frame.extend_from_slice(&tag);
+        frame.extend(body);
+        Ok(frame)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<(Topic, Content), CodecError> {
+        let body = self.verify(bytes).map_err(|rejection| self.reject(rejection))?;
+        self.codec.decode(body)
+    }
+}
+
+const TAG_LEN: usize = 32;
+const BLOCK_LEN: usize = 64;
+
+/// HMAC (RFC 2104) over the concatenation of `parts`.
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; TAG_LEN] {
+    let mut block = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block[.. TAG_LEN].copy_from_slice(&Sha256::digest(&[key]));
+    } else {
+        block[.. key.len()].copy_from_slice(key);
+    }
+
+    let inner_pad: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    let outer_pad: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    let mut inner = vec![&inner_pad[..]];
+    inner.extend_from_slice(parts);
+    let inner = Sha256::digest(&inner);
+    Sha256::digest(&[&outer_pad, &inner])
+}
+
+/// SHA-256, as in FIPS 180-4.
+struct Sha256 {
+    state: [u32; 8],
+}
+
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+impl Sha256 {
+    fn digest(parts: &[&[u8]]) -> [u8; TAG_LEN] {
+        let mut message: Vec<u8> = parts.concat();
+        let bits = (message.len() as u64).wrapping_mul(8);
+        message.push(0x80);
+        while message.len() % BLOCK_LEN != BLOCK_LEN - 8 { message.push(0); }
+        message.extend_from_slice(&bits.to_be_bytes());
+
+        let mut hash = Sha256 { state: [
+            0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+        ] };
+        for block in message.chunks(BLOCK_LEN) { hash.compress(block); }
+
+        let mut out = [0u8; TAG_LEN];
+        for (bytes, word) in out.chunks_mut(4).zip(&hash.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16 .. 64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (&k, &w) in ROUND_CONSTANTS.iter().zip(&w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(k).wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g; g = f; f = e; e = d.wrapping_add(t1);
+            d = c; c = b; b = a; a = t1.wrapping_add(t2);
+        }
+        for (state, value) in self.state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(*value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    struct Plain;
+
+    impl Codec<String, String> for Plain {
+        fn encode(&self, topic: &String, content: &String) -> Result<Vec<u8>, CodecError> {
+            Ok(format!("{}:{}", topic, content).into_bytes())
+        }
+
+        fn decode(&self, bytes: &[u8]) -> Result<(String, String), CodecError> {
+            let text = String::from_utf8_lossy(bytes);
+            let (topic, content) = text.split_once(':').ok_or_else(|| CodecError::new("no colon"))?;
+            Ok((topic.to_owned(), content.to_owned()))
+        }
+    }
+
+    #[test]
+    fn tampered_and_unknown_frames_are_rejected() {
+        // RFC 4231, test case 2.
+        let tag = hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"]);
+        assert_eq!(tag[.. 4], [0x5b, 0xdc, 0xc1, 0x46]);
+        assert_eq!(tag[28 ..], [0x64, 0xec, 0x38, 0x43]);
+
+        let rejections = Arc::new(Mutex::new(Vec::new()));
+        let log = rejections.clone();
+        let ground = Signed::new(Plain, "ground", b"ground key").trust("rover", b"rover key")
+            .on_rejected(move |rejection| log.lock().unwrap().push(rejection.clone()));
+        let rover = Signed::new(Plain, "rover", b"rover key");
+        let intruder = Signed::new(Plain, "intruder", b"guess");
+
+        let (topic, order) = ("orders".to_owned(), "halt".to_owned());
+        let frame = rover.encode(&topic, &order).unwrap();
+        assert_eq!(ground.decode(&frame).unwrap(), (topic.clone(), order.clone()));
+
+        let mut tampered = frame.clone();
+        *tampered.last_mut().unwrap() = b'?';
+        assert!(ground.decode(&tampered).is_err());
+        assert!(ground.decode(&intruder.encode(&topic, &order).unwrap()).is_err());
+        assert!(ground.decode(&Plain.encode(&topic, &order).unwrap()).is_err());
+
+        assert_eq!(*rejections.lock().unwrap(), vec![
+            Rejection::BadSignature("rover".to_owned()),
+            Rejection::UnknownKey("intruder".to_owned()),
+            Rejection::Unsigned,
+        ]);
+    }
+}
@@ -0,0 +1,86 @@
+//! Length-prefixed frames over a byte stream.
+
+use std::io::{self, Read, Write};
+
+use super::{FrameSink, FrameSource};
+
+/// Largest frame `Framed` will accept, to keep a corrupt length prefix from
+/// allocating without bound.
+const MAX_FRAME: usize = 64 << 20;
+
+/// Sends and receives frames over a byte stream, each prefixed with its length
+/// as a big-endian `u32`.
+///
+/// To let a bridge notice when it is stopped, give blocking streams a read
+/// timeout (for example with `TcpStream::set_read_timeout()`). Partial frames
+/// are kept across timeouts, so no data is lost when one expires.
+pub struct Framed<S> {
+    stream: S,
+    buffer: Vec<u8>,
+}
+
+impl<S> Framed<S> {
+    /// Wraps a stream.
+    pub fn new(stream: S) -> Self {
+        Framed { stream, buffer: Vec::new() }
+    }
+
+    /// Returns the wrapped stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Unwraps the stream. Any partially received frame is lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Removes and returns the first complete frame in the buffer.
+    fn buffered_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.buffer.len() < 4 { return Ok(None); }
+
+        let mut prefix = [0; 4];
+        prefix.copy_from_slice(&self.buffer[.. 4]);
+        let len = u32::from_be_bytes(prefix) as usize;
+        if len > MAX_FRAME {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too large"));
+        }
+
+        if self.buffer.len() < 4 + len { return Ok(None); }
+        let frame = self.buffer[4 .. 4 + len].to_vec();
+        self.buffer.drain(.. 4 + len);
+        Ok(Some(frame))
+    }
+}
+
+impl<S: Write + Send> FrameSink for Framed<S> {
+    fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        if frame.len() > MAX_FRAME {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame too large"));
+        }
+
+        self.stream.write_all(&(frame.len() as u32).to_be_bytes())?;
+        self.stream.write_all(frame)?;
+        self.stream.flush()
+    }
+}
+
+impl<S: Read + Send> FrameSource for Framed<S> {
+    fn recv_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(frame) = self.buffered_frame()? {
+                return Ok(Some(frame));
+            }
+
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[.. n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
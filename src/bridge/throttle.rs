@@ -0,0 +1,176 @@
+//! Counting and capping the bytes that cross a link.
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{FrameSink, FrameSource};
+use super::super::OverQuota;
+
+/// Running totals for one `Throttled` transport. Clones share the same
+/// totals, so one can be kept to watch a link from elsewhere.
+#[derive(Clone, Debug, Default)]
+pub struct Traffic {
+    counts: Arc<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    sent: AtomicU64,
+    received: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Traffic {
+    /// Bytes written to the transport, not counting frames that were dropped.
+    pub fn sent(&self) -> u64 {
+        self.counts.sent.load(Ordering::Relaxed)
+    }
+
+    /// Bytes read from the transport and passed on, not counting frames that
+    /// were dropped.
+    pub fn received(&self) -> u64 {
+        self.counts.received.load(Ordering::Relaxed)
+    }
+
+    /// Frames discarded for being over the cap.
+    pub fn dropped(&self) -> u64 {
+        self.counts.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// A `FrameSink` or `FrameSource` that counts the bytes passing through it
+/// and can hold them to a cap, so one peer can't take the whole of a slow
+/// link. Wrap the transport of each peer's link in one of its own.
+///
+/// The cap is a token bucket holding one second's worth of bytes, like a
+/// publisher's bandwidth quota. A frame over the cap either waits until the
+/// bucket recovers, which slows the link down and, over a stream, pushes back
+/// on the peer, or is dropped, in which case a frame bigger than a second's
+/// worth never gets through.
+pub struct Throttled<T> {
+    inner: T,
+    traffic: Traffic,
+    cap: Option<(f64, OverQuota)>,
+    level: f64,
+    topped_up: Option<Instant>,
+}
+
+impl<T> Throttled<T> {
+    /// Wraps `inner`, counting its traffic but not capping it.
+    pub fn new(inner: T) -> Self {
+        Throttled { inner, traffic: Traffic::default(), cap: None, level: 0.0, topped_up: None }
+    }
+
+    /// Allows `bytes_per_second` through on average, and deals with frames
+    /// over that as `over` says.
+    pub fn limit(mut self, bytes_per_second: u64, over: OverQuota) -> Self {
+        self.cap = Some((bytes_per_second as f64, over));
+        self
+    }
+
+    /// The link's running totals.
+    pub fn traffic(&self) -> Traffic {
+        self.traffic.clone()
+    }
+
+    /// Charges a frame of `len` bytes against the cap, waiting if need be.
+    /// Returns false if it should be dropped instead.
+    fn admit(&mut self, len: usize) -> bool {
+        let (per_second, over) = match self.cap {
+            Some(cap) => cap,
+            None => return true,
+        };
+
+        let now = Instant::now();
+        let elapsed = self.topped_up.map_or(1.0, |at| now.duration_since(at).as_secs_f64());
+        self.topped_up = Some(now);
+        self.level = (self.level + per_second * elapsed).min(per_second);
+
+        let cost = len as f64;
+        if over == OverQuota::Refuse && self.level < cost {
+            self.traffic.counts.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        self.level -= cost;
+        if self.level < 0.0 {
+            thread::sleep(Duration::from_secs_f64(-self.level / per_second));
+        }
+        true
+    }
+}
+
+impl<S: FrameSink> FrameSink for Throttled<S> {
+    fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        if !self.admit(frame.len()) { return Ok(()); }
+        self.inner.send_frame(frame)?;
+        self.traffic.counts.sent.fetch_add(frame.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn idle(&mut self) -> io::Result<()> {
+        self.inner.idle()
+    }
+
+    fn reconnected(&mut self) -> bool {
+        self.inner.reconnected()
+    }
+}
+
+impl<S: FrameSource> FrameSource for Throttled<S> {
+    fn recv_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let frame = match self.inner.recv_frame()? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        if !self.admit(frame.len()) { return Ok(None); }
+        self.traffic.counts.received.fetch_add(frame.len() as u64, Ordering::Relaxed);
+        Ok(Some(frame))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    struct Loopback(VecDeque<Vec<u8>>);
+
+    impl FrameSink for Loopback {
+        fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+            self.0.push_back(frame.to_vec());
+            Ok(())
+        }
+    }
+
+    impl FrameSource for Loopback {
+        fn recv_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.0.pop_front())
+        }
+    }
+
+    #[test]
+    fn frames_over_the_cap_wait_or_are_dropped() {
+        let mut dashboard = Throttled::new(Loopback(VecDeque::new())).limit(1000, OverQuota::Refuse);
+        let traffic = dashboard.traffic();
+        dashboard.send_frame(&[0; 600]).unwrap();
+        dashboard.send_frame(&[0; 600]).unwrap();
+        dashboard.send_frame(&[0; 300]).unwrap();
+        assert_eq!((traffic.sent(), traffic.dropped()), (900, 1));
+
+        let mut incoming = Throttled::new(dashboard.inner).limit(20_000, OverQuota::Wait);
+        let started = Instant::now();
+        assert_eq!(incoming.recv_frame().unwrap().map(|f| f.len()), Some(600));
+        assert_eq!(incoming.recv_frame().unwrap().map(|f| f.len()), Some(300));
+        assert_eq!(incoming.recv_frame().unwrap(), None);
+        assert!(started.elapsed() < Duration::from_millis(10));
+        assert_eq!(incoming.traffic().received(), 900);
+
+        incoming.inner.send_frame(&[0; 20_000]).unwrap();
+        assert!(incoming.recv_frame().unwrap().is_some());
+        assert!(started.elapsed() >= Duration::from_millis(40));
+    }
+}
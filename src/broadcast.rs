@@ -0,0 +1,183 @@
+//! One shared buffer per topic, read at each subscriber's own pace.
+//!
+//! A topic with many subscribers normally costs a copy of every message in
+//! every subscriber's queue, and a subscriber that stops reading holds on
+//! to everything sent to it since. `Publisher::broadcast()` keeps a single
+//! buffer of the latest messages instead. Each `BroadcastReceiver` only
+//! remembers how far through it has read, and hears every message published
+//! after it was made, unless the buffer moves on without it: then it is told
+//! how many it missed with `Lagged`, and carries on from the oldest message
+//! still kept.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{Outbox, Publisher, SubscriptionGuard};
+
+/// Returned to a receiver that fell so far behind that messages it hadn't
+/// read were discarded, with how many.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lagged(pub u64);
+
+impl fmt::Display for Lagged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fell behind and missed {} messages", self.0)
+    }
+}
+
+impl Error for Lagged {}
+
+struct Buffer<Topic, Content> {
+    /// The position of the oldest message kept.
+    start: u64,
+    messages: VecDeque<(Topic, Content)>,
+}
+
+struct Shared<Topic, Content> {
+    capacity: usize,
+    buffer: Mutex<Buffer<Topic, Content>>,
+    written: Condvar,
+}
+
+/// A topic's broadcast buffer, made by `Publisher::broadcast()`, which hands
+/// out receivers. Clones share the same buffer.
+pub struct Broadcast<Topic, Content> {
+    shared: Arc<Shared<Topic, Content>>,
+}
+
+impl<Topic, Content> Clone for Broadcast<Topic, Content> {
+    fn clone(&self) -> Self {
+        Broadcast { shared: self.shared.clone() }
+    }
+}
+
+impl<Topic, Content> Broadcast<Topic, Content> {
+    /// Makes a receiver for every message published from now on.
+    pub fn subscribe(&self) -> BroadcastReceiver<Topic, Content> {
+        let buffer = self.shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        let cursor = buffer.start + buffer.messages.len() as u64;
+        BroadcastReceiver { shared: self.shared.clone(), cursor: Cell::new(cursor) }
+    }
+
+    /// How many messages are kept for receivers to read.
+    pub fn len(&self) -> usize {
+        self.shared.buffer.lock().unwrap_or_else(|e| e.into_inner()).messages.len()
+    }
+
+    /// Returns true until something has been published.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Reads a `Broadcast` buffer from where it last left off.
+pub struct BroadcastReceiver<Topic, Content> {
+    shared: Arc<Shared<Topic, Content>>,
+    cursor: Cell<u64>,
+}
+
+impl<Topic: Clone, Content: Clone> BroadcastReceiver<Topic, Content> {
+    /// Takes the next message, if one is waiting.
+    pub fn try_recv(&self) -> Result<Option<(Topic, Content)>, Lagged> {
+        let buffer = self.shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        self.take(&buffer)
+    }
+
+    /// Like `try_recv()`, but waits up to `timeout` for a message.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<(Topic, Content)>, Lagged> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer = self.shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(message) = self.take(&buffer)? { return Ok(Some(message)); }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left == Duration::ZERO { return Ok(None); }
+            buffer = self.shared.written.wait_timeout(buffer, left)
+                .unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+
+    /// How many messages are waiting, not counting any already missed.
+    pub fn pending(&self) -> usize {
+        let buffer = self.shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        let end = buffer.start + buffer.messages.len() as u64;
+        (end - self.cursor.get().max(buffer.start)) as usize
+    }
+
+    fn take(&self, buffer: &Buffer<Topic, Content>) -> Result<Option<(Topic, Content)>, Lagged> {
+        let cursor = self.cursor.get();
+        if cursor < buffer.start {
+            self.cursor.set(buffer.start);
+            return Err(Lagged(buffer.start - cursor));
+        }
+        let message = buffer.messages.get((cursor - buffer.start) as usize).cloned();
+        if message.is_some() { self.cursor.set(cursor + 1); }
+        Ok(message)
+    }
+}
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + 'static,
+          Content: Clone + Send + 'static,
+{
+    /// Keeps the latest `capacity` messages on `topic` in one buffer shared
+    /// by every receiver made from it, until the guard is dropped. Nothing is
+    /// cloned on publish beyond the one copy kept; receivers clone what they
+    /// read. The buffer doesn't count towards the memory budget.
+    pub fn broadcast(&self, topic: Topic, capacity: usize)
+        -> (Broadcast<Topic, Content>, SubscriptionGuard<Topic, Content>)
+    {
+        let bus = &self.handle.bus;
+        let shared = Arc::new(Shared {
+            capacity: capacity.max(1),
+            buffer: Mutex::new(Buffer { start: 0, messages: VecDeque::new() }),
+            written: Condvar::new(),
+        });
+        let writer = shared.clone();
+        let outbox = Outbox::Forward(Arc::new(move |topic: Topic, content: Content| {
+            let mut buffer = writer.buffer.lock().unwrap_or_else(|e| e.into_inner());
+            if buffer.messages.len() == writer.capacity {
+                buffer.messages.pop_front();
+                buffer.start += 1;
+            }
+            buffer.messages.push_back((topic, content));
+            drop(buffer);
+            writer.written.notify_all();
+        }));
+        let id = bus.subscribe(&[topic], outbox, None);
+        (Broadcast { shared }, SubscriptionGuard { bus: bus.clone(), id })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slow_receivers_are_told_what_they_missed() {
+        let publisher = Publisher::new().build();
+        let (broadcast, _guard) = publisher.broadcast("quote", 3);
+        publisher.publish("quote", 0);
+
+        let fast = broadcast.subscribe();
+        let slow = broadcast.subscribe();
+        for n in 1 ..= 2 {
+            publisher.publish("quote", n);
+            assert_eq!(fast.try_recv(), Ok(Some(("quote", n))));
+        }
+        for n in 3 ..= 5 { publisher.publish("quote", n); }
+
+        assert_eq!(fast.pending(), 3);
+        assert_eq!(slow.try_recv(), Err(Lagged(2)));
+        assert_eq!(slow.try_recv(), Ok(Some(("quote", 3))));
+        assert_eq!(fast.try_recv(), Ok(Some(("quote", 3))));
+        assert_eq!(broadcast.len(), 3);
+        assert_eq!(slow.recv_timeout(Duration::from_millis(1)), Ok(Some(("quote", 4))));
+        assert_eq!(slow.try_recv(), Ok(Some(("quote", 5))));
+        assert_eq!(slow.recv_timeout(Duration::from_millis(1)), Ok(None));
+    }
+}
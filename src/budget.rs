@@ -0,0 +1,176 @@
+//! Accounting for the memory held in subscriber queues.
+
+use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::sync::{AtomicUsize, Ordering};
+
+/// Content that can estimate how much memory it occupies, for use with
+/// `Builder::memory_budget()`. Estimates should include heap allocations the
+/// content owns, since those are what queued copies cost.
+pub trait ContentSize {
+    /// Returns the approximate size in bytes.
+    fn content_size(&self) -> usize;
+}
+
+macro_rules! fixed_size {
+    ($($t:ty),*) => {
+        $(impl ContentSize for $t {
+            fn content_size(&self) -> usize {
+                ::std::mem::size_of::<$t>()
+            }
+        })*
+    };
+}
+
+fixed_size!((), bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize,
+            f32, f64);
+
+impl ContentSize for str {
+    fn content_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl ContentSize for &str {
+    fn content_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl ContentSize for String {
+    fn content_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: ContentSize> ContentSize for [T] {
+    fn content_size(&self) -> usize {
+        self.iter().map(ContentSize::content_size).sum()
+    }
+}
+
+impl<T: ContentSize> ContentSize for Vec<T> {
+    fn content_size(&self) -> usize {
+        self[..].content_size()
+    }
+}
+
+impl<T: ContentSize> ContentSize for Option<T> {
+    fn content_size(&self) -> usize {
+        self.as_ref().map_or(0, ContentSize::content_size)
+    }
+}
+
+impl<T: ContentSize + ?Sized> ContentSize for Box<T> {
+    fn content_size(&self) -> usize {
+        (**self).content_size()
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl ContentSize for ::bytes::Bytes {
+    fn content_size(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Shared content is only stored once, however many queues it sits in, but
+/// each queued copy still keeps all of it alive.
+impl<T: ContentSize + ?Sized> ContentSize for Arc<T> {
+    fn content_size(&self) -> usize {
+        (**self).content_size()
+    }
+}
+
+impl<T: ContentSize + ?Sized> ContentSize for Rc<T> {
+    fn content_size(&self) -> usize {
+        (**self).content_size()
+    }
+}
+
+/// How readily a topic's messages are shed when the network is short of
+/// memory. See `Builder::memory_budget()`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Priority {
+    /// Shed once queues hold half the budget.
+    Low,
+    /// Shed once queues hold three quarters of the budget. Topics without a
+    /// priority of their own are treated as normal.
+    #[default]
+    Normal,
+    /// Shed only when the budget is used up.
+    High,
+}
+
+impl Priority {
+    fn share(self, budget: usize) -> usize {
+        match self {
+            Priority::Low => budget / 2,
+            Priority::Normal => budget / 4 * 3,
+            Priority::High => budget,
+        }
+    }
+}
+
+type Sizer<Content> = Box<dyn Fn(&Content) -> usize + Send + Sync>;
+
+/// The network's memory budget, shared by every subscriber so each can give
+/// back what its messages cost as it reads them. Until a budget is set,
+/// nothing is charged or released.
+pub(crate) struct MemoryBudget<Content> {
+    limit: OnceLock<(usize, Sizer<Content>)>,
+    used: AtomicUsize,
+}
+
+impl<Content> MemoryBudget<Content> {
+    pub(crate) fn new() -> Self {
+        MemoryBudget { limit: OnceLock::new(), used: AtomicUsize::new(0) }
+    }
+
+    /// Sets the budget. Only the first call has any effect.
+    pub(crate) fn set(&self, bytes: usize, sizer: Sizer<Content>) {
+        let _ = self.limit.set((bytes, sizer));
+    }
+
+    pub(crate) fn limit(&self) -> Option<usize> {
+        self.limit.get().map(|&(bytes, _)| bytes)
+    }
+
+    pub(crate) fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Measures `content`, if there is a budget.
+    pub(crate) fn size(&self, content: &Content) -> Option<usize> {
+        self.limit.get().map(|(_, sizer)| sizer(content))
+    }
+
+    /// Charges for one queued copy of a message of `size` bytes, unless that
+    /// would take usage past what `priority` is allowed.
+    pub(crate) fn charge(&self, size: usize, priority: Priority) -> bool {
+        let allowed = match self.limit.get() {
+            Some(&(bytes, _)) => priority.share(bytes),
+            None => return true,
+        };
+
+        self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            Some(used + size).filter(|&total| total <= allowed)
+        }).is_ok()
+    }
+
+    /// Gives back what a message read from a queue was charged.
+    pub(crate) fn release(&self, content: &Content) {
+        if let Some(size) = self.size(content) { self.release_bytes(size); }
+    }
+
+    pub(crate) fn release_bytes(&self, size: usize) {
+        let _ = self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            Some(used.saturating_sub(size))
+        });
+    }
+}
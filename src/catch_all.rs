@@ -0,0 +1,88 @@
+//! Subscribers that receive every topic, and subscribers that would receive
+//! none.
+//!
+//! `Builder::add_subscriber()` takes the list of topics a subscriber wants,
+//! so an empty list makes a subscriber that never receives anything, which
+//! is seldom what was meant. Where the list comes from configuration or is
+//! built at run time, `Builder::try_add_subscriber()` refuses an empty one.
+//! A subscriber that really should see everything, such as a recorder or a
+//! debugging console, is asked for by name with
+//! `Builder::add_broadcast_subscriber()`, and receives every message on
+//! every topic, including topics nobody had heard of when it subscribed.
+//! Single-consumer topics still go only to their one subscriber.
+
+use std::hash::Hash;
+
+use super::{Builder, Bus, Publisher, SubscribeError, Subscriber, SubscriptionGuard, SubscriptionOptions};
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Bus<Topic, Content> {
+    /// Creates a subscriber with a route on every topic, returning it with
+    /// its route id.
+    fn add_broadcast_subscriber(&self, options: SubscriptionOptions) -> (Subscriber<Topic, Content>, usize) {
+        for extension in &self.extensions {
+            extension.on_subscribe(&[]);
+        }
+        let (subscriber, route) = self.new_subscriber(options);
+        self.routing.write().unwrap_or_else(|e| e.into_inner()).insert_everywhere(&route);
+        self.route_changes.notify();
+        (subscriber, route.id)
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Like `add_subscriber()`, but refuses an empty list of topics, and a
+    /// single-consumer topic that already has its subscriber.
+    pub fn try_add_subscriber(&mut self, topics: &[Topic]) -> Result<Subscriber<Topic, Content>, SubscribeError> {
+        if topics.is_empty() { return Err(SubscribeError::NoTopics); }
+        Ok(self.bus.try_add_subscriber(topics, SubscriptionOptions::new())?.0)
+    }
+
+    /// Adds a subscriber that receives every message published on the
+    /// network, whatever its topic.
+    pub fn add_broadcast_subscriber(&mut self) -> Subscriber<Topic, Content> {
+        self.add_broadcast_subscriber_with(SubscriptionOptions::new())
+    }
+
+    /// Like `add_broadcast_subscriber()`, with extra control over how
+    /// messages are delivered to it.
+    pub fn add_broadcast_subscriber_with(&mut self, options: SubscriptionOptions) -> Subscriber<Topic, Content> {
+        self.bus.add_broadcast_subscriber(options).0
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Adds a subscriber to the running network that receives every message
+    /// on it, until the returned guard is dropped.
+    pub fn subscribe_broadcast_scoped(&self)
+        -> (Subscriber<Topic, Content>, SubscriptionGuard<Topic, Content>)
+    {
+        let bus = &self.handle.bus;
+        let (subscriber, id) = bus.add_broadcast_subscriber(SubscriptionOptions::new());
+        (subscriber, SubscriptionGuard { bus: bus.clone(), id })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn broadcast_subscribers_hear_every_topic() {
+        let mut builder = Publisher::new();
+        assert_eq!(builder.try_add_subscriber(&[]).err(), Some(SubscribeError::NoTopics));
+        let recorder = builder.add_broadcast_subscriber();
+        let orders = builder.try_add_subscriber(&["orders"]).unwrap();
+        let publisher = builder.build();
+
+        publisher.publish("orders", 1);
+        publisher.publish("never/declared", 2);
+        let (console, guard) = publisher.subscribe_broadcast_scoped();
+        publisher.publish("orders", 3);
+        drop(guard);
+        publisher.publish("orders", 4);
+
+        assert_eq!(recorder.fetch(), vec![("orders", 1), ("never/declared", 2), ("orders", 3), ("orders", 4)]);
+        assert_eq!(orders.fetch(), vec![("orders", 1), ("orders", 3), ("orders", 4)]);
+        assert_eq!(console.fetch(), vec![("orders", 3)]);
+    }
+}
@@ -0,0 +1,71 @@
+//! Attaching components that speak plain channels.
+//!
+//! Code written before the network, or for some other library, often reads
+//! from an `mpsc::Receiver` or hands its output to an `mpsc::Sender`.
+//! `Publisher::subscribe_sender()` feeds messages on some topics into an
+//! existing sender, and `Publisher::publish_from()` publishes whatever
+//! arrives on an existing receiver, so such code can be attached as it is.
+
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use super::{Outbox, Publisher, SubscriptionGuard};
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+{
+    /// Sends each message on `topics` to `sender`, in the publishing thread,
+    /// until the guard is dropped. Once the receiving end is gone, messages
+    /// are dropped quietly.
+    pub fn subscribe_sender(&self, topics: &[Topic], sender: Sender<(Topic, Content)>)
+        -> SubscriptionGuard<Topic, Content>
+    {
+        let bus = &self.handle.bus;
+        let outbox = Outbox::Forward(Arc::new(move |topic, content| {
+            let _ = sender.send((topic, content));
+        }));
+        let id = bus.subscribe(topics, outbox, None);
+        SubscriptionGuard { bus: bus.clone(), id }
+    }
+
+    /// Starts a thread that publishes every message `receiver` gets, through
+    /// a clone of this handle, until every sender for it has been dropped.
+    pub fn publish_from(&self, receiver: Receiver<(Topic, Content)>) -> JoinHandle<()> {
+        let publisher = self.clone();
+        thread::spawn(move || for (topic, content) in receiver {
+            publisher.publish(topic, content);
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn channels_plug_into_the_network() {
+        let mut builder = Publisher::new();
+        let display = builder.add_subscriber(&["reading"]);
+        let publisher = builder.build();
+
+        let (legacy_in, readings) = mpsc::channel();
+        let guard = publisher.subscribe_sender(&["command"], legacy_in);
+        let (sensor, legacy_out) = mpsc::channel();
+        let pump = publisher.publish_from(legacy_out);
+
+        publisher.publish("command", 1);
+        sensor.send(("reading", 20)).unwrap();
+        drop(sensor);
+        pump.join().unwrap();
+        drop(guard);
+        publisher.publish("command", 2);
+
+        assert_eq!(readings.try_iter().collect::<Vec<_>>(), vec![("command", 1)]);
+        assert_eq!(display.fetch(), vec![("reading", 20)]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Sending one topic's messages to different groups by their content.
+//!
+//! A topic like `"alerts"` may carry traffic for many regions, each with its
+//! own consumers. Rather than have every consumer throw away the alerts that
+//! aren't theirs, `Builder::route_by()` gives the topic a classifier, which
+//! looks at each message once, as it is published, and names the groups that
+//! should get it. Subscribers join a group with
+//! `Builder::add_group_subscriber()`. Subscribers outside any group are
+//! unaffected and keep receiving everything, which suits loggers and
+//! auditors.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::{Builder, Subscriber, SubscriptionOptions};
+
+/// Which groups a message goes to, as decided by a classifier given to
+/// `Builder::route_by()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RouteDecision {
+    /// The members of one group.
+    Group(String),
+    /// The members of each of these groups.
+    Groups(Vec<String>),
+    /// Every member of every group.
+    All,
+    /// No group at all. Subscribers outside groups still get it.
+    Nobody,
+}
+
+impl RouteDecision {
+    fn includes(&self, group: &str) -> bool {
+        match *self {
+            RouteDecision::Group(ref only) => only == group,
+            RouteDecision::Groups(ref groups) => groups.iter().any(|g| g == group),
+            RouteDecision::All => true,
+            RouteDecision::Nobody => false,
+        }
+    }
+}
+
+type Classifier<Content> = Box<dyn Fn(&Content) -> RouteDecision + Send + Sync>;
+
+/// Every topic's classifier, and which group each route belongs to.
+pub(crate) struct ContentRoutes<Topic, Content> {
+    classifiers: HashMap<Topic, Classifier<Content>>,
+    /// Groups, by route id.
+    members: HashMap<usize, String>,
+}
+
+impl<Topic: Hash + Eq, Content> ContentRoutes<Topic, Content> {
+    pub(crate) fn new() -> Self {
+        ContentRoutes { classifiers: HashMap::new(), members: HashMap::new() }
+    }
+
+    /// Asks the topic's classifier, if it has one, where `content` goes.
+    pub(crate) fn classify(&self, topic: &Topic, content: &Content) -> Option<RouteDecision> {
+        self.classifiers.get(topic).map(|classify| classify(content))
+    }
+
+    /// Whether the route with id `id` should get a message the classifier
+    /// made `decision` about.
+    pub(crate) fn admits(&self, decision: &RouteDecision, id: usize) -> bool {
+        self.members.get(&id).is_none_or(|group| decision.includes(group))
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Makes `classifier` decide which groups receive each message on
+    /// `topic`. It is called once per publish, on the publishing thread.
+    /// Setting another classifier for the topic replaces this one.
+    pub fn route_by<F>(&mut self, topic: Topic, classifier: F)
+        where F: Fn(&Content) -> RouteDecision + Send + Sync + 'static
+    {
+        self.bus.content_routes.classifiers.insert(topic, Box::new(classifier));
+    }
+
+    /// Adds a subscriber to `topics` as a member of `group`. On topics with
+    /// a classifier, it only receives messages sent to its group; on other
+    /// topics it receives everything, like any subscriber.
+    pub fn add_group_subscriber(&mut self, topics: &[Topic], group: &str) -> Subscriber<Topic, Content> {
+        let (subscriber, id) = self.bus.add_subscriber(topics, SubscriptionOptions::new());
+        self.bus.content_routes.members.insert(id, group.to_owned());
+        subscriber
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Publisher;
+
+    #[test]
+    fn classifiers_pick_groups_by_content() {
+        let mut builder = Publisher::new();
+        builder.route_by("alert", |&(region, severity): &(&str, u8)| match severity {
+            0 => RouteDecision::Nobody,
+            9 => RouteDecision::All,
+            _ => RouteDecision::Group(region.to_owned()),
+        });
+        let eu = builder.add_group_subscriber(&["alert"], "eu");
+        let us = builder.add_group_subscriber(&["alert", "status"], "us");
+        let auditor = builder.add_subscriber(&["alert"]);
+        let publisher = builder.build();
+
+        publisher.publish("alert", ("eu", 3));
+        publisher.publish("alert", ("us", 0));
+        publisher.publish("alert", ("us", 9));
+        publisher.publish("status", ("eu", 1));
+
+        assert_eq!(eu.fetch(), vec![("alert", ("eu", 3)), ("alert", ("us", 9))]);
+        assert_eq!(us.fetch(), vec![("alert", ("us", 9)), ("status", ("eu", 1))]);
+        assert_eq!(auditor.fetch().len(), 3);
+    }
+}
@@ -0,0 +1,100 @@
+//! Where the network gets the time from.
+//!
+//! Throttling, debouncing and traffic statistics all measure time. By default
+//! they read the system clock, but a network can be given any `Clock`
+//! instead, with `Builder::clock()`. A `ManualClock` only moves when told to,
+//! which makes time-dependent behaviour reproducible in tests, and lets a
+//! game world run the network on its own time scale. Scheduled messages,
+//! from `Publisher::publish_after()` and friends, follow the clock too.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {Publisher, SubscriptionOptions};
+
+    #[test]
+    fn throttling_follows_the_network_clock() {
+        let clock = ManualClock::new();
+        let mut builder = Publisher::new();
+        builder.clock(clock.clone());
+        let throttled = builder.add_subscriber_with(&["pos"],
+            SubscriptionOptions::new().throttle(Duration::from_secs(60)));
+        let debounced = builder.add_subscriber_with(&["pos"],
+            SubscriptionOptions::new().debounce(Duration::from_secs(1)));
+        let publisher = builder.build();
+
+        publisher.publish("pos", 1);
+        publisher.publish("pos", 2);
+        assert_eq!(throttled.fetch(), vec![("pos", 1)]);
+        assert_eq!(debounced.fetch(), vec![]);
+
+        clock.advance(Duration::from_secs(61));
+        publisher.publish("pos", 3);
+        assert_eq!(throttled.fetch(), vec![("pos", 3)]);
+        assert_eq!(debounced.fetch(), vec![]);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(debounced.fetch(), vec![("pos", 3)]);
+        assert_eq!(publisher.clock().now(), clock.now());
+    }
+}
+
+/// A source of the current time.
+pub trait Clock: Send + Sync {
+    /// Returns the current time. It must never go backwards.
+    fn now(&self) -> Instant;
+}
+
+/// The system's monotonic clock, which networks use unless told otherwise.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that stands still until it is advanced. Clones share the same
+/// time.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    start: Instant,
+    /// Nanoseconds advanced since `start`.
+    elapsed: Arc<AtomicU64>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at the current system time.
+    pub fn new() -> Self {
+        ManualClock { start: Instant::now(), elapsed: Arc::new(AtomicU64::new(0)) }
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        self.elapsed.fetch_add(by.as_nanos() as u64, Ordering::AcqRel);
+    }
+
+    /// Moves the clock forward to `at`. Does nothing if it is already past
+    /// it.
+    pub fn advance_to(&self, at: Instant) {
+        let elapsed = at.saturating_duration_since(self.start).as_nanos() as u64;
+        self.elapsed.fetch_max(elapsed, Ordering::AcqRel);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + Duration::from_nanos(self.elapsed.load(Ordering::Acquire))
+    }
+}
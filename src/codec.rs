@@ -0,0 +1,243 @@
+//! Converting messages to and from bytes.
+//!
+//! A `Codec` turns a topic and its content into a self-contained byte string
+//! and back again. Anything that carries messages outside the process, or
+//! stores them for later, does so through a codec, so each endpoint can pick
+//! the format its peers understand.
+//!
+//! Ready-made codecs for types implementing serde's traits are available
+//! behind cargo features:
+//!
+//!  - `json`: `Json`, using `serde_json`
+//!  - `bincode`: `Bincode`, using `bincode`
+//!  - `msgpack`: `MessagePack`, using `rmp-serde`
+//!
+//! The `rkyv` feature adds `Rkyv`, for types implementing rkyv's traits
+//! instead. Its encoding can be read in place with `Rkyv::view()`, without
+//! deserializing anything, which pays off for large messages that are only
+//! partly inspected.
+//!
+//! Any codec can be wrapped in a `Redacted`, which strips secrets from
+//! chosen topics before they are encoded.
+
+use std::error::Error;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use std::marker::PhantomData;
+#[cfg(feature = "serde")]
+use serde::{Serialize, de::DeserializeOwned};
+
+/// A way of encoding messages as bytes.
+pub trait Codec<Topic, Content>: Send + Sync {
+    /// Encodes one message.
+    fn encode(&self, topic: &Topic, content: &Content) -> Result<Vec<u8>, CodecError>;
+
+    /// Decodes one message previously produced by `encode()`.
+    fn decode(&self, bytes: &[u8]) -> Result<(Topic, Content), CodecError>;
+}
+
+/// A message could not be encoded or decoded.
+#[derive(Debug)]
+pub struct CodecError {
+    cause: Box<dyn Error + Send + Sync>,
+}
+
+impl CodecError {
+    /// Wraps the underlying error from a serialization library.
+    pub fn new<E>(cause: E) -> Self
+        where E: Into<Box<dyn Error + Send + Sync>>
+    {
+        CodecError { cause: cause.into() }
+    }
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "codec error: {}", self.cause)
+    }
+}
+
+impl Error for CodecError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.cause)
+    }
+}
+
+/// Declares a codec that encodes the `(topic, content)` pair with a serde
+/// format. The marker is `fn() -> T` so the codec is `Send + Sync` whatever
+/// the message types are.
+#[cfg(feature = "serde")]
+macro_rules! serde_codec {
+    ($(#[$attr:meta])* $name:ident, $encode:expr, $decode:expr) => {
+        $(#[$attr])*
+        pub struct $name<Topic, Content> {
+            marker: PhantomData<fn() -> (Topic, Content)>,
+        }
+
+        impl<Topic, Content> $name<Topic, Content> {
+            /// Creates the codec.
+            pub fn new() -> Self {
+                $name { marker: PhantomData }
+            }
+        }
+
+        impl<Topic, Content> Default for $name<Topic, Content> {
+            fn default() -> Self {
+                $name::new()
+            }
+        }
+
+        impl<Topic, Content> Codec<Topic, Content> for $name<Topic, Content>
+            where Topic: Serialize + DeserializeOwned,
+                  Content: Serialize + DeserializeOwned,
+        {
+            fn encode(&self, topic: &Topic, content: &Content) -> Result<Vec<u8>, CodecError> {
+                $encode(&(topic, content)).map_err(CodecError::new)
+            }
+
+            fn decode(&self, bytes: &[u8]) -> Result<(Topic, Content), CodecError> {
+                $decode(bytes).map_err(CodecError::new)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "json")]
+serde_codec! {
+    /// Encodes messages as JSON arrays of `[topic, content]`.
+    Json, ::serde_json::to_vec, ::serde_json::from_slice
+}
+
+#[cfg(feature = "bincode")]
+serde_codec! {
+    /// Encodes messages with bincode's compact binary format.
+    Bincode, ::bincode::serialize, ::bincode::deserialize
+}
+
+#[cfg(feature = "msgpack")]
+serde_codec! {
+    /// Encodes messages as MessagePack arrays of `[topic, content]`.
+    MessagePack, ::rmp_serde::to_vec, ::rmp_serde::from_slice
+}
+
+#[cfg(feature = "rkyv")]
+mod zero_copy {
+    use std::marker::PhantomData;
+
+    use rkyv::{Archive, Archived, Deserialize, Serialize};
+    use rkyv::api::high::{HighDeserializer, HighSerializer, HighValidator};
+    use rkyv::bytecheck::CheckBytes;
+    use rkyv::rancor::Error as RkyvError;
+    use rkyv::ser::allocator::ArenaHandle;
+    use rkyv::util::AlignedVec;
+
+    use super::{Codec, CodecError};
+
+    /// Encodes messages in rkyv's archive format, which can be read in place.
+    pub struct Rkyv<Topic, Content> {
+        marker: PhantomData<fn() -> (Topic, Content)>,
+    }
+
+    impl<Topic, Content> Rkyv<Topic, Content> {
+        /// Creates the codec.
+        pub fn new() -> Self {
+            Rkyv { marker: PhantomData }
+        }
+    }
+
+    impl<Topic, Content> Default for Rkyv<Topic, Content> {
+        fn default() -> Self {
+            Rkyv::new()
+        }
+    }
+
+    impl<Topic, Content> Rkyv<Topic, Content>
+        where (Topic, Content): Archive,
+              Archived<(Topic, Content)>: for<'a> CheckBytes<HighValidator<'a, RkyvError>>,
+    {
+        /// Validates an encoded message and returns a view of it that borrows
+        /// from `bytes`, with nothing deserialized. The bytes must be aligned
+        /// the way rkyv expects, as they are when read into an `AlignedVec`;
+        /// misaligned input is reported as an error rather than copied.
+        pub fn view(bytes: &[u8]) -> Result<&Archived<(Topic, Content)>, CodecError> {
+            rkyv::access::<Archived<(Topic, Content)>, RkyvError>(bytes)
+                .map_err(CodecError::new)
+        }
+    }
+
+    impl<Topic, Content> Codec<Topic, Content> for Rkyv<Topic, Content>
+        where Topic: Clone,
+              Content: Clone,
+              (Topic, Content): Archive
+                  + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, RkyvError>>,
+              Archived<(Topic, Content)>: for<'a> CheckBytes<HighValidator<'a, RkyvError>>
+                  + Deserialize<(Topic, Content), HighDeserializer<RkyvError>>,
+    {
+        /// Encoding needs the pair by value, so the message is cloned once.
+        fn encode(&self, topic: &Topic, content: &Content) -> Result<Vec<u8>, CodecError> {
+            let message = (topic.clone(), content.clone());
+            rkyv::to_bytes::<RkyvError>(&message)
+                .map(|bytes| bytes.to_vec())
+                .map_err(CodecError::new)
+        }
+
+        /// Copies `bytes` into aligned storage first, so any slice will do.
+        fn decode(&self, bytes: &[u8]) -> Result<(Topic, Content), CodecError> {
+            let mut aligned = AlignedVec::<16>::with_capacity(bytes.len());
+            aligned.extend_from_slice(bytes);
+            rkyv::from_bytes::<(Topic, Content), RkyvError>(&aligned)
+                .map_err(CodecError::new)
+        }
+    }
+}
+
+#[cfg(feature = "rkyv")]
+pub use self::zero_copy::Rkyv;
+
+#[cfg(all(test, any(feature = "serde", feature = "rkyv")))]
+mod test {
+    use super::*;
+
+    #[allow(dead_code)]
+    fn round_trip<C: Codec<String, Vec<u32>>>(codec: C) {
+        let topic = "telemetry".to_owned();
+        let content = vec![1, 2, 3];
+        let bytes = codec.encode(&topic, &content).unwrap();
+        assert_eq!(codec.decode(&bytes).unwrap(), (topic, content));
+        assert!(codec.decode(&[0xff, 0x00]).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip() {
+        round_trip(Json::new());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_round_trip() {
+        round_trip(Bincode::new());
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_round_trip() {
+        round_trip(MessagePack::new());
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn rkyv_round_trip_and_view() {
+        round_trip(Rkyv::new());
+
+        let codec = Rkyv::<String, Vec<u32>>::new();
+        let bytes = codec.encode(&"frames".to_owned(), &vec![7; 1024]).unwrap();
+        let mut aligned = rkyv::util::AlignedVec::<16>::new();
+        aligned.extend_from_slice(&bytes);
+
+        let view = Rkyv::<String, Vec<u32>>::view(&aligned).unwrap();
+        assert_eq!(view.0.as_str(), "frames");
+        assert_eq!(view.1.len(), 1024);
+    }
+}
@@ -0,0 +1,88 @@
+//! Following the latest value of several topics at once.
+//!
+//! Derived state often depends on more than one input: a dashboard tile
+//! showing speed against the limit needs the newest of each. `CombineLatest`
+//! keeps the most recent message on every topic it watches, and reports all
+//! of them together each time any one changes, once every topic has been
+//! heard from at least once.
+
+use std::hash::Hash;
+
+use super::{Builder, Subscriber};
+
+/// Combines the latest messages on several topics. Made by
+/// `Builder::combine_latest()`, or from any subscriber with `new()`.
+pub struct CombineLatest<Topic, Content> {
+    subscriber: Subscriber<Topic, Content>,
+    topics: Vec<Topic>,
+    latest: Vec<Option<Content>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Adds a subscriber to `topics` that combines their latest values.
+    pub fn combine_latest(&mut self, topics: &[Topic]) -> CombineLatest<Topic, Content> {
+        CombineLatest::new(self.add_subscriber(topics), topics)
+    }
+}
+
+impl<Topic: PartialEq, Content: Clone> CombineLatest<Topic, Content> {
+    /// Combines the latest values `subscriber` receives on `topics`, in that
+    /// order. Messages on other topics are ignored.
+    pub fn new(subscriber: Subscriber<Topic, Content>, topics: &[Topic]) -> Self
+        where Topic: Clone
+    {
+        CombineLatest { subscriber, topics: topics.to_vec(), latest: vec![None; topics.len()] }
+    }
+
+    /// Reads pending messages until one produces a combined update, and
+    /// returns the latest value of every topic, in the order they were given.
+    /// Returns `None` if no pending message did, either because none was
+    /// pending or because some topic has yet to be heard from.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Vec<Content>> {
+        while let Some((topic, content)) = self.subscriber.next() {
+            let slot = match self.topics.iter().position(|t| *t == topic) {
+                Some(slot) => slot,
+                None => continue,
+            };
+            self.latest[slot] = Some(content);
+            if let Some(latest) = self.latest() {
+                return Some(latest.into_iter().cloned().collect());
+            }
+        }
+        None
+    }
+
+    /// Consumes all pending messages, returning every combined update they
+    /// produced, oldest first.
+    pub fn fetch(&mut self) -> Vec<Vec<Content>> {
+        let mut updates = vec![];
+        while let Some(update) = self.next() { updates.push(update); }
+        updates
+    }
+
+    /// The latest value of every topic, if each has been heard from.
+    pub fn latest(&self) -> Option<Vec<&Content>> {
+        self.latest.iter().map(Option::as_ref).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use Publisher;
+
+    #[test]
+    fn updates_carry_every_latest_value() {
+        let mut builder = Publisher::new();
+        let mut combined = builder.combine_latest(&["speed", "limit"]);
+        let publisher = builder.build();
+
+        publisher.publish("speed", 40);
+        assert_eq!(combined.next(), None);
+        publisher.publish("limit", 50);
+        publisher.publish("speed", 55);
+        publisher.publish("gear", 3);
+        assert_eq!(combined.fetch(), vec![vec![40, 50], vec![55, 50]]);
+        assert_eq!(combined.latest(), Some(vec![&55, &50]));
+    }
+}
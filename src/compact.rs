@@ -0,0 +1,80 @@
+//! Merging runs of messages as they are fetched.
+//!
+//! Some streams only matter in sum: position deltas, damage ticks, counters.
+//! A subscriber that registers a merge function for such a topic gets each
+//! run of consecutive messages on it from `fetch()` as one message, so the
+//! consumer handles a frame's worth of deltas once instead of one by one.
+
+use super::Subscriber;
+
+/// Combines two consecutive messages on one topic.
+pub(crate) struct Merge<Topic, Content> {
+    applies: Box<dyn Fn(&Topic) -> bool + Send>,
+    merge: Box<dyn Fn(Content, Content) -> Content + Send>,
+}
+
+impl<Topic, Content> Subscriber<Topic, Content> {
+    /// Makes every fetch of pending messages, through `fetch()`,
+    /// `fetch_into()` or `fetch_extend()`, combine each run of consecutive
+    /// messages on `topic` into one, by calling `merge` with the run so far
+    /// and the next message. The merged message takes the place of the run.
+    /// Registering a topic again replaces its merge function.
+    pub fn compact<F>(&mut self, topic: Topic, merge: F)
+        where Topic: PartialEq + Send + 'static,
+              F: Fn(Content, Content) -> Content + Send + 'static,
+    {
+        self.merges.retain(|m| !(m.applies)(&topic));
+        self.merges.push(Merge {
+            applies: Box::new(move |t| *t == topic),
+            merge: Box::new(merge),
+        });
+    }
+}
+
+/// Merges runs in `messages` as `merges` say to.
+pub(crate) fn compacted<'a, Topic, Content, I>(messages: I, merges: &'a [Merge<Topic, Content>])
+    -> impl Iterator<Item = (Topic, Content)> + 'a
+    where I: Iterator<Item = (Topic, Content)> + 'a
+{
+    let mut messages = messages.fuse();
+    let mut held: Option<(Topic, Content)> = None;
+    ::std::iter::from_fn(move || {
+        let (topic, mut content) = held.take().or_else(|| messages.next())?;
+        let merge = match merges.iter().find(|m| (m.applies)(&topic)) {
+            Some(merge) => merge,
+            None => return Some((topic, content)),
+        };
+        for (next_topic, next) in messages.by_ref() {
+            if !(merge.applies)(&next_topic) {
+                held = Some((next_topic, next));
+                break;
+            }
+            content = (merge.merge)(content, next);
+        }
+        Some((topic, content))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use Publisher;
+
+    #[test]
+    fn consecutive_runs_are_merged() {
+        let mut builder = Publisher::new();
+        let mut subscriber = builder.add_subscriber(&["move", "hit", "chat"]);
+        let publisher = builder.build();
+        subscriber.compact("move", |a, b| a + b);
+        subscriber.compact("hit", |a: i32, b| a.max(b));
+
+        for &(topic, n) in &[("move", 1), ("move", 2), ("hit", 5), ("hit", 9), ("hit", 3),
+                             ("chat", 7), ("chat", 8), ("move", 4)] {
+            publisher.publish(topic, n);
+        }
+
+        assert_eq!(subscriber.fetch(), vec![
+            ("move", 3), ("hit", 9), ("chat", 7), ("chat", 8), ("move", 4),
+        ]);
+        assert_eq!(subscriber.fetch(), vec![]);
+    }
+}
@@ -0,0 +1,169 @@
+//! Channels carried by a network, for code written against `std::sync::mpsc`.
+//!
+//! A program built around raw channels can move onto a network one channel
+//! at a time. `channel_on_bus()` returns a `Sender` and `Receiver` that work
+//! like mpsc's, down to the error types, but whose messages are published
+//! on a topic, so they show up in the network's statistics, taps and drop
+//! reports like any other traffic. Other subscribers to the topic see them
+//! too, and the receiver also gets what anyone else publishes there.
+//!
+//! As with mpsc, the receiver is disconnected once every sender has been
+//! dropped, and sending fails once the receiver has been. Messages the
+//! network refuses, for instance because of a quota, are reported to
+//! `Builder::on_drop()` rather than to the sender.
+
+use std::hash::Hash;
+use std::iter;
+use std::slice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+use std::time::Duration;
+
+use super::{Publisher, Subscriber, SubscriptionGuard};
+use super::sync::Mutex;
+
+/// What both ends of a channel share.
+struct Link<Topic: Hash + Eq + Clone, Content: Clone> {
+    /// The receiver's subscription, until either end closes the channel.
+    guard: Mutex<Option<SubscriptionGuard<Topic, Content>>>,
+    senders: AtomicUsize,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Link<Topic, Content> {
+    fn is_closed(&self) -> bool {
+        self.guard.lock().unwrap_or_else(|e| e.into_inner()).is_none()
+    }
+
+    /// Removes the subscription, which disconnects the receiver once it has
+    /// read what had already arrived.
+    fn close(&self) {
+        let guard = self.guard.lock().unwrap_or_else(|e| e.into_inner()).take();
+        drop(guard);
+    }
+}
+
+/// The sending half of a channel made by `channel_on_bus()`. Clones send on
+/// the same channel.
+pub struct Sender<Topic: Hash + Eq + Clone, Content: Clone> {
+    publisher: Publisher<Topic, Content>,
+    topic: Topic,
+    link: Arc<Link<Topic, Content>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Sender<Topic, Content> {
+    /// Publishes `content` on the channel's topic. Fails, handing `content`
+    /// back, if the receiver has been dropped.
+    pub fn send(&self, content: Content) -> Result<(), SendError<Content>> {
+        if self.link.is_closed() { return Err(SendError(content)); }
+        self.publisher.publish(self.topic.clone(), content);
+        Ok(())
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Clone for Sender<Topic, Content> {
+    fn clone(&self) -> Self {
+        self.link.senders.fetch_add(1, Ordering::AcqRel);
+        Sender { publisher: self.publisher.clone(), topic: self.topic.clone(), link: self.link.clone() }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Drop for Sender<Topic, Content> {
+    fn drop(&mut self) {
+        if self.link.senders.fetch_sub(1, Ordering::AcqRel) == 1 { self.link.close(); }
+    }
+}
+
+/// The receiving half of a channel made by `channel_on_bus()`.
+pub struct Receiver<Topic: Hash + Eq + Clone, Content: Clone> {
+    subscriber: Subscriber<Topic, Content>,
+    link: Arc<Link<Topic, Content>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Receiver<Topic, Content> {
+    /// Returns the next message if one is waiting, like
+    /// `mpsc::Receiver::try_recv()`.
+    pub fn try_recv(&self) -> Result<Content, TryRecvError> {
+        let message = self.subscriber.inbox.try_recv()?;
+        Ok(self.read(message))
+    }
+
+    /// Waits for the next message, like `mpsc::Receiver::recv()`.
+    pub fn recv(&self) -> Result<Content, RecvError> {
+        let message = self.subscriber.inbox.recv()?;
+        Ok(self.read(message))
+    }
+
+    /// Waits up to `timeout` for the next message, like
+    /// `mpsc::Receiver::recv_timeout()`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Content, RecvTimeoutError> {
+        let message = self.subscriber.inbox.recv_timeout(timeout)?;
+        Ok(self.read(message))
+    }
+
+    /// Iterates over messages as they arrive, until the channel is
+    /// disconnected.
+    pub fn iter(&self) -> impl Iterator<Item = Content> + '_ {
+        iter::from_fn(move || self.recv().ok())
+    }
+
+    /// Iterates over the messages already waiting.
+    pub fn try_iter(&self) -> impl Iterator<Item = Content> + '_ {
+        iter::from_fn(move || self.try_recv().ok())
+    }
+
+    fn read(&self, message: (Topic, Content)) -> Content {
+        let (_, content) = self.subscriber.take(Some(message)).unwrap();
+        content
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Drop for Receiver<Topic, Content> {
+    fn drop(&mut self) {
+        self.link.close();
+    }
+}
+
+/// Makes a channel whose messages are published on `topic` through
+/// `publisher`. See the module documentation.
+pub fn channel_on_bus<Topic, Content>(publisher: &Publisher<Topic, Content>, topic: Topic)
+    -> (Sender<Topic, Content>, Receiver<Topic, Content>)
+    where Topic: Hash + Eq + Clone, Content: Clone
+{
+    let (subscriber, guard) = publisher.subscribe_scoped(slice::from_ref(&topic));
+    let link = Arc::new(Link { guard: Mutex::new(Some(guard)), senders: AtomicUsize::new(1) });
+    let sender = Sender { publisher: publisher.clone(), topic, link: link.clone() };
+    (sender, Receiver { subscriber, link })
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn channels_behave_like_mpsc() {
+        let mut builder = Publisher::new();
+        let observer = builder.add_subscriber(&["jobs"]);
+        let publisher = builder.build();
+
+        let (tx, rx) = channel_on_bus(&publisher, "jobs");
+        let workers: Vec<_> = (0 .. 3).map(|n| {
+            let tx = tx.clone();
+            thread::spawn(move || tx.send(n).unwrap())
+        }).collect();
+        drop(tx);
+        for worker in workers { worker.join().unwrap(); }
+
+        let mut received: Vec<u32> = rx.iter().collect();
+        received.sort();
+        assert_eq!(received, vec![0, 1, 2]);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(observer.fetch().len(), 3);
+
+        let (tx, rx) = channel_on_bus(&publisher, "jobs");
+        drop(rx);
+        assert_eq!(tx.send(4), Err(SendError(4)));
+    }
+}
@@ -0,0 +1,84 @@
+//! Several workers reading one subscription.
+//!
+//! Each subscriber gets its own copy of every message, so adding subscribers
+//! doesn't spread the work on a topic; it repeats it. A `SharedSubscriber`,
+//! made by `Subscriber::into_shared()`, can be cloned and handed to as many
+//! worker threads as needed, and each message it receives goes to whichever
+//! worker asks first. Only one worker waits on the inbox at a time, and the
+//! rest wait for it to take a message and step aside, much as workers share
+//! an `mpsc::Receiver` behind a mutex.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::Subscriber;
+use super::sync::Mutex;
+
+/// A subscriber that can be read from several threads at once, each message
+/// going to only one of them. Clones read the same queue.
+pub struct SharedSubscriber<Topic, Content> {
+    inner: Arc<Mutex<Subscriber<Topic, Content>>>,
+}
+
+impl<Topic, Content> Clone for SharedSubscriber<Topic, Content> {
+    fn clone(&self) -> Self {
+        SharedSubscriber { inner: self.inner.clone() }
+    }
+}
+
+impl<Topic, Content> SharedSubscriber<Topic, Content> {
+    /// Takes the next message, if one is waiting. If another worker is
+    /// waiting in `recv_timeout()`, waits for it to finish first.
+    pub fn try_recv(&self) -> Option<(Topic, Content)> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).next()
+    }
+
+    /// Waits up to `timeout` for a message, counting any time spent waiting
+    /// for another worker to take the one before.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).next_timeout(timeout)
+    }
+
+    /// How many messages are waiting for a worker.
+    pub fn pending(&self) -> usize {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).pending()
+    }
+}
+
+impl<Topic, Content> Subscriber<Topic, Content> {
+    /// Turns this subscriber into one that can be cloned and read by several
+    /// workers, each message going to only one of them.
+    pub fn into_shared(self) -> SharedSubscriber<Topic, Content> {
+        SharedSubscriber { inner: Arc::new(Mutex::new(self)) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+    use Publisher;
+
+    #[test]
+    fn each_message_goes_to_one_worker() {
+        let mut builder = Publisher::new();
+        let jobs = builder.add_subscriber(&["job"]).into_shared();
+        let publisher = builder.build();
+        for n in 0 .. 100 { publisher.publish("job", n); }
+
+        let workers: Vec<_> = (0 .. 4).map(|_| {
+            let jobs = jobs.clone();
+            thread::spawn(move || {
+                let mut done = vec![];
+                while let Some((_, n)) = jobs.recv_timeout(Duration::from_millis(50)) { done.push(n); }
+                done
+            })
+        }).collect();
+
+        let mut done: Vec<u32> = workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect();
+        done.sort();
+        assert_eq!(done, (0 .. 100).collect::<Vec<_>>());
+        assert_eq!(jobs.pending(), 0);
+    }
+}
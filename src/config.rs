@@ -0,0 +1,870 @@
+//! Building networks from a declarative description.
+//!
+//! A `BusConfig` lists a network's subscribers and routing rules as plain
+//! data, so they can live in a configuration file instead of the code. With
+//! the `serde` feature it can be deserialized from any format serde
+//! supports; the field names below are the ones to use in the file.
+//!
+//! `Builder::from_config()` sets a builder up from the description, creating
+//! each subscriber it names. The program then claims them by name with
+//! `Builder::configured_subscriber()`. Topics listed under `topics` are
+//! declared with the settings given there, as by `Builder::declare_topic()`.
+//! Bridges listed under `bridges` are connected when the network is built
+//! with `Builder::build_bridged()`.
+//!
+//! A running network can be brought in line with an edited description by
+//! `Publisher::apply_config()`. Only changes that don't disturb the
+//! subscribers the program already holds are made live: routing rules,
+//! priorities, which topics a subscriber receives, and its sampling and
+//! throttling, and declared topics' capacities and retention. Anything else,
+//! such as adding a subscriber or changing a topic's order, is reported as
+//! needing a rebuild and left alone. Each set of changes goes to
+//! `Builder::on_config_change()`, and can be published as a system event
+//! with `Builder::announce_config_changes()`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::slice;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serde")]
+use std::io;
+#[cfg(feature = "serde")]
+use std::net::TcpStream;
+
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "serde")]
+use super::bridge::{self, Framed, Link};
+#[cfg(feature = "serde")]
+use super::codec::{Codec, CodecError};
+use super::{Builder, CloneStrategy, Priority, Publisher, Route, Sampling, SubscriptionOptions, TopicOptions,
+            TopicOrder};
+use super::options::Admission;
+use super::sync::Mutex;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn subscribers_are_created_by_name() {
+        let mut config = BusConfig::default();
+        config.subscribers.insert("ui".to_owned(), SubscriberConfig {
+            topics: vec!["input", "state"],
+            ..SubscriberConfig::default()
+        });
+        config.subscribers.insert("logger".to_owned(), SubscriberConfig {
+            topics: vec!["state"],
+            sample_every: Some(2),
+            ..SubscriberConfig::default()
+        });
+        config.restricted.push(Restriction { topic: "state", publishers: vec!["core".to_owned()] });
+
+        let mut builder = Builder::from_config(&config);
+        let ui = builder.configured_subscriber("ui").unwrap();
+        let logger = builder.configured_subscriber("logger").unwrap();
+        assert!(builder.configured_subscriber("ui").is_none());
+        let publisher = builder.build();
+
+        publisher.publish("input", 0);
+        publisher.publish("state", 1);
+        for i in 2 .. 4 { publisher.named("core").publish("state", i); }
+
+        assert_eq!(ui.fetch(), vec![("input", 0), ("state", 2), ("state", 3)]);
+        assert_eq!(logger.fetch(), vec![("state", 2)]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn parsed_from_json() {
+        let config: BusConfig<String> = ::serde_json::from_str(r#"{
+            "subscribers": { "alarms": { "topics": ["alarm"], "throttle_ms": 500 } },
+            "priorities": [{ "topic": "alarm", "priority": "High" }],
+            "single_consumer": ["frames"],
+            "topics": [{ "topic": "position", "capacity": 8, "order": "Total" }],
+            "bridges": [{ "connect": "10.0.0.2:7000", "export": ["alarm"] }]
+        }"#).unwrap();
+
+        assert_eq!(config.subscribers["alarms"].throttle_ms, Some(500));
+        assert_eq!(config.priorities[0].priority, Priority::High);
+        assert_eq!(config.single_consumer, vec!["frames".to_owned()]);
+        assert!(config.audit_topic.is_none());
+        assert_eq!(config.topics[0].capacity, Some(8));
+        assert_eq!(config.topics[0].order, TopicOrder::Total);
+        assert_eq!(config.topics[0].retain_last, 0);
+        assert_eq!(config.bridges[0].export, vec!["alarm".to_owned()]);
+        assert_eq!((config.bridges[0].import, config.bridges[0].codec), (false, BridgeCodec::Json));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn bridges_connect_when_built() {
+        use std::net::TcpListener;
+        use codec::Json;
+        use Unmatched;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = BusConfig::default();
+        config.bridges.push(BridgeConfig {
+            connect: listener.local_addr().unwrap().to_string(),
+            export: vec!["out".to_owned()],
+            import: true,
+            codec: BridgeCodec::Json,
+        });
+        let mut builder = Builder::<String, u32>::from_config(&config);
+        let incoming = builder.add_subscriber(&["in".to_owned()]);
+        let (publisher, _links) = builder.build_bridged().unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+        let mut peer = Publisher::new();
+        let received = peer.add_subscriber(&["out".to_owned()]);
+        let outgoing = peer.add_subscriber(&["in".to_owned()]);
+        let peer = peer.build();
+        let _import = bridge::import(Framed::new(stream.try_clone().unwrap()), Json::new(), peer.clone());
+        let _export = bridge::export(outgoing, Json::new(), Framed::new(stream));
+
+        let any = |_: &String, _: &u32| true;
+        publisher.publish("out".to_owned(), 1);
+        assert_eq!(received.wait_for(any, Duration::from_secs(5), Unmatched::Keep), Some(("out".to_owned(), 1)));
+        peer.publish("in".to_owned(), 2);
+        assert_eq!(incoming.wait_for(any, Duration::from_secs(5), Unmatched::Keep), Some(("in".to_owned(), 2)));
+    }
+
+    #[test]
+    fn declared_topics_get_their_settings() {
+        let mut config = BusConfig::default();
+        config.topics.push(TopicConfig { retain_last: 1, ..TopicConfig::new("status") });
+        config.topics.push(TopicConfig { capacity: Some(1), ..TopicConfig::new("input") });
+        config.subscribers.insert("ui".to_owned(), SubscriberConfig {
+            topics: vec!["input"],
+            ..SubscriberConfig::default()
+        });
+
+        let mut builder = Builder::from_config(&config);
+        let ui = builder.configured_subscriber("ui").unwrap();
+        let mut events = Publisher::new();
+        let announced = events.add_subscriber(&["config"]);
+        builder.announce_config_changes(events.build(), "config");
+        let publisher = builder.build();
+        for i in 0 .. 2 { publisher.publish("input", i); }
+        publisher.publish("status", 7);
+        assert_eq!(ui.fetch(), vec![("input", 0)]);
+
+        let (late, _guard) = publisher.subscribe_scoped(&["status"]);
+        assert_eq!(late.fetch(), vec![("status", 7)]);
+
+        let mut edited = config.clone();
+        edited.topics[0].retain_last = 0;
+        edited.topics[1].capacity = Some(2);
+        edited.topics[1].order = TopicOrder::Total;
+        let changes = publisher.apply_config(&edited);
+        assert_eq!(changes, vec![
+            ConfigChange::Retention { topic: "status", retain_last: 0, retain_for_ms: None },
+            ConfigChange::Capacity { topic: "input", capacity: Some(2) },
+            ConfigChange::NeedsRebuild("a declared topic's order, clone strategy or description changed".to_owned()),
+        ]);
+        assert_eq!(announced.fetch(), vec![("config", changes)]);
+
+        for i in 0 .. 3 { publisher.publish("input", i); }
+        assert_eq!(ui.fetch(), vec![("input", 0), ("input", 1)]);
+        let (later, _guard) = publisher.subscribe_scoped(&["status"]);
+        assert!(later.fetch().is_empty());
+        assert_eq!(publisher.apply_config(&edited).len(), 1);
+    }
+
+    #[test]
+    fn changes_are_applied_live() {
+        let mut config = BusConfig::default();
+        config.subscribers.insert("ui".to_owned(), SubscriberConfig {
+            topics: vec!["input"],
+            ..SubscriberConfig::default()
+        });
+
+        let mut builder = Builder::from_config(&config);
+        let ui = builder.configured_subscriber("ui").unwrap();
+        let reported = Arc::new(Mutex::new(vec![]));
+        let log = reported.clone();
+        builder.on_config_change(move |changes: &[ConfigChange<&'static str>]| {
+            log.lock().unwrap().extend_from_slice(changes);
+        });
+        let publisher = builder.build();
+
+        let mut edited = config.clone();
+        {
+            let entry = edited.subscribers.get_mut("ui").unwrap();
+            entry.topics = vec!["state"];
+            entry.sample_every = Some(2);
+        }
+        edited.restricted.push(Restriction { topic: "state", publishers: vec!["core".to_owned()] });
+        edited.subscribers.insert("logger".to_owned(), SubscriberConfig::default());
+
+        let changes = publisher.apply_config(&edited);
+        assert_eq!(changes, vec![
+            ConfigChange::Restricted { topic: "state", publishers: vec!["core".to_owned()] },
+            ConfigChange::NeedsRebuild("subscriber logger was added".to_owned()),
+            ConfigChange::Subscribed { subscriber: "ui".to_owned(), topic: "state" },
+            ConfigChange::Unsubscribed { subscriber: "ui".to_owned(), topic: "input" },
+            ConfigChange::RateLimited { subscriber: "ui".to_owned() },
+        ]);
+        assert_eq!(*reported.lock().unwrap(), changes);
+
+        publisher.publish("input", 0);
+        for i in 1 .. 5 { publisher.named("core").publish("state", i); }
+        assert_eq!(ui.fetch(), vec![("state", 1), ("state", 3)]);
+
+        // Applying the same thing again changes nothing more, apart from the
+        // subscriber that still can't be added.
+        assert_eq!(publisher.apply_config(&edited).len(), 1);
+    }
+}
+
+/// A declarative description of a network.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct BusConfig<Topic> {
+    /// Subscribers to create, by name.
+    pub subscribers: BTreeMap<String, SubscriberConfig<Topic>>,
+    /// Topics only some publishers may use. See `Builder::restrict_topic()`.
+    pub restricted: Vec<Restriction<Topic>>,
+    /// Where refused messages go. See `Builder::audit_topic()`.
+    pub audit_topic: Option<Topic>,
+    /// Topic priorities for the memory budget. See
+    /// `Builder::topic_priority()`.
+    pub priorities: Vec<TopicPriority<Topic>>,
+    /// Topics with a single consumer. See `Builder::declare_single_consumer()`.
+    pub single_consumer: Vec<Topic>,
+    /// Topics to declare, with their settings. See `Builder::declare_topic()`.
+    pub topics: Vec<TopicConfig<Topic>>,
+    /// Bridges to other processes. See `Builder::build_bridged()`.
+    pub bridges: Vec<BridgeConfig<Topic>>,
+}
+
+impl<Topic> Default for BusConfig<Topic> {
+    fn default() -> Self {
+        BusConfig {
+            subscribers: BTreeMap::new(),
+            restricted: Vec::new(),
+            audit_topic: None,
+            priorities: Vec::new(),
+            single_consumer: Vec::new(),
+            topics: Vec::new(),
+            bridges: Vec::new(),
+        }
+    }
+}
+
+/// One subscriber in a `BusConfig`. The optional fields correspond to the
+/// methods of `SubscriptionOptions`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SubscriberConfig<Topic> {
+    /// The topics it receives.
+    pub topics: Vec<Topic>,
+    /// Receive only every `n`th message.
+    pub sample_every: Option<usize>,
+    /// Receive each message with this probability. Ignored if
+    /// `sample_every` is set.
+    pub sample_chance: Option<f64>,
+    /// Receive at most one message per this many milliseconds.
+    pub throttle_ms: Option<u64>,
+    /// Debounce messages over this many milliseconds.
+    pub debounce_ms: Option<u64>,
+    /// The subscriber's shutdown phase.
+    pub shutdown_phase: Option<u32>,
+    /// Whether the subscriber lives on the thread that builds the network.
+    pub same_thread: bool,
+}
+
+impl<Topic> Default for SubscriberConfig<Topic> {
+    fn default() -> Self {
+        SubscriberConfig {
+            topics: Vec::new(),
+            sample_every: None,
+            sample_chance: None,
+            throttle_ms: None,
+            debounce_ms: None,
+            shutdown_phase: None,
+            same_thread: false,
+        }
+    }
+}
+
+impl<Topic> SubscriberConfig<Topic> {
+    /// The subscription options this describes.
+    pub fn options(&self) -> SubscriptionOptions {
+        let mut options = SubscriptionOptions::new();
+        let sampling = self.sample_every.map(Sampling::Every)
+            .or(self.sample_chance.map(Sampling::Chance));
+        if let Some(sampling) = sampling { options = options.sample(sampling); }
+        if let Some(ms) = self.throttle_ms { options = options.throttle(Duration::from_millis(ms)); }
+        if let Some(ms) = self.debounce_ms { options = options.debounce(Duration::from_millis(ms)); }
+        if let Some(phase) = self.shutdown_phase { options = options.shutdown_phase(phase); }
+        if self.same_thread { options = options.same_thread(); }
+        options
+    }
+}
+
+/// A declared topic in a `BusConfig`. The fields other than `topic`
+/// correspond to the methods of `TopicOptions`, and may be left out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TopicConfig<Topic> {
+    /// The topic.
+    pub topic: Topic,
+    /// Keep this many recent messages for new subscribers.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub retain_last: usize,
+    /// Keep messages for new subscribers for this many milliseconds.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub retain_for_ms: Option<u64>,
+    /// Skip subscribers with this many messages already waiting.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub capacity: Option<usize>,
+    /// The order subscribers see messages in.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub order: TopicOrder,
+    /// How subscribers' copies are made.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub clone_strategy: CloneStrategy,
+    /// What the topic is for.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub description: Option<String>,
+}
+
+impl<Topic> TopicConfig<Topic> {
+    /// A declaration of `topic` with the default settings.
+    pub fn new(topic: Topic) -> Self {
+        TopicConfig {
+            topic,
+            retain_last: 0,
+            retain_for_ms: None,
+            capacity: None,
+            order: TopicOrder::default(),
+            clone_strategy: CloneStrategy::default(),
+            description: None,
+        }
+    }
+
+    /// The topic options this describes.
+    pub fn options(&self) -> TopicOptions {
+        let mut options = TopicOptions::new()
+            .retain_last(self.retain_last)
+            .order(self.order)
+            .clone_strategy(self.clone_strategy);
+        if let Some(ms) = self.retain_for_ms { options = options.retain_for(Duration::from_millis(ms)); }
+        if let Some(messages) = self.capacity { options = options.capacity(messages); }
+        if let Some(ref text) = self.description { options = options.description(text); }
+        options
+    }
+}
+
+/// A bridge in a `BusConfig`: a TCP connection to another process's network,
+/// carrying length-prefixed frames as `bridge::Framed` does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BridgeConfig<Topic> {
+    /// Where the peer listens, as `host:port`.
+    pub connect: String,
+    /// Topics whose messages are sent to the peer.
+    #[cfg_attr(feature = "serde", serde(default = "Vec::new"))]
+    pub export: Vec<Topic>,
+    /// Whether messages from the peer are published here.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub import: bool,
+    /// How messages are encoded.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub codec: BridgeCodec,
+}
+
+/// The codec a configured bridge uses. Each needs the cargo feature of the
+/// same name; see `codec`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BridgeCodec {
+    /// `codec::Json`, with the `json` feature.
+    #[default]
+    Json,
+    /// `codec::Bincode`, with the `bincode` feature.
+    Bincode,
+    /// `codec::MessagePack`, with the `msgpack` feature.
+    MessagePack,
+}
+
+#[cfg(feature = "serde")]
+impl BridgeCodec {
+    fn codec<Topic, Content>(self) -> io::Result<Chosen<Topic, Content>>
+        where Topic: Serialize + DeserializeOwned + 'static,
+              Content: Serialize + DeserializeOwned + 'static,
+    {
+        let codec: Box<dyn Codec<Topic, Content>> = match self {
+            #[cfg(feature = "json")]
+            BridgeCodec::Json => Box::new(super::codec::Json::new()),
+            #[cfg(feature = "bincode")]
+            BridgeCodec::Bincode => Box::new(super::codec::Bincode::new()),
+            #[cfg(feature = "msgpack")]
+            BridgeCodec::MessagePack => Box::new(super::codec::MessagePack::new()),
+            #[allow(unreachable_patterns)]
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported,
+                                           format!("the {:?} codec's feature isn't enabled", self))),
+        };
+        Ok(Chosen(codec))
+    }
+}
+
+/// Whichever codec a configured bridge named.
+#[cfg(feature = "serde")]
+struct Chosen<Topic, Content>(Box<dyn Codec<Topic, Content>>);
+
+#[cfg(feature = "serde")]
+impl<Topic, Content> Codec<Topic, Content> for Chosen<Topic, Content> {
+    fn encode(&self, topic: &Topic, content: &Content) -> Result<Vec<u8>, CodecError> {
+        self.0.encode(topic, content)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<(Topic, Content), CodecError> {
+        self.0.decode(bytes)
+    }
+}
+
+/// A restricted topic and the publishers allowed to use it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Restriction<Topic> {
+    /// The topic.
+    pub topic: Topic,
+    /// Names of the publishers allowed to use it.
+    pub publishers: Vec<String>,
+}
+
+/// A topic's priority for the memory budget.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TopicPriority<Topic> {
+    /// The topic.
+    pub topic: Topic,
+    /// Its priority.
+    pub priority: Priority,
+}
+
+/// A change made to a running network by `Publisher::apply_config()`, or one
+/// it had to leave for a rebuild.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigChange<Topic> {
+    /// A topic's allowed publishers were replaced by these.
+    Restricted {
+        /// The topic.
+        topic: Topic,
+        /// Names of the publishers now allowed to use it.
+        publishers: Vec<String>,
+    },
+    /// Anyone may publish a topic that used to be restricted.
+    Unrestricted {
+        /// The topic.
+        topic: Topic,
+    },
+    /// Refused messages now go to this topic, or nowhere.
+    AuditTopic(Option<Topic>),
+    /// A topic's priority changed. Topics left out of the new list go back to
+    /// the default.
+    Priority {
+        /// The topic.
+        topic: Topic,
+        /// Its new priority.
+        priority: Priority,
+    },
+    /// A subscriber started receiving a topic.
+    Subscribed {
+        /// The subscriber's name in the configuration.
+        subscriber: String,
+        /// The topic.
+        topic: Topic,
+    },
+    /// A subscriber stopped receiving a topic.
+    Unsubscribed {
+        /// The subscriber's name in the configuration.
+        subscriber: String,
+        /// The topic.
+        topic: Topic,
+    },
+    /// A declared topic's capacity changed.
+    Capacity {
+        /// The topic.
+        topic: Topic,
+        /// How many messages a subscriber may now have waiting before it is
+        /// skipped, if there is a limit.
+        capacity: Option<usize>,
+    },
+    /// A declared topic's retention changed. Messages it had kept beyond the
+    /// new limits are forgotten.
+    Retention {
+        /// The topic.
+        topic: Topic,
+        /// How many recent messages it now keeps.
+        retain_last: usize,
+        /// For how many milliseconds it now keeps them, if there is a limit.
+        retain_for_ms: Option<u64>,
+    },
+    /// A subscriber's sampling or throttling was replaced. Their counters and
+    /// timers start over.
+    RateLimited {
+        /// The subscriber's name in the configuration.
+        subscriber: String,
+    },
+    /// A difference that can't be applied while the network runs, described
+    /// in words. The network carries on as it was in this respect.
+    NeedsRebuild(String),
+}
+
+type Hook<Topic> = Box<dyn Fn(&[ConfigChange<Topic>]) + Send + Sync>;
+
+/// What a network was last configured with, for `apply_config()` to compare
+/// against, and who to tell when that changes.
+pub(crate) struct ConfigState<Topic, Content> {
+    applied: Mutex<Option<Applied<Topic, Content>>>,
+    hook: OnceLock<Hook<Topic>>,
+    announce: OnceLock<Hook<Topic>>,
+}
+
+struct Applied<Topic, Content> {
+    config: BusConfig<Topic>,
+    /// A route for each configured subscriber, from which more can be made.
+    routes: HashMap<String, Route<Topic, Content>>,
+}
+
+impl<Topic, Content> ConfigState<Topic, Content> {
+    pub(crate) fn new() -> Self {
+        ConfigState { applied: Mutex::new(None), hook: OnceLock::new(), announce: OnceLock::new() }
+    }
+
+    pub(crate) fn set_hook(&self, hook: Hook<Topic>) {
+        let _ = self.hook.set(hook);
+    }
+
+    /// The configured name of each subscriber, by route id.
+    pub(crate) fn names(&self) -> HashMap<usize, String> {
+        let applied = self.applied.lock().unwrap_or_else(|e| e.into_inner());
+        applied.iter()
+            .flat_map(|applied| applied.routes.iter())
+            .map(|(name, route)| (route.id, name.clone()))
+            .collect()
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Starts a network as described by `config`. Its subscribers are
+    /// created straight away, and held by the builder until claimed with
+    /// `configured_subscriber()`; more can be added as usual.
+    pub fn from_config(config: &BusConfig<Topic>) -> Self {
+        let mut builder = Publisher::new();
+
+        // Single-consumer topics have to be declared before anyone subscribes.
+        for topic in &config.single_consumer {
+            builder.declare_single_consumer(topic.clone());
+        }
+        for entry in &config.topics {
+            builder.declare_topic(entry.topic.clone(), entry.options());
+        }
+        for restriction in &config.restricted {
+            let publishers: Vec<&str> = restriction.publishers.iter().map(|p| &p[..]).collect();
+            builder.restrict_topic(restriction.topic.clone(), &publishers);
+        }
+        if let Some(ref audit) = config.audit_topic {
+            builder.audit_topic(audit.clone());
+        }
+        for entry in &config.priorities {
+            builder.topic_priority(entry.topic.clone(), entry.priority);
+        }
+
+        let mut routes = HashMap::new();
+        for (name, subscriber) in &config.subscribers {
+            let (created, route) = builder.bus
+                .add_subscriber_route(&subscriber.topics, subscriber.options(), None);
+            builder.configured.insert(name.clone(), created);
+            routes.insert(name.clone(), route);
+        }
+
+        for bridge in &config.bridges {
+            let source = Some(&bridge.export).filter(|topics| !topics.is_empty())
+                .map(|topics| builder.add_subscriber(topics));
+            builder.bridges.push((bridge.clone(), source));
+        }
+
+        let applied = Applied { config: config.clone(), routes };
+        *builder.bus.config.applied.lock().unwrap_or_else(|e| e.into_inner()) = Some(applied);
+        builder
+    }
+
+    /// Calls `hook` with the list of changes each time `apply_config()`
+    /// finds any, including those it couldn't make. It runs on the thread
+    /// that applied them. Only the first call has any effect.
+    pub fn on_config_change<F>(&mut self, hook: F)
+        where F: Fn(&[ConfigChange<Topic>]) + Send + Sync + 'static
+    {
+        self.bus.config.set_hook(Box::new(hook));
+    }
+
+    /// Publishes the list of changes on `topic` each time `apply_config()`
+    /// finds any, as a system event for whoever needs to know: an audit log,
+    /// or components that size themselves to the topics they use. It is
+    /// published on the thread that applied them. Only the first call has
+    /// any effect.
+    pub fn announce_config_changes<OutTopic>(&mut self, publisher: Publisher<OutTopic, Vec<ConfigChange<Topic>>>,
+                                             topic: OutTopic)
+        where Topic: Send + Sync + 'static,
+              OutTopic: Hash + Eq + Clone + Send + Sync + 'static,
+    {
+        let _ = self.bus.config.announce.set(Box::new(move |changes| {
+            publisher.publish(topic.clone(), changes.to_vec());
+        }));
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<Topic, Content> Builder<Topic, Content>
+    where Topic: Hash + Eq + Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+          Content: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Builds the network, then connects each bridge its configuration
+    /// lists, exporting the topics it names and importing whatever the peer
+    /// sends if it says to. A bridge's peer has to be listening already. The
+    /// links stay up until they are dropped or their connection fails; an
+    /// error connecting any of them drops the rest.
+    ///
+    /// Bridges are only made by this, so a network built with `build()`
+    /// leaves them out.
+    pub fn build_bridged(mut self) -> io::Result<(Publisher<Topic, Content>, Vec<Link>)> {
+        let bridges = ::std::mem::take(&mut self.bridges);
+        let publisher = self.build();
+        let mut links = vec![];
+        for (bridge, source) in bridges {
+            let stream = TcpStream::connect(&bridge.connect)?;
+            if bridge.import {
+                let incoming = stream.try_clone()?;
+                incoming.set_read_timeout(Some(bridge::POLL_INTERVAL))?;
+                links.push(bridge::import(Framed::new(incoming), bridge.codec.codec()?, publisher.clone()));
+            }
+            if let Some(source) = source {
+                links.push(bridge::export(source, bridge.codec.codec()?, Framed::new(stream)));
+            }
+        }
+        Ok((publisher, links))
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Brings the network in line with `config`, as far as can be done
+    /// without a rebuild, and returns what changed. See the module
+    /// documentation for what can change. The comparison is with the
+    /// configuration last applied, or the one given to
+    /// `Builder::from_config()`; a network built some other way is compared
+    /// with an empty one.
+    ///
+    /// Subscribers are matched up by name. A subscriber dropped by the
+    /// program keeps its name, and changes to it are harmless.
+    pub fn apply_config(&self, config: &BusConfig<Topic>) -> Vec<ConfigChange<Topic>> {
+        let bus = self.bus();
+        let mut applied = bus.config.applied.lock().unwrap_or_else(|e| e.into_inner());
+        let current = applied.get_or_insert_with(|| Applied {
+            config: BusConfig::default(),
+            routes: HashMap::new(),
+        });
+        let mut changes = vec![];
+
+        let old_acl = restrictions(&current.config);
+        let new_acl = restrictions(config);
+        {
+            let mut acl = bus.acl.write().unwrap_or_else(|e| e.into_inner());
+            for restriction in &config.restricted {
+                let topic = &restriction.topic;
+                if old_acl.get(topic) == new_acl.get(topic) || acl.get(topic) == new_acl.get(topic) {
+                    continue;
+                }
+                acl.insert(topic.clone(), new_acl[topic].clone());
+                let mut publishers: Vec<String> = new_acl[topic].iter().cloned().collect();
+                publishers.sort();
+                changes.push(ConfigChange::Restricted { topic: topic.clone(), publishers });
+            }
+            for restriction in &current.config.restricted {
+                let topic = &restriction.topic;
+                if new_acl.contains_key(topic) || acl.remove(topic).is_none() { continue; }
+                changes.push(ConfigChange::Unrestricted { topic: topic.clone() });
+            }
+        }
+
+        if config.audit_topic != current.config.audit_topic {
+            *bus.audit_topic.write().unwrap_or_else(|e| e.into_inner()) = config.audit_topic.clone();
+            changes.push(ConfigChange::AuditTopic(config.audit_topic.clone()));
+        }
+
+        {
+            let mut priorities = bus.priorities.write().unwrap_or_else(|e| e.into_inner());
+            for entry in &config.priorities {
+                if priorities.insert(entry.topic.clone(), entry.priority) == Some(entry.priority) {
+                    continue;
+                }
+                changes.push(ConfigChange::Priority {
+                    topic: entry.topic.clone(),
+                    priority: entry.priority,
+                });
+            }
+            for entry in &current.config.priorities {
+                if config.priorities.iter().any(|e| e.topic == entry.topic) { continue; }
+                if priorities.remove(&entry.topic).is_none() { continue; }
+                changes.push(ConfigChange::Priority {
+                    topic: entry.topic.clone(),
+                    priority: Priority::default(),
+                });
+            }
+        }
+
+        let old_single: HashSet<&Topic> = current.config.single_consumer.iter().collect();
+        let new_single: HashSet<&Topic> = config.single_consumer.iter().collect();
+        if old_single != new_single {
+            changes.push(ConfigChange::NeedsRebuild("single-consumer topics changed".to_owned()));
+        }
+        let mut declared = current.config.topics.clone();
+        for wanted in &config.topics {
+            let now = declared.iter_mut().find(|now| now.topic == wanted.topic);
+            let (Some(now), Some(settings)) = (now, bus.topic_settings.get(&wanted.topic)) else {
+                changes.push(ConfigChange::NeedsRebuild("a topic was declared".to_owned()));
+                continue;
+            };
+            if wanted.capacity != now.capacity {
+                settings.set_capacity(wanted.capacity);
+                now.capacity = wanted.capacity;
+                changes.push(ConfigChange::Capacity { topic: wanted.topic.clone(), capacity: wanted.capacity });
+            }
+            if (wanted.retain_last, wanted.retain_for_ms) != (now.retain_last, now.retain_for_ms) {
+                let retain_for = wanted.retain_for_ms.map(Duration::from_millis);
+                settings.set_retention(wanted.retain_last, retain_for, bus.clock.now());
+                now.retain_last = wanted.retain_last;
+                now.retain_for_ms = wanted.retain_for_ms;
+                changes.push(ConfigChange::Retention {
+                    topic: wanted.topic.clone(),
+                    retain_last: wanted.retain_last,
+                    retain_for_ms: wanted.retain_for_ms,
+                });
+            }
+            let fixed = (now.order, now.clone_strategy, &now.description);
+            if (wanted.order, wanted.clone_strategy, &wanted.description) != fixed {
+                changes.push(ConfigChange::NeedsRebuild(
+                    "a declared topic's order, clone strategy or description changed".to_owned()));
+            }
+        }
+        if declared.iter().any(|old| !config.topics.iter().any(|new| new.topic == old.topic)) {
+            changes.push(ConfigChange::NeedsRebuild("a declared topic was removed".to_owned()));
+        }
+        if config.bridges != current.config.bridges {
+            changes.push(ConfigChange::NeedsRebuild("bridges changed".to_owned()));
+        }
+
+        let mut subscribers = BTreeMap::new();
+        let mut readmitted = vec![];
+        for (name, wanted) in &config.subscribers {
+            let (old, route) = match (current.config.subscribers.get(name), current.routes.get(name)) {
+                (Some(old), Some(route)) => (old, route),
+                _ => {
+                    changes.push(ConfigChange::NeedsRebuild(format!("subscriber {} was added", name)));
+                    continue;
+                },
+            };
+            let mut now = old.clone();
+
+            for topic in &wanted.topics {
+                if now.topics.contains(topic) { continue; }
+                let taken = bus.single_consumer.get(topic).is_some_and(|slot| slot.is_taken());
+                if taken {
+                    changes.push(ConfigChange::NeedsRebuild(
+                        format!("subscriber {} can't join a single-consumer topic", name)));
+                    continue;
+                }
+                bus.add_routes(route.id, slice::from_ref(topic), route.outbox.clone(), route.admission.clone(), None);
+                now.topics.push(topic.clone());
+                changes.push(ConfigChange::Subscribed { subscriber: name.clone(), topic: topic.clone() });
+            }
+            for topic in &old.topics {
+                if wanted.topics.contains(topic) { continue; }
+                if bus.single_consumer.contains_key(topic) {
+                    changes.push(ConfigChange::NeedsRebuild(
+                        format!("subscriber {} can't leave a single-consumer topic", name)));
+                    continue;
+                }
+                bus.remove_route(route.id, topic);
+                now.topics.retain(|t| t != topic);
+                changes.push(ConfigChange::Unsubscribed { subscriber: name.clone(), topic: topic.clone() });
+            }
+
+            let rates = (wanted.sample_every, wanted.sample_chance, wanted.throttle_ms);
+            if rates != (old.sample_every, old.sample_chance, old.throttle_ms) {
+                // Debouncing shares state with the subscriber, and the
+                // single-consumer slot can't be changed once filled.
+                let fixed = old.debounce_ms.is_some()
+                    || old.topics.iter().any(|topic| bus.single_consumer.contains_key(topic));
+                if fixed {
+                    changes.push(ConfigChange::NeedsRebuild(
+                        format!("subscriber {} can't change its rate limits", name)));
+                } else {
+                    now.sample_every = wanted.sample_every;
+                    now.sample_chance = wanted.sample_chance;
+                    now.throttle_ms = wanted.throttle_ms;
+                    let admission = Admission::new(&now.options(), &bus.clock).map(Arc::new);
+                    bus.set_admission(route.id, admission.clone());
+                    readmitted.push((name.clone(), admission));
+                    changes.push(ConfigChange::RateLimited { subscriber: name.clone() });
+                }
+            }
+
+            let others = (wanted.debounce_ms, wanted.shutdown_phase, wanted.same_thread);
+            if others != (old.debounce_ms, old.shutdown_phase, old.same_thread) {
+                changes.push(ConfigChange::NeedsRebuild(
+                    format!("subscriber {} changed options that can't change live", name)));
+            }
+            subscribers.insert(name.clone(), now);
+        }
+        for (name, old) in &current.config.subscribers {
+            if config.subscribers.contains_key(name) { continue; }
+            changes.push(ConfigChange::NeedsRebuild(format!("subscriber {} was removed", name)));
+            subscribers.insert(name.clone(), old.clone());
+        }
+
+        // Remember what is actually in effect, so anything left undone is
+        // reported again next time.
+        for (name, admission) in readmitted {
+            if let Some(route) = current.routes.get_mut(&name) { route.admission = admission; }
+        }
+        current.config = BusConfig {
+            subscribers,
+            single_consumer: current.config.single_consumer.clone(),
+            topics: declared,
+            bridges: current.config.bridges.clone(),
+            ..config.clone()
+        };
+        drop(applied);
+
+        if !changes.is_empty() {
+            if let Some(hook) = bus.config.hook.get() { hook(&changes); }
+            if let Some(announce) = bus.config.announce.get() { announce(&changes); }
+        }
+        changes
+    }
+}
+
+fn restrictions<Topic: Hash + Eq + Clone>(config: &BusConfig<Topic>)
+    -> HashMap<Topic, HashSet<String>>
+{
+    let mut acl: HashMap<Topic, HashSet<String>> = HashMap::new();
+    for restriction in &config.restricted {
+        acl.entry(restriction.topic.clone()).or_default()
+            .extend(restriction.publishers.iter().cloned());
+    }
+    acl
+}
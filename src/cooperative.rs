@@ -0,0 +1,78 @@
+//! Reading a subscriber from inside a cooperative scheduler.
+//!
+//! A fiber or task that reads a busy subscriber has to give the scheduler
+//! its thread back now and then, or everything else on that thread starves.
+//! `Subscriber::recv_budgeted()` handles messages until it runs out of them
+//! or of budget, and says which, so the caller knows whether to reschedule
+//! itself straight away or wait for the next message.
+
+use std::time::{Duration, Instant};
+
+use super::Subscriber;
+
+/// Why `Subscriber::recv_budgeted()` returned, and how many messages it
+/// handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Budgeted {
+    /// Nothing more was waiting.
+    Drained(usize),
+    /// The budget ran out with messages still waiting; call again soon.
+    Yielded(usize),
+}
+
+impl Budgeted {
+    /// How many messages were handled.
+    pub fn handled(&self) -> usize {
+        match *self { Budgeted::Drained(n) | Budgeted::Yielded(n) => n }
+    }
+
+    /// Returns true if messages were left waiting.
+    pub fn is_yielded(&self) -> bool {
+        matches!(*self, Budgeted::Yielded(_))
+    }
+}
+
+impl<Topic, Content> Subscriber<Topic, Content> {
+    /// Passes pending messages to `handler` until none are left, or
+    /// `max_messages` have been handled, or `max_time` has passed. Never
+    /// blocks. The time is checked before each message, so a slow handler
+    /// can overrun it by at most one call.
+    pub fn recv_budgeted<F>(&self, max_messages: usize, max_time: Duration, mut handler: F) -> Budgeted
+        where F: FnMut(Topic, Content)
+    {
+        let started = Instant::now();
+        let mut handled = 0;
+        while handled < max_messages && started.elapsed() < max_time {
+            match self.next() {
+                Some((topic, content)) => handler(topic, content),
+                None => return Budgeted::Drained(handled),
+            }
+            handled += 1;
+        }
+        if self.pending() == 0 { Budgeted::Drained(handled) } else { Budgeted::Yielded(handled) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Publisher;
+
+    #[test]
+    fn budgets_yield_with_messages_left() {
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["job"]);
+        let publisher = builder.build();
+        for n in 0 .. 5 { publisher.publish("job", n); }
+
+        let mut seen = vec![];
+        let budget = Duration::from_secs(5);
+        assert_eq!(subscriber.recv_budgeted(3, budget, |_, n| seen.push(n)), Budgeted::Yielded(3));
+        assert_eq!(subscriber.recv_budgeted(3, budget, |_, n| seen.push(n)), Budgeted::Drained(2));
+        assert_eq!(seen, vec![0, 1, 2, 3, 4]);
+
+        publisher.publish("job", 5);
+        assert!(subscriber.recv_budgeted(10, Duration::ZERO, |_, _| ()).is_yielded());
+        assert_eq!(subscriber.recv_budgeted(1, budget, |_, _| ()).handled(), 1);
+    }
+}
@@ -0,0 +1,96 @@
+//! Writing down the state of a network when something goes wrong.
+//!
+//! `Publisher::write_crash_report()` saves what is most useful for working
+//! out afterwards what happened: the network's health, the messages kept by
+//! `Builder::remember_recent()`, and its routing, drawn as with
+//! `export_topology()`. `Publisher::crash_report_on_panic()` writes one
+//! whenever the program panics, along with the panic's details.
+
+use std::fmt::{Debug, Write as _};
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use super::Publisher;
+use super::panics::PanicReport;
+use super::topology::Format;
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + Debug,
+          Content: Clone + Debug,
+{
+    /// Writes a report on the network's state to `path`, replacing anything
+    /// there, as plain text.
+    pub fn write_crash_report<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.crash_report(None))
+    }
+
+    /// Writes a crash report to `path` whenever any thread panics, before the
+    /// panic hook that was there before runs. The hook keeps this network
+    /// alive for as long as it stays installed.
+    ///
+    /// A thread that panics while holding one of the network's locks would
+    /// deadlock writing the report, as with `panics::install()`.
+    pub fn crash_report_on_panic<P: Into<PathBuf>>(&self, path: P)
+        where Topic: Send + Sync + 'static,
+              Content: Send + Sync + 'static,
+    {
+        let (publisher, path) = (self.clone(), path.into());
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let panicked = PanicReport::new(info);
+            fs::write(&path, publisher.crash_report(Some(&panicked))).unwrap_or(());
+            previous(info);
+        }));
+    }
+
+    fn crash_report(&self, panicked: Option<&PanicReport>) -> String {
+        let mut out = String::from("alewife crash report\n");
+        if let Some(panicked) = panicked {
+            let unknown = || "unknown".to_owned();
+            let _ = writeln!(out, "\npanic in thread {}: {}", panicked.thread.clone().unwrap_or_else(unknown),
+                             panicked.message.clone().unwrap_or_else(unknown));
+            let _ = writeln!(out, "at {}", panicked.location.clone().unwrap_or_else(unknown));
+            if let Some(ref backtrace) = panicked.backtrace { let _ = writeln!(out, "{}", backtrace); }
+        }
+
+        let _ = writeln!(out, "\n{:#?}", self.health());
+
+        let recent = self.recent(usize::MAX);
+        let _ = writeln!(out, "\nrecent messages, oldest first: {}", recent.len());
+        for (topic, content) in recent {
+            let _ = writeln!(out, "    {:?}: {:?}", topic, content);
+        }
+
+        let _ = writeln!(out, "\ntopology:\n{}", self.export_topology(Format::Dot));
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn reports_cover_recent_messages_and_routing() {
+        let mut builder = Publisher::new();
+        builder.remember_recent(2);
+        let _subscriber = builder.add_subscriber(&["motor"]);
+        let publisher = builder.build();
+        for n in 0 .. 3 { publisher.publish("motor", n); }
+
+        let path = env::temp_dir().join(format!("alewife-crash-{}.txt", process::id()));
+        publisher.write_crash_report(&path).unwrap();
+        let report = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(report.contains("recent messages, oldest first: 2\n    \"motor\": 1\n    \"motor\": 2\n"));
+        assert!(report.contains("digraph alewife"));
+        assert!(report.contains("queued: 3"));
+    }
+}
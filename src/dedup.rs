@@ -0,0 +1,91 @@
+//! Dropping repeats of a message sent by several publishers.
+//!
+//! Redundant sources, such as a cluster of sensors watching the same thing,
+//! publish the same logical event more than once. Given a way to read a
+//! message's id, `Builder::deduplicate()` lets only the first message with
+//! each id through on a topic, and refuses any repeat arriving within a
+//! window after it, as measured by the network's clock.
+
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use super::Builder;
+use super::sync::Mutex;
+
+type IdOf<Content> = Box<dyn Fn(&Content) -> Option<u64> + Send + Sync>;
+
+/// The ids seen on one topic lately, oldest first.
+struct Seen {
+    order: VecDeque<(Instant, u64)>,
+    ids: HashSet<u64>,
+}
+
+/// One topic's duplicate filter.
+pub(crate) struct Dedup<Content> {
+    window: Duration,
+    id_of: IdOf<Content>,
+    seen: Mutex<Seen>,
+}
+
+impl<Content> Dedup<Content> {
+    /// Returns false if `content` repeats an id seen within the window. Content
+    /// without an id always passes.
+    pub(crate) fn admit(&self, content: &Content, now: Instant) -> bool {
+        let id = match (self.id_of)(content) {
+            Some(id) => id,
+            None => return true,
+        };
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        while let Some(&(at, old)) = seen.order.front() {
+            if now.duration_since(at) < self.window { break; }
+            seen.order.pop_front();
+            seen.ids.remove(&old);
+        }
+        if !seen.ids.insert(id) { return false; }
+        seen.order.push_back((now, id));
+        true
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Refuses messages on `topic` whose id, as read by `id_of`, was already
+    /// published on it in the last `window`. Repeats fail with
+    /// `PublishError::Duplicate`.
+    pub fn deduplicate<F>(&mut self, topic: Topic, window: Duration, id_of: F)
+        where F: Fn(&Content) -> Option<u64> + Send + Sync + 'static
+    {
+        let seen = Seen { order: VecDeque::new(), ids: HashSet::new() };
+        let dedup = Dedup { window, id_of: Box::new(id_of), seen: Mutex::new(seen) };
+        self.bus.dedup.insert(topic, dedup);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {ManualClock, PublishError, Publisher};
+    use envelope::Envelope;
+
+    #[test]
+    fn repeats_within_the_window_are_refused() {
+        let clock = ManualClock::new();
+        let mut builder = Publisher::new();
+        builder.clock(clock.clone());
+        builder.deduplicate("reading", Duration::from_secs(10), |e: &Envelope<&str, u32>| e.id);
+        let subscriber = builder.add_subscriber(&["reading", "other"]);
+        let publisher = builder.build();
+
+        let (left, right) = (publisher.named("left"), publisher.named("right"));
+        assert_eq!(left.try_publish("reading", Envelope::new(20).with_id(1)), Ok(()));
+        assert_eq!(right.try_publish("reading", Envelope::new(20).with_id(1)), Err(PublishError::Duplicate));
+        right.publish("reading", Envelope::new(21).with_id(2));
+        right.publish("reading", Envelope::new(0));
+        left.publish("other", Envelope::new(20).with_id(1));
+        clock.advance(Duration::from_secs(10));
+        left.publish("reading", Envelope::new(20).with_id(1));
+
+        let bodies: Vec<u32> = subscriber.fetch().into_iter().map(|(_, e)| e.body).collect();
+        assert_eq!(bodies, vec![20, 21, 0, 20, 20]);
+    }
+}
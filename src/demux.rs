@@ -0,0 +1,98 @@
+//! Handing each of a subscriber's topics to a different consumer.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::Arc;
+
+use super::Subscriber;
+use super::sync::Mutex;
+
+struct Shared<Topic, Content> {
+    subscriber: Subscriber<Topic, Content>,
+    /// Messages read from the subscriber but not yet by their receiver.
+    queues: HashMap<Topic, VecDeque<Content>>,
+}
+
+/// A subscriber split up by topic, made by `Subscriber::split_by_topic()`.
+/// Each receiver it hands out reads only one topic, and can be moved to a
+/// thread of its own. Messages on a topic nobody has a receiver for yet are
+/// kept until someone asks for one.
+pub struct Demux<Topic, Content> {
+    shared: Arc<Mutex<Shared<Topic, Content>>>,
+}
+
+/// One topic's share of a `Demux`.
+pub struct TopicReceiver<Topic, Content> {
+    shared: Arc<Mutex<Shared<Topic, Content>>>,
+    topic: Topic,
+}
+
+impl<Topic: Hash + Eq + Clone, Content> Subscriber<Topic, Content> {
+    /// Splits the subscriber's messages up by topic.
+    pub fn split_by_topic(self) -> Demux<Topic, Content> {
+        let shared = Shared { subscriber: self, queues: HashMap::new() };
+        Demux { shared: Arc::new(Mutex::new(shared)) }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content> Demux<Topic, Content> {
+    /// Returns a receiver for `topic`. Receivers for the same topic share
+    /// its messages, each message going to whichever reads first.
+    pub fn receiver(&self, topic: Topic) -> TopicReceiver<Topic, Content> {
+        TopicReceiver { shared: self.shared.clone(), topic }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content> TopicReceiver<Topic, Content> {
+    /// The topic this receiver reads.
+    pub fn topic(&self) -> &Topic {
+        &self.topic
+    }
+
+    /// Takes the next pending message on the topic, if any.
+    pub fn try_next(&self) -> Option<Content> {
+        let mut shared = self.sort();
+        shared.queues.get_mut(&self.topic).and_then(|queue| queue.pop_front())
+    }
+
+    /// Consumes all pending messages on the topic.
+    pub fn fetch(&self) -> Vec<Content> {
+        let mut shared = self.sort();
+        shared.queues.remove(&self.topic).map(Vec::from).unwrap_or_default()
+    }
+
+    /// Moves whatever the subscriber has received into the per-topic queues,
+    /// and returns them still locked.
+    fn sort(&self) -> impl ::std::ops::DerefMut<Target = Shared<Topic, Content>> + '_ {
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        let Shared { ref subscriber, ref mut queues } = *shared;
+        while let Some((topic, content)) = subscriber.next() {
+            queues.entry(topic).or_default().push_back(content);
+        }
+        shared
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use Publisher;
+
+    #[test]
+    fn each_topic_goes_its_own_way() {
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["audio", "video", "subtitles"]);
+        let publisher = builder.build();
+
+        let demux = subscriber.split_by_topic();
+        let audio = demux.receiver("audio");
+        let video = demux.receiver("video");
+        for (topic, frame) in [("audio", 1), ("video", 2), ("subtitles", 3), ("audio", 4)] {
+            publisher.publish(topic, frame);
+        }
+
+        assert_eq!(audio.fetch(), vec![1, 4]);
+        assert_eq!(video.try_next(), Some(2));
+        assert_eq!(video.try_next(), None);
+        assert_eq!(demux.receiver("subtitles").fetch(), vec![3]);
+    }
+}
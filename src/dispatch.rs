@@ -0,0 +1,866 @@
+//! Running handlers for subscribers on background threads.
+//!
+//! A `Dispatcher` owns a set of handlers, each with its own `Subscriber` and
+//! thread, and calls the handler for every message the subscriber receives. A
+//! handler that panics doesn't take its thread down with it: the panic is
+//! caught, reported as a `HandlerEvent` on a topic of your choosing, and the
+//! handler carries on with the next message.
+//!
+//! Handlers spawned with `Dispatcher::spawn_supervised()` may also fail by
+//! returning an error. After each failure a supervised handler is replaced
+//! with a fresh one, optionally after a pause that grows while the failures
+//! continue, and a handler that keeps failing can be removed altogether. The
+//! `Supervision` policy decides which; every step is reported as an event.
+//!
+//! Some handlers drive resources that are tied to one thread, like a GL
+//! context or an audio client. `Dispatcher::spawn_pinned()` runs such a
+//! handler on a named thread shared by every handler with the same affinity,
+//! and builds the handler there, so it needn't be `Send`. Handlers sharing a
+//! thread take turns, each handling at most `Dispatcher::messages_per_turn()`
+//! messages before the next gets its go, so a flooded subscriber can't starve
+//! the others.
+//!
+//! On a network of `Envelope`s, `Dispatcher::spawn_with_context()` hands
+//! each handler a `Ctx` with the message. Publishing through it traces the
+//! new message as caused by the one being handled, so handlers needn't
+//! capture a publisher of their own or pass trace ids along by hand.
+//!
+//! Stopping a dispatcher waits for each handler to finish the message it is
+//! on, which could take a while. Handlers spawned with
+//! `Dispatcher::spawn_cancellable()`, or given a `Ctx`, get a
+//! `CancellationToken` with each message, which is cancelled once the
+//! dispatcher is stopping or the message's topic is retired; a long-running
+//! handler should check it now and then and give up early.
+
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{Bus, Publish, PublishError, Publisher, Subscriber};
+use super::envelope::Envelope;
+
+/// How long a worker may block before checking whether it was stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long a pinned thread that has just run out of work sleeps before
+/// checking its handlers' subscribers again. The sleep doubles while there is
+/// still nothing to do, up to `POLL_INTERVAL`.
+const PINNED_IDLE: Duration = Duration::from_millis(1);
+
+/// Something that happened to a handler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HandlerEvent {
+    /// The handler panicked while handling a message.
+    Panicked(HandlerPanicked),
+    /// A supervised handler returned an error.
+    Failed(HandlerFailed),
+    /// A supervised handler was replaced after failing, and will resume
+    /// after `delay`.
+    Restarted {
+        /// The name the handler was spawned with.
+        handler: String,
+        /// How long the new handler waits before taking the next message.
+        delay: Duration,
+    },
+    /// A supervised handler failed too often and was stopped for good. Its
+    /// subscriber has been dropped.
+    Removed {
+        /// The name the handler was spawned with.
+        handler: String,
+    },
+    /// Making a pinned handler panicked, so it never ran. Its subscriber has
+    /// been dropped.
+    FailedToStart {
+        /// The name the handler was spawned with.
+        handler: String,
+        /// The panic's message, if it had one.
+        message: Option<String>,
+    },
+}
+
+/// Details of a panic in a handler. The message is described with its
+/// `Debug` formatting, since its type may not be known to whoever is
+/// listening.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HandlerPanicked {
+    /// The name the handler was spawned with.
+    pub handler: String,
+    /// The topic of the message being handled.
+    pub topic: String,
+    /// The content of the message being handled.
+    pub content: String,
+    /// The panic's message, if it had one.
+    pub message: Option<String>,
+}
+
+/// Details of an error returned by a supervised handler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HandlerFailed {
+    /// The name the handler was spawned with.
+    pub handler: String,
+    /// The topic of the message being handled.
+    pub topic: String,
+    /// The content of the message being handled.
+    pub content: String,
+    /// The error, as formatted by `Display`.
+    pub error: String,
+}
+
+/// How a supervised handler is treated when it fails.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Supervision {
+    backoff: Duration,
+    max_backoff: Duration,
+    give_up: Option<(usize, Duration)>,
+}
+
+impl Default for Supervision {
+    fn default() -> Self {
+        Supervision::new()
+    }
+}
+
+impl Supervision {
+    /// Restarts failed handlers straight away, however often they fail.
+    pub fn new() -> Self {
+        Supervision {
+            backoff: Duration::from_secs(0),
+            max_backoff: Duration::from_secs(0),
+            give_up: None,
+        }
+    }
+
+    /// Pauses a restarted handler for `initial` after its first failure,
+    /// doubling the pause for every further failure in a row, up to `max`.
+    /// Messages wait in the subscriber meanwhile. A message handled
+    /// successfully resets the pause.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Removes a handler that fails `failures` times within `within`.
+    pub fn give_up_after(mut self, failures: usize, within: Duration) -> Self {
+        self.give_up = Some((failures.max(1), within));
+        self
+    }
+
+    fn delay(&self, consecutive: u32) -> Duration {
+        let factor = 1u32.checked_shl(consecutive.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.backoff.checked_mul(factor).unwrap_or(self.max_backoff).min(self.max_backoff)
+    }
+}
+
+type Reporter = Arc<dyn Fn(HandlerEvent) + Send + Sync>;
+
+/// A pinned handler with its subscriber, handling one turn's worth of waiting
+/// messages per call.
+type Task = Box<dyn FnMut() -> Turn>;
+
+/// Sent to a pinned thread to build a task there. Returns `None` if building
+/// it panicked.
+type Job = Box<dyn FnOnce() -> Option<Task> + Send>;
+
+/// How a pinned handler's turn went.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Turn {
+    /// It handled at least one message.
+    Busy,
+    /// There was nothing waiting.
+    Idle,
+    /// Its subscriber will never receive another message, so the task can
+    /// go.
+    Done,
+}
+
+struct Worker {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// A set of handlers running on background threads. Dropping it stops them
+/// all and waits for their threads to finish.
+pub struct Dispatcher {
+    report: Option<Reporter>,
+    workers: Vec<Worker>,
+    /// Pinned threads, by affinity.
+    pinned: HashMap<String, Sender<Job>>,
+    handlers: usize,
+    per_turn: usize,
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Dispatcher::new()
+    }
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher with no handlers, which doesn't report events.
+    /// Pinned handlers take one message per turn.
+    pub fn new() -> Self {
+        Dispatcher {
+            report: None,
+            workers: vec![],
+            pinned: HashMap::new(),
+            handlers: 0,
+            per_turn: 1,
+        }
+    }
+
+    /// Lets each pinned handler spawned from now on handle up to `messages`
+    /// waiting messages per turn, rather than one. Larger turns switch
+    /// between handlers less often, at the cost of making the others wait
+    /// longer behind a busy one. Zero is taken as one.
+    pub fn messages_per_turn(mut self, messages: usize) -> Self {
+        self.per_turn = messages.max(1);
+        self
+    }
+
+    /// Publishes events about handlers spawned from now on to `publisher`
+    /// under `topic`.
+    pub fn report_to<Topic>(mut self, publisher: Publisher<Topic, HandlerEvent>, topic: Topic)
+        -> Self
+        where Topic: Hash + Eq + Clone + Send + Sync + 'static
+    {
+        self.report = Some(Arc::new(move |event| publisher.publish(topic.clone(), event)));
+        self
+    }
+
+    /// Calls `handler` with every message `source` receives, on a thread of
+    /// its own. `name` identifies the handler in events.
+    pub fn spawn<Topic, Content, F>(&mut self, name: &str, source: Subscriber<Topic, Content>,
+                                    mut handler: F)
+        where Topic: Debug + Send + 'static,
+              Content: Debug + Send + 'static,
+              F: FnMut(&Topic, &Content) + Send + 'static,
+    {
+        let handler = move |topic: &Topic, content: &Content| {
+            handler(topic, content);
+            Ok::<(), Infallible>(())
+        };
+        self.start(name, source, Supervision::new(), handler, None, Arc::new(AtomicBool::new(false)));
+    }
+
+    /// Like `spawn()`, but gives `handler` a `CancellationToken` with each
+    /// message, cancelled when the dispatcher stops or the message's topic is
+    /// retired on `publisher`'s network.
+    pub fn spawn_cancellable<Topic, Content, F>(&mut self, name: &str, source: Subscriber<Topic, Content>,
+                                                publisher: &Publisher<Topic, Content>, mut handler: F)
+        where Topic: Hash + Eq + Clone + Debug + Send + Sync + 'static,
+              Content: Clone + Debug + Send + Sync + 'static,
+              F: FnMut(&Topic, &Content, &CancellationToken) + Send + 'static,
+    {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = stopped.clone();
+        let bus = publisher.handle.bus.clone();
+        let handler = move |topic: &Topic, content: &Content| {
+            handler(topic, content, &CancellationToken::for_message(&flag, &bus, topic));
+            Ok::<(), Infallible>(())
+        };
+        self.start(name, source, Supervision::new(), handler, None, stopped);
+    }
+
+    /// Like `spawn()`, but calls `handler` with a `Ctx` for each envelope,
+    /// through which it can publish on `publisher`. The envelope is entered
+    /// while the handler runs, as with `Envelope::enter()`.
+    pub fn spawn_with_context<Topic, Body, F>(&mut self, name: &str,
+                                              source: Subscriber<Topic, Envelope<Topic, Body>>,
+                                              publisher: Publisher<Topic, Envelope<Topic, Body>>,
+                                              mut handler: F)
+        where Topic: Hash + Eq + Clone + Debug + Send + Sync + 'static,
+              Body: Clone + Debug + Send + Sync + 'static,
+              F: FnMut(&Ctx<Topic, Body>) + Send + 'static,
+    {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = stopped.clone();
+        let handler = move |topic: &Topic, envelope: &Envelope<Topic, Body>| {
+            let token = CancellationToken::for_message(&flag, &publisher.handle.bus, topic);
+            let ctx = Ctx { publisher: &publisher, topic, envelope, token };
+            envelope.enter(|| handler(&ctx));
+            Ok::<(), Infallible>(())
+        };
+        self.start(name, source, Supervision::new(), handler, None, stopped);
+    }
+
+    /// Calls a handler made by `make` with every message `source` receives,
+    /// on a thread of its own. When the handler panics or returns an error,
+    /// it is dropped and `make` is called for a replacement, as `policy`
+    /// allows.
+    pub fn spawn_supervised<Topic, Content, M, H, E>(&mut self, name: &str,
+                                                     source: Subscriber<Topic, Content>,
+                                                     policy: Supervision, mut make: M)
+        where Topic: Debug + Send + 'static,
+              Content: Debug + Send + 'static,
+              M: FnMut() -> H + Send + 'static,
+              H: FnMut(&Topic, &Content) -> Result<(), E> + Send + 'static,
+              E: Display,
+    {
+        let handler = make();
+        self.start(name, source, policy, handler, Some(Box::new(make)), Arc::new(AtomicBool::new(false)));
+    }
+
+    /// Calls a handler with every message `source` receives, on the thread
+    /// for `affinity`, which is started the first time it is named. Every
+    /// handler with the same affinity shares that thread, taking turns of up
+    /// to `messages_per_turn()` messages. The handler is made by calling
+    /// `make` on the thread, so it can own resources that must stay there.
+    ///
+    /// Panics are caught and reported as with `spawn()`.
+    pub fn spawn_pinned<Topic, Content, M, H>(&mut self, affinity: &str, name: &str,
+                                              source: Subscriber<Topic, Content>, make: M)
+        where Topic: Debug + Send + 'static,
+              Content: Debug + Send + 'static,
+              M: FnOnce() -> H + Send + 'static,
+              H: FnMut(&Topic, &Content) + 'static,
+    {
+        let report = self.report.clone();
+        let name = name.to_owned();
+        let per_turn = self.per_turn;
+        let job: Job = Box::new(move || {
+            let mut handler = match panic::catch_unwind(AssertUnwindSafe(make)) {
+                Ok(handler) => handler,
+                Err(payload) => {
+                    if let Some(report) = report.as_ref() {
+                        report(HandlerEvent::FailedToStart { handler: name, message: panic_message(&*payload) });
+                    }
+                    return None;
+                },
+            };
+            Some(Box::new(move || {
+                let mut handled = 0;
+                while handled < per_turn {
+                    let (topic, content) = match source.next() {
+                        Some(message) => message,
+                        None => break,
+                    };
+                    handled += 1;
+
+                    let outcome = panic::catch_unwind(AssertUnwindSafe(|| handler(&topic, &content)));
+                    if let (Err(payload), Some(report)) = (outcome, report.as_ref()) {
+                        report(panicked(&name, &topic, &content, &*payload));
+                    }
+                }
+                match handled {
+                    0 if source.is_finished() => Turn::Done,
+                    0 => Turn::Idle,
+                    _ => Turn::Busy,
+                }
+            }))
+        });
+
+        if !self.pinned.contains_key(affinity) {
+            let jobs = self.start_pinned(affinity);
+            self.pinned.insert(affinity.to_owned(), jobs);
+        }
+        self.pinned[affinity].send(job).unwrap_or(());
+        self.handlers += 1;
+    }
+
+    fn start_pinned(&mut self, affinity: &str) -> Sender<Job> {
+        let (tx, jobs) = mpsc::channel::<Job>();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = stopped.clone();
+
+        let thread = thread::Builder::new().name(affinity.to_owned()).spawn(move || {
+            let mut tasks: Vec<Task> = Vec::new();
+            let mut idle = PINNED_IDLE;
+            while !flag.load(Ordering::Acquire) {
+                tasks.extend(jobs.try_iter().filter_map(|job| job()));
+
+                let mut busy = false;
+                tasks.retain_mut(|task| match task() {
+                    Turn::Busy => { busy = true; true },
+                    Turn::Idle => true,
+                    Turn::Done => false,
+                });
+                if busy {
+                    idle = PINNED_IDLE;
+                    continue;
+                }
+
+                match jobs.recv_timeout(idle) {
+                    Ok(job) => tasks.extend(job()),
+                    Err(_) => idle = (idle * 2).min(POLL_INTERVAL),
+                }
+            }
+        }).expect("failed to start a pinned dispatcher thread");
+
+        self.workers.push(Worker { stopped, thread: Some(thread) });
+        tx
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn start<Topic, Content, H, E>(&mut self, name: &str, source: Subscriber<Topic, Content>,
+                                   policy: Supervision, mut handler: H,
+                                   mut restart: Option<Box<dyn FnMut() -> H + Send>>,
+                                   stopped: Arc<AtomicBool>)
+        where Topic: Debug + Send + 'static,
+              Content: Debug + Send + 'static,
+              H: FnMut(&Topic, &Content) -> Result<(), E> + Send + 'static,
+              E: Display,
+    {
+        let flag = stopped.clone();
+        let report: Reporter = self.report.clone().unwrap_or_else(|| Arc::new(|_| ()));
+        let name = name.to_owned();
+
+        let thread = thread::spawn(move || {
+            let mut failures = VecDeque::new();
+            let mut consecutive = 0;
+
+            while !flag.load(Ordering::Acquire) {
+                let (topic, content) = match source.next_timeout(POLL_INTERVAL) {
+                    Some(message) => message,
+                    None => continue,
+                };
+
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| handler(&topic, &content)));
+                report(match outcome {
+                    Ok(Ok(())) => {
+                        consecutive = 0;
+                        continue;
+                    },
+                    Ok(Err(error)) => HandlerEvent::Failed(HandlerFailed {
+                        handler: name.clone(),
+                        topic: format!("{:?}", topic),
+                        content: format!("{:?}", content),
+                        error: error.to_string(),
+                    }),
+                    Err(payload) => panicked(&name, &topic, &content, &*payload),
+                });
+
+                let restart = match restart.as_mut() {
+                    Some(restart) => restart,
+                    None => continue,
+                };
+
+                let now = Instant::now();
+                failures.push_back(now);
+                if let Some((limit, within)) = policy.give_up {
+                    while failures.front().is_some_and(|&t| now - t > within) {
+                        failures.pop_front();
+                    }
+                    if failures.len() >= limit {
+                        report(HandlerEvent::Removed { handler: name.clone() });
+                        return;
+                    }
+                }
+
+                consecutive += 1;
+                let delay = policy.delay(consecutive);
+                handler = restart();
+                report(HandlerEvent::Restarted { handler: name.clone(), delay });
+
+                let resume = Instant::now() + delay;
+                while !flag.load(Ordering::Acquire) && Instant::now() < resume {
+                    thread::sleep((resume - Instant::now()).min(POLL_INTERVAL));
+                }
+            }
+        });
+
+        self.workers.push(Worker { stopped, thread: Some(thread) });
+        self.handlers += 1;
+    }
+
+    /// Returns the number of handlers.
+    pub fn len(&self) -> usize {
+        self.handlers
+    }
+
+    /// Returns true if there are no handlers.
+    pub fn is_empty(&self) -> bool {
+        self.handlers == 0
+    }
+
+    /// Stops every handler and waits for their threads to finish. Messages
+    /// not yet handled are left with their subscribers.
+    pub fn stop(self) {}
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        for worker in &self.workers {
+            worker.stopped.store(true, Ordering::Release);
+        }
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                thread.join().unwrap_or(());
+            }
+        }
+    }
+}
+
+/// What a handler spawned with `Dispatcher::spawn_with_context()` is given
+/// along with each message.
+pub struct Ctx<'a, Topic: Hash + Eq + Clone + 'a, Body: Clone + 'a> {
+    publisher: &'a Publisher<Topic, Envelope<Topic, Body>>,
+    topic: &'a Topic,
+    envelope: &'a Envelope<Topic, Body>,
+    token: CancellationToken,
+}
+
+impl<'a, Topic: Hash + Eq + Clone, Body: Clone> Ctx<'a, Topic, Body> {
+    /// The topic the message arrived on.
+    pub fn topic(&self) -> &Topic {
+        self.topic
+    }
+
+    /// The message, with its metadata.
+    pub fn envelope(&self) -> &Envelope<Topic, Body> {
+        self.envelope
+    }
+
+    /// The message's body.
+    pub fn body(&self) -> &Body {
+        &self.envelope.body
+    }
+
+    /// Publishes `body` on `topic`, traced as caused by the message being
+    /// handled.
+    pub fn publish(&self, topic: Topic, body: Body) {
+        self.publisher.publish(topic, Envelope::caused_by(self.envelope, body));
+    }
+
+    /// Answers the message, as with `Envelope::reply()`. Returns false if
+    /// its sender didn't ask for a reply.
+    pub fn reply(&self, body: Body) -> bool {
+        self.envelope.reply(self.publisher, body)
+    }
+
+    /// Returns true once the dispatcher is stopping or the topic has been
+    /// retired. A handler with a lot of work to do for one message should
+    /// check now and then, and return early if so.
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// The message's cancellation token, which can be cloned and handed to
+    /// whatever does the work.
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// The publisher the handler was spawned with, for anything `publish()`
+    /// doesn't cover.
+    pub fn publisher(&self) -> &Publisher<Topic, Envelope<Topic, Body>> {
+        self.publisher
+    }
+}
+
+impl<'a, Topic: Hash + Eq + Clone, Body: Clone> Publish<Topic, Body> for Ctx<'a, Topic, Body> {
+    /// Publishes as with `Ctx::publish()`.
+    fn try_publish(&self, topic: Topic, body: Body) -> Result<(), PublishError> {
+        self.publisher.try_publish(topic, Envelope::caused_by(self.envelope, body))
+    }
+}
+
+type Check = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// Tells a handler that the work it is doing is no longer wanted. Clones
+/// share their state, so cancelling one cancels them all.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    /// Whether anything else has cancelled the work, such as the dispatcher
+    /// stopping.
+    check: Option<Check>,
+}
+
+impl CancellationToken {
+    /// A token that is only cancelled by `cancel()`.
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    fn for_message<Topic, Content>(stopped: &Arc<AtomicBool>, bus: &Arc<Bus<Topic, Content>>, topic: &Topic)
+        -> Self
+        where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+              Content: Clone + Send + Sync + 'static,
+    {
+        let (stopped, bus, topic) = (stopped.clone(), bus.clone(), topic.clone());
+        let check = move || stopped.load(Ordering::Acquire) || bus.topics.is_retired(&topic);
+        CancellationToken { cancelled: Arc::new(AtomicBool::new(false)), check: Some(Arc::new(check)) }
+    }
+
+    /// Cancels the work.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Returns true once the work has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        if self.cancelled.load(Ordering::Acquire) { return true; }
+        let cancelled = self.check.as_ref().is_some_and(|check| check());
+        if cancelled { self.cancel(); }
+        cancelled
+    }
+}
+
+fn panicked<Topic: Debug, Content: Debug>(handler: &str, topic: &Topic, content: &Content,
+                                          payload: &(dyn Any + Send)) -> HandlerEvent {
+    HandlerEvent::Panicked(HandlerPanicked {
+        handler: handler.to_owned(),
+        topic: format!("{:?}", topic),
+        content: format!("{:?}", content),
+        message: panic_message(payload),
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> Option<String> {
+    payload.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    #[test]
+    fn panics_are_reported_and_survived() {
+        let mut builder = Publisher::new();
+        let numbers = builder.add_subscriber(&["numbers"]);
+        let publisher = builder.build();
+
+        let mut builder = Publisher::new();
+        let events = builder.add_subscriber(&["handlers"]);
+        let reporter = builder.build();
+
+        let handled = Arc::new(Mutex::new(vec![]));
+        let log = handled.clone();
+        let mut dispatcher = Dispatcher::new().report_to(reporter, "handlers");
+        dispatcher.spawn("evens", numbers, move |_, &n: &u32| {
+            if n % 2 == 1 { panic!("odd number {}", n); }
+            log.lock().unwrap().push(n);
+        });
+
+        for n in 0 .. 4u32 { publisher.publish("numbers", n); }
+        let mut reported = vec![];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while reported.len() < 2 && Instant::now() < deadline {
+            reported.extend(events.fetch().into_iter().map(|(_, event)| event));
+            thread::sleep(Duration::from_millis(1));
+        }
+        dispatcher.stop();
+
+        assert_eq!(*handled.lock().unwrap(), vec![0, 2]);
+        assert_eq!(reported[0], HandlerEvent::Panicked(HandlerPanicked {
+            handler: "evens".to_owned(),
+            topic: "\"numbers\"".to_owned(),
+            content: "1".to_owned(),
+            message: Some("odd number 1".to_owned()),
+        }));
+        assert_eq!(reported.len(), 2);
+    }
+
+    #[test]
+    fn handlers_publish_through_their_context() {
+        let mut builder = Publisher::new();
+        let orders = builder.add_subscriber(&["orders"]);
+        let invoices = builder.add_subscriber(&["invoices"]);
+        let publisher = builder.build();
+
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.spawn_with_context("billing", orders, publisher.clone(), |ctx| {
+            assert!(!ctx.is_cancelled());
+            ctx.publish("invoices", ctx.body() * 100);
+        });
+
+        publisher.publish("orders", Envelope::traced(3u32));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while invoices.pending() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        dispatcher.stop();
+
+        let (_, invoice) = invoices.fetch().pop().unwrap();
+        assert_eq!(invoice.body, 300);
+        let trace = invoice.trace.unwrap();
+        assert!(trace.causation.is_some());
+        assert_eq!(trace.causation, Some(trace.correlation));
+    }
+
+    #[test]
+    fn long_handlers_are_cancelled_by_retirement_and_stopping() {
+        let mut builder = Publisher::new();
+        let jobs = builder.add_subscriber(&["render", "encode"]);
+        let publisher = builder.build();
+
+        let (log, events) = mpsc::channel();
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.spawn_cancellable("worker", jobs, &publisher, move |&topic: &&str, _: &u32, token| {
+            log.send(("started", topic)).unwrap();
+            while !token.is_cancelled() { thread::sleep(Duration::from_millis(1)); }
+            log.send(("cancelled", topic)).unwrap();
+        });
+
+        publisher.publish("render", 1);
+        publisher.publish("encode", 2);
+        let next = || events.recv_timeout(Duration::from_secs(5));
+        assert_eq!(next(), Ok(("started", "render")));
+        assert!(publisher.retire_topic(&"render"));
+        assert_eq!(next(), Ok(("cancelled", "render")));
+
+        assert_eq!(next(), Ok(("started", "encode")));
+        dispatcher.stop();
+        assert_eq!(next(), Ok(("cancelled", "encode")));
+    }
+
+    #[test]
+    fn pinned_handlers_share_their_thread() {
+        use std::rc::Rc;
+
+        let mut builder = Publisher::new();
+        let frames = builder.add_subscriber(&["frames"]);
+        let sounds = builder.add_subscriber(&["sounds"]);
+        let publisher = builder.build();
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let mut dispatcher = Dispatcher::new();
+        for (name, source) in [("frames", frames), ("sounds", sounds)] {
+            let log = seen.clone();
+            dispatcher.spawn_pinned("render", name, source, move || {
+                // Stands in for a resource that can't leave its thread.
+                let context = Rc::new(thread::current().name().map(str::to_owned));
+                move |&topic: &&str, _: &u32| {
+                    log.lock().unwrap().push((topic, (*context).clone()));
+                }
+            });
+        }
+        assert_eq!(dispatcher.len(), 2);
+
+        publisher.publish("frames", 0);
+        publisher.publish("sounds", 1);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while seen.lock().unwrap().len() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        dispatcher.stop();
+
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort();
+        let render = Some("render".to_owned());
+        assert_eq!(seen, vec![("frames", render.clone()), ("sounds", render)]);
+    }
+
+    #[test]
+    fn pinned_handlers_take_bounded_turns() {
+        let mut builder = Publisher::new();
+        let frames = builder.add_subscriber(&["frames"]);
+        let sounds = builder.add_subscriber(&["sounds"]);
+        let publisher = builder.build();
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let mut dispatcher = Dispatcher::new().messages_per_turn(4);
+        // The frames handler holds up the thread until both are spawned and
+        // everything is published, so both take their turns from the start.
+        let (go, wait) = mpsc::channel();
+        let log = seen.clone();
+        dispatcher.spawn_pinned("render", "frames", frames, move || {
+            wait.recv().unwrap_or(());
+            move |&topic: &&str, _: &u32| log.lock().unwrap().push(topic)
+        });
+        let log = seen.clone();
+        dispatcher.spawn_pinned("render", "sounds", sounds, move || {
+            move |&topic: &&str, _: &u32| log.lock().unwrap().push(topic)
+        });
+
+        for n in 0 .. 100 { publisher.publish("frames", n); }
+        publisher.publish("sounds", 0);
+        go.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while seen.lock().unwrap().len() < 101 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        dispatcher.stop();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 101);
+        assert_eq!(seen.iter().position(|&topic| topic == "sounds"), Some(4));
+    }
+
+    #[test]
+    fn pinned_handlers_that_fail_to_start_or_finish_are_let_go() {
+        let mut builder = Publisher::new();
+        let frames = builder.add_subscriber(&["frames"]);
+        let sounds = builder.add_subscriber(&["sounds"]);
+        let publisher = builder.build();
+
+        let mut builder = Publisher::new();
+        let events = builder.add_subscriber(&["handlers"]);
+        let reporter = builder.build();
+
+        let mut dispatcher = Dispatcher::new().report_to(reporter, "handlers");
+        dispatcher.spawn_pinned("render", "frames", frames, || -> fn(&&str, &u32) {
+            panic!("no display")
+        });
+        let (log, seen) = mpsc::channel();
+        dispatcher.spawn_pinned("render", "sounds", sounds, move || {
+            move |_: &&str, &n: &u32| log.send(n).unwrap()
+        });
+
+        publisher.publish("sounds", 1);
+        assert_eq!(seen.recv_timeout(Duration::from_secs(5)), Ok(1));
+        let (_, event) = events.fetch().pop().unwrap();
+        assert_eq!(event, HandlerEvent::FailedToStart {
+            handler: "frames".to_owned(),
+            message: Some("no display".to_owned()),
+        });
+
+        // With the network gone, the sounds handler is dropped, and its
+        // sender with it.
+        drop(publisher);
+        assert_eq!(seen.recv_timeout(Duration::from_secs(5)), Err(mpsc::RecvTimeoutError::Disconnected));
+        dispatcher.stop();
+    }
+
+    #[test]
+    fn failing_handlers_back_off_and_are_removed() {
+        let mut builder = Publisher::new();
+        let numbers = builder.add_subscriber(&["numbers"]);
+        let publisher = builder.build();
+
+        let mut builder = Publisher::new();
+        let events = builder.add_subscriber(&["handlers"]);
+        let reporter = builder.build();
+
+        let made = Arc::new(Mutex::new(0));
+        let counter = made.clone();
+        let policy = Supervision::new()
+            .backoff(Duration::from_millis(1), Duration::from_millis(10))
+            .give_up_after(3, Duration::from_secs(60));
+        let mut dispatcher = Dispatcher::new().report_to(reporter, "handlers");
+        dispatcher.spawn_supervised("flaky", numbers, policy, move || {
+            *counter.lock().unwrap() += 1;
+            |_: &&str, n: &u32| Err(format!("refused {}", n))
+        });
+
+        for n in 0 .. 5u32 { publisher.publish("numbers", n); }
+        let mut reported = vec![];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while reported.len() < 6 && Instant::now() < deadline {
+            reported.extend(events.fetch().into_iter().map(|(_, event)| event));
+            thread::sleep(Duration::from_millis(1));
+        }
+        dispatcher.stop();
+
+        let restarted: Vec<Duration> = reported.iter().filter_map(|event| match *event {
+            HandlerEvent::Restarted { delay, .. } => Some(delay),
+            _ => None,
+        }).collect();
+        assert_eq!(restarted, vec![Duration::from_millis(1), Duration::from_millis(2)]);
+        assert_eq!(reported.last(), Some(&HandlerEvent::Removed { handler: "flaky".to_owned() }));
+        assert_eq!(reported.len(), 6);
+        assert_eq!(*made.lock().unwrap(), 3);
+    }
+}
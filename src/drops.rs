@@ -0,0 +1,63 @@
+//! Notifying the application when messages are dropped.
+
+use std::sync::OnceLock;
+
+/// Why the network dropped a message, as reported to `Builder::on_drop()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// The publisher isn't allowed to publish on the topic.
+    Forbidden,
+    /// A `BusExtension` declined to deliver it.
+    Rejected,
+    /// The content was over the topic's size limit.
+    TooLarge,
+    /// The publisher had used up its quota.
+    OverQuota,
+    /// It repeated a message published on the topic recently.
+    Duplicate,
+    /// The network was frozen, and its `FreezePolicy` had no room for it.
+    Frozen,
+    /// A panic had left the network's routes inconsistent.
+    Poisoned,
+    /// The topic was undeclared in a sealed network, or retired.
+    UnknownTopic,
+    /// A subscriber made with `Publisher::subscribe_versions()` doesn't
+    /// accept the envelope's version.
+    UnsupportedVersion,
+    /// The subscriber already had as many messages waiting as the topic's
+    /// capacity allows.
+    QueueFull,
+    /// A sampling subscriber skipped it.
+    Sampled,
+    /// A throttled subscriber had already received a message recently.
+    Throttled,
+    /// A debouncing subscriber received a newer message before this one was
+    /// released.
+    Superseded,
+    /// Queuing it would have taken the topic over its share of the memory
+    /// budget.
+    OverBudget,
+}
+
+type Hook<Topic> = Box<dyn Fn(&Topic, DropReason) + Send + Sync>;
+
+/// The network's drop callback, shared by every subscriber so drops on the
+/// reading side can be reported too.
+pub(crate) struct DropHook<Topic> {
+    hook: OnceLock<Hook<Topic>>,
+}
+
+impl<Topic> DropHook<Topic> {
+    pub(crate) fn new() -> Self {
+        DropHook { hook: OnceLock::new() }
+    }
+
+    /// Sets the callback. Only the first call has any effect.
+    pub(crate) fn set(&self, hook: Hook<Topic>) {
+        let _ = self.hook.set(hook);
+    }
+
+    pub(crate) fn notify(&self, topic: &Topic, reason: DropReason) {
+        if let Some(hook) = self.hook.get() { hook(topic, reason); }
+    }
+}
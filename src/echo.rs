@@ -0,0 +1,72 @@
+//! Publishing without hearing yourself.
+//!
+//! A component that both publishes and subscribes to a topic, such as one
+//! side of a shared document or a node relaying state to its peers, normally
+//! receives its own messages back, and has to tag them and filter them out
+//! again to avoid acting on them twice or looping. `Publisher::without_echo()`
+//! makes a handle whose messages skip the component's own subscription
+//! instead, while everyone else on the topic receives them as usual.
+
+use std::hash::Hash;
+
+use super::{Publish, PublishError, Publisher, SubscriptionGuard};
+
+/// A publisher whose messages aren't delivered to one subscription. Made by
+/// `Publisher::without_echo()`.
+pub struct NoEcho<Topic: Hash + Eq + Clone, Content: Clone> {
+    publisher: Publisher<Topic, Content>,
+    route: usize,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Clone for NoEcho<Topic, Content> {
+    fn clone(&self) -> Self {
+        NoEcho { publisher: self.publisher.clone(), route: self.route }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> NoEcho<Topic, Content> {
+    /// Like `Publisher::try_publish()`, except that the subscription this
+    /// handle was made for doesn't receive the message.
+    pub fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        self.publisher.publish_skipping(&topic, content, Some(self.route))
+    }
+
+    /// Like `try_publish()`, ignoring refusals.
+    pub fn publish(&self, topic: Topic, content: Content) {
+        self.try_publish(topic, content).unwrap_or(());
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publish<Topic, Content> for NoEcho<Topic, Content> {
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        NoEcho::try_publish(self, topic, content)
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Returns a handle, under this one's name, that publishes to everyone
+    /// but the subscription `guard` keeps in place.
+    pub fn without_echo(&self, guard: &SubscriptionGuard<Topic, Content>) -> NoEcho<Topic, Content> {
+        NoEcho { publisher: self.clone(), route: guard.id }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn components_dont_hear_themselves() {
+        let mut builder = Publisher::new();
+        let observer = builder.add_subscriber(&["doc/edit"]);
+        let publisher = builder.build();
+
+        let (editor, guard) = publisher.subscribe_scoped(&["doc/edit"]);
+        let edits = publisher.without_echo(&guard);
+        edits.publish("doc/edit", 1);
+        publisher.publish("doc/edit", 2);
+
+        assert_eq!(editor.fetch(), vec![("doc/edit", 2)]);
+        assert_eq!(observer.fetch(), vec![("doc/edit", 1), ("doc/edit", 2)]);
+    }
+}
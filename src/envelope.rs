@@ -0,0 +1,547 @@
+//! Content wrapped with delivery metadata.
+//!
+//! A network whose content is an `Envelope` can carry, next to each message,
+//! the topic its sender wants replies published on. Subscribers that answer
+//! requests then all do it the same way, with `Envelope::reply()`, instead of
+//! every application encoding a return address in its own message types.
+//!
+//! Envelopes can also carry a `Trace`, which links a message to the one that
+//! caused it and to the first message of its chain. Start a chain with
+//! `Envelope::traced()`. Replies, and envelopes created inside
+//! `Envelope::enter()`, continue the chain of the message being handled, so
+//! the whole history of an event can be put back together from a log of the
+//! traces alone.
+//!
+//! A sender with a real-time budget can give an envelope a deadline. The
+//! receiver checks it as it dequeues the message, through `Lateness`, which
+//! flags envelopes that arrived too late and counts them, so scheduling
+//! problems show up as numbers rather than as stutters.
+//!
+//! While a message format is being migrated, old and new versions of it can
+//! share a topic. Senders mark each envelope with `Envelope::versioned()`,
+//! and a receiver subscribed with `Publisher::subscribe_versions()` only gets
+//! the versions it understands. Messages it can't read are reported to
+//! `Builder::on_drop()` as `DropReason::UnsupportedVersion`, so a reader that
+//! is missing traffic after a migration shows up as a drop count.
+//!
+//! An envelope's `id` names the event it carries, for deduplication,
+//! acknowledgement, and matching messages up with records elsewhere. Senders
+//! can pick one themselves, derive one from an external key such as a
+//! database row's, or have `Publisher::publish_with_id()` hand out a fresh
+//! one and return it.
+
+use std::cell::Cell;
+use std::hash::Hash;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use super::{DropReason, Outbox, PublishError, Publisher, Subscriber, SubscriptionGuard};
+use super::sync;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replies_go_to_the_requested_topic() {
+        let mut builder = Publisher::new();
+        let requests = builder.add_subscriber(&["ping"]);
+        let replies = builder.add_subscriber(&["ping/replies"]);
+        let publisher = builder.build();
+
+        publisher.publish("ping", Envelope::new(1u32).reply_to("ping/replies"));
+        publisher.publish("ping", Envelope::new(2u32));
+
+        let answered: Vec<bool> = requests.fetch().iter()
+            .map(|(_, request)| request.reply(&publisher, request.body + 10))
+            .collect();
+        assert_eq!(answered, vec![true, false]);
+
+        let replies = replies.fetch();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].1.body, 11);
+        assert_eq!(replies[0].1.reply_to, None);
+    }
+
+    #[test]
+    fn ids_are_generated_or_given() {
+        let mut builder = Publisher::new();
+        let orders = builder.add_subscriber(&["order"]);
+        let publisher = builder.build();
+
+        let first = publisher.publish_with_id("order", Envelope::new("apples")).unwrap();
+        let second = publisher.publish_with_id("order", Envelope::new("pears")).unwrap();
+        assert!(first != second);
+        assert_eq!(publisher.publish_with_id("order", Envelope::new("plums").with_id(7)), Ok(7));
+        let ids: Vec<Option<u64>> = orders.fetch().iter().map(|(_, e)| e.message_id()).collect();
+        assert_eq!(ids, vec![Some(first), Some(second), Some(7)]);
+
+        let row = Envelope::<(), _>::new("x").with_key("orders/1042");
+        assert_eq!(row.id, Envelope::<(), _>::new("y").with_key("orders/1042").id);
+        assert!(row.id != Envelope::<(), _>::new("x").with_key("orders/1043").id);
+        assert_eq!(Envelope::<(), _>::new(0).message_id(), None);
+        let traced = Envelope::<(), _>::traced(0);
+        assert_eq!(traced.message_id(), traced.trace.map(|trace| trace.id));
+    }
+
+    #[test]
+    fn traces_follow_the_chain_of_handlers() {
+        let mut builder = Publisher::new();
+        let orders = builder.add_subscriber(&["orders"]);
+        let invoices = builder.add_subscriber(&["invoices"]);
+        let receipts = builder.add_subscriber(&["receipts"]);
+        let publisher = builder.build();
+
+        publisher.publish("orders", Envelope::traced("order").reply_to("receipts"));
+        let (_, order) = orders.fetch().pop().unwrap();
+        order.enter(|| publisher.publish("invoices", Envelope::new("invoice")));
+        order.reply(&publisher, "receipt");
+
+        let (_, invoice) = invoices.fetch().pop().unwrap();
+        let (_, receipt) = receipts.fetch().pop().unwrap();
+        let root = order.trace.unwrap();
+        for trace in [invoice.trace.unwrap(), receipt.trace.unwrap()] {
+            assert_eq!(trace.correlation, root.id);
+            assert_eq!(trace.causation, Some(root.id));
+            assert!(trace.id != root.id);
+        }
+        assert_eq!(root.causation, None);
+        assert_eq!(Envelope::<&str, _>::new(()).trace, None);
+    }
+
+    #[test]
+    fn late_envelopes_are_flagged_and_reported() {
+        let mut builder = Publisher::new();
+        let frames = builder.add_subscriber(&["frame"]);
+        let publisher = builder.build();
+
+        let mut builder = Publisher::new();
+        let alerts = builder.add_subscriber(&["late"]);
+        let lateness = Lateness::new().report_to(builder.build(), "late");
+
+        let past = Instant::now() - Duration::from_millis(5);
+        publisher.publish("frame", Envelope::new(1u32).due_by(past));
+        publisher.publish("frame", Envelope::new(2u32).due_within(Duration::from_secs(60)));
+        publisher.publish("frame", Envelope::new(3u32));
+
+        let received = lateness.fetch(&frames);
+        let late: Vec<bool> = received.iter().map(|(_, frame)| frame.is_late()).collect();
+        assert_eq!(late, vec![true, false, false]);
+        assert!(received[0].1.late_by.unwrap() >= Duration::from_millis(5));
+
+        assert_eq!(lateness.late(), 1);
+        assert_eq!(lateness.worst(), received[0].1.late_by.unwrap());
+        match alerts.fetch()[..] {
+            [("late", LateMessage { topic: "frame", late_by })] => {
+                assert_eq!(Some(late_by), received[0].1.late_by);
+            },
+            ref other => panic!("unexpected reports {:?}", other),
+        }
+    }
+
+    #[test]
+    fn subscribers_only_get_versions_they_accept() {
+        let refused = Arc::new(AtomicU64::new(0));
+        let counter = refused.clone();
+        let mut builder = Publisher::new();
+        builder.on_drop(move |_: &&str, reason| {
+            assert_eq!(reason, DropReason::UnsupportedVersion);
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        let publisher = builder.build();
+
+        let (old, _old_guard) = publisher.subscribe_versions(&["save"], ..2);
+        let (new, _new_guard) = publisher.subscribe_versions(&["save"], 2..=3);
+        publisher.publish("save", Envelope::new("legacy"));
+        publisher.publish("save", Envelope::new("v1").versioned(1));
+        publisher.publish("save", Envelope::new("v2").versioned(2));
+
+        let old: Vec<&str> = old.fetch().into_iter().map(|(_, envelope)| envelope.body).collect();
+        let new: Vec<&str> = new.fetch().into_iter().map(|(_, envelope)| envelope.body).collect();
+        assert_eq!(old, vec!["legacy", "v1"]);
+        assert_eq!(new, vec!["v2"]);
+        assert_eq!(refused.load(Ordering::Relaxed), 3);
+    }
+}
+
+/// Where a message sits in a chain of messages that caused one another.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Trace {
+    /// Identifies this message. Unique within the process.
+    pub id: u64,
+    /// The `id` of the message that started the chain.
+    pub correlation: u64,
+    /// The `id` of the message being handled when this one was created, if
+    /// any.
+    pub causation: Option<u64>,
+}
+
+static NEXT_TRACE_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_MESSAGE_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static CONTEXT: Cell<Option<Trace>> = const { Cell::new(None) };
+}
+
+impl Trace {
+    /// Starts a new chain.
+    pub fn root() -> Self {
+        let id = NEXT_TRACE_ID.fetch_add(1, Ordering::Relaxed);
+        Trace { id, correlation: id, causation: None }
+    }
+
+    /// Traces a message caused by this one.
+    pub fn child(&self) -> Self {
+        let id = NEXT_TRACE_ID.fetch_add(1, Ordering::Relaxed);
+        Trace { id, correlation: self.correlation, causation: Some(self.id) }
+    }
+
+    /// Formats the trace as a W3C `traceparent` header, for handing to
+    /// tracing tools or sending to another process. The trace id is the
+    /// correlation id and the parent id is this message's id.
+    pub fn traceparent(&self) -> String {
+        format!("00-{:032x}-{:016x}-01", self.correlation, self.id)
+    }
+
+    /// Continues a chain from a `traceparent` header sent by another process:
+    /// the result is a new message whose cause is the one the header
+    /// describes. Only the low 64 bits of the trace id are kept. Returns `None`
+    /// if the header isn't valid.
+    pub fn continue_from(traceparent: &str) -> Option<Self> {
+        let fields: Vec<&str> = traceparent.trim().split('-').collect();
+        let (trace_id, parent_id) = match fields[..] {
+            [version, trace_id, parent_id, flags]
+                if version.len() == 2 && version != "ff" && flags.len() == 2
+                    && trace_id.len() == 32 && parent_id.len() == 16 => (trace_id, parent_id),
+            _ => return None,
+        };
+        let correlation = u128::from_str_radix(trace_id, 16).ok()? as u64;
+        let parent = u64::from_str_radix(parent_id, 16).ok()?;
+        if correlation == 0 || parent == 0 { return None; }
+
+        let id = NEXT_TRACE_ID.fetch_add(1, Ordering::Relaxed);
+        Some(Trace { id, correlation, causation: Some(parent) })
+    }
+
+    /// Returns the trace of the message being handled on this thread, as set
+    /// by `Envelope::enter()`.
+    pub fn current() -> Option<Self> {
+        CONTEXT.with(|context| context.get())
+    }
+}
+
+/// A message body with optional metadata about how to answer it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Envelope<Topic, Body> {
+    /// The message itself.
+    pub body: Body,
+    /// Where the sender wants replies published, if it wants any.
+    pub reply_to: Option<Topic>,
+    /// The message's place in a causal chain, if it is being traced.
+    pub trace: Option<Trace>,
+    /// When the sender needs the message handled by, if it has a deadline.
+    pub deadline: Option<Instant>,
+    /// How far past its deadline the message was when it was dequeued, as
+    /// recorded by `Envelope::check_deadline()`. `None` if it was on time or
+    /// hasn't been checked.
+    pub late_by: Option<Duration>,
+    /// The version of the body's format, if the sender marked one. See
+    /// `Envelope::versioned()`.
+    pub version: Option<u32>,
+    /// Identifies the logical event the message carries, if the sender gave
+    /// it an id. Redundant senders give copies of one event the same id.
+    pub id: Option<u64>,
+}
+
+impl<Topic, Body> Envelope<Topic, Body> {
+    /// Wraps `body`. Inside `Envelope::enter()` the new envelope continues
+    /// the entered chain; otherwise it is untraced.
+    pub fn new(body: Body) -> Self {
+        let trace = Trace::current().map(|parent| parent.child());
+        Envelope { body, reply_to: None, trace, deadline: None, late_by: None, version: None, id: None }
+    }
+
+    /// Wraps `body` as a message caused by the one in `parent`, continuing
+    /// the parent's chain if it has one.
+    pub fn caused_by<B>(parent: &Envelope<Topic, B>, body: Body) -> Self {
+        let trace = parent.trace.map(|parent| parent.child());
+        Envelope { body, reply_to: None, trace, deadline: None, late_by: None, version: None, id: None }
+    }
+
+    /// Wraps `body` as the first message of a new chain.
+    pub fn traced(body: Body) -> Self {
+        Envelope {
+            body,
+            reply_to: None,
+            trace: Some(Trace::root()),
+            deadline: None,
+            late_by: None,
+            version: None,
+            id: None,
+        }
+    }
+
+    /// Asks for replies to be published on `topic`.
+    pub fn reply_to(mut self, topic: Topic) -> Self {
+        self.reply_to = Some(topic);
+        self
+    }
+
+    /// Gives the message an id, as used by `Builder::deduplicate()` and
+    /// `Subscriber::effectively_once()`.
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Gives the message a fresh id, unique within the process, unless it
+    /// already has one.
+    pub fn with_new_id(mut self) -> Self {
+        if self.id.is_none() { self.id = Some(NEXT_MESSAGE_ID.fetch_add(1, Ordering::Relaxed)); }
+        self
+    }
+
+    /// Gives the message an id derived from `key`, an identifier from outside
+    /// the network such as a request id or a row's primary key. The same key
+    /// always gives the same id, in every process, so copies of an event sent
+    /// from different places are recognised as one.
+    pub fn with_key(self, key: &str) -> Self {
+        // 64-bit FNV-1a, which unlike the standard library's hasher is the
+        // same everywhere.
+        let id = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        self.with_id(id)
+    }
+
+    /// The id that best identifies this message: the one it was given, or
+    /// else its trace's, if it is traced.
+    pub fn message_id(&self) -> Option<u64> {
+        self.id.or_else(|| self.trace.map(|trace| trace.id))
+    }
+
+    /// Marks the body as being in version `version` of its format.
+    pub fn versioned(mut self, version: u32) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Asks for the message to be handled by `deadline`.
+    pub fn due_by(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Asks for the message to be handled within `budget` from now.
+    pub fn due_within(self, budget: Duration) -> Self {
+        self.due_by(Instant::now() + budget)
+    }
+
+    /// Records how late the message is, if its deadline has passed, and
+    /// returns that. Call it as the message is dequeued; `Lateness` does.
+    pub fn check_deadline(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        self.late_by = self.deadline
+            .filter(|&deadline| now > deadline)
+            .map(|deadline| now - deadline);
+        self.late_by
+    }
+
+    /// Whether the message missed its deadline when it was checked.
+    pub fn is_late(&self) -> bool {
+        self.late_by.is_some()
+    }
+
+    /// Unwraps the body, discarding the metadata.
+    pub fn into_body(self) -> Body {
+        self.body
+    }
+
+    /// Runs `handler` with this envelope as the current message on this
+    /// thread, so envelopes it creates with `Envelope::new()` are traced as
+    /// caused by this one. Contexts nest, and the previous one is restored
+    /// afterwards.
+    pub fn enter<F, R>(&self, handler: F) -> R
+        where F: FnOnce() -> R
+    {
+        struct Restore(Option<Trace>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CONTEXT.with(|context| context.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CONTEXT.with(|context| context.replace(self.trace)));
+        handler()
+    }
+}
+
+impl<Topic, Body> Envelope<Topic, Body>
+    where Topic: Hash + Eq + Clone,
+          Body: Clone,
+{
+    /// Publishes `body` on this envelope's reply topic, traced as caused by
+    /// this envelope. Returns false, having published nothing, if the sender
+    /// didn't ask for a reply.
+    pub fn reply(&self, publisher: &Publisher<Topic, Envelope<Topic, Body>>, body: Body)
+        -> bool
+    {
+        match self.reply_to {
+            Some(ref topic) => {
+                publisher.publish(topic.clone(), Envelope::caused_by(self, body));
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// What `Publisher::subscribe_versions()` returns.
+type Versioned<Topic, Body> =
+    (Subscriber<Topic, Envelope<Topic, Body>>, SubscriptionGuard<Topic, Envelope<Topic, Body>>);
+
+impl<Topic, Body> Publisher<Topic, Envelope<Topic, Body>>
+    where Topic: Hash + Eq + Clone + Send + 'static,
+          Body: Clone + Send + 'static,
+{
+    /// Publishes `envelope`, first giving it a fresh id if it has none, and
+    /// returns its id so the message can be found again later, in a log or
+    /// another system.
+    pub fn publish_with_id(&self, topic: Topic, envelope: Envelope<Topic, Body>)
+        -> Result<u64, PublishError>
+    {
+        let envelope = envelope.with_new_id();
+        let id = envelope.id.unwrap();
+        self.try_publish(topic, envelope).map(|()| id)
+    }
+
+    /// Subscribes to `topics` until the guard is dropped, receiving only
+    /// envelopes whose version is in `accepts`. Envelopes without a version
+    /// count as version 0. Others are converted with the functions given to
+    /// `Builder::upgrade_version()`, where they lead to an accepted version,
+    /// and the rest are reported as `DropReason::UnsupportedVersion`.
+    pub fn subscribe_versions<R>(&self, topics: &[Topic], accepts: R) -> Versioned<Topic, Body>
+        where R: RangeBounds<u32> + Send + Sync + 'static
+    {
+        let bus = &self.handle.bus;
+        let (tx, rx) = mpsc::channel();
+        let pending = Arc::new(sync::AtomicUsize::new(0));
+        let counted = pending.clone();
+        let drops = bus.drops.clone();
+        let upgrades = bus.upgrades.clone();
+
+        let outbox = Outbox::Forward(Arc::new(move |topic: Topic, mut envelope: Envelope<Topic, Body>| {
+            let version = envelope.version.unwrap_or(0);
+            if !accepts.contains(&version) {
+                match upgrades.path(version, &accepts) {
+                    Some(chain) => envelope = chain.iter().fold(envelope, |envelope, convert| convert(envelope)),
+                    None => {
+                        drops.notify(&topic, DropReason::UnsupportedVersion);
+                        return;
+                    },
+                }
+            }
+            counted.fetch_add(1, sync::Ordering::AcqRel);
+            if tx.send((topic, envelope)).is_err() {
+                counted.fetch_sub(1, sync::Ordering::AcqRel);
+            }
+        }));
+        let id = bus.subscribe(topics, outbox, None);
+
+        let subscriber = Subscriber::forwarded(rx, pending, bus.drops.clone());
+        (subscriber, SubscriptionGuard { bus: bus.clone(), id })
+    }
+}
+
+/// A message that was dequeued after its deadline, as published by
+/// `Lateness::report_to()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LateMessage<Topic> {
+    /// The topic the message was published on.
+    pub topic: Topic,
+    /// How far past its deadline it was.
+    pub late_by: Duration,
+}
+
+type Report<Topic> = Arc<dyn Fn(&Topic, Duration) + Send + Sync>;
+
+/// Checks the deadlines of envelopes as they are dequeued, and keeps count of
+/// the late ones. Clones share their counts, so one can be handed to each
+/// consumer thread.
+pub struct Lateness<Topic> {
+    late: Arc<AtomicU64>,
+    /// The worst lateness seen, in nanoseconds.
+    worst: Arc<AtomicU64>,
+    report: Option<Report<Topic>>,
+}
+
+impl<Topic> Clone for Lateness<Topic> {
+    fn clone(&self) -> Self {
+        Lateness { late: self.late.clone(), worst: self.worst.clone(), report: self.report.clone() }
+    }
+}
+
+impl<Topic> Default for Lateness<Topic> {
+    fn default() -> Self {
+        Lateness::new()
+    }
+}
+
+impl<Topic> Lateness<Topic> {
+    /// Creates a tracker that has seen nothing, and doesn't report.
+    pub fn new() -> Self {
+        Lateness { late: Arc::new(AtomicU64::new(0)), worst: Arc::new(AtomicU64::new(0)), report: None }
+    }
+
+    /// Publishes a `LateMessage` to `publisher` under `topic` for every late
+    /// envelope seen from now on.
+    pub fn report_to<OutTopic>(mut self, publisher: Publisher<OutTopic, LateMessage<Topic>>,
+                               topic: OutTopic) -> Self
+        where Topic: Clone + Send + Sync + 'static,
+              OutTopic: Hash + Eq + Clone + Send + Sync + 'static,
+    {
+        self.report = Some(Arc::new(move |late: &Topic, late_by| {
+            publisher.publish(topic.clone(), LateMessage { topic: late.clone(), late_by });
+        }));
+        self
+    }
+
+    /// Checks the deadline of `envelope`, received on `topic`, and records
+    /// it if it was missed. Returns whether it was.
+    pub fn check<Body>(&self, topic: &Topic, envelope: &mut Envelope<Topic, Body>) -> bool {
+        let late_by = match envelope.check_deadline() {
+            Some(late_by) => late_by,
+            None => return false,
+        };
+        self.late.fetch_add(1, Ordering::Relaxed);
+        self.worst.fetch_max(late_by.as_nanos() as u64, Ordering::Relaxed);
+        if let Some(ref report) = self.report { report(topic, late_by); }
+        true
+    }
+
+    /// Consumes all pending messages for `subscriber`, checking each one's
+    /// deadline.
+    pub fn fetch<Body>(&self, subscriber: &Subscriber<Topic, Envelope<Topic, Body>>)
+        -> Vec<(Topic, Envelope<Topic, Body>)>
+    {
+        let mut messages = subscriber.fetch();
+        for &mut (ref topic, ref mut envelope) in &mut messages {
+            self.check(topic, envelope);
+        }
+        messages
+    }
+
+    /// How many late envelopes have been seen.
+    pub fn late(&self) -> u64 {
+        self.late.load(Ordering::Relaxed)
+    }
+
+    /// The furthest past its deadline any envelope has been.
+    pub fn worst(&self) -> Duration {
+        Duration::from_nanos(self.worst.load(Ordering::Relaxed))
+    }
+}
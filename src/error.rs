@@ -0,0 +1,136 @@
+//! Error types.
+
+use std::error::Error;
+use std::fmt;
+
+/// Reasons a message can be refused by `Publisher::try_publish()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PublishError {
+    /// The topic is restricted and this publisher isn't on its list.
+    Forbidden,
+    /// A `BusExtension` declined to deliver the message.
+    Rejected,
+    /// The content is over the topic's size limit.
+    TooLarge,
+    /// The publisher has used up its quota, as set by
+    /// `Builder::limit_publish_rate()` or `Builder::limit_publish_bandwidth()`.
+    OverQuota,
+    /// The network's topics are sealed, and this one wasn't declared.
+    Undeclared,
+    /// The topic has been retired.
+    Retired,
+    /// The message repeats one published on the topic recently. See
+    /// `Builder::deduplicate()`.
+    Duplicate,
+    /// The network is frozen and isn't holding any more messages. See
+    /// `Publisher::freeze()`.
+    Frozen,
+    /// A thread panicked while changing the network's routes, so they can no
+    /// longer be trusted. See `Publisher::is_alive()`.
+    BusPoisoned,
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PublishError::Forbidden => {
+                write!(f, "publisher is not allowed to publish on this topic")
+            },
+            PublishError::Rejected => {
+                write!(f, "message was rejected by an extension")
+            },
+            PublishError::TooLarge => {
+                write!(f, "content exceeds the topic's size limit")
+            },
+            PublishError::OverQuota => {
+                write!(f, "publisher has exceeded its quota")
+            },
+            PublishError::Undeclared => {
+                write!(f, "topic was never declared")
+            },
+            PublishError::Retired => {
+                write!(f, "topic has been retired")
+            },
+            PublishError::Duplicate => {
+                write!(f, "message repeats one published recently")
+            },
+            PublishError::Frozen => {
+                write!(f, "network is frozen and holding no more messages")
+            },
+            PublishError::BusPoisoned => {
+                write!(f, "network's routes were left inconsistent by a panic")
+            },
+        }
+    }
+}
+
+impl Error for PublishError {}
+
+/// Returned by `Publisher::shutdown()` when a phase didn't finish in time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ShutdownTimedOut {
+    /// The phase that was still shutting down.
+    pub phase: u32,
+    /// How many of its subscribers hadn't acknowledged.
+    pub waiting: usize,
+}
+
+impl fmt::Display for ShutdownTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} subscribers in shutdown phase {} did not acknowledge",
+               self.waiting, self.phase)
+    }
+}
+
+impl Error for ShutdownTimedOut {}
+
+/// Returned by `Publisher::wait_ready()` when expected subscribers didn't
+/// arrive in time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotReady {
+    /// The names given to `Builder::expect_subscribers()` that never
+    /// subscribed.
+    pub missing: Vec<String>,
+}
+
+impl fmt::Display for NotReady {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "still waiting for subscribers: {}", self.missing.join(", "))
+    }
+}
+
+impl Error for NotReady {}
+
+/// Reasons a subscription can be refused by `Builder::try_add_subscriber()`,
+/// `Publisher::try_subscribe_scoped()` or `Publisher::tap()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SubscribeError {
+    /// No topics were given, which would make a subscriber that can never
+    /// receive anything. Use `Builder::add_broadcast_subscriber()` for one
+    /// that receives everything.
+    NoTopics,
+    /// One of the topics is single-consumer and already has its subscriber.
+    /// See `Builder::declare_single_consumer()`.
+    SingleConsumerTaken,
+    /// The topic is single-consumer, so its messages go to its subscriber
+    /// alone and can't be tapped.
+    SingleConsumer,
+}
+
+impl fmt::Display for SubscribeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SubscribeError::NoTopics => {
+                write!(f, "a subscriber needs at least one topic")
+            },
+            SubscribeError::SingleConsumerTaken => {
+                write!(f, "single-consumer topic already has a subscriber")
+            },
+            SubscribeError::SingleConsumer => {
+                write!(f, "single-consumer topics can't be tapped")
+            },
+        }
+    }
+}
+
+impl Error for SubscribeError {}
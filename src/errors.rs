@@ -0,0 +1,132 @@
+//! A convention for reporting errors on a network.
+//!
+//! Subsystems that hit an error they can't handle themselves publish an
+//! `ErrorReport` on the topic `TOPIC`, so one subscriber can log, count or
+//! alert on every failure in the program, whatever it came from. The
+//! `publish_error!` macro fills in where it was called from:
+//!
+//! ```ignore
+//! if let Err(e) = save(&order) {
+//!     publish_error!(publisher, "orders", e);
+//! }
+//! ```
+//!
+//! Anything that implements `Publish` can be reported to, as long as its
+//! topics can be made from `TOPIC` and its content from an `ErrorReport`.
+
+use std::error::Error;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::Publish;
+
+/// The topic error reports are published on.
+pub const TOPIC: &str = "error";
+
+/// One error, as published by `publish_error!`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ErrorReport {
+    /// Which subsystem it came from, as its reporter named it.
+    pub source: String,
+    /// The error's own description.
+    pub message: String,
+    /// The descriptions of the errors that caused it, innermost last.
+    pub causes: Vec<String>,
+    /// The module it was reported from.
+    pub module: String,
+    /// The source file it was reported from.
+    pub file: String,
+    /// The line it was reported from.
+    pub line: u32,
+}
+
+impl ErrorReport {
+    /// A report of `error` from `source`, made at the given place in the
+    /// code. `publish_error!` fills those in.
+    pub fn new(source: &str, error: &dyn Error, module: &str, file: &str, line: u32) -> Self {
+        let mut causes = vec![];
+        let mut cause = error.source();
+        while let Some(error) = cause {
+            causes.push(error.to_string());
+            cause = error.source();
+        }
+        ErrorReport {
+            source: source.to_owned(),
+            message: error.to_string(),
+            causes,
+            module: module.to_owned(),
+            file: file.to_owned(),
+            line,
+        }
+    }
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.source, self.message)?;
+        for cause in &self.causes { write!(f, ": {}", cause)?; }
+        write!(f, " ({}:{})", self.file, self.line)
+    }
+}
+
+/// Publishes `report` to `publisher` on `TOPIC`. Used by `publish_error!`.
+pub fn publish<P, Topic, Content>(publisher: &P, report: ErrorReport)
+    where P: Publish<Topic, Content> + ?Sized,
+          Topic: From<&'static str>,
+          Content: From<ErrorReport>,
+{
+    publisher.publish(Topic::from(TOPIC), Content::from(report));
+}
+
+/// Publishes an `ErrorReport` of `error`, from the subsystem `source`, on
+/// `errors::TOPIC`, noting the module, file and line it was called from.
+/// The error is borrowed, so it can still be returned afterwards.
+#[macro_export]
+macro_rules! publish_error {
+    ($publisher:expr, $source:expr, $error:expr) => {
+        $crate::errors::publish(&$publisher, $crate::errors::ErrorReport::new(
+            $source, &$error, module_path!(), file!(), line!()))
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use super::*;
+    use Publisher;
+
+    #[derive(Debug)]
+    struct SaveFailed(io::Error);
+
+    impl fmt::Display for SaveFailed {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("couldn't save the order")
+        }
+    }
+
+    impl Error for SaveFailed {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn reports_say_where_they_came_from() {
+        let mut builder = Publisher::new();
+        let alerts = builder.add_subscriber(&[TOPIC]);
+        let publisher = builder.build();
+
+        let error = SaveFailed(io::Error::other("disk full"));
+        publish_error!(publisher, "orders", error);
+
+        let (topic, report): (_, ErrorReport) = alerts.fetch().pop().unwrap();
+        assert_eq!(topic, "error");
+        assert_eq!(report.causes, vec!["disk full"]);
+        assert_eq!(report.module, "alewife::errors::test");
+        assert!(report.to_string().starts_with("orders: couldn't save the order: disk full (src/errors.rs:"));
+    }
+}
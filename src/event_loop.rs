@@ -0,0 +1,91 @@
+//! Waking external event loops.
+//!
+//! GUI toolkits and game engines usually own the main thread and sleep until
+//! an event arrives, so polling a `Subscriber` from there means either busy
+//! waiting or adding latency. `forward()` instead hands each message on the
+//! chosen topics straight to a callback, in the publishing thread, as soon as
+//! it is published. With winit, the callback would pass the message to
+//! `EventLoopProxy::send_event()`, which queues it for the main thread and
+//! wakes the loop:
+//!
+//! ```ignore
+//! let proxy = event_loop.create_proxy();
+//! let forwarder = alewife::event_loop::forward(&publisher, &["ui/redraw"], move |topic, content| {
+//!     proxy.send_event(UserEvent::Bus(topic, content)).is_ok()
+//! });
+//! ```
+
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use super::{Outbox, Publisher, SubscriptionGuard};
+use super::sync::{AtomicBool, Ordering};
+
+/// Keeps a `forward()` subscription in place. Dropping it unsubscribes.
+pub struct Forwarder<Topic: Hash + Eq + Clone, Content: Clone> {
+    closed: Arc<AtomicBool>,
+    _guard: SubscriptionGuard<Topic, Content>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Forwarder<Topic, Content> {
+    /// Returns true once the callback has reported that its event loop is
+    /// gone. No more messages are passed to it after that.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+}
+
+/// Calls `callback` with every message published on `topics`, in whichever
+/// thread published it, until the returned `Forwarder` is dropped. The
+/// callback should return quickly, since the publisher waits for it, and
+/// should return false once its event loop has shut down.
+///
+/// Calls are serialized, so the callback doesn't need to be `Sync`.
+pub fn forward<Topic, Content, F>(publisher: &Publisher<Topic, Content>, topics: &[Topic],
+                                  callback: F) -> Forwarder<Topic, Content>
+    where Topic: Hash + Eq + Clone + 'static,
+          Content: Clone + 'static,
+          F: FnMut(Topic, Content) -> bool + Send + 'static,
+{
+    let closed = Arc::new(AtomicBool::new(false));
+    let callback = Mutex::new(callback);
+
+    let flag = closed.clone();
+    let outbox = Outbox::Forward(Arc::new(move |topic, content| {
+        if flag.load(Ordering::Acquire) { return; }
+        let mut callback = callback.lock().unwrap_or_else(|e| e.into_inner());
+        if !(*callback)(topic, content) {
+            flag.store(true, Ordering::Release);
+        }
+    }));
+
+    let bus = publisher.handle.bus.clone();
+    let id = bus.subscribe(topics, outbox, None);
+    Forwarder {
+        closed,
+        _guard: SubscriptionGuard { bus, id },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn forwards_until_closed() {
+        let publisher: Publisher<&str, i32> = Publisher::new().build();
+
+        let received = Arc::new(Mutex::new(vec![]));
+        let log = received.clone();
+        let forwarder = forward(&publisher, &["ui"], move |_, content| {
+            let mut log = log.lock().unwrap();
+            log.push(content);
+            log.len() < 2
+        });
+
+        for i in 0 .. 3 { publisher.publish("ui", i); }
+        publisher.publish("other", 9);
+        assert!(forwarder.is_closed());
+        assert_eq!(*received.lock().unwrap(), vec![0, 1]);
+    }
+}
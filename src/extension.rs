@@ -0,0 +1,75 @@
+//! Hooks for extending a network with extra behavior.
+
+/// A pluggable component that observes, and optionally filters, a network's
+/// traffic. Register one with `Builder::register_extension()`.
+///
+/// Every method has a default that does nothing, so an extension only needs
+/// to implement the hooks it cares about. Hooks run on whichever thread
+/// triggered them, while routing is in progress, so they should be quick.
+pub trait BusExtension<Topic, Content>: Send + Sync {
+    /// Called once when `Builder::build()` finishes setting up the network.
+    fn on_build(&self) {}
+
+    /// Called for each subscriber added after this extension was registered.
+    fn on_subscribe(&self, _topics: &[Topic]) {}
+
+    /// Called before a message is routed, with the name of the publisher that
+    /// sent it, if any. Returning `false` drops the message; later extensions
+    /// are not consulted and no subscriber sees it.
+    fn on_publish(&self, _publisher: Option<&str>, _topic: &Topic, _content: &Content) -> bool {
+        true
+    }
+
+    /// Called once when the last publisher handle for the network is dropped.
+    fn on_shutdown(&self) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl BusExtension<&'static str, u32> for Recorder {
+        fn on_build(&self) {
+            self.log.lock().unwrap().push("build".to_owned());
+        }
+
+        fn on_subscribe(&self, topics: &[&'static str]) {
+            self.log.lock().unwrap().push(format!("subscribe {:?}", topics));
+        }
+
+        fn on_publish(&self, _: Option<&str>, topic: &&'static str, content: &u32) -> bool {
+            self.log.lock().unwrap().push(format!("publish {} {}", topic, content));
+            *content != 0
+        }
+
+        fn on_shutdown(&self) {
+            self.log.lock().unwrap().push("shutdown".to_owned());
+        }
+    }
+
+    #[test]
+    fn lifecycle_hooks() {
+        let recorder = Recorder::default();
+        let log = recorder.log.clone();
+
+        let mut builder = Publisher::new();
+        builder.register_extension(recorder);
+        let subscriber = builder.add_subscriber(&["a"]);
+        let publisher = builder.build();
+
+        assert_eq!(publisher.try_publish("a", 1), Ok(()));
+        assert_eq!(publisher.try_publish("a", 0), Err(PublishError::Rejected));
+        assert_eq!(subscriber.fetch(), vec![("a", 1)]);
+
+        drop(publisher);
+        assert_eq!(*log.lock().unwrap(), vec![
+            "subscribe [\"a\"]", "build", "publish a 1", "publish a 0", "shutdown",
+        ]);
+    }
+}
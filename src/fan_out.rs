@@ -0,0 +1,124 @@
+//! Sending one message to many subscribers from several threads.
+//!
+//! A publish normally sends each subscriber its copy in turn, on the
+//! publishing thread. For a topic with hundreds of subscribers, such as one
+//! queue per connection in a server, that loop is most of the cost of a
+//! publish. `Builder::parallel_fan_out()` splits it across a few scoped
+//! threads once a message has enough recipients, and the publish returns
+//! when they are all done, so messages still arrive in the order they were
+//! published. Same-thread subscribers are always sent to from the
+//! publishing thread.
+
+use std::hash::Hash;
+use std::panic;
+use std::thread;
+
+use super::{Builder, Bus, Outbox, Priority, Route};
+
+type SendAll<Topic, Content> = fn(&Bus<Topic, Content>, &[&Route<Topic, Content>], &Topic,
+                                  Option<(usize, Priority)>, &Content, usize) -> Sent;
+
+/// How many copies were made, and which routes are now spent.
+type Sent = (u64, Vec<usize>);
+
+pub(crate) struct FanOut<Topic: Hash + Eq + Clone, Content: Clone> {
+    threshold: usize,
+    threads: usize,
+    send_all: SendAll<Topic, Content>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> FanOut<Topic, Content> {
+    /// Offers `content` to every one of `routes` in parallel, or returns
+    /// `None` if there are too few of them to be worth it.
+    pub(crate) fn send(&self, bus: &Bus<Topic, Content>, routes: &[&Route<Topic, Content>], topic: &Topic,
+                       cost: Option<(usize, Priority)>, content: &Content) -> Option<Sent>
+    {
+        if routes.len() < self.threshold { return None; }
+        Some((self.send_all)(bus, routes, topic, cost, content, self.threads))
+    }
+}
+
+/// Only made where the network's types can cross threads, and stored as a
+/// plain function pointer, so the bus needn't know that they can.
+fn send_all<Topic, Content>(bus: &Bus<Topic, Content>, routes: &[&Route<Topic, Content>], topic: &Topic,
+                            cost: Option<(usize, Priority)>, content: &Content, threads: usize) -> Sent
+    where Topic: Hash + Eq + Clone + Send + Sync,
+          Content: Clone + Send + Sync,
+{
+    let (local, shared): (Vec<_>, Vec<_>) = routes.iter()
+        .partition(|route| matches!(route.outbox, Outbox::Local(..)));
+    let chunk = shared.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let mut chunks = shared.chunks(chunk);
+        let first = chunks.next().unwrap_or_default();
+        let workers: Vec<_> = chunks
+            .map(|chunk| scope.spawn(move || offer_all(bus, chunk, topic, cost, content)))
+            .collect();
+
+        let (mut copies, mut spent) = offer_all(bus, &local, topic, cost, content);
+        for (more, ids) in Some(offer_all(bus, first, topic, cost, content)).into_iter()
+            .chain(workers.into_iter().map(|worker| worker.join().unwrap_or_else(|e| panic::resume_unwind(e))))
+        {
+            copies += more;
+            spent.extend(ids);
+        }
+        (copies, spent)
+    })
+}
+
+fn offer_all<Topic, Content>(bus: &Bus<Topic, Content>, routes: &[&Route<Topic, Content>], topic: &Topic,
+                             cost: Option<(usize, Priority)>, content: &Content) -> Sent
+    where Topic: Hash + Eq + Clone, Content: Clone
+{
+    let mut copies = 0;
+    let mut spent = vec![];
+    for route in routes {
+        let copy = || {
+            copies += 1;
+            content.clone()
+        };
+        if bus.offer(route, topic, cost, copy) { spent.push(route.id); }
+    }
+    (copies, spent)
+}
+
+impl<Topic, Content> Builder<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + Sync,
+          Content: Clone + Send + Sync,
+{
+    /// Sends each message that has at least `threshold` recipients from up
+    /// to `threads` threads at once, the publishing thread included.
+    ///
+    /// Starting the threads costs more than sending to a plain subscriber,
+    /// so this only pays off for large topics, or where the sends themselves
+    /// are slow, as with forwarding closures or subscription filters.
+    pub fn parallel_fan_out(&mut self, threshold: usize, threads: usize) {
+        self.bus.fan_out = Some(FanOut {
+            threshold: threshold.max(2),
+            threads: threads.max(1),
+            send_all: send_all::<Topic, Content>,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use Publisher;
+
+    #[test]
+    fn every_subscriber_gets_each_message_in_order() {
+        let mut builder = Publisher::new();
+        builder.parallel_fan_out(16, 4);
+        let connections: Vec<_> = (0 .. 100).map(|_| builder.add_subscriber(&["tick"])).collect();
+        let lone = builder.add_subscriber(&["rare"]);
+        let publisher = builder.build();
+
+        for n in 0 .. 10 { publisher.publish("tick", n); }
+        publisher.publish("rare", 0);
+        for connection in &connections {
+            assert_eq!(connection.fetch(), (0 .. 10).map(|n| ("tick", n)).collect::<Vec<_>>());
+        }
+        assert_eq!(lone.fetch(), vec![("rare", 0)]);
+    }
+}
@@ -0,0 +1,147 @@
+//! Subscribing from code that isn't written in Rust.
+//!
+//! A `ByteSubscriber` wraps an ordinary `Subscriber` together with a `Codec`,
+//! and hands each message over as a byte string encoded by that codec. It has
+//! no type parameters, so a pointer to one can be passed across a C ABI to a
+//! plugin written in C, or to Lua or Python through their C interfaces.
+//!
+//! The host creates the subscriber, turns it into a raw pointer with
+//! `ByteSubscriber::into_raw()`, and gives the pointer to the plugin. The
+//! plugin calls `alewife_subscriber_poll()` whenever it is ready for
+//! messages, receiving each one through its callback on its own thread, and
+//! `alewife_subscriber_free()` when it is done.
+
+use std::os::raw::c_void;
+
+use super::Subscriber;
+use super::codec::Codec;
+
+/// Receives one encoded message. `bytes` points to `len` bytes that are only
+/// valid until the callback returns; `user_data` is whatever the caller of
+/// `alewife_subscriber_poll()` passed.
+pub type MessageCallback = extern "C" fn(user_data: *mut c_void, bytes: *const u8, len: usize);
+
+/// A subscriber that delivers messages as encoded bytes.
+pub struct ByteSubscriber {
+    next: Box<dyn FnMut() -> Option<Vec<u8>> + Send>,
+}
+
+impl ByteSubscriber {
+    /// Wraps `subscriber`, encoding its messages with `codec`. Messages that
+    /// fail to encode are skipped.
+    pub fn new<Topic, Content, K>(subscriber: Subscriber<Topic, Content>, codec: K) -> Self
+        where Topic: Send + 'static,
+              Content: Send + 'static,
+              K: Codec<Topic, Content> + 'static,
+    {
+        let next = move || {
+            while let Some((topic, content)) = subscriber.next() {
+                if let Ok(bytes) = codec.encode(&topic, &content) { return Some(bytes); }
+            }
+            None
+        };
+        ByteSubscriber { next: Box::new(next) }
+    }
+
+    /// Returns the next pending message, encoded, without waiting.
+    pub fn try_next(&mut self) -> Option<Vec<u8>> {
+        (self.next)()
+    }
+
+    /// Passes every pending message to `callback`, returning how many there
+    /// were.
+    pub fn deliver(&mut self, callback: MessageCallback, user_data: *mut c_void) -> usize {
+        let mut count = 0;
+        while let Some(bytes) = self.try_next() {
+            callback(user_data, bytes.as_ptr(), bytes.len());
+            count += 1;
+        }
+        count
+    }
+
+    /// Moves the subscriber to the heap and returns a pointer to it, for the
+    /// functions below. Unless it is passed to `alewife_subscriber_free()`,
+    /// it stays subscribed forever.
+    pub fn into_raw(self) -> *mut ByteSubscriber {
+        Box::into_raw(Box::new(self))
+    }
+}
+
+/// Passes every pending message for `subscriber` to `callback`, on the
+/// calling thread, and returns how many there were. Does nothing if either
+/// pointer is null.
+///
+/// # Safety
+///
+/// `subscriber` must have come from `ByteSubscriber::into_raw()`, must not
+/// have been freed, and must not be in use on another thread.
+#[no_mangle]
+pub unsafe extern "C" fn alewife_subscriber_poll(
+    subscriber: *mut ByteSubscriber,
+    callback: Option<MessageCallback>,
+    user_data: *mut c_void,
+) -> usize {
+    match (subscriber.as_mut(), callback) {
+        (Some(subscriber), Some(callback)) => subscriber.deliver(callback, user_data),
+        _ => 0,
+    }
+}
+
+/// Unsubscribes and frees `subscriber`. Does nothing if it is null.
+///
+/// # Safety
+///
+/// `subscriber` must have come from `ByteSubscriber::into_raw()`, and must
+/// not be used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn alewife_subscriber_free(subscriber: *mut ByteSubscriber) {
+    if !subscriber.is_null() { drop(Box::from_raw(subscriber)); }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ptr;
+    use std::slice;
+    use Publisher;
+    use codec::CodecError;
+
+    struct Decimal;
+
+    impl Codec<&'static str, u32> for Decimal {
+        fn encode(&self, topic: &&'static str, content: &u32) -> Result<Vec<u8>, CodecError> {
+            Ok(format!("{}={}", topic, content).into_bytes())
+        }
+
+        fn decode(&self, _: &[u8]) -> Result<(&'static str, u32), CodecError> {
+            Err(CodecError::new("not needed"))
+        }
+    }
+
+    extern "C" fn collect(user_data: *mut c_void, bytes: *const u8, len: usize) {
+        let received = unsafe { &mut *(user_data as *mut Vec<String>) };
+        let bytes = unsafe { slice::from_raw_parts(bytes, len) };
+        received.push(String::from_utf8(bytes.to_vec()).unwrap());
+    }
+
+    #[test]
+    fn messages_cross_the_c_abi() {
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["score"]);
+        let publisher = builder.build();
+        let raw = ByteSubscriber::new(subscriber, Decimal).into_raw();
+
+        publisher.publish("score", 10);
+        publisher.publish("score", 20);
+
+        let mut received: Vec<String> = vec![];
+        let user_data = &mut received as *mut Vec<String> as *mut c_void;
+        unsafe {
+            assert_eq!(alewife_subscriber_poll(raw, Some(collect), user_data), 2);
+            assert_eq!(alewife_subscriber_poll(raw, None, user_data), 0);
+            assert_eq!(alewife_subscriber_poll(ptr::null_mut(), Some(collect), user_data), 0);
+            alewife_subscriber_free(raw);
+        }
+        assert_eq!(received, vec!["score=10", "score=20"]);
+    }
+}
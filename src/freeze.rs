@@ -0,0 +1,154 @@
+//! Pausing delivery across a whole network.
+//!
+//! `Publisher::freeze()` stops every message from reaching subscribers until
+//! `Publisher::thaw()`, so a debugger or a test can look at queues without
+//! them changing underfoot, much like pausing the world in a simulation.
+//! Publishes made meanwhile still go through the network's checks, then wait
+//! in a queue of their own or are refused, as the `FreezePolicy` says. Thawing
+//! delivers the waiting messages in the order they were published.
+
+use std::collections::VecDeque;
+use std::hash::Hash;
+
+use super::{DropReason, PublishError, Publisher};
+use super::sync::{AtomicBool, Mutex, Ordering};
+use super::token::Resolved;
+
+/// What happens to messages published while a network is frozen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FreezePolicy {
+    /// Keep every one for delivery when the network thaws.
+    Hold,
+    /// Keep up to this many, and refuse the rest.
+    HoldUpTo(usize),
+    /// Refuse them all.
+    Refuse,
+}
+
+struct Held<Topic, Content> {
+    limit: Option<usize>,
+    messages: VecDeque<(Topic, Content, Option<usize>)>,
+}
+
+/// Whether the network is frozen, and what is waiting for it to thaw.
+pub(crate) struct Freeze<Topic, Content> {
+    /// Set while `held` is, so publishing needn't take the lock.
+    frozen: AtomicBool,
+    held: Mutex<Option<Held<Topic, Content>>>,
+}
+
+impl<Topic: Clone, Content> Freeze<Topic, Content> {
+    pub(crate) fn new() -> Self {
+        Freeze { frozen: AtomicBool::new(false), held: Mutex::new(None) }
+    }
+
+    /// Keeps a message back if the network is frozen. Gives the content back
+    /// if it should be delivered now, and refuses it if there's no room.
+    pub(crate) fn hold(&self, topic: &Topic, content: Content, skip: Option<usize>)
+        -> Result<Option<Content>, ()>
+    {
+        if !self.frozen.load(Ordering::Acquire) { return Ok(Some(content)); }
+        let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+        let held = match *held {
+            Some(ref mut held) => held,
+            None => return Ok(Some(content)),
+        };
+        if held.limit.is_some_and(|limit| held.messages.len() >= limit) { return Err(()); }
+        held.messages.push_back((topic.clone(), content, skip));
+        Ok(None)
+    }
+
+    /// Takes the oldest waiting message. Thaws the network once there are
+    /// none left, so later publishes can't overtake earlier ones.
+    fn release(&self) -> Option<(Topic, Content, Option<usize>)> {
+        let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+        let message = held.as_mut().and_then(|held| held.messages.pop_front());
+        if message.is_none() {
+            *held = None;
+            self.frozen.store(false, Ordering::Release);
+        }
+        message
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Stops delivering messages until `thaw()`. Messages published in the
+    /// meantime are dealt with according to `policy`; refused ones are
+    /// reported as `DropReason::Frozen`. Returns false, and leaves the policy
+    /// alone, if the network was already frozen.
+    pub fn freeze(&self, policy: FreezePolicy) -> bool {
+        let freeze = &self.bus().freeze;
+        let mut held = freeze.held.lock().unwrap_or_else(|e| e.into_inner());
+        if held.is_some() { return false; }
+        let limit = match policy {
+            FreezePolicy::Hold => None,
+            FreezePolicy::HoldUpTo(limit) => Some(limit),
+            FreezePolicy::Refuse => Some(0),
+        };
+        *held = Some(Held { limit, messages: VecDeque::new() });
+        freeze.frozen.store(true, Ordering::Release);
+        true
+    }
+
+    /// Returns true between `freeze()` and `thaw()`.
+    pub fn is_frozen(&self) -> bool {
+        self.bus().freeze.frozen.load(Ordering::Acquire)
+    }
+
+    /// Resumes delivery, starting with the messages held while the network
+    /// was frozen, and returns how many of those there were. Messages
+    /// published while they are delivered wait their turn behind them.
+    pub fn thaw(&self) -> usize {
+        let bus = self.bus();
+        let mut released = 0;
+        while let Some((topic, content, skip)) = bus.freeze.release() {
+            bus.deliver_and_forward(&topic, content, skip);
+            released += 1;
+        }
+        released
+    }
+
+    /// Holds back a checked message if the network is frozen, and delivers
+    /// it otherwise.
+    pub(crate) fn deliver_unless_frozen(&self, topic: &Topic, content: Content, skip: Option<usize>,
+                                        resolved: Option<&Resolved<Topic, Content>>)
+        -> Result<(), PublishError>
+    {
+        let bus = self.bus();
+        match bus.freeze.hold(topic, content, skip) {
+            Ok(Some(content)) => bus.deliver_and_forward_resolved(topic, content, skip, resolved),
+            Ok(None) => (),
+            Err(()) => {
+                bus.drops.notify(topic, DropReason::Frozen);
+                return Err(PublishError::Frozen);
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frozen_networks_hold_publishes_until_thawed() {
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["tick"]);
+        let publisher = builder.build();
+
+        assert!(publisher.freeze(FreezePolicy::HoldUpTo(2)));
+        assert!(!publisher.freeze(FreezePolicy::Refuse));
+        assert_eq!(publisher.try_publish("tick", 1), Ok(()));
+        assert_eq!(publisher.try_publish("tick", 2), Ok(()));
+        assert_eq!(publisher.try_publish("tick", 3), Err(PublishError::Frozen));
+        assert!(publisher.is_frozen());
+        assert_eq!(subscriber.pending(), 0);
+
+        assert_eq!(publisher.thaw(), 2);
+        assert!(!publisher.is_frozen());
+        publisher.publish("tick", 4);
+        assert_eq!(subscriber.fetch(), vec![("tick", 1), ("tick", 2), ("tick", 4)]);
+        assert_eq!(publisher.thaw(), 0);
+    }
+}
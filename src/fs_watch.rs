@@ -0,0 +1,155 @@
+//! Publishing changes to files.
+//!
+//! Tools that reload configuration, assets or scripts when they change on
+//! disk can subscribe to a topic instead of each watching the files itself.
+//! `watch()` starts a thread that looks over the given paths every interval,
+//! directories included, and publishes an `FsEvent` for each file created,
+//! modified or removed since the last look. It compares modification times
+//! and sizes rather than asking the operating system, so it behaves the same
+//! everywhere, at the cost of noticing changes up to one interval late.
+
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use super::Publisher;
+use super::worker::{self, Worker};
+
+/// What happened to a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FsEventKind {
+    /// It appeared.
+    Created,
+    /// Its contents or modification time changed.
+    Modified,
+    /// It went away.
+    Removed,
+}
+
+/// A change to one file, as published by `watch()`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FsEvent {
+    /// The file's path, under whichever watched path it was found.
+    pub path: PathBuf,
+    /// What happened to it.
+    pub kind: FsEventKind,
+}
+
+/// A running file watcher. Dropping it stops the thread.
+pub struct FsWatcher {
+    worker: Worker,
+}
+
+impl FsWatcher {
+    /// Stops watching and waits for the thread to finish.
+    pub fn stop(self) {
+        self.worker.stop();
+    }
+}
+
+type Scan = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+/// Records every file at or under `path`. Anything that can't be read is
+/// left out, as if it weren't there.
+fn scan(path: &Path, found: &mut Scan) {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return,
+    };
+    if metadata.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() { scan(&entry.path(), found); }
+        }
+    } else {
+        found.insert(path.to_owned(), (metadata.modified().ok(), metadata.len()));
+    }
+}
+
+/// Starts watching `paths`, looking for changes every `interval`, and
+/// publishes each one to `publisher` under the topic `topic` picks for it.
+/// Files already there when it starts are not reported. Panics if `interval`
+/// is zero.
+pub fn watch<Topic, F>(paths: &[PathBuf], interval: Duration,
+                       publisher: Publisher<Topic, FsEvent>, topic: F) -> FsWatcher
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          F: Fn(&FsEvent) -> Topic + Send + 'static,
+{
+    let interval = worker::nonzero(interval);
+    let paths = paths.to_vec();
+    let mut last = Scan::new();
+    for path in &paths { scan(path, &mut last); }
+
+    let worker = Worker::spawn(move |stopped| {
+        while !stopped.load(Ordering::Acquire) {
+            thread::sleep(interval);
+            let mut now = Scan::new();
+            for path in &paths { scan(path, &mut now); }
+
+            let mut events = vec![];
+            for (path, stamp) in &now {
+                match last.get(path) {
+                    None => events.push(FsEvent { path: path.clone(), kind: FsEventKind::Created }),
+                    Some(old) if old != stamp => {
+                        events.push(FsEvent { path: path.clone(), kind: FsEventKind::Modified });
+                    },
+                    Some(_) => (),
+                }
+            }
+            for path in last.keys().filter(|path| !now.contains_key(*path)) {
+                events.push(FsEvent { path: path.clone(), kind: FsEventKind::Removed });
+            }
+            events.sort_by(|a, b| a.path.cmp(&b.path));
+            for event in events { publisher.publish(topic(&event), event); }
+            last = now;
+        }
+    });
+
+    FsWatcher { worker }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn changes_are_published() {
+        let dir = std::env::temp_dir().join(format!("alewife-fs-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("config.toml");
+        fs::write(&config, "a = 1").unwrap();
+
+        let mut builder = Publisher::new();
+        let changes = builder.add_subscriber(&["fs/config", "fs/other"]);
+        let publisher = builder.build();
+        let watcher = watch(std::slice::from_ref(&dir), Duration::from_millis(5), publisher, |event| {
+            if event.path.extension().is_some_and(|e| e == "toml") { "fs/config" } else { "fs/other" }
+        });
+
+        let wait_for = |count: usize| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut seen = vec![];
+            while seen.len() < count {
+                seen.extend(changes.fetch());
+                assert!(Instant::now() < deadline, "only saw {:?}", seen);
+                thread::sleep(Duration::from_millis(2));
+            }
+            seen
+        };
+
+        fs::write(&config, "a = 12").unwrap();
+        assert_eq!(wait_for(1), vec![("fs/config", FsEvent { path: config.clone(), kind: FsEventKind::Modified })]);
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        assert_eq!(wait_for(1)[0].1.kind, FsEventKind::Created);
+        fs::remove_file(&config).unwrap();
+        assert_eq!(wait_for(1), vec![("fs/config", FsEvent { path: config, kind: FsEventKind::Removed })]);
+
+        watcher.stop();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+//! Subscribing a type's methods by the events they take.
+//!
+//! A network commonly carries one enum of events, each variant wrapping a
+//! payload type with a topic of its own. Implement `Event` for each payload
+//! type, and a component can receive them through methods that simply take
+//! the payload: `Handlers` collects the topics those methods need and routes
+//! each message to the right one. With the `macros` feature,
+//! `#[alewife::handler]` writes the `Handlers` implementation from an `impl`
+//! block.
+
+use std::hash::Hash;
+use std::ops::ControlFlow;
+
+use super::{Builder, Subscriber};
+
+/// A payload type carried inside a network's content, on a topic of its own.
+pub trait Event<Topic, Content> {
+    /// The topic this payload is published on.
+    fn topic() -> Topic;
+
+    /// Returns the payload if `content` holds one of this type.
+    fn from_content(content: &Content) -> Option<&Self>;
+}
+
+/// A component whose methods each handle one kind of `Event`.
+pub trait Handlers<Topic, Content> {
+    /// The topics of every event handled.
+    fn topics() -> Vec<Topic>;
+
+    /// Passes `content` to the method for the payload it holds. Returns false
+    /// if none of them takes it.
+    fn handle(&mut self, content: &Content) -> bool;
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Adds a subscriber for every topic `H` handles.
+    pub fn add_handlers<H: Handlers<Topic, Content>>(&mut self) -> Subscriber<Topic, Content> {
+        self.add_subscriber(&H::topics())
+    }
+}
+
+impl<Topic, Content> Subscriber<Topic, Content> {
+    /// Passes each pending message to the matching method of `handlers`,
+    /// returning how many messages there were.
+    pub fn dispatch<H: Handlers<Topic, Content>>(&self, handlers: &mut H) -> usize {
+        self.drain(|_, content| {
+            handlers.handle(&content);
+            ControlFlow::Continue(())
+        })
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod test {
+    use super::*;
+    use Publisher;
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Game {
+        Damage(Damage),
+        Heal(Heal),
+        Chat(String),
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Damage(u32);
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Heal(u32);
+
+    impl Event<&'static str, Game> for Damage {
+        fn topic() -> &'static str { "damage" }
+        fn from_content(content: &Game) -> Option<&Self> {
+            match *content { Game::Damage(ref event) => Some(event), _ => None }
+        }
+    }
+
+    impl Event<&'static str, Game> for Heal {
+        fn topic() -> &'static str { "heal" }
+        fn from_content(content: &Game) -> Option<&Self> {
+            match *content { Game::Heal(ref event) => Some(event), _ => None }
+        }
+    }
+
+    struct Hud {
+        health: u32,
+    }
+
+    #[::alewife::handler]
+    impl Hud {
+        fn on_damage(&mut self, damage: &Damage) { self.health -= damage.0; }
+        fn on_heal(&mut self, heal: &Heal) { self.health += heal.0; }
+        fn health(&self) -> u32 { self.health }
+    }
+
+    #[test]
+    fn methods_are_subscribed_by_payload() {
+        assert_eq!(<Hud as Handlers<&str, Game>>::topics(), vec!["damage", "heal"]);
+
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_handlers::<Hud>();
+        let publisher = builder.build();
+
+        publisher.publish("damage", Game::Damage(Damage(30)));
+        publisher.publish("chat", Game::Chat("ouch".to_owned()));
+        publisher.publish("heal", Game::Heal(Heal(5)));
+
+        let mut hud = Hud { health: 100 };
+        assert_eq!(subscriber.dispatch(&mut hud), 2);
+        assert_eq!(hud.health(), 75);
+    }
+}
@@ -0,0 +1,81 @@
+//! Passing values that can't be cloned.
+//!
+//! A network's content must be `Clone`, since each subscriber gets a copy. A
+//! value that can't be copied, such as a file handle or a one-shot reply
+//! channel, can still be sent wrapped in a `Handoff`: copies of it are cheap
+//! and all refer to the same slot, and whoever calls `take()` first gets the
+//! value. Published on a single-consumer topic, declared with
+//! `TopicOptions::single_consumer()`, a handoff is moved to the topic's one
+//! subscriber and always arrives with its value. A second subscriber to such
+//! a topic is refused with `SubscribeError::SingleConsumerTaken` by
+//! `Builder::try_add_subscriber()` and `Publisher::try_subscribe_scoped()`,
+//! and otherwise receives nothing on it.
+
+use std::fmt;
+use std::sync::Arc;
+
+use super::sync::Mutex;
+
+/// A value that only one receiver can take. See the module documentation.
+pub struct Handoff<T> {
+    slot: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> Handoff<T> {
+    /// Wraps `value` for sending.
+    pub fn new(value: T) -> Self {
+        Handoff { slot: Arc::new(Mutex::new(Some(value))) }
+    }
+
+    /// Takes the value, unless a copy of this handoff already has.
+    pub fn take(&self) -> Option<T> {
+        self.slot.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    /// Returns true if the value has been taken.
+    pub fn is_taken(&self) -> bool {
+        self.slot.lock().unwrap_or_else(|e| e.into_inner()).is_none()
+    }
+}
+
+impl<T> Clone for Handoff<T> {
+    fn clone(&self) -> Self {
+        Handoff { slot: self.slot.clone() }
+    }
+}
+
+impl<T> fmt::Debug for Handoff<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = if self.is_taken() { "taken" } else { "waiting" };
+        f.debug_tuple("Handoff").field(&format_args!("{}", state)).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {Publisher, SubscribeError, TopicOptions};
+
+    /// Deliberately not `Clone`.
+    #[derive(Debug, PartialEq)]
+    struct Reply(u32);
+
+    #[test]
+    fn exclusive_topics_move_handoffs() {
+        let mut builder = Publisher::new();
+        builder.declare_topic("reply", TopicOptions::new().single_consumer());
+        let subscriber = builder.add_subscriber(&["reply"]);
+        assert_eq!(builder.try_add_subscriber(&["reply"]).err(), Some(SubscribeError::SingleConsumerTaken));
+        let bystander = builder.add_subscriber(&["reply"]);
+        let publisher = builder.build();
+        assert_eq!(publisher.try_subscribe_scoped(&["reply"]).err(), Some(SubscribeError::SingleConsumerTaken));
+
+        let sent = Handoff::new(Reply(7));
+        publisher.publish("reply", sent.clone());
+        let (_, received) = subscriber.next().unwrap();
+        assert_eq!(received.take(), Some(Reply(7)));
+        assert!(sent.is_taken());
+        assert_eq!(sent.take(), None);
+        assert!(bystander.fetch().is_empty());
+    }
+}
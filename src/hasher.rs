@@ -0,0 +1,123 @@
+//! Choosing how the routing table hashes topics.
+//!
+//! Every publish hashes its topic to find the subscribers. The standard
+//! library's SipHash is built to resist inputs chosen to collide, which a
+//! network whose topics come from its own code rarely needs, and it shows up
+//! in profiles of busy publishers with string topics. `TopicHasher::Fx` is
+//! the much cheaper hash rustc uses for its own tables.
+
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+
+use super::Builder;
+
+/// How the routing table hashes topics, set with `Builder::topic_hasher()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TopicHasher {
+    /// The standard library's hasher, seeded at random. Use this when
+    /// topics come from outside, as from a bridge, and someone could pick
+    /// them to collide.
+    #[default]
+    SipHash,
+    /// A fast multiply-and-rotate hash, with no protection from collisions
+    /// chosen on purpose.
+    Fx,
+}
+
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+/// Makes hashers of the chosen kind.
+#[derive(Clone)]
+pub(crate) struct TopicHashing {
+    kind: TopicHasher,
+    random: RandomState,
+}
+
+impl TopicHashing {
+    pub(crate) fn new(kind: TopicHasher) -> Self {
+        TopicHashing { kind, random: RandomState::new() }
+    }
+}
+
+impl BuildHasher for TopicHashing {
+    type Hasher = TopicHash;
+
+    fn build_hasher(&self) -> TopicHash {
+        match self.kind {
+            TopicHasher::SipHash => TopicHash::Sip(self.random.build_hasher()),
+            TopicHasher::Fx => TopicHash::Fx(0),
+        }
+    }
+}
+
+pub(crate) enum TopicHash {
+    Sip(DefaultHasher),
+    Fx(u64),
+}
+
+impl TopicHash {
+    fn add(&mut self, word: u64) {
+        match *self {
+            TopicHash::Sip(ref mut sip) => sip.write_u64(word),
+            TopicHash::Fx(ref mut hash) => *hash = (hash.rotate_left(5) ^ word).wrapping_mul(SEED),
+        }
+    }
+}
+
+impl Hasher for TopicHash {
+    fn write(&mut self, bytes: &[u8]) {
+        if let TopicHash::Sip(ref mut sip) = *self { return sip.write(bytes); }
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(word);
+            self.add(u64::from_le_bytes(buf));
+        }
+        for &byte in words.remainder() { self.add(byte as u64); }
+    }
+
+    fn write_u8(&mut self, n: u8) { self.add(n as u64) }
+    fn write_u16(&mut self, n: u16) { self.add(n as u64) }
+    fn write_u32(&mut self, n: u32) { self.add(n as u64) }
+    fn write_u64(&mut self, n: u64) { self.add(n) }
+    fn write_usize(&mut self, n: usize) { self.add(n as u64) }
+
+    fn finish(&self) -> u64 {
+        match *self {
+            TopicHash::Sip(ref sip) => sip.finish(),
+            TopicHash::Fx(hash) => hash,
+        }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Hashes topics in the routing table with `hasher`. Subscribers added
+    /// already keep their topics; they are just rehashed.
+    pub fn topic_hasher(&mut self, hasher: TopicHasher) {
+        self.bus.routing.write().unwrap_or_else(|e| e.into_inner()).set_hasher(hasher);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Publisher;
+
+    #[test]
+    fn topics_are_found_whichever_hasher_is_used() {
+        let hashing = TopicHashing::new(TopicHasher::Fx);
+        assert_eq!(hashing.hash_one("sensor/7"), hashing.hash_one("sensor/7".to_owned()));
+        assert_ne!(hashing.hash_one("sensor/7"), hashing.hash_one("sensor/8"));
+
+        let mut builder = Publisher::new();
+        let early = builder.add_subscriber(&["sensor/7".to_owned()]);
+        builder.topic_hasher(TopicHasher::Fx);
+        let late = builder.add_subscriber(&["sensor/7".to_owned(), "sensor/8".to_owned()]);
+        let publisher = builder.build();
+
+        publisher.publish("sensor/7".to_owned(), 1);
+        publisher.publish("sensor/8".to_owned(), 2);
+        assert_eq!(early.fetch().len(), 1);
+        assert_eq!(late.fetch().len(), 2);
+    }
+}
@@ -0,0 +1,72 @@
+//! A snapshot of a network's health, for status pages and probes.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// What `Publisher::health()` found. There is no broker thread to check on,
+/// since messages are routed by the threads that publish them, so this
+/// covers what can go wrong instead: subscribers that went away without
+/// unsubscribing, queues that are backing up, the memory budget, and bridge
+/// links.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Health {
+    /// Subscribers with at least one route.
+    pub subscribers: usize,
+    /// Subscribers that were dropped while their routes stayed in place, so
+    /// nothing reads what is sent to them. A subscriber given up with
+    /// `Subscriber::into_inner()` is counted here too.
+    pub disconnected: usize,
+    /// Messages waiting in all subscriber queues.
+    pub queued: usize,
+    /// The most messages waiting for any one subscriber.
+    pub deepest_queue: usize,
+    /// Bytes counted towards the memory budget.
+    pub memory_used: usize,
+    /// The memory budget, if there is one.
+    pub memory_budget: Option<usize>,
+    /// Bridge links registered with `Publisher::watch_link()`.
+    pub links: Vec<LinkHealth>,
+}
+
+impl Health {
+    /// True if no subscriber has been lost and every watched link is still
+    /// running. Queue depth isn't judged, since what counts as too deep is up
+    /// to the application.
+    pub fn is_healthy(&self) -> bool {
+        self.disconnected == 0 && self.links.iter().all(|link| link.running)
+    }
+}
+
+/// The state of one bridge link.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkHealth {
+    /// The name the link was registered under.
+    pub name: String,
+    /// False once the link's thread has exited.
+    pub running: bool,
+}
+
+/// Set while a bridge thread runs, and cleared when it exits, even by
+/// panicking.
+#[derive(Clone)]
+pub(crate) struct Liveness(Arc<AtomicBool>);
+
+impl Liveness {
+    pub(crate) fn start() -> (Self, Running) {
+        let flag = Arc::new(AtomicBool::new(true));
+        (Liveness(flag.clone()), Running(flag))
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Held by the thread whose liveness is tracked.
+pub(crate) struct Running(Arc<AtomicBool>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
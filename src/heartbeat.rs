@@ -0,0 +1,175 @@
+//! Noticing components that have stopped reading their messages.
+//!
+//! A component whose thread is stuck stops reading its subscriber, but an
+//! idle one looks just the same from outside, so queue depth alone can't
+//! tell them apart. Heartbeats can: `Subscriber::heartbeat()` makes a
+//! subscriber publish a `Heartbeat` on a system topic whenever it is read and
+//! one is due, including reads that find nothing, and a monitor watching
+//! that topic reports each component that falls silent for some number of
+//! intervals, and reports it again when it recovers. The component's loop
+//! should read at least once per interval, even when it has nothing to do,
+//! for instance with `Subscriber::process_for()`.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Publisher, Subscriber};
+use super::worker::{self, Worker};
+
+/// A sign of life from one component.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// The name the component's subscriber was given.
+    pub component: String,
+}
+
+/// A change in a component's liveness, as published by a monitor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeartbeatEvent {
+    /// The component has missed the given number of heartbeats in a row.
+    Missed {
+        /// Which component.
+        component: String,
+        /// How long since its last heartbeat.
+        silent_for: Duration,
+    },
+    /// A component reported missing has sent a heartbeat again.
+    Resumed {
+        /// Which component.
+        component: String,
+    },
+}
+
+impl<Topic, Content> Subscriber<Topic, Content> {
+    /// Publishes a `Heartbeat` for `component` on `topic`, at most once every
+    /// `interval`, whenever this subscriber is read.
+    pub fn heartbeat<OutTopic>(&mut self, component: &str, interval: Duration,
+                               publisher: Publisher<OutTopic, Heartbeat>, topic: OutTopic)
+        where OutTopic: Hash + Eq + Clone + Send + Sync + 'static
+    {
+        let component = component.to_owned();
+        let last: Cell<Option<Instant>> = Cell::new(None);
+        self.pulse = Some(Box::new(move || {
+            let now = Instant::now();
+            if last.get().is_some_and(|last| now.duration_since(last) < interval) { return; }
+            last.set(Some(now));
+            publisher.publish(topic.clone(), Heartbeat { component: component.clone() });
+        }));
+    }
+}
+
+/// A running heartbeat monitor. Dropping it stops the thread.
+pub struct Monitor {
+    worker: Worker,
+}
+
+impl Monitor {
+    /// Stops the monitor and waits for its thread to finish.
+    pub fn stop(self) {
+        self.worker.stop();
+    }
+}
+
+/// What the monitor remembers about one component.
+struct Watch {
+    last_beat: Instant,
+    missing: bool,
+}
+
+/// Starts watching the heartbeats `beats` receives, which were sent every
+/// `interval`, and publishes a `HeartbeatEvent` to `report` under `topic`
+/// when a component misses `missed` of them in a row. Components are known
+/// from their first heartbeat on. Panics if `interval` is zero.
+pub fn monitor<Topic, OutTopic>(
+    beats: Subscriber<Topic, Heartbeat>,
+    interval: Duration,
+    missed: u32,
+    report: Publisher<OutTopic, HeartbeatEvent>,
+    topic: OutTopic,
+) -> Monitor
+    where Topic: Send + 'static,
+          OutTopic: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    let silence = interval * missed.max(1);
+    let check_every = worker::check_every(interval);
+
+    let worker = Worker::spawn(move |stopped| {
+        let mut watches: HashMap<String, Watch> = HashMap::new();
+        while !stopped.load(Ordering::Acquire) {
+            let now = Instant::now();
+            for (_, beat) in beats.fetch() {
+                let watch = watches.entry(beat.component.clone())
+                    .or_insert(Watch { last_beat: now, missing: false });
+                watch.last_beat = now;
+                if watch.missing {
+                    watch.missing = false;
+                    report.publish(topic.clone(), HeartbeatEvent::Resumed { component: beat.component });
+                }
+            }
+
+            for (component, watch) in &mut watches {
+                let silent_for = now.duration_since(watch.last_beat);
+                if !watch.missing && silent_for >= silence {
+                    watch.missing = true;
+                    report.publish(topic.clone(), HeartbeatEvent::Missed {
+                        component: component.clone(),
+                        silent_for,
+                    });
+                }
+            }
+
+            thread::sleep(check_every);
+        }
+    });
+
+    Monitor { worker }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn silent_components_are_reported_until_they_resume() {
+        let interval = Duration::from_millis(10);
+        let mut builder = Publisher::new();
+        let beats = builder.add_subscriber(&["beat"]);
+        let heartbeats = builder.build();
+
+        let mut builder = Publisher::new();
+        let alerts = builder.add_subscriber(&["alerts"]);
+        let _monitor = monitor(beats, interval, 3, builder.build(), "alerts");
+
+        let mut builder = Publisher::<&str, u32>::new();
+        let mut worker = builder.add_subscriber(&["work"]);
+        worker.heartbeat("worker", interval, heartbeats, "beat");
+
+        let read_until = |until: Instant| while Instant::now() < until {
+            worker.fetch();
+            thread::sleep(Duration::from_millis(2));
+        };
+        let wait_for_alert = || {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                if let Some((_, event)) = alerts.fetch().pop() { return event; }
+                assert!(Instant::now() < deadline, "no alert arrived");
+                thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        read_until(Instant::now() + Duration::from_millis(20));
+        match wait_for_alert() {
+            HeartbeatEvent::Missed { component, silent_for } => {
+                assert_eq!(component, "worker");
+                assert!(silent_for >= interval * 3);
+            },
+            other => panic!("unexpected report {:?}", other),
+        }
+        read_until(Instant::now() + Duration::from_millis(20));
+        assert_eq!(wait_for_alert(), HeartbeatEvent::Resumed { component: "worker".to_owned() });
+    }
+}
@@ -0,0 +1,95 @@
+//! Looking at a subscriber's queue without reading it.
+//!
+//! When a consumer seems stuck, the first question is what it has waiting.
+//! `Subscriber::inspect()` answers it: it shows every message the subscriber
+//! holds, in the order they would be read, without taking any of them.
+//! Together with `Publisher::freeze()` it gives a picture of the whole
+//! network at one moment.
+
+use std::cell::Ref;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+use std::iter;
+
+use super::Subscriber;
+
+/// A subscriber's waiting messages, from `Subscriber::inspect()`. Its `Debug`
+/// formatting lists them, one per line with `{:#?}`.
+pub struct Inspection<'a, Topic: 'a, Content: 'a> {
+    backlog: Ref<'a, VecDeque<(Topic, Content)>>,
+    stack: Option<Ref<'a, Vec<(Topic, Content)>>>,
+}
+
+impl<'a, Topic, Content> Inspection<'a, Topic, Content> {
+    /// The messages, in the order they would be read.
+    pub fn iter(&self) -> impl Iterator<Item = &(Topic, Content)> {
+        let stacked = self.stack.iter().flat_map(|stack| stack.iter().rev());
+        self.backlog.iter().chain(stacked)
+    }
+
+    /// How many messages there are.
+    pub fn len(&self) -> usize {
+        self.backlog.len() + self.stack.as_ref().map_or(0, |stack| stack.len())
+    }
+
+    /// Returns true if nothing is waiting.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a, Topic: Debug, Content: Debug> Debug for Inspection<'a, Topic, Content> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<Topic, Content> Subscriber<Topic, Content> {
+    /// Shows the messages waiting for this subscriber, in the order they
+    /// would be read, without consuming them. A message a debouncer is still
+    /// holding back isn't shown until it is due.
+    ///
+    /// Messages are moved out of the channel to be shown, and stop counting
+    /// against the network's memory budget once they are. Drop the
+    /// inspection before reading from the subscriber again, or the read
+    /// panics.
+    pub fn inspect(&self) -> Inspection<'_, Topic, Content> {
+        match self.stack {
+            Some(ref stack) => stack.borrow_mut().extend(iter::from_fn(|| self.receive())),
+            None => self.backlog.borrow_mut().extend(iter::from_fn(|| self.receive())),
+        }
+        Inspection {
+            backlog: self.backlog.borrow(),
+            stack: self.stack.as_ref().map(|stack| stack.borrow()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {InboxOrder, Publisher, SubscriptionOptions};
+
+    #[test]
+    fn inspecting_leaves_messages_queued() {
+        let mut builder = Publisher::new();
+        let fifo = builder.add_subscriber(&["job"]);
+        let lifo = builder.add_subscriber_with(&["job"], SubscriptionOptions::new().inbox_order(InboxOrder::Lifo));
+        let publisher = builder.build();
+
+        publisher.publish("job", 1);
+        publisher.publish("job", 2);
+        {
+            let waiting = fifo.inspect();
+            assert_eq!(waiting.len(), 2);
+            assert_eq!(format!("{:?}", waiting), "[(\"job\", 1), (\"job\", 2)]");
+        }
+        publisher.publish("job", 3);
+        assert_eq!(fifo.pending(), 3);
+        assert_eq!(fifo.fetch(), vec![("job", 1), ("job", 2), ("job", 3)]);
+        assert!(fifo.inspect().is_empty());
+
+        let order: Vec<u32> = lifo.inspect().iter().map(|&(_, n)| n).collect();
+        assert_eq!(order, vec![3, 2, 1]);
+        assert_eq!(lifo.fetch(), vec![("job", 3), ("job", 2), ("job", 1)]);
+    }
+}
@@ -0,0 +1,109 @@
+//! Cheap topics for networks that name them with strings.
+//!
+//! String topics are convenient, but every publish hashes the string and
+//! every delivery clones it. An `Interner` hands out a small `TopicId` for
+//! each distinct name instead, which is `Copy` and hashes as one integer, so a
+//! `Publisher<TopicId, Content>` pays nothing per message for its topics.
+//! Intern each name once, when setting up, and keep the ids.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use super::sync::RwLock;
+
+/// A topic name interned by an `Interner`. Ids are only meaningful to the
+/// interner that made them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TopicId(u32);
+
+impl TopicId {
+    /// The id as a number. Ids are handed out counting up from zero.
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for TopicId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "topic #{}", self.0)
+    }
+}
+
+/// Maps topic names to `TopicId`s and back. Clones share the same table, so
+/// it can be handed to every part of the program that needs to look names up.
+#[derive(Clone, Default)]
+pub struct Interner {
+    table: Arc<RwLock<Table>>,
+}
+
+#[derive(Default)]
+struct Table {
+    ids: HashMap<Arc<str>, TopicId>,
+    names: Vec<Arc<str>>,
+}
+
+impl Interner {
+    /// Creates an empty interner.
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// Returns the id for `name`, assigning the next one if it's new.
+    pub fn intern(&self, name: &str) -> TopicId {
+        if let Some(id) = self.get(name) { return id; }
+
+        let mut table = self.table.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(&id) = table.ids.get(name) { return id; }
+        let id = TopicId(table.names.len() as u32);
+        let name: Arc<str> = Arc::from(name);
+        table.names.push(name.clone());
+        table.ids.insert(name, id);
+        id
+    }
+
+    /// Returns the id for `name` if it has been interned.
+    pub fn get(&self, name: &str) -> Option<TopicId> {
+        self.table.read().unwrap_or_else(|e| e.into_inner()).ids.get(name).cloned()
+    }
+
+    /// Returns the name `id` was interned from.
+    pub fn name(&self, id: TopicId) -> Option<Arc<str>> {
+        self.table.read().unwrap_or_else(|e| e.into_inner()).names.get(id.0 as usize).cloned()
+    }
+
+    /// The number of names interned so far.
+    pub fn len(&self) -> usize {
+        self.table.read().unwrap_or_else(|e| e.into_inner()).names.len()
+    }
+
+    /// Returns true if nothing has been interned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Publisher;
+
+    #[test]
+    fn names_round_trip() {
+        let topics = Interner::new();
+        let input = topics.intern("input");
+        let state = topics.clone().intern("state");
+        assert_eq!(topics.intern("input"), input);
+        assert_ne!(input, state);
+        assert_eq!(topics.get("missing"), None);
+        assert_eq!(&*topics.name(state).unwrap(), "state");
+        assert_eq!(topics.len(), 2);
+
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&[state]);
+        let publisher = builder.build();
+        publisher.publish(topics.intern("state"), 1);
+        publisher.publish(input, 2);
+        assert_eq!(subscriber.fetch(), vec![(state, 1)]);
+    }
+}
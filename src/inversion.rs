@@ -0,0 +1,162 @@
+//! Noticing urgent messages stuck behind less urgent ones.
+//!
+//! Subscriber queues are first in, first out, whatever the priority of the
+//! topics involved, so a high-priority message delivered to a subscriber
+//! that is working through a pile of low-priority ones waits for all of
+//! them. Once in a while that is harmless; when it keeps happening, the
+//! subscriber probably needs the urgent topic on a queue of its own.
+//! `Builder::detect_priority_inversion()` watches for it and reports each
+//! subscriber it keeps happening to.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::{Builder, Priority, Publisher};
+use super::sync::Mutex;
+
+/// A subscriber whose high-priority messages keep arriving behind a backlog
+/// of lower-priority ones, as published by
+/// `Builder::detect_priority_inversion()`. Subscribers made by
+/// `Builder::from_config()` are named as in the configuration; others as
+/// `subscriber #n`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PriorityInversion {
+    /// Which subscriber.
+    pub subscriber: String,
+    /// How many high-priority messages in a row found a backlog ahead of them.
+    pub delayed: u32,
+    /// How many messages were queued ahead of the latest one.
+    pub queued: usize,
+    /// How many lower-priority messages the subscriber was sent since its
+    /// queue was last seen empty, an upper bound on how many of those queued
+    /// were less urgent.
+    pub lower_priority: u64,
+}
+
+type Report = Box<dyn Fn(PriorityInversion) + Send + Sync>;
+
+/// What the detector knows about one subscriber.
+#[derive(Default)]
+struct Watch {
+    lower_since_empty: u64,
+    delayed: u32,
+    reported: bool,
+}
+
+pub(crate) struct Inversions {
+    backlog: usize,
+    times: u32,
+    watches: Mutex<HashMap<usize, Watch>>,
+    report: Report,
+}
+
+impl Inversions {
+    /// Notes a message of `priority` about to join the queue of subscriber
+    /// `id`, which holds `queued` messages already. Returns what to report,
+    /// if this makes an inversion, with the subscriber left to be named.
+    pub(crate) fn observe(&self, id: usize, queued: usize, priority: Priority)
+        -> Option<PriorityInversion>
+    {
+        let mut watches = self.watches.lock().unwrap_or_else(|e| e.into_inner());
+        let watch = watches.entry(id).or_default();
+        if queued == 0 { watch.lower_since_empty = 0; }
+        if priority < Priority::High {
+            watch.lower_since_empty += 1;
+            return None;
+        }
+
+        if queued < self.backlog || watch.lower_since_empty == 0 {
+            watch.delayed = 0;
+            watch.reported = false;
+            return None;
+        }
+        watch.delayed += 1;
+        if watch.delayed < self.times || watch.reported { return None; }
+        watch.reported = true;
+        Some(PriorityInversion {
+            subscriber: String::new(),
+            delayed: watch.delayed,
+            queued,
+            lower_priority: watch.lower_since_empty,
+        })
+    }
+
+    /// Names and reports each inversion.
+    pub(crate) fn report(&self, found: Vec<(usize, PriorityInversion)>, names: &HashMap<usize, String>) {
+        for (id, mut inversion) in found {
+            inversion.subscriber = names.get(&id).cloned()
+                .unwrap_or_else(|| format!("subscriber #{}", id));
+            (self.report)(inversion);
+        }
+    }
+
+    pub(crate) fn forget(&self, id: usize) {
+        self.watches.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Publishes a `PriorityInversion` to `report` under `topic` whenever a
+    /// subscriber is sent `times` messages in a row on `Priority::High`
+    /// topics that each find at least `backlog` messages queued ahead of
+    /// them, some of them lower priority. A subscriber is reported once, and
+    /// again only after a high-priority message finds its queue short.
+    ///
+    /// Only subscribers with queues are watched; single-consumer topics are
+    /// not.
+    pub fn detect_priority_inversion<OutTopic>(
+        &mut self,
+        backlog: usize,
+        times: u32,
+        report: Publisher<OutTopic, PriorityInversion>,
+        topic: OutTopic,
+    )
+        where OutTopic: Hash + Eq + Clone + Send + Sync + 'static,
+    {
+        self.bus.inversions = Some(Inversions {
+            backlog: backlog.max(1),
+            times: times.max(1),
+            watches: Mutex::new(HashMap::new()),
+            report: Box::new(move |inversion| report.publish(topic.clone(), inversion)),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn alarms_stuck_behind_debug_output_are_reported() {
+        let mut builder = Publisher::new();
+        let events = builder.add_subscriber(&["inversion"]);
+        let reporter = builder.build();
+
+        let mut builder = Publisher::new();
+        builder.topic_priority("alarm", Priority::High);
+        builder.topic_priority("debug", Priority::Low);
+        builder.detect_priority_inversion(3, 2, reporter, "inversion");
+        let console = builder.add_subscriber(&["alarm", "debug"]);
+        let publisher = builder.build();
+
+        for n in 0 .. 5 { publisher.publish("debug", n); }
+        publisher.publish("alarm", 100);
+        assert_eq!(events.pending(), 0);
+        publisher.publish("alarm", 101);
+        assert_eq!(events.fetch(), vec![("inversion", PriorityInversion {
+            subscriber: "subscriber #0".to_owned(),
+            delayed: 2,
+            queued: 6,
+            lower_priority: 5,
+        })]);
+        publisher.publish("alarm", 102);
+        assert_eq!(events.pending(), 0);
+
+        console.fetch();
+        publisher.publish("alarm", 103);
+        for n in 0 .. 5 { publisher.publish("debug", n); }
+        publisher.publish("alarm", 104);
+        publisher.publish("alarm", 105);
+        assert_eq!(events.fetch().len(), 1);
+    }
+}
@@ -0,0 +1,690 @@
+//! Keeping a durable record of a network's traffic.
+//!
+//! A `Journal` is an append-only file of messages, each stored with a
+//! sequence number and the time it was appended. Attach one to a subscriber
+//! with `record()` to capture everything it receives, then read the history
+//! back with `Journal::query()`, without involving a live network at all.
+//!
+//! Consumers that need to survive restarts can read a journal through
+//! `Journal::resume_durable()`, which remembers how far they got.
+//!
+//! To debug a recorded session, `Journal::cursor()` steps through it one
+//! message at a time, in either direction, republishing each message to a
+//! network of your choosing.
+//!
+//! Journals of state-style topics, where only the latest value of each key
+//! matters, can be shrunk with `Journal::compact()`.
+//!
+//! Messages are stored in whatever format the journal's `Codec` produces. A
+//! journal must always be reopened with a codec that understands what it was
+//! written with.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::Subscriber;
+use super::codec::Codec;
+
+mod cursor;
+
+pub use self::cursor::SessionCursor;
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use super::super::Publisher;
+    use super::super::codec::CodecError;
+    use std::env;
+
+    /// Stores a string topic followed by a `u32`.
+    pub(crate) struct Plain;
+
+    impl Codec<String, u32> for Plain {
+        fn encode(&self, topic: &String, content: &u32) -> Result<Vec<u8>, CodecError> {
+            let mut bytes = content.to_be_bytes().to_vec();
+            bytes.extend_from_slice(topic.as_bytes());
+            Ok(bytes)
+        }
+
+        fn decode(&self, bytes: &[u8]) -> Result<(String, u32), CodecError> {
+            if bytes.len() < 4 { return Err(CodecError::new("too short")); }
+            let mut word = [0; 4];
+            word.copy_from_slice(&bytes[.. 4]);
+            let topic = String::from_utf8(bytes[4 ..].to_vec()).map_err(CodecError::new)?;
+            Ok((topic, u32::from_be_bytes(word)))
+        }
+    }
+
+    pub(crate) fn journal_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("alewife-{}-{}.journal", name, ::std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn query_by_topic_and_time() {
+        let path = journal_path("query");
+        let journal = Journal::open(&path, Plain).unwrap();
+
+        journal.append(&"a".to_owned(), &1).unwrap();
+        journal.append(&"b".to_owned(), &2).unwrap();
+        let middle = SystemTime::now();
+        thread::sleep(Duration::from_millis(5));
+        journal.append(&"a".to_owned(), &3).unwrap();
+
+        let a: Vec<u32> = journal.query(|t: &String| t == "a", ..).unwrap()
+            .map(|entry| entry.unwrap().content)
+            .collect();
+        assert_eq!(a, vec![1, 3]);
+
+        let recent: Vec<u64> = journal.query(|_: &String| true, middle ..).unwrap()
+            .map(|entry| entry.unwrap().seq)
+            .collect();
+        assert_eq!(recent, vec![2]);
+
+        // Reopening picks up where the file left off.
+        drop(journal);
+        let journal = Journal::open(&path, Plain).unwrap();
+        assert_eq!(journal.append(&"c".to_owned(), &4).unwrap(), 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compaction_keeps_latest_per_key() {
+        let path = journal_path("compact");
+        let journal = Journal::open(&path, Plain).unwrap();
+
+        for &(topic, value) in &[("state/a", 1), ("log", 2), ("state/b", 3), ("state/a", 4),
+                                 ("log", 5)] {
+            journal.append(&topic.to_owned(), &value).unwrap();
+        }
+
+        let removed = journal.compact(|topic, _| {
+            if topic.starts_with("state/") { Some(topic.clone()) } else { None }
+        }).unwrap();
+        assert_eq!(removed, 1);
+
+        let seq = journal.append(&"state/b".to_owned(), &6).unwrap();
+        assert_eq!(seq, 5);
+
+        let left: Vec<(u64, u32)> = journal.query(|_: &String| true, ..).unwrap()
+            .map(|entry| entry.map(|e| (e.seq, e.content)).unwrap())
+            .collect();
+        assert_eq!(left, vec![(1, 2), (2, 3), (3, 4), (4, 5), (5, 6)]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn durable_subscription_resumes() {
+        let path = journal_path("durable");
+        let journal = Journal::open(&path, Plain).unwrap();
+        for i in 0 .. 3 { journal.append(&"x".to_owned(), &i).unwrap(); }
+
+        {
+            let mut durable = journal.resume_durable("analytics").unwrap();
+            assert_eq!(durable.next_entry().unwrap().unwrap().content, 0);
+            durable.commit().unwrap();
+            assert_eq!(durable.next_entry().unwrap().unwrap().content, 1);
+            // Not committed, so it is delivered again below.
+        }
+
+        journal.append(&"x".to_owned(), &3).unwrap();
+        let mut durable = journal.resume_durable("analytics").unwrap();
+        let mut seen = vec![];
+        while let Some(entry) = durable.next_entry().unwrap() { seen.push(entry.content); }
+        assert_eq!(seen, vec![1, 2, 3]);
+
+        journal.append(&"x".to_owned(), &4).unwrap();
+        assert_eq!(durable.next_entry().unwrap().unwrap().content, 4);
+        durable.commit().unwrap();
+        assert_eq!(durable.committed(), 5);
+
+        let mut offsets = path.clone().into_os_string();
+        offsets.push(".analytics.offset");
+        fs::remove_file(&offsets).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recorder_captures_traffic() {
+        let path = journal_path("record");
+        let journal = Arc::new(Journal::open(&path, Plain).unwrap());
+
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["x".to_owned()]);
+        let publisher = builder.build();
+        let recorder = record(journal.clone(), subscriber);
+
+        for i in 0 .. 3 { publisher.publish("x".to_owned(), i); }
+        thread::sleep(Duration::from_millis(50));
+        recorder.stop().unwrap();
+
+        assert_eq!(journal.query(|_: &String| true, ..).unwrap().count(), 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn damaged_lengths_are_bounded() {
+        let entries = |len: u32| {
+            let mut file = MAGIC.to_vec();
+            file.extend_from_slice(&len.to_be_bytes());
+            file.extend_from_slice(&[0; ENTRY_HEADER]);
+            RawEntries::new(io::Cursor::new(file)).unwrap()
+        };
+
+        let huge = entries(u32::MAX).next_raw();
+        assert_eq!(huge.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        assert!(entries(MAX_ENTRY as u32).next_raw().unwrap().is_none());
+    }
+}
+
+/// Identifies a journal file, and the version of its layout.
+const MAGIC: &[u8; 8] = b"alewifej";
+
+/// Size of the sequence number and timestamp stored before each message.
+const ENTRY_HEADER: usize = 16;
+
+/// The longest entry a journal will write or read, header included. A longer
+/// length prefix can only come from a damaged file.
+const MAX_ENTRY: usize = 1 << 30;
+
+/// One message read back from a journal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry<Topic, Content> {
+    /// Position in the journal, counting from zero.
+    pub seq: u64,
+    /// When the message was appended.
+    pub time: SystemTime,
+    /// The message's topic.
+    pub topic: Topic,
+    /// The message's content.
+    pub content: Content,
+}
+
+/// An append-only file of messages.
+pub struct Journal<Topic, Content> {
+    path: PathBuf,
+    codec: Box<dyn Codec<Topic, Content>>,
+    writer: Mutex<Writer>,
+    /// Bumped whenever compaction replaces the file, so readers following
+    /// the journal know to reopen it.
+    generation: AtomicU64,
+}
+
+struct Writer {
+    file: File,
+    next_seq: u64,
+}
+
+impl<Topic, Content> Journal<Topic, Content> {
+    /// Opens the journal at `path`, creating it if necessary. If the last
+    /// entry was only partly written, for instance because the process
+    /// crashed, it is discarded.
+    pub fn open<P, K>(path: P, codec: K) -> io::Result<Self>
+        where P: AsRef<Path>,
+              K: Codec<Topic, Content> + 'static,
+    {
+        let path = path.as_ref().to_owned();
+        let mut file = OpenOptions::new()
+            .read(true).write(true).create(true).truncate(false)
+            .open(&path)?;
+
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+        }
+
+        // The clone shares the file's cursor, which the seek below puts back.
+        file.seek(SeekFrom::Start(0))?;
+        let mut reader = RawEntries::new(BufReader::new(file.try_clone()?))?;
+        let mut next_seq = 0;
+        while let Some((seq, _, _)) = reader.next_raw()? {
+            next_seq = seq + 1;
+        }
+
+        file.set_len(reader.offset)?;
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(Journal {
+            path,
+            codec: Box::new(codec),
+            writer: Mutex::new(Writer { file, next_seq }),
+            generation: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns the path the journal was opened from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a message, returning its sequence number. Messages that fail
+    /// to encode are reported as `InvalidData` errors, and messages that
+    /// encode to a gigabyte or more as `InvalidInput`.
+    pub fn append(&self, topic: &Topic, content: &Content) -> io::Result<u64> {
+        let payload = self.codec.encode(topic, content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if ENTRY_HEADER + payload.len() > MAX_ENTRY {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "message too large for a journal"));
+        }
+
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let seq = writer.next_seq;
+        write_entry(&mut writer.file, seq, SystemTime::now(), &payload)?;
+        writer.next_seq += 1;
+        Ok(seq)
+    }
+
+    /// Rewrites the journal keeping only the latest entry for each key, and
+    /// returns how many entries were removed. `key` picks each message's key;
+    /// messages it returns `None` for are always kept, so a journal can mix
+    /// state-style topics with ordinary ones. Surviving entries keep their
+    /// sequence numbers and times.
+    ///
+    /// Appends wait while compaction runs. Queries already in progress carry
+    /// on reading the old file. Entries that can't be decoded are kept.
+    pub fn compact<K, F>(&self, mut key: F) -> io::Result<usize>
+        where K: Hash + Eq,
+              F: FnMut(&Topic, &Content) -> Option<K>,
+    {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut latest = HashMap::new();
+        let mut keyed = Vec::new();
+        let mut entries = RawEntries::new(BufReader::new(File::open(&self.path)?))?;
+        while let Some((seq, _, payload)) = entries.next_raw()? {
+            if let Ok((topic, content)) = self.codec.decode(&payload) {
+                if let Some(k) = key(&topic, &content) {
+                    keyed.push(seq);
+                    latest.insert(k, seq);
+                }
+            }
+        }
+
+        let keep: HashSet<u64> = latest.values().cloned().collect();
+        let superseded: HashSet<u64> = keyed.into_iter().filter(|seq| !keep.contains(seq)).collect();
+        if superseded.is_empty() { return Ok(0); }
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".compacting");
+        let temp = PathBuf::from(temp);
+
+        {
+            let mut out = BufWriter::new(File::create(&temp)?);
+            out.write_all(MAGIC)?;
+            let mut entries = RawEntries::new(BufReader::new(File::open(&self.path)?))?;
+            while let Some((seq, time, payload)) = entries.next_raw()? {
+                if !superseded.contains(&seq) {
+                    write_entry(&mut out, seq, time, &payload)?;
+                }
+            }
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+
+        fs::rename(&temp, &self.path)?;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        let mut file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        file.seek(SeekFrom::End(0))?;
+        writer.file = file;
+        Ok(superseded.len())
+    }
+
+    /// Waits for everything appended so far to reach the disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.writer.lock().unwrap_or_else(|e| e.into_inner()).file.sync_data()
+    }
+
+    /// Resumes the durable subscription called `name`, or starts a new one at
+    /// the beginning of the journal. It delivers every entry after the last
+    /// one it committed, including entries appended later, so a consumer that
+    /// restarts picks up exactly where it left off.
+    ///
+    /// The committed position is kept in a file next to the journal, named
+    /// after the subscription, so `name` must be usable in a file name.
+    pub fn resume_durable(&self, name: &str) -> io::Result<Durable<'_, Topic, Content>> {
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "bad subscription name"));
+        }
+
+        let mut offsets = self.path.clone().into_os_string();
+        offsets.push(format!(".{}.offset", name));
+        let offsets = PathBuf::from(offsets);
+
+        let committed = match fs::read(&offsets) {
+            Ok(bytes) => {
+                let mut word = [0; 8];
+                if bytes.len() != 8 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt offset file"));
+                }
+                word.copy_from_slice(&bytes);
+                u64::from_be_bytes(word)
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+
+        let generation = self.generation.load(Ordering::Acquire);
+        Ok(Durable {
+            journal: self,
+            offsets,
+            entries: RawEntries::new(BufReader::new(File::open(&self.path)?))?,
+            generation,
+            next: committed,
+            committed,
+        })
+    }
+
+    /// Iterates over the entries whose topic passes `topics` and whose time
+    /// falls in `times`, oldest first. Entries are decoded as they are
+    /// reached, and ones excluded by time are never decoded at all. Entries
+    /// appended while the iterator is in use may or may not be included.
+    pub fn query<F, R>(&self, topics: F, times: R) -> io::Result<Query<'_, Topic, Content, F>>
+        where F: FnMut(&Topic) -> bool,
+              R: RangeBounds<SystemTime>,
+    {
+        let file = File::open(&self.path)?;
+        Ok(Query {
+            entries: RawEntries::new(BufReader::new(file))?,
+            codec: &*self.codec,
+            topics,
+            start: times.start_bound().cloned(),
+            end: times.end_bound().cloned(),
+            marker: PhantomData,
+        })
+    }
+}
+
+/// Iterator returned by `Journal::query()`.
+pub struct Query<'a, Topic, Content, F> {
+    entries: RawEntries<BufReader<File>>,
+    codec: &'a dyn Codec<Topic, Content>,
+    topics: F,
+    start: Bound<SystemTime>,
+    end: Bound<SystemTime>,
+    marker: PhantomData<fn() -> (Topic, Content)>,
+}
+
+impl<'a, Topic, Content, F> Query<'a, Topic, Content, F> {
+    fn in_range(&self, time: SystemTime) -> bool {
+        (match self.start {
+            Bound::Included(start) => time >= start,
+            Bound::Excluded(start) => time > start,
+            Bound::Unbounded => true,
+        }) && (match self.end {
+            Bound::Included(end) => time <= end,
+            Bound::Excluded(end) => time < end,
+            Bound::Unbounded => true,
+        })
+    }
+}
+
+impl<'a, Topic, Content, F> Iterator for Query<'a, Topic, Content, F>
+    where F: FnMut(&Topic) -> bool,
+{
+    type Item = io::Result<Entry<Topic, Content>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (seq, time, payload) = match self.entries.next_raw() {
+                Ok(Some(raw)) => raw,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if !self.in_range(time) { continue; }
+
+            let (topic, content) = match self.codec.decode(&payload) {
+                Ok(message) => message,
+                Err(e) => return Some(Err(io::Error::new(io::ErrorKind::InvalidData, e))),
+            };
+
+            if (self.topics)(&topic) {
+                return Some(Ok(Entry { seq, time, topic, content }));
+            }
+        }
+    }
+}
+
+/// A subscription to a journal that survives restarts. Created by
+/// `Journal::resume_durable()`.
+///
+/// Entries count as consumed only once `commit()` is called, so entries
+/// handled just before a crash are delivered again after it.
+pub struct Durable<'a, Topic, Content> {
+    journal: &'a Journal<Topic, Content>,
+    offsets: PathBuf,
+    entries: RawEntries<BufReader<File>>,
+    generation: u64,
+    /// Sequence number of the first entry not yet returned.
+    next: u64,
+    committed: u64,
+}
+
+impl<'a, Topic, Content> Durable<'a, Topic, Content> {
+    /// Returns the next entry, or `None` if the subscription has caught up
+    /// with the journal. Calling it again later returns whatever has been
+    /// appended since. An entry that fails to decode is reported as an
+    /// `InvalidData` error and skipped.
+    pub fn next_entry(&mut self) -> io::Result<Option<Entry<Topic, Content>>> {
+        loop {
+            let (seq, time, payload) = match self.entries.next_raw()? {
+                Some(raw) => raw,
+                None => {
+                    if !self.reopen_if_compacted()? {
+                        // Leave the cursor at the start of any entry that is
+                        // still being written.
+                        let offset = self.entries.offset;
+                        self.entries.reader.seek(SeekFrom::Start(offset))?;
+                        return Ok(None);
+                    }
+                    continue;
+                },
+            };
+
+            if seq < self.next { continue; }
+            self.next = seq + 1;
+
+            let (topic, content) = self.journal.codec.decode(&payload)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            return Ok(Some(Entry { seq, time, topic, content }));
+        }
+    }
+
+    /// Records that every entry returned so far has been consumed.
+    pub fn commit(&mut self) -> io::Result<()> {
+        if self.next == self.committed { return Ok(()); }
+
+        let mut temp = self.offsets.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+
+        let mut file = File::create(&temp)?;
+        file.write_all(&self.next.to_be_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.offsets)?;
+
+        self.committed = self.next;
+        Ok(())
+    }
+
+    /// Sequence number of the first entry that hasn't been committed.
+    pub fn committed(&self) -> u64 {
+        self.committed
+    }
+
+    /// Switches to the journal's new file if it was compacted since this
+    /// subscription opened it. Entries already returned are skipped by
+    /// sequence number.
+    fn reopen_if_compacted(&mut self) -> io::Result<bool> {
+        let generation = self.journal.generation.load(Ordering::Acquire);
+        if generation == self.generation { return Ok(false); }
+
+        self.entries = RawEntries::new(BufReader::new(File::open(&self.journal.path)?))?;
+        self.generation = generation;
+        Ok(true)
+    }
+}
+
+/// Reads entries without decoding them.
+struct RawEntries<R> {
+    reader: R,
+    /// Offset just past the last complete entry read.
+    offset: u64,
+    #[cfg(feature = "debug-invariants")]
+    last_seq: Option<u64>,
+}
+
+impl<R: Read> RawEntries<R> {
+    fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a journal"));
+        }
+        Ok(RawEntries {
+            reader,
+            offset: MAGIC.len() as u64,
+            #[cfg(feature = "debug-invariants")]
+            last_seq: None,
+        })
+    }
+
+    /// Returns the next entry, or `None` at the end of the file. An entry cut
+    /// short by the end of the file counts as the end.
+    fn next_raw(&mut self) -> io::Result<Option<(u64, SystemTime, Vec<u8>)>> {
+        let mut prefix = [0; 4];
+        if !read_or_eof(&mut self.reader, &mut prefix)? { return Ok(None); }
+
+        let len = u32::from_be_bytes(prefix) as usize;
+        if !(ENTRY_HEADER ..= MAX_ENTRY).contains(&len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt journal entry"));
+        }
+
+        // Grown as the bytes arrive, so a bad length can't allocate more
+        // than the file holds.
+        let mut entry = Vec::new();
+        (&mut self.reader).take(len as u64).read_to_end(&mut entry)?;
+        if entry.len() < len { return Ok(None); }
+        self.offset += 4 + len as u64;
+
+        let mut word = [0; 8];
+        word.copy_from_slice(&entry[0 .. 8]);
+        let seq = u64::from_be_bytes(word);
+        #[cfg(feature = "debug-invariants")]
+        {
+            if let Some(last) = self.last_seq.filter(|&last| seq <= last) {
+                panic!("journal entry {} follows entry {} at offset {}", seq, last, self.offset);
+            }
+            self.last_seq = Some(seq);
+        }
+        word.copy_from_slice(&entry[8 .. 16]);
+        let time = UNIX_EPOCH + Duration::from_micros(u64::from_be_bytes(word));
+
+        entry.drain(.. ENTRY_HEADER);
+        Ok(Some((seq, time, entry)))
+    }
+}
+
+impl<R: Seek> RawEntries<R> {
+    /// Moves to the entry starting at `offset`, as found by an earlier read.
+    fn seek(&mut self, offset: u64) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.offset = offset;
+        #[cfg(feature = "debug-invariants")]
+        { self.last_seq = None; }
+        Ok(())
+    }
+}
+
+/// Writes one entry with a single call, so readers rarely see part of one.
+fn write_entry<W: Write>(out: &mut W, seq: u64, time: SystemTime, payload: &[u8])
+    -> io::Result<()>
+{
+    let mut entry = Vec::with_capacity(4 + ENTRY_HEADER + payload.len());
+    entry.extend_from_slice(&((ENTRY_HEADER + payload.len()) as u32).to_be_bytes());
+    entry.extend_from_slice(&seq.to_be_bytes());
+    entry.extend_from_slice(&micros_since_epoch(time).to_be_bytes());
+    entry.extend_from_slice(payload);
+    out.write_all(&entry)
+}
+
+/// Fills `buffer`, returning false if the input ended first.
+fn read_or_eof<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn micros_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or(0)
+}
+
+/// How long the recorder may block before checking whether it was stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Handle to a running recorder. Dropping it stops the background thread.
+pub struct Recorder {
+    stopped: Arc<AtomicBool>,
+    worker: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Recorder {
+    /// Stops recording, waits for the thread to finish, and reports the error
+    /// that ended it early, if any.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.stopped.store(true, Ordering::Release);
+        match self.worker.take().map(|w| w.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("recorder thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Appends everything `source` receives to `journal`, on a background thread.
+/// Messages that fail to encode are skipped; any other error stops the
+/// recorder.
+pub fn record<Topic, Content>(journal: Arc<Journal<Topic, Content>>,
+                              source: Subscriber<Topic, Content>) -> Recorder
+    where Topic: Send + 'static,
+          Content: Send + 'static,
+{
+    let stopped = Arc::new(AtomicBool::new(false));
+    let flag = stopped.clone();
+
+    let worker = thread::spawn(move || {
+        while !flag.load(Ordering::Acquire) {
+            if let Some((topic, content)) = source.next_timeout(POLL_INTERVAL) {
+                match journal.append(&topic, &content) {
+                    Err(ref e) if e.kind() == io::ErrorKind::InvalidData => (),
+                    result => { result?; },
+                }
+            }
+        }
+        Ok(())
+    });
+
+    Recorder {
+        stopped,
+        worker: Some(worker),
+    }
+}
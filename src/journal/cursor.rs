@@ -0,0 +1,169 @@
+//! Stepping back and forth through a recorded session.
+
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufReader};
+use std::time::SystemTime;
+
+use super::super::Publisher;
+use super::{Entry, Journal, RawEntries};
+
+/// A movable position in a journal, for replaying a recorded session one
+/// message at a time. Each step publishes the message it lands on to a
+/// network of your choosing, typically a fresh one with inspection tools
+/// subscribed, and also returns it.
+///
+/// The cursor starts before the first entry. Stepping forward lands on the
+/// next entry; stepping backward lands on the previous one and publishes it
+/// again, since messages can't be taken back. The cursor indexes the journal
+/// when it is created, and doesn't see entries appended afterwards.
+pub struct SessionCursor<'a, Topic: Hash + Eq + Clone, Content: Clone> {
+    journal: &'a Journal<Topic, Content>,
+    entries: RawEntries<BufReader<File>>,
+    index: Vec<Mark>,
+    /// Index of the entry the cursor is on, if it has moved onto one yet.
+    current: Option<usize>,
+    output: Publisher<Topic, Content>,
+}
+
+/// Where to find one entry.
+struct Mark {
+    seq: u64,
+    time: SystemTime,
+    offset: u64,
+}
+
+impl<Topic, Content> Journal<Topic, Content>
+    where Topic: Hash + Eq + Clone,
+          Content: Clone,
+{
+    /// Creates a cursor over the journal that publishes to `output`.
+    pub fn cursor(&self, output: Publisher<Topic, Content>)
+        -> io::Result<SessionCursor<'_, Topic, Content>>
+    {
+        let mut entries = RawEntries::new(BufReader::new(File::open(&self.path)?))?;
+        let mut index = Vec::new();
+        loop {
+            let offset = entries.offset;
+            match entries.next_raw()? {
+                Some((seq, time, _)) => index.push(Mark { seq, time, offset }),
+                None => break,
+            }
+        }
+
+        Ok(SessionCursor {
+            journal: self,
+            entries,
+            index,
+            current: None,
+            output,
+        })
+    }
+}
+
+impl<'a, Topic, Content> SessionCursor<'a, Topic, Content>
+    where Topic: Hash + Eq + Clone,
+          Content: Clone,
+{
+    /// Number of entries in the session.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns true if the session has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Sequence number of the entry the cursor is on, if any.
+    pub fn seq(&self) -> Option<u64> {
+        self.current.map(|i| self.index[i].seq)
+    }
+
+    /// Moves to just before the first entry with a sequence number of at
+    /// least `seq`, so the next step forward lands on it. Nothing is
+    /// published.
+    pub fn seek_seq(&mut self, seq: u64) {
+        let next = self.index.partition_point(|mark| mark.seq < seq);
+        self.current = next.checked_sub(1);
+    }
+
+    /// Moves to just before the first entry recorded at or after `time`.
+    /// Nothing is published. If the clock went backwards while recording,
+    /// this finds the first such entry in journal order.
+    pub fn seek_time(&mut self, time: SystemTime) {
+        let next = self.index.iter().position(|mark| mark.time >= time)
+            .unwrap_or(self.index.len());
+        self.current = next.checked_sub(1);
+    }
+
+    /// Moves back to before the first entry.
+    pub fn rewind(&mut self) {
+        self.current = None;
+    }
+
+    /// Moves onto the next entry and publishes it. Returns `None`, without
+    /// moving, at the end of the session.
+    pub fn step_forward(&mut self) -> io::Result<Option<Entry<Topic, Content>>> {
+        let next = self.current.map_or(0, |i| i + 1);
+        if next >= self.index.len() { return Ok(None); }
+        self.visit(next).map(Some)
+    }
+
+    /// Moves onto the previous entry and publishes it. Returns `None`, without
+    /// moving, when there is no previous entry.
+    pub fn step_backward(&mut self) -> io::Result<Option<Entry<Topic, Content>>> {
+        match self.current {
+            Some(i) if i > 0 => self.visit(i - 1).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn visit(&mut self, i: usize) -> io::Result<Entry<Topic, Content>> {
+        let offset = self.index[i].offset;
+        self.entries.seek(offset)?;
+
+        let (seq, time, payload) = self.entries.next_raw()?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        let (topic, content) = self.journal.codec.decode(&payload)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        self.current = Some(i);
+        self.output.publish(topic.clone(), content.clone());
+        Ok(Entry { seq, time, topic, content })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::test::{Plain, journal_path};
+    use std::fs;
+
+    #[test]
+    fn step_both_ways() {
+        let path = journal_path("cursor");
+        let journal = Journal::open(&path, Plain).unwrap();
+        for i in 0 .. 5 { journal.append(&"tick".to_owned(), &i).unwrap(); }
+
+        let mut builder = Publisher::new();
+        let inspector = builder.add_subscriber(&["tick".to_owned()]);
+        let mut cursor = journal.cursor(builder.build()).unwrap();
+        assert_eq!(cursor.len(), 5);
+
+        let content = |entry: io::Result<Option<Entry<String, u32>>>| entry.unwrap().map(|e| e.content);
+        assert_eq!(content(cursor.step_backward()), None);
+        assert_eq!(content(cursor.step_forward()), Some(0));
+
+        cursor.seek_seq(3);
+        assert_eq!(content(cursor.step_forward()), Some(3));
+        assert_eq!(content(cursor.step_forward()), Some(4));
+        assert_eq!(content(cursor.step_forward()), None);
+        assert_eq!(content(cursor.step_backward()), Some(3));
+        assert_eq!(cursor.seq(), Some(3));
+
+        let published: Vec<u32> = inspector.fetch().into_iter().map(|(_, c)| c).collect();
+        assert_eq!(published, vec![0, 3, 4, 3]);
+        fs::remove_file(&path).unwrap();
+    }
+}
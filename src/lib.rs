@@ -4,109 +4,2516 @@
 //! registered as subscribers during initial network setup, providing a list of
 //! desired Topics. Subscribers will only receive messages containing the Topic
 //! values they specify. After setup, new publishers can continue to be added to
-//! the network, and can publish messages with any Topic.
+//! the network, and can publish messages with any Topic. Short-lived
+//! subscribers can also be added after setup with
+//! `Publisher::subscribe_scoped()`, and are removed when their guard is
+//! dropped.
+//!
+//! A `Publisher` is `Send` and `Sync`, so a single handle can be shared
+//! between threads, inside an `Arc` for instance. A `Subscriber` is `Send`,
+//! so it can be handed to the thread that reads it, but not `Sync`.
 //!
 //! The following features are not presently supported:
 //!
-//!  - Adding publishers during initial network setup
-//!  - Adding new subscribers after initial network setup
-//!  - Removing subscribers from the network at any time
+//!  - Removing subscribers created during initial network setup
 //!  - Detecting or handling the disappearance of parts of the network
 
 #![warn(missing_docs)]
 
-use std::hash::Hash;
-use std::sync::mpsc::{self, Sender, Receiver};
-use std::collections::HashMap;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
+#[cfg(feature = "bincode")]
+extern crate bincode;
+#[cfg(feature = "rmp-serde")]
+extern crate rmp_serde;
+#[cfg(feature = "rkyv")]
+extern crate rkyv;
+#[cfg(feature = "memmap2")]
+extern crate memmap2;
+#[cfg(feature = "bevy_ecs")]
+extern crate bevy_ecs;
+#[cfg(feature = "alewife-macros")]
+extern crate alewife_macros;
+#[cfg(feature = "futures-core")]
+extern crate futures_core;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "libc")]
+extern crate libc;
+// Lets the macros' `::alewife` paths resolve in this crate's own tests.
+#[cfg(all(test, feature = "macros"))]
+extern crate self as alewife;
+#[cfg(loom)]
+extern crate loom;
+
+use std::borrow::Cow;
+use std::hash::Hash;
+use std::iter;
+use std::mem;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::time::{Duration, Instant};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+pub mod actor;
+pub mod adapters;
+pub mod aggregate;
+#[cfg(feature = "bevy")]
+pub mod bevy;
+pub mod blackboard;
+pub mod bridge;
+pub mod codec;
+pub mod compat;
+pub mod config;
+pub mod dispatch;
+pub mod envelope;
+pub mod errors;
+#[cfg(feature = "event-loop")]
+pub mod event_loop;
+pub mod ffi;
+#[cfg(feature = "fs-watch")]
+pub mod fs_watch;
+pub mod heartbeat;
+pub mod journal;
+pub mod logging;
+#[cfg(feature = "os-events")]
+pub mod os_events;
+pub mod panics;
+pub mod params;
+pub mod plugin;
+pub mod projection;
+pub mod raw;
+#[cfg(feature = "stress")]
+pub mod stress;
+pub mod supervisor;
+pub mod testkit;
+pub mod watchdog;
+
+mod async_subscriber;
+mod broadcast;
+mod budget;
+mod catch_all;
+mod channels;
+mod classify;
+mod clock;
+mod combine;
+mod compact;
+mod competing;
+mod cooperative;
+mod crash;
+mod dedup;
+mod demux;
+mod drops;
+mod echo;
+mod error;
+mod extension;
+mod fan_out;
+mod freeze;
+mod handlers;
+mod handoff;
+mod hasher;
+mod health;
+mod inspect;
+mod intern;
+mod inversion;
+#[cfg(feature = "debug-invariants")]
+mod invariants;
+mod lifecycle;
+mod limits;
+mod local;
+mod once;
+mod options;
+mod par_drain;
+mod pipeline;
+mod pool;
+mod publish;
+mod quota;
+mod readiness;
+mod receipts;
+mod recent;
+mod redact;
+mod registry;
+mod replay;
+mod ring;
+mod routing;
+mod sequence;
+mod shard;
+mod shutdown;
+mod simulation;
+#[cfg(feature = "futures")]
+mod sink;
+mod snapshot;
+mod staging;
+mod static_router;
+mod stats;
+mod strategies;
+mod strictness;
+mod subscribe;
+mod sync;
+mod tap;
+mod tenant;
+mod threads;
+mod ticks;
+mod timers;
+mod token;
+mod topology;
+mod upgrade;
+mod wake;
+mod watch_count;
+mod worker;
+
+pub use async_subscriber::{AsyncSubscriber, Next, NextUntil};
+pub use broadcast::{Broadcast, BroadcastReceiver, Lagged};
+pub use budget::{ContentSize, Priority};
+pub use classify::RouteDecision;
+pub use combine::CombineLatest;
+pub use competing::SharedSubscriber;
+pub use cooperative::Budgeted;
+pub use demux::{Demux, TopicReceiver};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use drops::DropReason;
+pub use echo::NoEcho;
+pub use error::{NotReady, PublishError, ShutdownTimedOut, SubscribeError};
+pub use extension::BusExtension;
+pub use freeze::FreezePolicy;
+pub use handlers::{Event, Handlers};
+pub use handoff::Handoff;
+#[cfg(feature = "macros")]
+pub use alewife_macros::{handler, EnumTopic};
+pub use hasher::TopicHasher;
+pub use health::{Health, LinkHealth};
+pub use inspect::Inspection;
+pub use intern::{Interner, TopicId};
+pub use inversion::PriorityInversion;
+pub use lifecycle::{CloneStrategy, TopicDocs, TopicOptions, TopicOrder};
+pub use once::EffectivelyOnce;
+pub use options::{InboxOrder, SubscriptionOptions, Sampling};
+pub use pipeline::PipelineStage;
+pub use pool::{BufferMut, PooledBuffer};
+pub use publish::Publish;
+pub use quota::OverQuota;
+pub use receipts::Receipt;
+pub use redact::Redacted;
+pub use registry::PublisherEvent;
+pub use ring::{Ring, RingWords};
+pub use sequence::{Sequence, SequenceWatch};
+pub use shard::Sharded;
+pub use simulation::Simulation;
+#[cfg(feature = "futures")]
+pub use sink::{Forward, PublisherSink};
+pub use snapshot::{Scheduled, Snapshot};
+pub use staging::PrePublisher;
+pub use static_router::{EnumTopic, StaticBuilder, StaticRouter};
+pub use stats::TopicStats;
+pub use strategies::{DeliveryStrategy, Recommendation};
+pub use strictness::Strictness;
+pub use subscribe::{Messages, Subscribe};
+pub use tap::TapOptions;
+pub use tenant::{Tenant, TenantTopic};
+pub use ticks::{TickGuard, TickRate};
+pub use timers::TimerId;
+pub use token::PublishToken;
+pub use topology::Format;
+#[cfg(unix)]
+pub use wake::WakeFd;
+pub use watch_count::{Changed, CountWatcher};
+use budget::MemoryBudget;
+use classify::ContentRoutes;
+use compact::Merge;
+use config::ConfigState;
+use dedup::Dedup;
+use fan_out::FanOut;
+use freeze::Freeze;
+use drops::DropHook;
+use health::Liveness;
+#[cfg(feature = "debug-invariants")]
+use invariants::Invariants;
+use inversion::Inversions;
+use lifecycle::{TopicSet, TopicSettings};
+use limits::{Enforced, SizeLimit};
+use local::LocalQueue;
+use options::{Admission, Debouncer};
+use pool::BufferPool;
+use quota::Quota;
+use readiness::Readiness;
+use receipts::SendReceipt;
+use recent::Recent;
+use registry::Registry;
+use replay::{Rejoins, Seen};
+use routing::{RoutingTable, SingleSlot};
+use shutdown::{Member, Shutdown};
+use simulation::Scheduler;
+use staging::Staged;
+use stats::StatsTracker;
+use timers::Timers;
+use token::Resolved;
+use upgrade::Upgrades;
+use watch_count::RouteChanges;
+use sync::{AtomicUsize, Mutex, Ordering, RwLock};
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn simple_network() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["widgets"]);
+        let publisher = builder.build();
+
+        publisher.publish("widgets", "sprocket");
+
+        for (topic, content) in subscriber.fetch() {
+            println!("{}: {}", topic, content);
+        }
+    }
+
+    #[test]
+    fn overlapping_topics_deliver_once() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["widgets", "widgets"]);
+        let publisher = builder.build();
+
+        publisher.publish("widgets", "sprocket");
+
+        assert_eq!(subscriber.fetch(), vec![("widgets", "sprocket")]);
+    }
+
+    #[test]
+    fn fetch_into_reuses_buffer() {
+        use super::*;
+        use std::collections::VecDeque;
+
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&[0, 1]);
+        let publisher = builder.build();
+
+        let mut buffer = Vec::with_capacity(4);
+        publisher.publish(0, 'a');
+        publisher.publish(1, 'b');
+        assert_eq!(subscriber.fetch_into(&mut buffer), 2);
+        assert_eq!(subscriber.fetch_into(&mut buffer), 0);
+        assert_eq!(buffer, vec![(0, 'a'), (1, 'b')]);
+
+        let mut queue = VecDeque::new();
+        publisher.publish(1, 'c');
+        assert_eq!(subscriber.fetch_extend(&mut queue), 1);
+        assert_eq!(queue.pop_front(), Some((1, 'c')));
+    }
+
+    #[test]
+    fn drain_stops_on_break() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["n"]);
+        let publisher = builder.build();
+
+        for i in 0 .. 5 { publisher.publish("n", i); }
+
+        let mut seen = vec![];
+        let handled = subscriber.drain(|_, i| {
+            seen.push(i);
+            if i == 2 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        });
+
+        assert_eq!(handled, 3);
+        assert_eq!(seen, vec![0, 1, 2]);
+        assert_eq!(subscriber.drain(|_, _| ControlFlow::Continue(())), 2);
+    }
+
+    #[test]
+    fn process_for_reports_remaining() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["n"]);
+        let publisher = builder.build();
+
+        for i in 0 .. 3 { publisher.publish("n", i); }
+        assert_eq!(subscriber.pending(), 3);
+
+        let remaining = subscriber.process_for(Duration::from_secs(0), |_, _| {
+            panic!("no budget to spend");
+        });
+        assert_eq!(remaining, 3);
+
+        let mut handled = 0;
+        let remaining = subscriber.process_for(Duration::from_secs(5), |_, _| {
+            handled += 1;
+        });
+        assert_eq!((handled, remaining), (3, 0));
+    }
+
+    #[test]
+    fn borrowed_arc_topics_share_one_allocation() {
+        use super::*;
+
+        let topic: Arc<str> = Arc::from("a/rather/long/topic/name");
+        let mut builder = Publisher::new();
+        let first = builder.add_subscriber(std::slice::from_ref(&topic));
+        let second = builder.add_subscriber(std::slice::from_ref(&topic));
+        let publisher = builder.build();
+
+        publisher.publish_ref(&topic, 1);
+        for subscriber in &[first, second] {
+            let (delivered, content) = subscriber.fetch().pop().unwrap();
+            assert!(Arc::ptr_eq(&delivered, &topic));
+            assert_eq!(content, 1);
+        }
+    }
+
+    #[test]
+    fn shared_keys_are_published_by_reference() {
+        use super::*;
+        use std::path::{Path, PathBuf};
+
+        let mut builder = Publisher::<Arc<Path>, u32>::new();
+        let watched: Arc<Path> = Arc::from(PathBuf::from("/var/log/a/rather/long/path"));
+        let first = builder.add_subscriber(std::slice::from_ref(&watched));
+        let second = builder.add_subscriber(std::slice::from_ref(&watched));
+        let everything = builder.add_broadcast_subscriber();
+        let publisher = builder.build();
+
+        let key = PathBuf::from("/var/log/a/rather/long/path");
+        publisher.publish_borrowed(&key, 1);
+        for subscriber in &[first, second] {
+            let (delivered, content) = subscriber.fetch().pop().unwrap();
+            assert!(Arc::ptr_eq(&delivered, &watched));
+            assert_eq!(content, 1);
+        }
+
+        assert_eq!(everything.fetch().len(), 1);
+        publisher.publish_borrowed(Path::new("/tmp/elsewhere"), 2);
+        let (unknown, _) = everything.fetch().remove(0);
+        assert_eq!(&*unknown, Path::new("/tmp/elsewhere"));
+    }
+
+    #[test]
+    fn topic_stats_are_shared_by_clones() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        builder.track_topic_stats(Duration::from_secs(60), |s: &String| s.len());
+        let publisher = builder.build();
+        let clone = publisher.clone();
+
+        publisher.publish("flood", "aaaa".to_owned());
+        clone.publish("flood", "bb".to_owned());
+
+        let stats = publisher.topic_stats();
+        assert_eq!(stats[&"flood"], TopicStats { messages: 2, bytes: 6, clones: 0 });
+    }
+
+    #[test]
+    fn topic_stats_count_clones_made_for_subscribers() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        builder.track_topic_stats(Duration::from_secs(60), |_: &u32| 4);
+        builder.declare_topic("moved", TopicOptions::new().clone_strategy(CloneStrategy::MoveToLast));
+        let _subscribers: Vec<_> = (0 .. 3).map(|_| builder.add_subscriber(&["copied", "moved"])).collect();
+        let publisher = builder.build();
+
+        publisher.publish("copied", 1);
+        publisher.publish("moved", 2);
+
+        let stats = publisher.topic_stats();
+        assert_eq!(stats[&"copied"].clones, 3);
+        assert_eq!(stats[&"moved"].clones, 2);
+    }
+
+    #[test]
+    fn sampled_subscriber() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let everything = builder.add_subscriber(&["a", "b"]);
+        let options = SubscriptionOptions::new().sample(Sampling::Every(2));
+        let sampled = builder.add_subscriber_with(&["a", "b"], options);
+        let publisher = builder.build();
+
+        for i in 0 .. 3 {
+            publisher.publish("a", i);
+            publisher.publish("b", i);
+        }
+
+        assert_eq!(everything.fetch().len(), 6);
+        assert_eq!(sampled.fetch(), vec![("a", 0), ("a", 1), ("a", 2)]);
+    }
+
+    #[test]
+    fn throttled_and_debounced_subscribers() {
+        use super::*;
+
+        let interval = Duration::from_millis(20);
+        let mut builder = Publisher::new();
+        let throttled = builder.add_subscriber_with(&["pos"],
+            SubscriptionOptions::new().throttle(Duration::from_secs(60)));
+        let debounced = builder.add_subscriber_with(&["pos"],
+            SubscriptionOptions::new().debounce(interval));
+        let publisher = builder.build();
+
+        for i in 0 .. 5 { publisher.publish("pos", i); }
+
+        assert_eq!(throttled.fetch(), vec![("pos", 0)]);
+        assert_eq!(debounced.fetch(), vec![]);
+        assert_eq!(debounced.pending(), 1);
+        assert_eq!(debounced.next_timeout(interval * 10), Some(("pos", 4)));
+        assert_eq!(debounced.pending(), 0);
+    }
+
+    #[test]
+    fn restricted_topics() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        builder.restrict_topic("core/quit", &["core"]);
+        builder.audit_topic("audit");
+        let quit = builder.add_subscriber(&["core/quit"]);
+        let audit = builder.add_subscriber(&["audit"]);
+        let publisher = builder.build();
+
+        let core = publisher.named("core");
+        let plugin = publisher.named("plugin");
+        let nested = plugin.named("core");
+        assert_eq!(nested.name(), Some("plugin/core"));
+
+        assert_eq!(core.try_publish("core/quit", 1), Ok(()));
+        assert_eq!(plugin.try_publish("core/quit", 2), Err(PublishError::Forbidden));
+        nested.publish("core/quit", 3);
+        publisher.publish("core/quit", 4);
+
+        assert_eq!(quit.fetch(), vec![("core/quit", 1)]);
+        assert_eq!(audit.fetch(), vec![("audit", 2), ("audit", 3), ("audit", 4)]);
+    }
+
+    #[test]
+    fn oversized_content() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        builder.max_content_size("upload", 4, |s: &String| s.len());
+        builder.truncate_content("log", 4, |s: &String| s.len(), |mut s, limit| {
+            s.truncate(limit);
+            s
+        });
+        builder.overflow_content("frame", 4, |s: &String| s.len(), "frame/bulk");
+        let upload = builder.add_subscriber(&["upload"]);
+        let log = builder.add_subscriber(&["log"]);
+        let frames = builder.add_subscriber(&["frame", "frame/bulk"]);
+        let publisher = builder.build();
+
+        assert_eq!(publisher.try_publish("upload", "abcd".to_owned()), Ok(()));
+        assert_eq!(publisher.try_publish("upload", "abcde".to_owned()),
+                   Err(PublishError::TooLarge));
+        publisher.publish("log", "verbose".to_owned());
+        publisher.publish("frame", "tiny".to_owned());
+        publisher.publish("frame", "enormous".to_owned());
+
+        assert_eq!(upload.fetch(), vec![("upload", "abcd".to_owned())]);
+        assert_eq!(log.fetch(), vec![("log", "verb".to_owned())]);
+        assert_eq!(frames.fetch(), vec![("frame", "tiny".to_owned()), ("frame/bulk", "enormous".to_owned())]);
+    }
+
+    #[test]
+    fn memory_budget_sheds_low_priority_first() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        builder.memory_budget(16);
+        builder.topic_priority("debug", Priority::Low);
+        builder.topic_priority("alarm", Priority::High);
+        let everything = builder.add_subscriber(&["debug", "info", "alarm"]);
+        let publisher = builder.build();
+
+        for topic in &["debug", "info", "alarm", "debug", "info", "alarm", "alarm"] {
+            publisher.publish(*topic, "abcd".to_owned());
+        }
+        assert_eq!(publisher.memory_used(), 16);
+
+        let topics: Vec<&str> = everything.fetch().into_iter().map(|(t, _)| t).collect();
+        assert_eq!(topics, vec!["debug", "info", "alarm", "alarm"]);
+        assert_eq!(publisher.memory_used(), 0);
+    }
+
+    #[test]
+    fn drops_are_reported() {
+        use super::*;
+        use std::sync::Mutex;
+
+        let dropped = Arc::new(Mutex::new(vec![]));
+        let log = dropped.clone();
+        let mut builder = Publisher::new();
+        builder.on_drop(move |topic: &&str, reason| log.lock().unwrap().push((*topic, reason)));
+        builder.restrict_topic("admin", &["root"]);
+        builder.max_content_size("small", 1, |&n: &u32| n as usize);
+        let _sampled = builder.add_subscriber_with(&["small"], SubscriptionOptions::new()
+                                                   .sample(Sampling::Every(2)));
+        let publisher = builder.build();
+
+        publisher.publish("admin", 0);
+        publisher.publish("small", 5);
+        publisher.publish("small", 1);
+        publisher.publish("small", 1);
+
+        assert_eq!(*dropped.lock().unwrap(), vec![("admin", DropReason::Forbidden),
+                                                  ("small", DropReason::TooLarge),
+                                                  ("small", DropReason::Sampled)]);
+    }
+
+    #[test]
+    fn health_reports_lost_subscribers() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let kept = builder.add_subscriber(&["a", "b"]);
+        let lost = builder.add_subscriber(&["a"]);
+        let publisher = builder.build();
+        drop(lost);
+
+        publisher.publish("a", 1);
+        publisher.publish("b", 2);
+        let health = publisher.health();
+        assert_eq!((health.subscribers, health.disconnected), (2, 1));
+        assert_eq!((health.queued, health.deepest_queue), (2, 2));
+        assert!(!health.is_healthy());
+        assert_eq!(kept.pending(), 2);
+    }
+
+    #[test]
+    fn publisher_handles_are_counted() {
+        use super::*;
+        use std::sync::Mutex;
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let log = events.clone();
+        let mut builder = Publisher::<&str, u32>::new();
+        builder.on_publisher_event(move |event| log.lock().unwrap().push(event.clone()));
+        let publisher = builder.build();
+
+        let sensor = publisher.named("sensor");
+        let copy = sensor.clone();
+        assert_eq!(publisher.live_publishers()[&Some("sensor".to_owned())], 2);
+        drop(sensor);
+        drop(copy);
+        assert_eq!(publisher.live_publishers().get(&Some("sensor".to_owned())), None);
+        assert_eq!(publisher.live_publishers()[&None], 1);
+
+        let sensor = Some("sensor".to_owned());
+        assert_eq!(*events.lock().unwrap(), vec![
+            PublisherEvent::Created { name: None, live: 1 },
+            PublisherEvent::Created { name: sensor.clone(), live: 1 },
+            PublisherEvent::Created { name: sensor.clone(), live: 2 },
+            PublisherEvent::Dropped { name: sensor.clone(), live: 1 },
+            PublisherEvent::Dropped { name: sensor, live: 0 },
+        ]);
+    }
+
+    #[test]
+    fn single_consumer_topics_move_content() {
+        use super::*;
+
+        /// Counts its clones, which a moved message never needs.
+        struct Token(Arc<AtomicUsize>);
+
+        impl Clone for Token {
+            fn clone(&self) -> Self {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Token(self.0.clone())
+            }
+        }
+
+        let clones = Arc::new(AtomicUsize::new(0));
+        let mut builder = Publisher::new();
+        builder.declare_single_consumer("frames");
+        let frames = builder.add_subscriber(&["frames"]);
+        let publisher = builder.build();
+
+        for _ in 0 .. 3 { publisher.publish("frames", Token(clones.clone())); }
+        assert_eq!(frames.fetch().len(), 3);
+        assert_eq!(clones.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn single_consumer_topics_refuse_a_second_subscriber() {
+        use super::*;
+
+        let mut builder = Publisher::<&str, u32>::new();
+        builder.declare_single_consumer("frames");
+        builder.declare_single_consumer("replays");
+        let first = builder.try_add_subscriber(&["frames"]).unwrap();
+        assert_eq!(builder.try_add_subscriber(&["frames"]).err(), Some(SubscribeError::SingleConsumerTaken));
+        let publisher = builder.build();
+
+        let (_, guard) = publisher.subscribe_scoped(&["frames"]);
+        drop(guard);
+        publisher.publish("frames", 1);
+        assert_eq!(first.fetch(), vec![("frames", 1)]);
+        assert!(publisher.is_alive());
+
+        let (_, guard) = publisher.try_subscribe_scoped(&["replays"]).unwrap();
+        let taken = publisher.try_subscribe_scoped(&["other", "replays"]);
+        assert_eq!(taken.err(), Some(SubscribeError::SingleConsumerTaken));
+        drop(guard);
+        let (replays, _guard) = publisher.try_subscribe_scoped(&["replays"]).unwrap();
+        publisher.publish("replays", 2);
+        assert_eq!(replays.fetch(), vec![("replays", 2)]);
+    }
+
+    #[test]
+    fn panicking_under_the_routing_lock_poisons_the_network() {
+        use super::*;
+        use std::panic::{self, AssertUnwindSafe};
+
+        let mut builder = Publisher::new();
+        let _first = builder.add_subscriber(&["frames"]);
+        let publisher = builder.build();
+        assert!(publisher.is_alive());
+
+        let crashed = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _routing = publisher.bus().routing.write();
+            panic!("crashed while routing");
+        }));
+        assert!(crashed.is_err());
+        assert!(!publisher.is_alive());
+        assert_eq!(publisher.try_publish("frames", 1), Err(PublishError::BusPoisoned));
+    }
+
+    #[test]
+    fn dropped_subscribers_drain_unread_messages() {
+        use super::*;
+        use std::sync::Mutex;
+
+        let mut builder = Publisher::new();
+        let mut audit = builder.add_subscriber(&["event"]);
+        let publisher = builder.build();
+
+        let drained = Arc::new(Mutex::new(vec![]));
+        let log = drained.clone();
+        audit.on_drop_drain(move |_, n| log.lock().unwrap().push(n));
+        for n in 0 .. 4 { publisher.publish("event", n); }
+        assert_eq!(audit.wait_for(|_, &n| n == 2, Duration::from_secs(1), Unmatched::Keep), Some(("event", 2)));
+        drop(audit);
+
+        assert_eq!(*drained.lock().unwrap(), vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn lifo_subscribers_read_newest_first() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let latest = builder.add_subscriber_with(&["pos"], SubscriptionOptions::new().inbox_order(InboxOrder::Lifo));
+        let publisher = builder.build();
+
+        for i in 0 .. 3 { publisher.publish("pos", i); }
+        assert_eq!(latest.pending(), 3);
+        assert_eq!(latest.wait_for(|_, _| true, Duration::from_secs(1), Unmatched::Keep), Some(("pos", 2)));
+        publisher.publish("pos", 3);
+        assert_eq!(latest.fetch(), vec![("pos", 3), ("pos", 1), ("pos", 0)]);
+    }
+
+    #[test]
+    fn same_thread_subscribers() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let local = builder.add_subscriber_with(&["tick"], SubscriptionOptions::new().same_thread());
+        let publisher = builder.build();
+
+        for i in 0 .. 3 { publisher.publish("tick", i); }
+        assert_eq!(local.pending(), 3);
+        assert_eq!(local.fetch(), vec![("tick", 0), ("tick", 1), ("tick", 2)]);
+
+        let other = publisher.clone();
+        ::std::thread::spawn(move || other.publish("tick", 3)).join().unwrap();
+        let read = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| local.fetch()));
+        assert_eq!(read.is_err(), cfg!(debug_assertions));
+
+        publisher.publish("tick", 4);
+        let moved = ::std::thread::spawn(move || local.fetch()).join();
+        assert!(moved.is_err());
+    }
+
+    #[test]
+    fn child_bus_forwarding() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let app_saves = builder.add_subscriber(&["app/save"]);
+        let app = builder.build();
+
+        let mut builder = Publisher::new();
+        builder.attach_parent(&app, &[("save", "app/save"), ("pause", "app/pause")]);
+        let level_saves = builder.add_subscriber(&["save"]);
+        let level_pauses = builder.add_subscriber(&["pause"]);
+        let level = builder.build();
+
+        level.publish("save", 1);
+        app.publish("app/pause", 2);
+        app.publish("app/save", 3);
+
+        assert_eq!(app_saves.fetch(), vec![("app/save", 1), ("app/save", 3)]);
+        assert_eq!(level_saves.fetch(), vec![("save", 1), ("save", 3)]);
+        assert_eq!(level_pauses.fetch(), vec![("pause", 2)]);
+
+        drop(level);
+        app.publish("app/pause", 4);
+        assert_eq!(level_pauses.fetch(), vec![]);
+        assert!(app.bus().routing.read().unwrap().recipients(&"app/pause").next().is_none());
+    }
+
+    #[test]
+    fn scoped_subscription() {
+        use super::*;
+
+        let publisher = Publisher::new().build();
+        let (dialog, guard) = publisher.subscribe_scoped(&["input"]);
+
+        publisher.publish("input", 'y');
+        drop(guard);
+        publisher.publish("input", 'n');
+
+        assert_eq!(dialog.fetch(), vec![("input", 'y')]);
+        assert!(publisher.bus().routing.read().unwrap().recipients(&"input").next().is_none());
+    }
+
+    #[test]
+    fn one_shot_subscription() {
+        use super::*;
+
+        let publisher = Publisher::new().build();
+        let reply = publisher.subscribe_once("reply");
+        assert_eq!(reply.try_recv(), None);
+
+        let worker = publisher.clone();
+        let worker = std::thread::spawn(move || {
+            worker.publish("reply", 1);
+            worker.publish("reply", 2);
+        });
+
+        assert_eq!(reply.recv(), Some(("reply", 1)));
+        worker.join().unwrap();
+        assert_eq!(reply.try_recv(), None);
+        assert_eq!(reply.recv(), None);
+        assert!(publisher.bus().routing.read().unwrap().recipients(&"reply").next().is_none());
+    }
+
+    #[test]
+    fn simulated_interleavings_follow_the_seed() {
+        use super::*;
+
+        fn trace(seed: u64) -> String {
+            let mut builder = Publisher::new();
+            let a = builder.add_subscriber(&["x"]);
+            let b = builder.add_subscriber(&["x"]);
+            let simulation = builder.simulate(seed);
+            let publisher = builder.build();
+
+            for i in 0 .. 4 { publisher.publish("x", i); }
+            assert!(a.fetch().is_empty());
+            assert_eq!(simulation.pending(), 8);
+
+            let mut trace = String::new();
+            while simulation.step() {
+                trace.push(if a.pending() > 0 { 'a' } else { 'b' });
+                a.fetch();
+                b.fetch();
+            }
+            trace
+        }
+
+        assert_eq!(trace(7), trace(7));
+        let distinct: HashSet<String> = (0 .. 20).map(trace).collect();
+        assert!(distinct.len() > 1);
+    }
+
+    #[test]
+    fn into_inner_keeps_held_messages() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["a", "b"]);
+        let publisher = builder.build();
+
+        publisher.publish("a", 1);
+        publisher.publish("b", 2);
+        assert_eq!(subscriber.wait_for(|t, _| *t == "b", Duration::from_secs(1), Unmatched::Keep),
+                   Some(("b", 2)));
+
+        let (held, receiver) = subscriber.into_inner();
+        assert_eq!(held, vec![("a", 1)]);
+        publisher.publish("a", 3);
+        assert_eq!(receiver.try_recv(), Ok(("a", 3)));
+    }
+
+    #[test]
+    fn wait_for_matching_message() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let keeper = builder.add_subscriber(&["state"]);
+        let discarder = builder.add_subscriber(&["state"]);
+        let publisher = builder.build();
+
+        for i in 0 .. 4 { publisher.publish("state", i); }
+
+        let timeout = Duration::from_millis(10);
+        let is_two = |_: &&str, i: &i32| *i == 2;
+        assert_eq!(keeper.wait_for(is_two, timeout, Unmatched::Keep), Some(("state", 2)));
+        assert_eq!(discarder.wait_for(is_two, timeout, Unmatched::Discard), Some(("state", 2)));
+        assert_eq!(keeper.wait_for(|_, &i| i == 9, timeout, Unmatched::Keep), None);
+
+        assert_eq!(keeper.pending(), 3);
+        assert_eq!(keeper.fetch(), vec![("state", 0), ("state", 1), ("state", 3)]);
+        assert_eq!(discarder.fetch(), vec![("state", 3)]);
+    }
+
+    #[test]
+    fn compact_prunes_abandoned_topics() {
+        use super::*;
+
+        let mut builder = Publisher::new();
+        let kept = builder.add_subscriber(&["a"]);
+        let publisher = builder.build();
+
+        let (_, guard) = publisher.subscribe_scoped(&["a", "b", "c"]);
+        drop(guard);
+        assert_eq!(publisher.compact(), 2);
+        assert_eq!(publisher.compact(), 0);
+
+        publisher.publish("a", 1);
+        publisher.publish("b", 2);
+        assert_eq!(kept.fetch(), vec![("a", 1)]);
+    }
+}
+
+/// Interface for receiving messages from the network. Created by calling
+/// `Builder::add_subscriber()` during network setup, or
+/// `Publisher::subscribe_scoped()` afterwards.
+pub struct Subscriber<Topic, Content> {
+    inbox: Receiver<(Topic, Content)>,
+    pending: Arc<AtomicUsize>,
+    debouncer: Option<Debouncer<(Topic, Content)>>,
+    /// Messages set aside by `wait_for()`.
+    backlog: RefCell<VecDeque<(Topic, Content)>>,
+    /// Messages published on this thread, for a same-thread subscriber.
+    local: Option<Arc<LocalQueue<(Topic, Content)>>>,
+    budget: Arc<MemoryBudget<Content>>,
+    drops: Arc<DropHook<Topic>>,
+    shutdown: Option<Member>,
+    /// Merge functions registered with `compact()`.
+    merges: Vec<Merge<Topic, Content>>,
+    /// Messages taken from the channel but not yet read, newest last, for a
+    /// subscriber reading newest first.
+    stack: Option<RefCell<Vec<(Topic, Content)>>>,
+    /// Publishes a heartbeat if one is due. See `heartbeat()`.
+    pulse: Option<Box<dyn Fn() + Send>>,
+    /// Publishes a receipt for a message being read, if it needs one. See
+    /// `send_receipts()`.
+    receipt: Option<SendReceipt<Topic, Content>>,
+    /// Given every unread message when the subscriber is dropped. See
+    /// `on_drop_drain()`.
+    drain: Option<Box<dyn FnMut(Topic, Content) + Send>>,
+}
+
+/// What `Subscriber::wait_for()` does with messages that don't match.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Unmatched {
+    /// Keep them for later reads.
+    Keep,
+    /// Throw them away.
+    Discard,
+}
+
+impl<Topic, Content> Subscriber<Topic, Content> {
+    /// Consumes all pending messages in the subscriber's inbox.
+    pub fn fetch(&self) -> Vec<(Topic, Content)> {
+        let mut messages = vec![];
+        self.fetch_into(&mut messages);
+        messages
+    }
+
+    /// Appends all pending messages to `buffer`, returning how many were
+    /// added. Reusing one buffer across calls avoids allocating on every fetch.
+    pub fn fetch_into(&self, buffer: &mut Vec<(Topic, Content)>) -> usize {
+        self.fetch_extend(buffer)
+    }
+
+    /// Like `fetch_into()`, but accepts any collection that can be extended
+    /// with messages, such as a `SmallVec` or a `VecDeque`.
+    pub fn fetch_extend<B>(&self, buffer: &mut B) -> usize
+        where B: Extend<(Topic, Content)>
+    {
+        let mut count = 0;
+        let messages = iter::from_fn(|| self.next());
+        if self.merges.is_empty() {
+            buffer.extend(messages.inspect(|_| count += 1));
+        } else {
+            buffer.extend(compact::compacted(messages, &self.merges).inspect(|_| count += 1));
+        }
+        count
+    }
+
+    /// Passes each pending message to `handler` without collecting them first,
+    /// returning how many were handled. Returning `ControlFlow::Break` from the
+    /// handler stops early and leaves the remaining messages in the inbox.
+    pub fn drain<F>(&self, mut handler: F) -> usize
+        where F: FnMut(Topic, Content) -> ControlFlow<()>
+    {
+        let mut count = 0;
+        while let Some((topic, content)) = self.next() {
+            count += 1;
+            if handler(topic, content).is_break() { break; }
+        }
+        count
+    }
+
+    /// Handles pending messages until `budget` has elapsed or the inbox is
+    /// empty, returning how many messages are still waiting. The budget is
+    /// checked before each message, so a slow handler can overrun it by at
+    /// most one call.
+    pub fn process_for<F>(&self, budget: Duration, mut handler: F) -> usize
+        where F: FnMut(Topic, Content)
+    {
+        let start = Instant::now();
+        while start.elapsed() < budget {
+            match self.next() {
+                Some((topic, content)) => handler(topic, content),
+                None => break,
+            }
+        }
+        self.pending()
+    }
+
+    /// Waits up to `timeout` for a message that satisfies `predicate`, and
+    /// returns it. Messages that arrive first and don't match are handled
+    /// according to `unmatched`; kept messages are returned by later reads in
+    /// their original order, ahead of anything newer.
+    pub fn wait_for<F>(&self, mut predicate: F, timeout: Duration, unmatched: Unmatched)
+        -> Option<(Topic, Content)>
+        where F: FnMut(&Topic, &Content) -> bool
+    {
+        {
+            let mut backlog = self.backlog.borrow_mut();
+            let position = backlog.iter().position(|(t, c)| predicate(t, c));
+            if let Some(i) = position {
+                let message = backlog.remove(i);
+                drop(backlog);
+                return self.read(message);
+            }
+        }
+
+        if let Some(ref pulse) = self.pulse { pulse(); }
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let (topic, content) = self.unread_timeout(remaining)?;
+            if predicate(&topic, &content) { return self.read(Some((topic, content))); }
+
+            match unmatched {
+                Unmatched::Keep => self.backlog.borrow_mut().push_back((topic, content)),
+                Unmatched::Discard => (),
+            }
+        }
+    }
+
+    /// Returns true once `Publisher::shutdown()` has reached this
+    /// subscriber's phase. Always false for subscribers without one.
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown.as_ref().is_some_and(|member| member.is_requested())
+    }
+
+    /// Tells `Publisher::shutdown()` that this subscriber has finished
+    /// shutting down, so the next phase can begin. Dropping the subscriber
+    /// does the same.
+    pub fn acknowledge_shutdown(&self) {
+        if let Some(ref member) = self.shutdown { member.acknowledge(); }
+    }
+
+    /// Hands every message still unread when the subscriber is dropped to
+    /// `drain`, in the order they would have been read, rather than
+    /// discarding them. Useful for logging or persisting what a component
+    /// never got to. Setting another drain replaces this one.
+    pub fn on_drop_drain<F>(&mut self, drain: F)
+        where F: FnMut(Topic, Content) + Send + 'static
+    {
+        self.drain = Some(Box::new(drain));
+    }
+
+    /// Returns the number of messages waiting in the inbox.
+    pub fn pending(&self) -> usize {
+        let held = self.debouncer.as_ref().is_some_and(|d| d.is_holding());
+        let stacked = self.stack.as_ref().map_or(0, |stack| stack.borrow().len());
+        self.pending.load(Ordering::Acquire) + held as usize + self.backlog.borrow().len() + stacked
+    }
+
+    /// Gives up the subscriber's channel, for use in custom select loops and
+    /// schedulers. Messages the subscriber was already holding on to, such as
+    /// ones kept back by `wait_for()` or a debounced message, are returned
+    /// alongside it; they came before anything in the channel.
+    ///
+    /// The subscription itself is unaffected, so messages keep arriving on the
+    /// channel, but debouncing no longer applies to them. Nor does the memory
+    /// budget: messages read from the channel directly are never released
+    /// from it, so avoid this on networks that have one.
+    #[allow(clippy::type_complexity)]
+    pub fn into_inner(mut self) -> (Vec<(Topic, Content)>, Receiver<(Topic, Content)>) {
+        let mut held: Vec<_> = self.backlog.take().into_iter().collect();
+        held.extend(self.stack.take().into_iter().flat_map(|stack| stack.into_inner().into_iter().rev()));
+        held.extend(self.debouncer.take().and_then(|d| d.take()));
+        if let Some(queue) = self.local.take() {
+            held.extend(iter::from_fn(|| self.take(queue.pop())));
+        }
+        let (_, closed) = mpsc::channel();
+        (held, mem::replace(&mut self.inbox, closed))
+    }
+
+    /// A subscriber fed by a forwarding route of its own, such as a tap's,
+    /// rather than by the routing table. Nothing it holds is charged to the
+    /// network's memory budget.
+    fn forwarded(inbox: Receiver<(Topic, Content)>, pending: Arc<AtomicUsize>,
+                 drops: Arc<DropHook<Topic>>) -> Self
+    {
+        Subscriber {
+            inbox,
+            pending,
+            debouncer: None,
+            backlog: RefCell::new(VecDeque::new()),
+            local: None,
+            budget: Arc::new(MemoryBudget::new()),
+            drops,
+            shutdown: None,
+            merges: vec![],
+            stack: None,
+            pulse: None,
+            receipt: None,
+            drain: None,
+        }
+    }
+
+    /// Takes the next pending message, if any. Every way of reading the inbox
+    /// goes through here.
+    fn next(&self) -> Option<(Topic, Content)> {
+        if let Some(ref pulse) = self.pulse { pulse(); }
+        let kept = self.backlog.borrow_mut().pop_front();
+        let message = kept.or_else(|| match self.stack {
+            Some(ref stack) => self.newest(stack, None),
+            None => self.receive(),
+        });
+        self.read(message)
+    }
+
+    /// Like `next()`, but blocks for up to `timeout` waiting for a message.
+    fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        if let Some(ref pulse) = self.pulse { pulse(); }
+        let kept = self.backlog.borrow_mut().pop_front();
+        self.read(kept.or_else(|| self.unread_timeout(timeout)))
+    }
+
+    /// Passes a message on its way to the component, sending a receipt for
+    /// it if one is wanted.
+    fn read(&self, message: Option<(Topic, Content)>) -> Option<(Topic, Content)> {
+        if let (Some(receipt), Some((topic, content))) = (self.receipt.as_ref(), message.as_ref()) {
+            receipt(topic, content);
+        }
+        message
+    }
+
+    /// Takes the next message not set aside by `wait_for()`, in the
+    /// subscriber's inbox order, blocking for up to `timeout`.
+    fn unread_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        match self.stack {
+            Some(ref stack) => self.newest(stack, Some(timeout)),
+            None => self.receive_timeout(timeout),
+        }
+    }
+
+    /// Takes everything that has arrived onto `stack`, then the newest
+    /// message from it, waiting up to `timeout` if there are none at all.
+    fn newest(&self, stack: &RefCell<Vec<(Topic, Content)>>, timeout: Option<Duration>)
+        -> Option<(Topic, Content)>
+    {
+        let mut stack = stack.borrow_mut();
+        stack.extend(iter::from_fn(|| self.receive()));
+        if let (true, Some(timeout)) = (stack.is_empty(), timeout) {
+            stack.extend(self.receive_timeout(timeout));
+            stack.extend(iter::from_fn(|| self.receive()));
+        }
+        stack.pop()
+    }
+
+    /// Takes a message that hasn't been seen by this subscriber yet.
+    fn receive(&self) -> Option<(Topic, Content)> {
+        let debouncer = match self.debouncer {
+            Some(ref d) => d,
+            None => return self.take(self.poll_inbox()),
+        };
+
+        while let Some(message) = self.take(self.poll_inbox()) {
+            self.supersede(debouncer.hold(message));
+        }
+        debouncer.release()
+    }
+
+    /// Like `receive()`, but blocks for up to `timeout` waiting for a message.
+    fn receive_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        let debouncer = match self.debouncer {
+            Some(ref d) => d,
+            None => return self.take(self.wait_inbox(timeout)),
+        };
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(message) = self.receive() { return Some(message); }
+
+            let now = Instant::now();
+            if now >= deadline { return None; }
+
+            let wait = debouncer.until_release().map_or(deadline - now, |w| {
+                w.min(deadline - now)
+            });
+            if let Some(message) = self.take(self.wait_inbox(wait)) {
+                self.supersede(debouncer.hold(message));
+            }
+        }
+    }
+
+    /// Takes a message from the local queue or the channel, without waiting.
+    fn poll_inbox(&self) -> Option<(Topic, Content)> {
+        self.local.as_ref().and_then(|queue| queue.pop()).or_else(|| self.inbox.try_recv().ok())
+    }
+
+    /// Whether the subscriber will never read another message: every route to
+    /// it is gone and it has nothing left unread. A message that turns up
+    /// while checking is kept for the next read.
+    pub(crate) fn is_finished(&self) -> bool {
+        let message = match self.inbox.try_recv() {
+            Ok(message) => self.take(Some(message)),
+            Err(mpsc::TryRecvError::Empty) => return false,
+            Err(mpsc::TryRecvError::Disconnected) => return self.pending() == 0,
+        };
+        if let Some(message) = message {
+            match (self.debouncer.as_ref(), self.stack.as_ref()) {
+                (Some(debouncer), _) => self.supersede(debouncer.hold(message)),
+                (None, Some(stack)) => stack.borrow_mut().push(message),
+                (None, None) => self.backlog.borrow_mut().push_back(message),
+            }
+        }
+        false
+    }
+
+    /// Like `poll_inbox()`, but waits up to `timeout` on the channel if the
+    /// local queue is empty. Nothing can be added to that queue while this
+    /// thread waits.
+    fn wait_inbox(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        self.local.as_ref().and_then(|queue| queue.pop())
+            .or_else(|| self.inbox.recv_timeout(timeout).ok())
+    }
+
+    fn take(&self, message: Option<(Topic, Content)>) -> Option<(Topic, Content)> {
+        if let Some((_, ref content)) = message {
+            self.pending.fetch_sub(1, Ordering::AcqRel);
+            self.budget.release(content);
+        }
+        message
+    }
+
+    /// Reports a message the debouncer threw away.
+    fn supersede(&self, message: Option<(Topic, Content)>) {
+        if let Some((topic, _)) = message {
+            self.drops.notify(&topic, DropReason::Superseded);
+        }
+    }
+}
+
+impl<Topic, Content> Drop for Subscriber<Topic, Content> {
+    /// Gives back the memory budget held by unread messages, and hands them
+    /// to the drain if there is one.
+    fn drop(&mut self) {
+        let mut drain = self.drain.take();
+        if let Some(ref mut drain) = drain {
+            let backlog = self.backlog.get_mut().drain(..);
+            let stacked = self.stack.as_mut().map(|stack| mem::take(stack.get_mut())).unwrap_or_default();
+            let held = self.debouncer.take().and_then(|debouncer| debouncer.take());
+            for (topic, content) in backlog.chain(stacked.into_iter().rev()).chain(held) {
+                drain(topic, content);
+            }
+        }
+
+        let local = self.local.take();
+        let next = || local.as_ref().and_then(|queue| queue.try_pop())
+            .or_else(|| self.inbox.try_recv().ok());
+        while let Some((topic, content)) = self.take(next()) {
+            if let Some(ref mut drain) = drain { drain(topic, content); }
+        }
+    }
+}
+
+/// Interface for sending messages to the network. To add more publishers, just
+/// clone this object and distribute the clones to your clients.
+pub struct Publisher<Topic: Hash + Eq + Clone, Content: Clone> {
+    handle: Arc<Handle<Topic, Content>>,
+    name: Option<Arc<str>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Clone for Publisher<Topic, Content> {
+    fn clone(&self) -> Self {
+        Publisher::with_handle(self.handle.clone(), self.name.clone())
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Drop for Publisher<Topic, Content> {
+    fn drop(&mut self) {
+        self.bus().registry.leave(&self.name);
+    }
+}
+
+/// Shared by every clone of a publisher. When the last clone goes away, so
+/// does this, and the network shuts down.
+///
+/// Routes that forward into this network hold the `Bus` directly rather than
+/// a `Handle`, so they never keep it from shutting down.
+struct Handle<Topic: Hash + Eq + Clone, Content: Clone> {
+    bus: Arc<Bus<Topic, Content>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Drop for Handle<Topic, Content> {
+    fn drop(&mut self) {
+        if let Some(ref parent) = self.bus.parent {
+            parent.publisher.bus().unsubscribe(parent.link);
+        }
+
+        for extension in &self.bus.extensions {
+            extension.on_shutdown();
+        }
+    }
+}
+
+/// Routing state. Only the routing table changes after setup.
+struct Bus<Topic: Hash + Eq + Clone, Content: Clone> {
+    routing: RwLock<RoutingTable<Topic, Content>>,
+    next_id: AtomicUsize,
+    stats: Option<StatsTracker<Topic, Content>>,
+    acl: RwLock<HashMap<Topic, HashSet<String>>>,
+    audit_topic: RwLock<Option<Topic>>,
+    size_limits: HashMap<Topic, SizeLimit<Topic, Content>>,
+    /// Limits on named publishers, by name.
+    quotas: HashMap<String, Quota<Content>>,
+    budget: Arc<MemoryBudget<Content>>,
+    drops: Arc<DropHook<Topic>>,
+    shutdown: Arc<Shutdown>,
+    links: Mutex<Vec<(String, Liveness)>>,
+    registry: Registry,
+    /// Topics declared single-consumer, and their one route. They never go
+    /// through the routing table.
+    single_consumer: HashMap<Topic, SingleSlot<Topic, Content>>,
+    priorities: RwLock<HashMap<Topic, Priority>>,
+    config: ConfigState<Topic, Content>,
+    extensions: Vec<Box<dyn BusExtension<Topic, Content>>>,
+    parent: Option<ParentLink<Topic, Content>>,
+    simulation: Option<Scheduler<Topic, Content>>,
+    clock: Arc<dyn Clock>,
+    timers: Timers<Topic, Content>,
+    buffers: Arc<BufferPool>,
+    upgrades: Arc<Upgrades<Content>>,
+    dedup: HashMap<Topic, Dedup<Content>>,
+    content_routes: ContentRoutes<Topic, Content>,
+    freeze: Freeze<Topic, Content>,
+    readiness: Readiness,
+    rejoins: Rejoins<Topic>,
+    recent: Option<Recent<Topic, Content>>,
+    inversions: Option<Inversions>,
+    fan_out: Option<FanOut<Topic, Content>>,
+    strictness: Strictness,
+    route_changes: RouteChanges,
+    topics: TopicSet<Topic>,
+    topic_settings: HashMap<Topic, TopicSettings<Content>>,
+    #[cfg(feature = "debug-invariants")]
+    invariants: Invariants,
+}
+
+/// Connection from a child network to its parent.
+struct ParentLink<Topic: Hash + Eq + Clone, Content: Clone> {
+    publisher: Publisher<Topic, Content>,
+    /// Child topics that are forwarded up, and what they're called upstairs.
+    upward: HashMap<Topic, Topic>,
+    /// Id of the route in the parent that forwards messages down to us.
+    link: usize,
+}
+
+/// How to reach one subscriber. The routing table keeps one per subscriber,
+/// whatever topics it is on; single-consumer topics keep copies of their own,
+/// under the same id.
+#[derive(Clone)]
+struct Route<Topic, Content> {
+    id: usize,
+    outbox: Outbox<Topic, Content>,
+    admission: Option<Arc<Admission>>,
+}
+
+#[derive(Clone)]
+enum Outbox<Topic, Content> {
+    /// A `Subscriber`'s channel, along with its count of unread messages.
+    Inbox(Sender<(Topic, Content)>, Arc<AtomicUsize>),
+    /// A same-thread subscriber's queue, with its channel for messages from
+    /// other threads.
+    Local(Arc<LocalQueue<(Topic, Content)>>, Sender<(Topic, Content)>, Arc<AtomicUsize>),
+    /// Hands messages to another part of the program, usually another network.
+    Forward(Arc<dyn Fn(Topic, Content) + Send + Sync>),
+}
+
+impl<Topic, Content> Route<Topic, Content> {
+    fn admit(&self) -> Result<(), Option<DropReason>> {
+        self.admission.as_ref().map_or(Ok(()), |a| a.admit())
+    }
+
+    fn is_spent(&self) -> bool {
+        self.admission.as_ref().is_some_and(|a| a.is_spent())
+    }
+
+    fn woken(&self) {
+        if let Some(ref admission) = self.admission { admission.woken(); }
+    }
+
+    /// How many messages wait in the subscriber's queue, if it has one.
+    fn queued(&self) -> Option<usize> {
+        match self.outbox {
+            Outbox::Inbox(_, ref pending) | Outbox::Local(_, _, ref pending) => {
+                Some(pending.load(Ordering::Acquire))
+            },
+            Outbox::Forward(_) => None,
+        }
+    }
+
+    /// Whether messages sent on this route wait in a queue, and so count
+    /// towards the memory budget.
+    fn is_queued(&self) -> bool {
+        match self.outbox {
+            Outbox::Inbox(..) | Outbox::Local(..) => true,
+            Outbox::Forward(_) => false,
+        }
+    }
 
-#[cfg(test)]
-mod test {
-    #[test]
-    fn simple_network() {
-        use super::*;
+    /// Sends a message, returning false if it was queued for a subscriber
+    /// that has gone away.
+    fn send(&self, topic: Topic, content: Content) -> bool {
+        if let Some(ref admission) = self.admission {
+            admission.sent();
+        }
 
-        let mut builder = Publisher::new();
-        let subscriber = builder.add_subscriber(&["widgets"]);
-        let publisher = builder.build();
+        match self.outbox {
+            Outbox::Inbox(ref tx, ref pending) => {
+                // Count the message before it becomes visible to the
+                // subscriber, so the counter can never dip below zero.
+                let was_empty = pending.fetch_add(1, Ordering::AcqRel) == 0;
+                if tx.send((topic, content)).is_err() {
+                    pending.fetch_sub(1, Ordering::AcqRel);
+                    return false;
+                }
+                if was_empty { self.woken(); }
+                true
+            },
 
-        publisher.publish("widgets", "sprocket");
+            Outbox::Local(ref queue, ref tx, ref pending) => {
+                let was_empty = pending.fetch_add(1, Ordering::AcqRel) == 0;
+                let message = match queue.push((topic, content)) {
+                    Ok(()) => {
+                        if was_empty { self.woken(); }
+                        return true;
+                    },
+                    // Sent from another thread. The queue tells its reader.
+                    Err(message) => message,
+                };
+                if tx.send(message).is_err() {
+                    pending.fetch_sub(1, Ordering::AcqRel);
+                    return false;
+                }
+                if was_empty { self.woken(); }
+                true
+            },
 
-        for (topic, content) in subscriber.fetch() {
-            println!("{}: {}", topic, content);
+            Outbox::Forward(ref forward) => {
+                forward(topic, content);
+                true
+            },
         }
     }
 }
 
-/// Interface for receiving messages from the network. Created by calling
-/// `Builder::add_subscriber()` during network setup.
-pub struct Subscriber<Topic, Content> {
-    inbox: Receiver<(Topic, Content)>,
-}
+impl<Topic: Hash + Eq + Clone, Content: Clone> Bus<Topic, Content> {
+    fn new() -> Self {
+        Bus {
+            routing: RwLock::new(RoutingTable::new()),
+            next_id: AtomicUsize::new(0),
+            stats: None,
+            acl: RwLock::new(HashMap::new()),
+            audit_topic: RwLock::new(None),
+            size_limits: HashMap::new(),
+            quotas: HashMap::new(),
+            budget: Arc::new(MemoryBudget::new()),
+            drops: Arc::new(DropHook::new()),
+            shutdown: Arc::new(Shutdown::new()),
+            links: Mutex::new(Vec::new()),
+            registry: Registry::new(),
+            single_consumer: HashMap::new(),
+            priorities: RwLock::new(HashMap::new()),
+            config: ConfigState::new(),
+            extensions: Vec::new(),
+            parent: None,
+            simulation: None,
+            clock: Arc::new(SystemClock),
+            timers: Timers::new(),
+            buffers: Arc::new(BufferPool::new()),
+            upgrades: Arc::new(Upgrades::new()),
+            dedup: HashMap::new(),
+            content_routes: ContentRoutes::new(),
+            freeze: Freeze::new(),
+            readiness: Readiness::new(),
+            rejoins: Rejoins::new(),
+            recent: None,
+            inversions: None,
+            fan_out: None,
+            strictness: Strictness::Lenient,
+            route_changes: RouteChanges::new(),
+            topics: TopicSet::new(),
+            topic_settings: HashMap::new(),
+            #[cfg(feature = "debug-invariants")]
+            invariants: Invariants::new(),
+        }
+    }
 
-impl<Topic, Content> Subscriber<Topic, Content> {
-    /// Consumes all pending messages in the subscriber's inbox.
-    pub fn fetch(&self) -> Vec<(Topic, Content)> {
-        // TODO: Instead of a Vec, use some kind of iterator
-        let mut messages = vec![];
-        while let Ok(message) = self.inbox.try_recv() {
-            messages.push(message);
+    /// Creates a subscriber and its routes, returning it with its route id.
+    fn add_subscriber(&self, topics: &[Topic], options: SubscriptionOptions)
+        -> (Subscriber<Topic, Content>, usize)
+    {
+        let (subscriber, route) = self.add_subscriber_route(topics, options, None);
+        (subscriber, route.id)
+    }
+
+    /// Like `add_subscriber()`, but creates nothing if one of the topics is a
+    /// single-consumer topic that already has its subscriber.
+    fn try_add_subscriber(&self, topics: &[Topic], options: SubscriptionOptions)
+        -> Result<(Subscriber<Topic, Content>, usize), SubscribeError>
+    {
+        let (subscriber, route) = self.new_subscriber(options);
+        self.try_add_routes(route.id, topics, route.outbox.clone(), route.admission.clone(), None)?;
+        for extension in &self.extensions {
+            extension.on_subscribe(topics);
+        }
+        Ok((subscriber, route.id))
+    }
+
+    /// Like `add_subscriber()`, but returns a copy of the subscriber's route,
+    /// from which more routes can be made later. Retained messages already in
+    /// `seen` aren't sent.
+    fn add_subscriber_route(&self, topics: &[Topic], options: SubscriptionOptions, seen: Option<&Seen<Topic>>)
+        -> (Subscriber<Topic, Content>, Route<Topic, Content>)
+    {
+        for extension in &self.extensions {
+            extension.on_subscribe(topics);
+        }
+
+        let (subscriber, route) = self.new_subscriber(options);
+        self.add_routes(route.id, topics, route.outbox.clone(), route.admission.clone(), seen);
+        (subscriber, route)
+    }
+
+    /// Creates a subscriber and the route to it, on no topics yet.
+    fn new_subscriber(&self, options: SubscriptionOptions) -> (Subscriber<Topic, Content>, Route<Topic, Content>) {
+        let (tx, rx) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let admission = Admission::new(&options, &self.clock).map(Arc::new);
+        let debouncer = admission.as_ref().and_then(|a| Debouncer::new(&options, a));
+
+        let local = if options.is_same_thread() { Some(Arc::new(LocalQueue::new())) } else { None };
+        let outbox = match local {
+            Some(ref queue) => Outbox::Local(queue.clone(), tx, pending.clone()),
+            None => Outbox::Inbox(tx, pending.clone()),
+        };
+        let route = Route { id: self.reserve_id(), outbox, admission };
+
+        let subscriber = Subscriber {
+            inbox: rx,
+            pending,
+            debouncer,
+            backlog: RefCell::new(VecDeque::new()),
+            local,
+            budget: self.budget.clone(),
+            drops: self.drops.clone(),
+            shutdown: options.phase().map(|phase| Shutdown::join(&self.shutdown, phase)),
+            merges: vec![],
+            stack: if options.is_lifo() { Some(RefCell::new(vec![])) } else { None },
+            pulse: None,
+            receipt: None,
+            drain: None,
+        };
+
+        (subscriber, route)
+    }
+
+    /// Adds a route on each of `topics`, all sharing a fresh id, which is
+    /// returned.
+    fn subscribe(&self, topics: &[Topic], outbox: Outbox<Topic, Content>,
+                 admission: Option<Arc<Admission>>) -> usize
+    {
+        let id = self.reserve_id();
+        self.add_routes(id, topics, outbox, admission, None);
+        id
+    }
+
+    fn reserve_id(&self) -> usize {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn add_routes(&self, id: usize, topics: &[Topic], outbox: Outbox<Topic, Content>,
+                  admission: Option<Arc<Admission>>, seen: Option<&Seen<Topic>>)
+    {
+        if self.route_topics(id, topics, outbox, admission, seen, false).is_err() {
+            self.strictness.misuse("subscribed a second time to a single-consumer topic");
+        }
+    }
+
+    /// Like `add_routes()`, but adds no routes at all if a single-consumer
+    /// topic among `topics` already has its subscriber.
+    fn try_add_routes(&self, id: usize, topics: &[Topic], outbox: Outbox<Topic, Content>,
+                      admission: Option<Arc<Admission>>, seen: Option<&Seen<Topic>>)
+        -> Result<(), SubscribeError>
+    {
+        self.route_topics(id, topics, outbox, admission, seen, true)
+    }
+
+    /// Adds the routes for `add_routes()` and `try_add_routes()`. Taken
+    /// single-consumer topics are skipped, or, if `all_or_nothing`, undo
+    /// the whole subscription. Either way they are reported as an error.
+    fn route_topics(&self, id: usize, topics: &[Topic], outbox: Outbox<Topic, Content>,
+                    admission: Option<Arc<Admission>>, seen: Option<&Seen<Topic>>, all_or_nothing: bool)
+        -> Result<(), SubscribeError>
+    {
+        // Checked before locking, so a panic doesn't poison the routes.
+        if topics.iter().any(|topic| self.topics.check(topic).is_err()) {
+            self.strictness.misuse("subscribed to a topic that was retired or never declared");
+        }
+
+        // Single-consumer topics are claimed before locking too, so a taken
+        // one can be refused without touching the table.
+        let route = Route { id, outbox, admission };
+        let mut claimed = vec![];
+        let mut taken = Ok(());
+        for topic in topics {
+            if self.topics.is_retired(topic) { continue; }
+            let Some(slot) = self.single_consumer.get(topic) else { continue };
+            match slot.claim(&route, |route| self.send_retained(topic, route, seen)) {
+                Ok(true) => claimed.push(slot),
+                Ok(false) => (),
+                Err(e) => taken = Err(e),
+            }
+        }
+        if taken.is_err() && all_or_nothing {
+            for slot in claimed { slot.release(id); }
+            return taken;
+        }
+
+        let mut routing = self.routing.write().unwrap_or_else(|e| e.into_inner());
+        for topic in topics {
+            if self.topics.is_retired(topic) || self.single_consumer.contains_key(topic) { continue; }
+
+            // Naming a topic twice must not produce a second route.
+            if routing.contains(topic, id) { continue; }
+            // Retained messages are sent while the routing table is locked,
+            // so none can be missed or repeated by a publish in between.
+            self.send_retained(topic, &route, seen);
+            routing.insert(topic.clone(), &route);
+            #[cfg(feature = "debug-invariants")]
+            self.invariants.check_routes(routing.recipients(topic).map(|route| route.id));
+        }
+        drop(routing);
+        self.route_changes.notify();
+        taken
+    }
+
+    /// Sends `route` the messages `topic` has retained, leaving out any a
+    /// rejoining subscriber has `seen`.
+    fn send_retained(&self, topic: &Topic, route: &Route<Topic, Content>, seen: Option<&Seen<Topic>>) {
+        let after = seen.and_then(|seen| seen.get(topic)).cloned().unwrap_or(0);
+        for content in self.topic_settings.get(topic).map(|s| s.retained_after(after, self.clock.now())).unwrap_or_default() {
+            route.send(topic.clone(), content);
+        }
+    }
+
+    /// Removes the route with the given id from one topic.
+    fn remove_route(&self, id: usize, topic: &Topic) {
+        self.routing.write().unwrap_or_else(|e| e.into_inner()).remove(id, topic);
+        self.route_changes.notify();
+    }
+
+    /// Replaces the admission rules on every route with the given id.
+    fn set_admission(&self, id: usize, admission: Option<Arc<Admission>>) {
+        let mut routing = self.routing.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(route) = routing.route_mut(id) { route.admission = admission; }
+    }
+
+    /// Removes every route with the given id.
+    fn unsubscribe(&self, id: usize) {
+        let mut routing = self.routing.write().unwrap_or_else(|e| e.into_inner());
+        routing.unsubscribe(id);
+        self.rejoins.leave(id, |topic| self.topic_settings.get(topic).map_or(0, |s| s.published()));
+        drop(routing);
+        self.route_changes.notify();
+        if let Some(ref inversions) = self.inversions { inversions.forget(id); }
+        for slot in self.single_consumer.values() { slot.release(id); }
+
+        #[cfg(feature = "debug-invariants")]
+        self.invariants.retire(id);
+    }
+
+    /// Routes a message to local subscribers, skipping the route with id
+    /// `skip` (if any) so a forwarded message doesn't bounce straight back.
+    /// Content passed in owned is moved to the last subscriber if the topic
+    /// asks for that, and otherwise only cloned from.
+    fn deliver(&self, topic: &Topic, content: Cow<'_, Content>, skip: Option<usize>) {
+        self.deliver_resolved(topic, content, skip, None)
+    }
+
+    /// Like `deliver()`, taking the recipients from a token's copy of them if
+    /// there is one.
+    fn deliver_resolved(&self, topic: &Topic, content: Cow<'_, Content>, skip: Option<usize>,
+                        resolved: Option<&Resolved<Topic, Content>>)
+    {
+        if let Some(ref stats) = self.stats {
+            stats.record(topic, &content, self.clock.now());
+        }
+
+        let cost = self.cost(topic, &content);
+        // Copies made for subscribers, for the topic's stats.
+        let clones = Cell::new(0);
+        let borrowed = matches!(content, Cow::Borrowed(_));
+        if let Some(slot) = self.single_consumer.get(topic) {
+            self.record_clones(topic, self.deliver_single(slot, topic, cost, skip, content));
+            return;
+        }
+
+        let settings = self.topic_settings.get(topic);
+        let _order = settings.and_then(|s| s.order.as_ref())
+            .map(|order| order.lock().unwrap_or_else(|e| e.into_inner()));
+        let routing = self.routing.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(settings) = settings { settings.retain(&content, self.clock.now()); }
+
+        #[cfg(feature = "debug-invariants")]
+        self.invariants.check_routes(routing.recipients(topic).map(|route| route.id));
+        let capacity = settings.and_then(|s| s.capacity());
+        let decision = self.content_routes.classify(topic, &content);
+        let cached = resolved.map(|resolved| resolved.routes(&routing, topic));
+        let looked_up = if cached.is_none() { Some(routing.recipients(topic)) } else { None };
+        let recipients = cached.iter().flat_map(|cached| cached.routes.iter())
+            .chain(looked_up.into_iter().flatten());
+        let mut routes = vec![];
+        for route in recipients {
+            if skip == Some(route.id) { continue; }
+            if decision.as_ref().is_some_and(|d| !self.content_routes.admits(d, route.id)) { continue; }
+            if capacity.is_some_and(|capacity| route.queued().is_some_and(|q| q >= capacity)) {
+                self.drops.notify(topic, DropReason::QueueFull);
+                continue;
+            }
+            routes.push(route);
+        }
+        let inversions = self.inversions.as_ref().map(|inversions| {
+            let priority = self.priorities.read().unwrap_or_else(|e| e.into_inner())
+                .get(topic).cloned().unwrap_or_default();
+            routes.iter().filter_map(|route| {
+                let queued = route.queued()?;
+                inversions.observe(route.id, queued, priority).map(|found| (route.id, found))
+            }).collect::<Vec<_>>()
+        });
+
+        let mut spent = vec![];
+        let last = match settings {
+            Some(settings) if settings.move_to_last => routes.pop(),
+            _ => None,
+        };
+        match self.fan_out.as_ref().and_then(|fan_out| fan_out.send(self, &routes, topic, cost, &content)) {
+            Some((copies, ids)) => {
+                clones.set(clones.get() + copies);
+                spent = ids;
+            },
+            None => for route in routes {
+                let copy = || {
+                    clones.set(clones.get() + 1);
+                    Content::clone(&content)
+                };
+                if self.offer(route, topic, cost, copy) { spent.push(route.id); }
+            },
+        }
+        if let Some(route) = last {
+            let moved = || {
+                if borrowed { clones.set(clones.get() + 1); }
+                content.into_owned()
+            };
+            if self.offer(route, topic, cost, moved) { spent.push(route.id); }
+        }
+
+        drop(cached);
+        drop(routing);
+        self.record_clones(topic, clones.get());
+        if let (Some(inversions), Some(found)) = (self.inversions.as_ref(), inversions) {
+            if !found.is_empty() { inversions.report(found, &self.config.names()); }
+        }
+        for id in spent {
+            self.unsubscribe(id);
+        }
+    }
+
+    fn record_clones(&self, topic: &Topic, clones: u64) {
+        if let Some(ref stats) = self.stats {
+            stats.record_clones(topic, clones, self.clock.now());
+        }
+    }
+
+    /// What each queued copy of a message is charged against the memory
+    /// budget, and at what priority, if there is a budget.
+    fn cost(&self, topic: &Topic, content: &Content) -> Option<(usize, Priority)> {
+        self.budget.size(content).map(|size| {
+            let priorities = self.priorities.read().unwrap_or_else(|e| e.into_inner());
+            (size, priorities.get(topic).cloned().unwrap_or_default())
+        })
+    }
+
+    /// Delivers to a single-consumer topic's route, if it has one yet, with
+    /// the topic's retention, ordering and capacity applied as they are for
+    /// any other topic. Returns how many times the content was cloned.
+    fn deliver_single(&self, slot: &SingleSlot<Topic, Content>, topic: &Topic,
+                      cost: Option<(usize, Priority)>, skip: Option<usize>, content: Cow<Content>) -> u64
+    {
+        let settings = self.topic_settings.get(topic);
+        let _order = settings.and_then(|s| s.order.as_ref())
+            .map(|order| order.lock().unwrap_or_else(|e| e.into_inner()));
+        slot.with(|route| {
+            // Retained while the slot is locked, so a subscriber claiming it
+            // gets the message either replayed or delivered, not both.
+            if let Some(settings) = settings { settings.retain(&content, self.clock.now()); }
+            let Some(route) = route.filter(|route| skip != Some(route.id)) else { return 0 };
+            if settings.and_then(|s| s.capacity()).is_some_and(|capacity| route.queued().is_some_and(|q| q >= capacity)) {
+                self.drops.notify(topic, DropReason::QueueFull);
+                return 0;
+            }
+            let clones = Cell::new(0);
+            self.offer(route, topic, cost, || {
+                if matches!(content, Cow::Borrowed(_)) { clones.set(1); }
+                content.into_owned()
+            });
+            clones.get()
+        })
+    }
+
+    /// Sends `route` its copy of a message, which is only made once the
+    /// budget and the route's admission have let it through. Returns true if
+    /// the route is now spent.
+    fn offer<F>(&self, route: &Route<Topic, Content>, topic: &Topic,
+                cost: Option<(usize, Priority)>, content: F) -> bool
+        where F: FnOnce() -> Content
+    {
+        #[cfg(feature = "debug-invariants")]
+        self.invariants.check_delivery(route.id);
+        let charged = cost.filter(|_| route.is_queued());
+        if charged.is_some_and(|(size, priority)| !self.budget.charge(size, priority)) {
+            self.drops.notify(topic, DropReason::OverBudget);
+            return false;
+        }
+        if let Err(reason) = route.admit() {
+            if let Some((size, _)) = charged { self.budget.release_bytes(size); }
+            if let Some(reason) = reason { self.drops.notify(topic, reason); }
+            return false;
+        }
+        match self.simulation {
+            Some(ref scheduler) => scheduler.schedule(route, topic.clone(), content()),
+            None => if !route.send(topic.clone(), content()) {
+                if let Some((size, _)) = charged { self.budget.release_bytes(size); }
+            },
+        }
+        route.is_spent()
+    }
+
+    /// Like `deliver()`, then passes the message on to the parent network if
+    /// the topic is forwarded there. The local routing table is unlocked by
+    /// then, so locks are only ever nested from parent to child.
+    ///
+    /// A message on a single-consumer topic that stays in this network is
+    /// moved to its subscriber rather than cloned.
+    fn deliver_and_forward(&self, topic: &Topic, content: Content, skip: Option<usize>) {
+        self.deliver_and_forward_resolved(topic, content, skip, None)
+    }
+
+    /// Like `deliver_and_forward()`, with a token's copy of the recipients.
+    fn deliver_and_forward_resolved(&self, topic: &Topic, content: Content, skip: Option<usize>,
+                                    resolved: Option<&Resolved<Topic, Content>>)
+    {
+        let upward = self.parent.as_ref().is_some_and(|p| p.upward.contains_key(topic));
+        if let (Some(slot), false) = (self.single_consumer.get(topic), upward) {
+            if let Some(ref stats) = self.stats {
+                stats.record(topic, &content, self.clock.now());
+            }
+            let cost = self.cost(topic, &content);
+            self.deliver_single(slot, topic, cost, skip, Cow::Owned(content));
+            return;
+        }
+
+        let upstairs = self.parent.as_ref()
+            .and_then(|parent| parent.upward.get(topic).map(|upstairs| (parent, upstairs)));
+        match upstairs {
+            Some((parent, upstairs)) => {
+                self.deliver_resolved(topic, Cow::Borrowed(&content), skip, resolved);
+                let bus = parent.publisher.bus();
+                bus.deliver_and_forward(upstairs, content, Some(parent.link));
+            },
+            None => self.deliver_resolved(topic, Cow::Owned(content), skip, resolved),
         }
-        messages
     }
 }
 
-/// Interface for sending messages to the network. To add more publishers, just
-/// clone this object and distribute the clones to your clients.
-#[derive(Clone)]
-pub struct Publisher<Topic: Hash + Eq + Clone, Content: Clone> {
-    subscribers: HashMap<Topic, Vec<Sender<(Topic, Content)>>>,
+impl<Key, Content> Publisher<Arc<Key>, Content>
+    where Key: ?Sized + Hash + Eq + ToOwned,
+          Arc<Key>: From<Key::Owned>,
+          Content: Clone,
+{
+    /// Publishes on a network whose topics are shared keys, such as
+    /// `Arc<str>` or `Arc<Path>`, given the key by reference. Subscribers are
+    /// delivered the `Arc` the network already holds for the key, so each of
+    /// them costs a reference count rather than a copy of the key, and
+    /// publishing allocates nothing. Only a key that no subscriber has named
+    /// is copied, once, into a new `Arc`.
+    pub fn publish_borrowed(&self, topic: &Key, content: Content) {
+        self.try_publish_borrowed(topic, content).unwrap_or(());
+    }
+
+    /// Like `try_publish()`, but borrows the key. See `publish_borrowed()`.
+    pub fn try_publish_borrowed(&self, topic: &Key, content: Content) -> Result<(), PublishError> {
+        let bus = self.bus();
+        let known = bus.routing.read().unwrap_or_else(|e| e.into_inner()).key(topic).cloned()
+            .or_else(|| bus.single_consumer.get_key_value(topic).map(|(shared, _)| shared.clone()));
+        let shared = known.unwrap_or_else(|| Arc::from(topic.to_owned()));
+        self.publish_skipping(&shared, content, None)
+    }
 }
 
 impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
     /// Called to initialize a network.
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> Builder<Topic, Content> {
         Builder {
-            publisher: Publisher {
-                subscribers: HashMap::new()
-            }
+            bus: Bus::new(),
+            wiring: Vec::new(),
+            configured: HashMap::new(),
+            bridges: Vec::new(),
+            staged: Arc::new(Staged::new()),
         }
     }
 
     /// Sends a message to the network. All topic filtering is done in the
-    /// calling thread.
+    /// calling thread. Each subscriber receives a given message at most once,
+    /// no matter how many of its routes match.
+    ///
+    /// Messages this publisher is not allowed to send are dropped, or
+    /// redirected to the audit topic if the network has one, and so is
+    /// everything once the network is no longer `is_alive()`. Use
+    /// `try_publish()` to find out when that happens.
     pub fn publish(&self, topic: Topic, content: Content) {
-        let outbox = match self.subscribers.get(&topic) {
-            Some(o) => o,
-            None => return,
+        self.try_publish(topic, content).unwrap_or(());
+    }
+
+    /// Like `publish()`, but reports an error if the message was rejected.
+    pub fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        self.publish_skipping(&topic, content, None)
+    }
+
+    /// Like `publish()`, but borrows the topic, which saves the clone a
+    /// caller holding on to its key would otherwise make to publish. That is
+    /// the only clone it saves: every subscriber that receives the message
+    /// still gets a clone of its own, since subscribers receive a `Topic`.
+    /// Where keys are expensive to clone, such as long strings or paths,
+    /// make the topic type an `Arc` of them and publish with
+    /// `publish_borrowed()`.
+    pub fn publish_ref(&self, topic: &Topic, content: Content) {
+        self.try_publish_ref(topic, content).unwrap_or(());
+    }
+
+    /// Like `try_publish()`, but borrows the topic. See `publish_ref()`.
+    pub fn try_publish_ref(&self, topic: &Topic, content: Content) -> Result<(), PublishError> {
+        self.publish_skipping(topic, content, None)
+    }
+
+    /// Like `try_publish_ref()`, but doesn't deliver to the route with id
+    /// `skip`, so a component can publish without hearing its own messages.
+    fn publish_skipping(&self, topic: &Topic, content: Content, skip: Option<usize>)
+        -> Result<(), PublishError>
+    {
+        self.publish_checked(topic, content, skip, None)
+    }
+
+    /// Checks and delivers a message, to the recipients `resolved` holds if
+    /// it comes from a `PublishToken`.
+    fn publish_checked(&self, topic: &Topic, content: Content, skip: Option<usize>,
+                       resolved: Option<&Resolved<Topic, Content>>)
+        -> Result<(), PublishError>
+    {
+        let bus = self.bus();
+
+        if !self.is_alive() {
+            bus.strictness.misuse("published after a panic poisoned its routes");
+            bus.drops.notify(topic, DropReason::Poisoned);
+            return Err(PublishError::BusPoisoned);
+        }
+
+        if let Err(e) = bus.topics.check(topic) {
+            bus.strictness.misuse(match e {
+                PublishError::Retired => "published on a retired topic",
+                _ => "published on a topic that was never declared",
+            });
+            bus.drops.notify(topic, DropReason::UnknownTopic);
+            return Err(e);
+        }
+
+        if !self.may_publish(topic) {
+            bus.drops.notify(topic, DropReason::Forbidden);
+            let audit = bus.audit_topic.read().unwrap_or_else(|e| e.into_inner()).clone();
+            if let Some(ref audit) = audit {
+                bus.deliver(audit, Cow::Owned(content), None);
+            }
+            return Err(PublishError::Forbidden);
+        }
+
+        let content = match bus.size_limits.get(topic) {
+            Some(limit) => match limit.enforce(content) {
+                Enforced::Deliver(content) => content,
+                Enforced::Reroute(overflow, content) => return self.publish_checked(overflow, content, skip, None),
+                Enforced::Refuse => {
+                    bus.drops.notify(topic, DropReason::TooLarge);
+                    return Err(PublishError::TooLarge);
+                },
+            },
+            None => content,
+        };
+
+        let quota = self.name.as_ref().and_then(|name| bus.quotas.get(&name[..]));
+        if quota.is_some_and(|quota| !quota.admit(&content, &*bus.clock)) {
+            bus.drops.notify(topic, DropReason::OverQuota);
+            return Err(PublishError::OverQuota);
+        }
+
+        if bus.dedup.get(topic).is_some_and(|dedup| !dedup.admit(&content, bus.clock.now())) {
+            bus.drops.notify(topic, DropReason::Duplicate);
+            return Err(PublishError::Duplicate);
+        }
+
+        let name = self.name();
+        for extension in &bus.extensions {
+            if !extension.on_publish(name, topic, &content) {
+                bus.drops.notify(topic, DropReason::Rejected);
+                return Err(PublishError::Rejected);
+            }
+        }
+
+        if let Some(ref recent) = bus.recent { recent.record(topic, &content); }
+        self.deliver_unless_frozen(topic, content, skip, resolved)
+    }
+
+    /// Returns a handle to the same network that publishes under the given
+    /// name, for use with `Builder::restrict_topic()`. Names nest: calling
+    /// this on a handle that already has a name produces `"outer/inner"`, so a
+    /// component handed a named publisher can't impersonate anyone else.
+    pub fn named(&self, name: &str) -> Self {
+        let name = match self.name {
+            Some(ref outer) => format!("{}/{}", outer, name),
+            None => name.to_owned(),
+        };
+
+        Publisher::with_handle(self.handle.clone(), Some(name.into()))
+    }
+
+    /// Every handle is made here, so the registry sees them all.
+    fn with_handle(handle: Arc<Handle<Topic, Content>>, name: Option<Arc<str>>) -> Self {
+        handle.bus.registry.join(&name);
+        Publisher { handle, name }
+    }
+
+    /// Counts the live handles to this network, grouped by name. Unnamed
+    /// handles are counted under `None`.
+    pub fn live_publishers(&self) -> HashMap<Option<String>, usize> {
+        self.bus().registry.snapshot()
+    }
+
+    /// Shuts down subscribers that were given a phase with
+    /// `SubscriptionOptions::shutdown_phase()`, one phase at a time, lowest
+    /// first. Each phase's subscribers see `shutdown_requested()` become true,
+    /// and the next phase only begins once they have all acknowledged or been
+    /// dropped. Gives up if a phase takes longer than `timeout`.
+    ///
+    /// The network itself keeps running; this only coordinates the
+    /// subscribers.
+    pub fn shutdown(&self, timeout: Duration) -> Result<(), ShutdownTimedOut> {
+        self.bus().shutdown.run(timeout)
+    }
+
+    /// Returns false once a thread has panicked while changing the network's
+    /// routes, for instance in a forwarding closure receiving retained
+    /// messages. The routes may be half changed by then, so from that point
+    /// every publish is refused with `PublishError::BusPoisoned`, and reported
+    /// as `DropReason::Poisoned`. The network never recovers; build a new one.
+    pub fn is_alive(&self) -> bool {
+        !sync::is_poisoned(&self.bus().routing)
+    }
+
+    /// Checks on the network. See `Health` for what is covered.
+    pub fn health(&self) -> Health {
+        let bus = self.bus();
+        let mut health = Health {
+            memory_used: bus.budget.used(),
+            memory_budget: bus.budget.limit(),
+            ..Health::default()
         };
 
-        for subscriber in outbox {
-            subscriber.send((topic.clone(), content.clone())).unwrap_or(());
+        let routing = bus.routing.read().unwrap_or_else(|e| e.into_inner());
+        let mut routes: HashMap<usize, (&Arc<AtomicUsize>, usize)> = HashMap::new();
+        let single: Vec<_> = bus.single_consumer.values().filter_map(|slot| slot.get()).collect();
+        for route in routing.routes().chain(&single) {
+            match route.outbox {
+                Outbox::Inbox(_, ref pending) | Outbox::Local(_, _, ref pending) => {
+                    routes.entry(route.id).or_insert((pending, 0)).1 += 1;
+                },
+                Outbox::Forward(_) => (),
+            }
+        }
+
+        for (pending, count) in routes.values() {
+            // Each route holds the counter, and so does the subscriber until
+            // it is dropped.
+            if Arc::strong_count(pending) <= *count { health.disconnected += 1; }
+            let queued = pending.load(Ordering::Acquire);
+            health.queued += queued;
+            health.deepest_queue = health.deepest_queue.max(queued);
+        }
+        health.subscribers = routes.len();
+        drop(routing);
+
+        let links = bus.links.lock().unwrap_or_else(|e| e.into_inner());
+        health.links = links.iter().map(|(name, liveness)| LinkHealth {
+            name: name.clone(),
+            running: liveness.is_running(),
+        }).collect();
+        health
+    }
+
+    /// Includes `link` in this network's `health()` reports, under `name`.
+    pub fn watch_link(&self, name: &str, link: &bridge::Link) {
+        let mut links = self.bus().links.lock().unwrap_or_else(|e| e.into_inner());
+        links.push((name.to_owned(), link.liveness()));
+    }
+
+    /// Returns roughly how many bytes of content are waiting in subscriber
+    /// queues, as counted for `Builder::memory_budget()`. Always zero if the
+    /// network has no budget.
+    pub fn memory_used(&self) -> usize {
+        self.bus().budget.used()
+    }
+
+    /// Returns the name this handle publishes under, if it has one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|n| &n[..])
+    }
+
+    /// Reports how much traffic each topic has seen during the window set by
+    /// `Builder::track_topic_stats()`. Topics with no recent traffic are left
+    /// out, and the map is always empty if stats tracking was not enabled.
+    pub fn topic_stats(&self) -> HashMap<Topic, TopicStats> {
+        match self.bus().stats {
+            Some(ref stats) => stats.snapshot(self.bus().clock.now()),
+            None => HashMap::new(),
         }
     }
+
+    /// Returns the clock the network measures time with, as set by
+    /// `Builder::clock()`.
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.bus().clock.clone()
+    }
+
+    /// Forgets topics that no longer have any subscribers, and gives back the
+    /// memory their entries held, returning how many there were. Dropping a
+    /// `SubscriptionGuard` leaves its topics' entries behind, empty, so a
+    /// long-running network that subscribes to short-lived topics should
+    /// call this now and then.
+    pub fn compact(&self) -> usize {
+        self.bus().routing.write().unwrap_or_else(|e| e.into_inner()).compact()
+    }
+
+    /// Adds a subscriber to the running network. It stays subscribed until
+    /// the returned guard is dropped, which removes its routes again.
+    pub fn subscribe_scoped(&self, topics: &[Topic])
+        -> (Subscriber<Topic, Content>, SubscriptionGuard<Topic, Content>)
+    {
+        let bus = &self.handle.bus;
+        let (subscriber, id) = bus.add_subscriber(topics, SubscriptionOptions::new());
+        (subscriber, SubscriptionGuard { bus: bus.clone(), id })
+    }
+
+    /// Like `subscribe_scoped()`, but refuses a single-consumer topic that
+    /// already has its subscriber, instead of leaving the new subscriber
+    /// without it. The topic is free again once the other's guard is dropped.
+    pub fn try_subscribe_scoped(&self, topics: &[Topic])
+        -> Result<Scoped<Topic, Content>, SubscribeError>
+    {
+        let bus = &self.handle.bus;
+        let (subscriber, id) = bus.try_add_subscriber(topics, SubscriptionOptions::new())?;
+        Ok((subscriber, SubscriptionGuard { bus: bus.clone(), id }))
+    }
+
+    /// Waits for the next message on `topic`. Once it has been delivered, or
+    /// the `OneShot` is dropped, the subscription removes itself.
+    pub fn subscribe_once(&self, topic: Topic) -> OneShot<Topic, Content> {
+        let bus = &self.handle.bus;
+        let options = SubscriptionOptions::new().once();
+        let (subscriber, id) = bus.add_subscriber(&[topic], options);
+        let guard = SubscriptionGuard { bus: bus.clone(), id };
+        OneShot { subscriber, _guard: guard }
+    }
+
+    fn bus(&self) -> &Bus<Topic, Content> {
+        &self.handle.bus
+    }
+
+    fn may_publish(&self, topic: &Topic) -> bool {
+        let acl = self.bus().acl.read().unwrap_or_else(|e| e.into_inner());
+        let allowed = match acl.get(topic) {
+            Some(allowed) => allowed,
+            None => return true,
+        };
+
+        self.name.as_ref().is_some_and(|name| allowed.contains(&name[..]))
+    }
+}
+
+/// Keeps a subscription made by `Publisher::subscribe_scoped()` in place.
+/// Dropping the guard unsubscribes; the `Subscriber` can still read whatever
+/// had already arrived.
+pub struct SubscriptionGuard<Topic: Hash + Eq + Clone, Content: Clone> {
+    bus: Arc<Bus<Topic, Content>>,
+    id: usize,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Drop for SubscriptionGuard<Topic, Content> {
+    fn drop(&mut self) {
+        self.bus.unsubscribe(self.id);
+    }
+}
+
+/// A scoped subscriber and the guard that keeps it subscribed.
+type Scoped<Topic, Content> = (Subscriber<Topic, Content>, SubscriptionGuard<Topic, Content>);
+
+/// A subscription that receives a single message. Created by
+/// `Publisher::subscribe_once()`.
+pub struct OneShot<Topic: Hash + Eq + Clone, Content: Clone> {
+    subscriber: Subscriber<Topic, Content>,
+    _guard: SubscriptionGuard<Topic, Content>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> OneShot<Topic, Content> {
+    /// Returns the message if it has arrived, without waiting.
+    pub fn try_recv(&self) -> Option<(Topic, Content)> {
+        self.subscriber.next()
+    }
+
+    /// Waits up to `timeout` for the message to arrive.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        self.subscriber.next_timeout(timeout)
+    }
+
+    /// Waits for the message to arrive, returning `None` if it already has
+    /// and was received. This blocks forever if nobody ever publishes on the
+    /// topic, so prefer `recv_timeout()` where that matters.
+    pub fn recv(&self) -> Option<(Topic, Content)> {
+        let message = self.subscriber.inbox.recv().ok();
+        self.subscriber.take(message)
+    }
 }
 
 /// Helper for building networks. Call `build()` to complete initialization.
 pub struct Builder<Topic: Hash + Eq + Clone, Content: Clone> {
-    publisher: Publisher<Topic, Content>,
+    bus: Bus<Topic, Content>,
+    /// Connections to other networks, which can only be made once this one
+    /// has been moved into its final, shared location.
+    wiring: Vec<Wiring<Topic, Content>>,
+    /// Subscribers created by `from_config()`, until they are claimed.
+    configured: HashMap<String, Subscriber<Topic, Content>>,
+    /// Bridges listed by `from_config()`, with the subscriber feeding each
+    /// one that exports anything, until `build_bridged()` connects them.
+    bridges: Vec<ConfiguredBridge<Topic, Content>>,
+    /// Messages published during setup, shared with pre-publishers. See
+    /// `Builder::stage()`.
+    staged: Arc<Staged<Topic, Content>>,
 }
 
+type Wiring<Topic, Content> = Box<dyn FnOnce(&Arc<Bus<Topic, Content>>) + Send>;
+type ConfiguredBridge<Topic, Content> = (config::BridgeConfig<Topic>, Option<Subscriber<Topic, Content>>);
+
 impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
     /// Adds a subscriber to the network, with a complete list of the Topics it
-    /// expects to receive. This list cannot be modified later.
+    /// expects to receive. This list cannot be modified later. An empty list
+    /// makes a subscriber that receives nothing; see `try_add_subscriber()`
+    /// and `add_broadcast_subscriber()`.
     pub fn add_subscriber(&mut self, topics: &[Topic]) -> Subscriber<Topic, Content> {
-        let (tx, rx) = mpsc::channel();
-        for topic in topics {
-            let topic = topic.clone();
-            let subscriber_list = self.publisher.subscribers.entry(topic);
-            subscriber_list.or_insert_with(|| Vec::new()).push(tx.clone());
-        }
+        self.add_subscriber_with(topics, SubscriptionOptions::new())
+    }
+
+    /// Hands over the subscriber that `from_config()` created under `name`.
+    /// Each can only be claimed once.
+    pub fn configured_subscriber(&mut self, name: &str) -> Option<Subscriber<Topic, Content>> {
+        self.configured.remove(name)
+    }
+
+    /// Like `add_subscriber()`, but with extra control over how messages are
+    /// delivered to this subscriber.
+    pub fn add_subscriber_with(&mut self, topics: &[Topic], options: SubscriptionOptions)
+        -> Subscriber<Topic, Content>
+    {
+        self.bus.add_subscriber(topics, options).0
+    }
+
+    /// Enables per-topic traffic statistics covering the last `window` of
+    /// time. The `sizer` estimates how many bytes a message's content occupies;
+    /// it is called once per publish. Messages count towards the stats even if
+    /// no subscriber wants their topic.
+    pub fn track_topic_stats<F>(&mut self, window: Duration, sizer: F)
+        where F: Fn(&Content) -> usize + Send + Sync + 'static
+    {
+        self.bus.stats = Some(StatsTracker::new(window, sizer));
+    }
+
+    /// Makes the network measure time with `clock` instead of the system
+    /// clock. Subscribers added before this keep using the old clock, so
+    /// call it first.
+    pub fn clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.bus.clock = Arc::new(clock);
+    }
+
+    /// Allows only publishers with one of the given names to publish `topic`.
+    /// See `Publisher::named()`. Unnamed publishers are always refused.
+    /// Calling this again for the same topic adds to the list.
+    pub fn restrict_topic(&mut self, topic: Topic, publishers: &[&str]) {
+        let mut acl = self.bus.acl.write().unwrap_or_else(|e| e.into_inner());
+        let allowed = acl.entry(topic).or_default();
+        allowed.extend(publishers.iter().map(|&name| name.to_owned()));
+    }
+
+    /// Redirects messages refused by `restrict_topic()` to `topic`, so that
+    /// spoofing attempts can be observed. The offending content is delivered
+    /// unchanged; its original topic is not preserved.
+    pub fn audit_topic(&mut self, topic: Topic) {
+        *self.bus.audit_topic.write().unwrap_or_else(|e| e.into_inner()) = Some(topic);
+    }
+
+    /// Refuses content on `topic` larger than `bytes`, as measured by
+    /// `sizer`, so a runaway producer can't fill every subscriber's queue
+    /// with copies of something huge. `try_publish()` reports such messages
+    /// as `PublishError::TooLarge`.
+    pub fn max_content_size<F>(&mut self, topic: Topic, bytes: usize, sizer: F)
+        where F: Fn(&Content) -> usize + Send + Sync + 'static
+    {
+        self.bus.size_limits.insert(topic, SizeLimit::reject(bytes, sizer));
+    }
 
-        Subscriber { inbox: rx }
+    /// Like `max_content_size()`, but oversized content is passed through
+    /// `truncate`, along with the limit, and what it returns is delivered
+    /// instead. The result isn't measured again.
+    pub fn truncate_content<F, G>(&mut self, topic: Topic, bytes: usize, sizer: F, truncate: G)
+        where F: Fn(&Content) -> usize + Send + Sync + 'static,
+              G: Fn(Content, usize) -> Content + Send + Sync + 'static,
+    {
+        self.bus.size_limits.insert(topic, SizeLimit::truncate(bytes, sizer, truncate));
+    }
+
+    /// Like `max_content_size()`, but oversized content is published on
+    /// `overflow` instead, so huge payloads can be handled out of band
+    /// without holding up the topic's own subscribers. It is checked again
+    /// there, against `overflow`'s own limit if it has one.
+    pub fn overflow_content<F>(&mut self, topic: Topic, bytes: usize, sizer: F, overflow: Topic)
+        where F: Fn(&Content) -> usize + Send + Sync + 'static
+    {
+        self.bus.size_limits.insert(topic, SizeLimit::reroute(bytes, sizer, overflow));
+    }
+
+    /// Limits publishers named `name` to `messages` per second between
+    /// them, with bursts of up to a second's worth. `over` says what happens
+    /// to messages beyond that. Unnamed publishers are never limited.
+    pub fn limit_publish_rate(&mut self, name: &str, messages: u32, over: OverQuota) {
+        self.bus.quotas.entry(name.to_owned()).or_insert_with(Quota::new)
+            .limit_messages(messages, over);
+    }
+
+    /// Like `limit_publish_rate()`, but limits the bytes published per
+    /// second, as measured by `sizer`. A single message larger than a
+    /// second's worth is refused outright if `over` is `OverQuota::Refuse`.
+    pub fn limit_publish_bandwidth<F>(&mut self, name: &str, bytes: u64, sizer: F, over: OverQuota)
+        where F: Fn(&Content) -> usize + Send + Sync + 'static
+    {
+        self.bus.quotas.entry(name.to_owned()).or_insert_with(Quota::new)
+            .limit_bytes(bytes, sizer, over);
+    }
+
+    /// Calls `hook` with the topic and reason whenever the network drops a
+    /// message it was given, whether the whole message was refused at publish
+    /// time or only one subscriber's copy was skipped. The hook runs on
+    /// whichever thread noticed, usually the publisher's, so it should be
+    /// quick. Only the first call has any effect.
+    pub fn on_drop<F>(&mut self, hook: F)
+        where F: Fn(&Topic, DropReason) + Send + Sync + 'static
+    {
+        self.bus.drops.set(Box::new(hook));
+    }
+
+    /// Calls `hook` whenever a publisher handle for this network is created
+    /// or dropped, so a producer that silently goes away can be noticed. The
+    /// handle made by `build()` is the first to be reported. Only the first
+    /// call has any effect.
+    pub fn on_publisher_event<F>(&mut self, hook: F)
+        where F: Fn(&PublisherEvent) + Send + Sync + 'static
+    {
+        self.bus.registry.set_hook(Box::new(hook));
+    }
+
+    /// Declares that `topic` has a single consumer, which lets messages on it
+    /// be moved to that subscriber instead of cloned, and skip the shared
+    /// routing table and its lock. Meant for busy point-to-point topics.
+    ///
+    /// The topic's route is kept in a slot of its own, which publishing only
+    /// read-locks, so it waits for nothing but a subscriber being added or
+    /// removed. Messages still arrive through the subscriber's ordinary
+    /// queue, among those on its other topics, and any number of publishers
+    /// may use the topic. What it saves over the generic path is the clone
+    /// and the table lookup; `cargo bench --bench single_consumer` measures
+    /// how much that is.
+    ///
+    /// Content that can't be cloned can travel on such a topic wrapped in a
+    /// `Handoff`.
+    ///
+    /// A second subscriber to the topic doesn't receive anything on it while
+    /// the first one is subscribed; `try_add_subscriber()` and
+    /// `Publisher::try_subscribe_scoped()` refuse it with
+    /// `SubscribeError::SingleConsumerTaken` instead. Once a scoped
+    /// subscriber's guard is dropped, the topic can be subscribed to again.
+    /// Messages are still cloned if the topic is forwarded to a parent
+    /// network.
+    ///
+    /// Panics if the topic already has more than one subscriber.
+    pub fn declare_single_consumer(&mut self, topic: Topic) {
+        let existing = self.bus.routing.write().unwrap_or_else(|e| e.into_inner()).remove_topic(&topic);
+        assert!(existing.len() <= 1, "a single-consumer topic can only have one subscriber");
+        self.bus.single_consumer.insert(topic, SingleSlot::new(existing.into_iter().next()));
+    }
+
+    /// Sets how readily messages on `topic` are shed when the network is
+    /// short of memory. Topics are `Priority::Normal` unless set otherwise.
+    pub fn topic_priority(&mut self, topic: Topic, priority: Priority) {
+        self.bus.priorities.write().unwrap_or_else(|e| e.into_inner()).insert(topic, priority);
+    }
+
+    /// Puts the network in simulation mode, where messages are only delivered
+    /// when the returned `Simulation` is stepped, in an order chosen by a
+    /// random number generator started from `seed`. Meant for tests that
+    /// need to explore delivery interleavings reproducibly.
+    pub fn simulate(&mut self, seed: u64) -> Simulation<Topic, Content> {
+        let simulation = Simulation::new(seed);
+        self.bus.simulation = Some(simulation.scheduler());
+        simulation
+    }
+
+    /// Adds an extension to the network. Extensions are consulted in the
+    /// order they were registered.
+    pub fn register_extension<E>(&mut self, extension: E)
+        where E: BusExtension<Topic, Content> + 'static
+    {
+        self.bus.extensions.push(Box::new(extension));
     }
 
     /// Finishes network setup. No more subscribers can be added after this.
     pub fn build(self) -> Publisher<Topic, Content> {
-        self.publisher
+        for extension in &self.bus.extensions {
+            extension.on_build();
+        }
+
+        let bus = Arc::new(self.bus);
+        for wire in self.wiring {
+            wire(&bus);
+        }
+
+        let publisher = Publisher::with_handle(Arc::new(Handle { bus }), None);
+        self.staged.go_live(&publisher);
+        publisher
+    }
+}
+
+impl<Topic, Content> Builder<Topic, Content>
+    where Topic: Hash + Eq + Clone,
+          Content: Clone + ContentSize + 'static,
+{
+    /// Limits the content waiting in all subscriber queues put together to
+    /// roughly `bytes`, as estimated by `ContentSize`. Each queued copy of a
+    /// message counts separately. Once a topic's share of the budget, set by
+    /// its `Priority`, is used up, new messages on it are dropped for any
+    /// subscriber that would have to queue them; low-priority topics go
+    /// first, and the last quarter of the budget is kept for high-priority
+    /// ones. Forwarding to other networks isn't limited.
+    ///
+    /// Only the first call has any effect.
+    pub fn memory_budget(&mut self, bytes: usize) {
+        self.bus.budget.set(bytes, Box::new(|content: &Content| content.content_size()));
+    }
+}
+
+impl<Topic, Content> Builder<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+{
+    /// Makes this network a child of the one `parent` publishes to. Each pair
+    /// in `topic_map` names a topic here and the corresponding topic in the
+    /// parent; messages on either side are forwarded to the other, but never
+    /// straight back to where they came from. Only one parent is supported.
+    ///
+    /// Once every publisher for the child network has been dropped, it is
+    /// detached from the parent. The child keeps the parent alive until then.
+    pub fn attach_parent(&mut self, parent: &Publisher<Topic, Content>,
+                         topic_map: &[(Topic, Topic)])
+    {
+        let link = parent.bus().reserve_id();
+        self.bus.parent = Some(ParentLink {
+            publisher: parent.clone(),
+            upward: topic_map.iter().cloned().collect(),
+            link,
+        });
+
+        let mut downward: HashMap<Topic, Vec<Topic>> = HashMap::new();
+        for (child, upstairs) in topic_map {
+            downward.entry(upstairs.clone()).or_default().push(child.clone());
+        }
+
+        let parent = parent.clone();
+        self.wiring.push(Box::new(move |bus: &Arc<Bus<Topic, Content>>| {
+            let upstairs: Vec<Topic> = downward.keys().cloned().collect();
+            let child = bus.clone();
+            let forward = Outbox::Forward(Arc::new(move |topic: Topic, content: Content| {
+                for local in downward.get(&topic).into_iter().flatten() {
+                    child.deliver(local, Cow::Borrowed(&content), None);
+                }
+            }));
+
+            parent.bus().add_routes(link, &upstairs, forward, None, None);
+        }));
     }
 }
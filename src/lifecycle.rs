@@ -0,0 +1,470 @@
+//! Declaring, sealing and retiring topics.
+//!
+//! By default any value is a valid topic, so a typo in a topic name just
+//! makes a channel nobody listens to. A network can instead declare its
+//! topics up front with `Builder::declare_topic()`, and seal the set with
+//! `Builder::seal_topics()`, after which publishing on anything else is an
+//! error. A topic that has served its purpose can be retired with
+//! `Publisher::retire_topic()`: its subscriptions are removed and later
+//! publishes on it fail, so nothing keeps talking on a channel that is
+//! supposed to be gone.
+//!
+//! Declaring a topic is also where it gets settings of its own: how many
+//! recent messages new subscribers are sent, and for how long, how far each
+//! subscriber may fall behind, whether concurrent publishers are put in one
+//! order, and how copies are made. It is also where a topic can be documented, so that what
+//! flows on it is written down in one place: `Publisher::describe()` and
+//! `Publisher::export_topology()` both show it.
+
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{Builder, Priority, PublishError, Publisher};
+use super::sync::{AtomicBool, AtomicUsize, Mutex, Ordering, RwLock};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ManualClock;
+
+    #[test]
+    fn sealed_topics_reject_typos_and_retirees() {
+        let mut builder = Publisher::new();
+        builder.declare_topic("score", TopicOptions::new());
+        builder.declare_topic("lobby", TopicOptions::new().priority(Priority::High));
+        builder.seal_topics();
+        let subscriber = builder.add_subscriber(&["score", "lobby"]);
+        let publisher = builder.build();
+
+        assert_eq!(publisher.try_publish("score", 1), Ok(()));
+        assert_eq!(publisher.try_publish("scroe", 2), Err(PublishError::Undeclared));
+        assert_eq!(publisher.try_publish("lobby", 3), Ok(()));
+        assert_eq!(subscriber.fetch(), vec![("score", 1), ("lobby", 3)]);
+
+        publisher.publish("lobby", 4);
+        assert!(publisher.retire_topic(&"lobby"));
+        assert!(!publisher.retire_topic(&"lobby"));
+        assert_eq!(publisher.try_publish("lobby", 5), Err(PublishError::Retired));
+        let (late, _guard) = publisher.subscribe_scoped(&["lobby", "score"]);
+        publisher.publish("score", 6);
+
+        assert_eq!(subscriber.fetch(), vec![("lobby", 4), ("score", 6)]);
+        assert_eq!(late.fetch(), vec![("score", 6)]);
+    }
+
+    #[test]
+    fn declared_topics_keep_their_own_settings() {
+        let mut builder = Publisher::new();
+        builder.declare_topic("weather", TopicOptions::new().retain_last(2));
+        builder.declare_topic("log", TopicOptions::new()
+            .capacity(2)
+            .order(TopicOrder::Total)
+            .clone_strategy(CloneStrategy::MoveToLast));
+        let logger = builder.add_subscriber(&["log"]);
+        let publisher = builder.build();
+
+        for forecast in ["rain", "fog", "sun"] { publisher.publish("weather", forecast); }
+        let (late, _guard) = publisher.subscribe_scoped(&["weather"]);
+        publisher.publish("weather", "snow");
+        assert_eq!(late.fetch(), vec![("weather", "fog"), ("weather", "sun"), ("weather", "snow")]);
+
+        for line in ["a", "b", "c"] { publisher.publish("log", line); }
+        assert_eq!(logger.fetch(), vec![("log", "a"), ("log", "b")]);
+    }
+
+    #[test]
+    fn single_consumer_topics_keep_their_settings_too() {
+        let mut builder = Publisher::new();
+        builder.declare_topic("job", TopicOptions::new().single_consumer().retain_last(2).capacity(1));
+        let publisher = builder.build();
+
+        for job in 1 .. 4 { publisher.publish("job", job); }
+        let (worker, guard) = publisher.subscribe_scoped(&["job"]);
+        assert_eq!(worker.fetch(), vec![("job", 2), ("job", 3)]);
+        publisher.publish("job", 4);
+        publisher.publish("job", 5);
+        assert_eq!(worker.fetch(), vec![("job", 4)]);
+        drop(guard);
+
+        let (next, _guard) = publisher.subscribe_scoped(&["job"]);
+        assert_eq!(next.fetch(), vec![("job", 4), ("job", 5)]);
+    }
+
+    #[test]
+    fn retained_messages_expire_with_age() {
+        let clock = ManualClock::new();
+        let mut builder = Publisher::new();
+        builder.clock(clock.clone());
+        builder.declare_topic("position", TopicOptions::new().retain_for(Duration::from_secs(60)));
+        builder.declare_topic("alert", TopicOptions::new().retain_last(1).retain_for(Duration::from_secs(5)));
+        let publisher = builder.build();
+
+        publisher.publish("position", 1);
+        publisher.publish("alert", 10);
+        clock.advance(Duration::from_secs(30));
+        publisher.publish("position", 2);
+        let (late, _guard) = publisher.subscribe_scoped(&["position", "alert"]);
+        assert_eq!(late.fetch(), vec![("position", 1), ("position", 2)]);
+
+        publisher.publish("alert", 11);
+        publisher.publish("alert", 12);
+        clock.advance(Duration::from_secs(40));
+        assert_eq!(publisher.expire_retained(), 2);
+        let (later, _guard) = publisher.subscribe_scoped(&["position", "alert"]);
+        assert_eq!(later.fetch(), vec![("position", 2)]);
+    }
+
+    #[test]
+    fn declared_topics_describe_themselves() {
+        let mut builder = Publisher::<&str, u32>::new();
+        builder.declare_topic("score", TopicOptions::new()
+            .description("Points scored, published by the referee")
+            .schema("u32"));
+        builder.declare_topic("lobby", TopicOptions::new());
+        let publisher = builder.build();
+
+        let docs = publisher.describe(&"score").unwrap();
+        assert_eq!(docs.description.as_deref(), Some("Points scored, published by the referee"));
+        assert_eq!(docs.schema.as_deref(), Some("u32"));
+        assert_eq!(publisher.describe(&"lobby"), Some(&TopicDocs::default()));
+        assert_eq!(publisher.describe(&"chat"), None);
+    }
+}
+
+/// In what order a topic's subscribers see messages.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TopicOrder {
+    /// Messages from one publishing thread arrive in the order they were
+    /// published, but two threads publishing at once may be seen in a
+    /// different order by different subscribers.
+    #[default]
+    PerPublisher,
+    /// Every subscriber sees the same order. Publishes on the topic take
+    /// turns, so concurrent publishers wait for one another.
+    Total,
+}
+
+/// How a topic's subscribers get their copies of a message.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CloneStrategy {
+    /// Every subscriber gets a clone, and the published value is dropped by
+    /// the publishing thread.
+    #[default]
+    PerSubscriber,
+    /// The last subscriber gets the published value itself, which saves a
+    /// clone per publish. Single-consumer topics always work this way.
+    MoveToLast,
+}
+
+/// How a topic declared with `Builder::declare_topic()` behaves. On a
+/// single-consumer topic, the clone strategy is moot, since messages are
+/// always moved.
+#[derive(Clone, Debug, Default)]
+pub struct TopicOptions {
+    priority: Option<Priority>,
+    single_consumer: bool,
+    retain: usize,
+    retain_for: Option<Duration>,
+    capacity: Option<usize>,
+    order: TopicOrder,
+    clone_strategy: CloneStrategy,
+    docs: TopicDocs,
+}
+
+/// What a topic is for, as written down by `TopicOptions::description()` and
+/// `TopicOptions::schema()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TopicDocs {
+    /// What the topic carries, and who is expected to publish and read it.
+    pub description: Option<String>,
+    /// The expected shape of its content, in whatever notation the team uses.
+    pub schema: Option<String>,
+}
+
+impl TopicOptions {
+    /// Options that declare the topic and nothing more.
+    pub fn new() -> Self {
+        TopicOptions::default()
+    }
+
+    /// Sets the topic's priority, as with `Builder::topic_priority()`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Makes the topic single-consumer, as with `Builder::declare_single_consumer()`.
+    pub fn single_consumer(mut self) -> Self {
+        self.single_consumer = true;
+        self
+    }
+
+    /// Keeps the last `messages` published on the topic, and sends them to
+    /// each new subscriber before anything else. Zero keeps nothing, unless
+    /// `retain_for()` says otherwise.
+    pub fn retain_last(mut self, messages: usize) -> Self {
+        self.retain = messages;
+        self
+    }
+
+    /// Keeps messages published on the topic for `age`, by the network's
+    /// clock, and sends them to each new subscriber before anything else.
+    /// With `retain_last()` as well, a message is kept while it is both
+    /// recent enough and among the last so many.
+    ///
+    /// Messages past their age are never sent. Their memory is given back
+    /// when the topic is next published to or subscribed to, or when
+    /// `Publisher::expire_retained()` runs, as `Publisher::fire_timers()`
+    /// does each time it is called.
+    pub fn retain_for(mut self, age: Duration) -> Self {
+        self.retain_for = Some(age);
+        self
+    }
+
+    /// Skips any subscriber that already has `messages` waiting to be read,
+    /// reporting the drop as `DropReason::QueueFull`.
+    pub fn capacity(mut self, messages: usize) -> Self {
+        self.capacity = Some(messages);
+        self
+    }
+
+    /// Sets the order subscribers see messages in.
+    pub fn order(mut self, order: TopicOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Sets how subscribers' copies are made.
+    pub fn clone_strategy(mut self, strategy: CloneStrategy) -> Self {
+        self.clone_strategy = strategy;
+        self
+    }
+
+    /// Describes what the topic is for.
+    pub fn description(mut self, text: &str) -> Self {
+        self.docs.description = Some(text.to_owned());
+        self
+    }
+
+    /// Records the shape its content is expected to have. The network doesn't
+    /// check content against it.
+    pub fn schema(mut self, schema: &str) -> Self {
+        self.docs.schema = Some(schema.to_owned());
+        self
+    }
+}
+
+/// A declared topic's settings, as the routing code consults them. Its
+/// capacity and retention can be changed while the network runs, by
+/// `Publisher::apply_config()`.
+pub(crate) struct TopicSettings<Content> {
+    /// The capacity, or `NO_CAPACITY`.
+    capacity: AtomicUsize,
+    /// Held while a message is delivered, for totally ordered topics.
+    pub(crate) order: Option<Mutex<()>>,
+    pub(crate) move_to_last: bool,
+    /// Whether the topic keeps any messages, so that publishing on one that
+    /// doesn't needn't lock `retained`.
+    retaining: AtomicBool,
+    retained: Mutex<Retained<Content>>,
+    pub(crate) docs: TopicDocs,
+}
+
+/// Stands for a topic without a capacity.
+const NO_CAPACITY: usize = usize::MAX;
+
+/// A topic's last few messages, numbered from one in the order they were
+/// published, with when they were, and how many and for how long to keep.
+struct Retained<Content> {
+    retain: usize,
+    retain_for: Option<Duration>,
+    published: u64,
+    messages: VecDeque<(u64, Instant, Content)>,
+}
+
+impl<Content: Clone> TopicSettings<Content> {
+    fn new(options: &TopicOptions) -> Self {
+        TopicSettings {
+            capacity: AtomicUsize::new(options.capacity.unwrap_or(NO_CAPACITY)),
+            order: if options.order == TopicOrder::Total { Some(Mutex::new(())) } else { None },
+            move_to_last: options.clone_strategy == CloneStrategy::MoveToLast,
+            retaining: AtomicBool::new(options.retain > 0 || options.retain_for.is_some()),
+            retained: Mutex::new(Retained {
+                retain: options.retain,
+                retain_for: options.retain_for,
+                published: 0,
+                messages: VecDeque::new(),
+            }),
+            docs: options.docs.clone(),
+        }
+    }
+
+    /// How many messages a subscriber may have waiting before it is skipped.
+    pub(crate) fn capacity(&self) -> Option<usize> {
+        Some(self.capacity.load(Ordering::Relaxed)).filter(|&capacity| capacity != NO_CAPACITY)
+    }
+
+    pub(crate) fn set_capacity(&self, capacity: Option<usize>) {
+        self.capacity.store(capacity.unwrap_or(NO_CAPACITY), Ordering::Relaxed);
+    }
+
+    /// Keeps `retain` messages, or those younger than `retain_for`, or both,
+    /// from now on, forgetting any already kept beyond that at `now`.
+    pub(crate) fn set_retention(&self, retain: usize, retain_for: Option<Duration>, now: Instant) {
+        let mut retained = self.retained.lock().unwrap_or_else(|e| e.into_inner());
+        retained.retain = retain;
+        retained.retain_for = retain_for;
+        let keeping = retain > 0 || retain_for.is_some();
+        if !keeping {
+            retained.messages.clear();
+        } else if retain > 0 && retained.messages.len() > retain {
+            let excess = retained.messages.len() - retain;
+            retained.messages.drain(.. excess);
+        }
+        Self::expire_from(&mut retained, now);
+        self.retaining.store(keeping, Ordering::Release);
+    }
+
+    /// Remembers a message published at `now`, if the topic keeps any.
+    pub(crate) fn retain(&self, content: &Content, now: Instant) {
+        if !self.retaining.load(Ordering::Acquire) { return; }
+        let mut retained = self.retained.lock().unwrap_or_else(|e| e.into_inner());
+        Self::expire_from(&mut retained, now);
+        let retain = retained.retain;
+        if retain > 0 && retained.messages.len() >= retain { retained.messages.pop_front(); }
+        retained.published += 1;
+        let number = retained.published;
+        retained.messages.push_back((number, now, content.clone()));
+    }
+
+    /// The messages a new subscriber should be sent at `now`, oldest first.
+    pub(crate) fn retained(&self, now: Instant) -> Vec<Content> {
+        self.retained_after(0, now)
+    }
+
+    /// Like `retained()`, but leaves out the first `seen` messages ever
+    /// retained.
+    pub(crate) fn retained_after(&self, seen: u64, now: Instant) -> Vec<Content> {
+        let mut retained = self.retained.lock().unwrap_or_else(|e| e.into_inner());
+        Self::expire_from(&mut retained, now);
+        retained.messages.iter().filter(|&&(number, _, _)| number > seen).map(|(_, _, content)| content.clone()).collect()
+    }
+
+    /// Forgets messages too old to keep at `now`, returning how many.
+    pub(crate) fn expire(&self, now: Instant) -> usize {
+        if !self.retaining.load(Ordering::Acquire) { return 0; }
+        Self::expire_from(&mut self.retained.lock().unwrap_or_else(|e| e.into_inner()), now)
+    }
+
+    fn expire_from(retained: &mut Retained<Content>, now: Instant) -> usize {
+        let age = match retained.retain_for {
+            Some(age) => age,
+            None => return 0,
+        };
+        let before = retained.messages.len();
+        while retained.messages.front().is_some_and(|&(_, at, _)| now.saturating_duration_since(at) > age) {
+            retained.messages.pop_front();
+        }
+        before - retained.messages.len()
+    }
+
+    /// How many messages have been retained so far, including ones since
+    /// pushed out.
+    pub(crate) fn published(&self) -> u64 {
+        self.retained.lock().unwrap_or_else(|e| e.into_inner()).published
+    }
+}
+
+struct State<Topic> {
+    declared: HashSet<Topic>,
+    sealed: bool,
+    retired: HashSet<Topic>,
+}
+
+/// Which topics the network knows about.
+pub(crate) struct TopicSet<Topic> {
+    state: RwLock<State<Topic>>,
+}
+
+impl<Topic: Hash + Eq + Clone> TopicSet<Topic> {
+    pub(crate) fn new() -> Self {
+        let state = State { declared: HashSet::new(), sealed: false, retired: HashSet::new() };
+        TopicSet { state: RwLock::new(state) }
+    }
+
+    /// Refuses topics that were retired, or never declared in a sealed set.
+    pub(crate) fn check(&self, topic: &Topic) -> Result<(), PublishError> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        if state.retired.contains(topic) { return Err(PublishError::Retired); }
+        if state.sealed && !state.declared.contains(topic) { return Err(PublishError::Undeclared); }
+        Ok(())
+    }
+
+    pub(crate) fn is_retired(&self, topic: &Topic) -> bool {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).retired.contains(topic)
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Declares `topic`, with the given options. Declaring a topic again
+    /// replaces its settings, except that its priority stays set and a
+    /// single-consumer topic stays one.
+    pub fn declare_topic(&mut self, topic: Topic, options: TopicOptions) {
+        self.bus.topics.state.write().unwrap_or_else(|e| e.into_inner()).declared.insert(topic.clone());
+        self.bus.topic_settings.insert(topic.clone(), TopicSettings::new(&options));
+        if let Some(priority) = options.priority {
+            self.topic_priority(topic.clone(), priority);
+        }
+        if options.single_consumer && !self.bus.single_consumer.contains_key(&topic) {
+            self.declare_single_consumer(topic);
+        }
+    }
+
+    /// Refuses publishes on topics that haven't been declared, with
+    /// `PublishError::Undeclared`. Topics can still be declared afterwards,
+    /// until the network is built.
+    pub fn seal_topics(&mut self) {
+        self.bus.topics.state.write().unwrap_or_else(|e| e.into_inner()).sealed = true;
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Removes every subscription to `topic` and refuses later publishes on
+    /// it, with `PublishError::Retired`. Subscribers keep what they had
+    /// already received, and new subscriptions never receive anything on it.
+    /// Returns false if it was already retired.
+    pub fn retire_topic(&self, topic: &Topic) -> bool {
+        let bus = self.bus();
+        let mut state = bus.topics.state.write().unwrap_or_else(|e| e.into_inner());
+        if !state.retired.insert(topic.clone()) { return false; }
+        drop(state);
+        bus.routing.write().unwrap_or_else(|e| e.into_inner()).remove_topic(topic);
+        bus.route_changes.notify();
+        true
+    }
+
+    /// Forgets retained messages that have outlived `TopicOptions::retain_for()`
+    /// on every topic, returning how many.
+    pub fn expire_retained(&self) -> usize {
+        let bus = self.bus();
+        let now = bus.clock.now();
+        bus.topic_settings.values().map(|settings| settings.expire(now)).sum()
+    }
+
+    /// Returns true once `topic` has been retired.
+    pub fn is_retired(&self, topic: &Topic) -> bool {
+        self.bus().topics.is_retired(topic)
+    }
+
+    /// Returns the documentation `topic` was declared with, if it was
+    /// declared.
+    pub fn describe(&self, topic: &Topic) -> Option<&TopicDocs> {
+        self.bus().topic_settings.get(topic).map(|settings| &settings.docs)
+    }
+}
@@ -0,0 +1,55 @@
+//! Per-topic limits on the size of published content.
+
+/// What to do with content over a topic's size limit.
+enum Oversize<Topic, Content> {
+    Reject,
+    Truncate(Box<dyn Fn(Content, usize) -> Content + Send + Sync>),
+    Reroute(Topic),
+}
+
+/// What becomes of content once its topic's size limit has been applied.
+pub(crate) enum Enforced<'a, Topic, Content> {
+    Deliver(Content),
+    Reroute(&'a Topic, Content),
+    Refuse,
+}
+
+/// A topic's maximum content size, as measured by a user-supplied function.
+pub(crate) struct SizeLimit<Topic, Content> {
+    bytes: usize,
+    sizer: Box<dyn Fn(&Content) -> usize + Send + Sync>,
+    oversize: Oversize<Topic, Content>,
+}
+
+impl<Topic, Content> SizeLimit<Topic, Content> {
+    pub(crate) fn reject<F>(bytes: usize, sizer: F) -> Self
+        where F: Fn(&Content) -> usize + Send + Sync + 'static
+    {
+        SizeLimit { bytes, sizer: Box::new(sizer), oversize: Oversize::Reject }
+    }
+
+    pub(crate) fn truncate<F, G>(bytes: usize, sizer: F, truncate: G) -> Self
+        where F: Fn(&Content) -> usize + Send + Sync + 'static,
+              G: Fn(Content, usize) -> Content + Send + Sync + 'static,
+    {
+        SizeLimit { bytes, sizer: Box::new(sizer), oversize: Oversize::Truncate(Box::new(truncate)) }
+    }
+
+    pub(crate) fn reroute<F>(bytes: usize, sizer: F, overflow: Topic) -> Self
+        where F: Fn(&Content) -> usize + Send + Sync + 'static
+    {
+        SizeLimit { bytes, sizer: Box::new(sizer), oversize: Oversize::Reroute(overflow) }
+    }
+
+    /// Passes content that fits through unchanged, and truncates, reroutes
+    /// or refuses anything larger.
+    pub(crate) fn enforce(&self, content: Content) -> Enforced<'_, Topic, Content> {
+        if (self.sizer)(&content) <= self.bytes { return Enforced::Deliver(content); }
+
+        match self.oversize {
+            Oversize::Reject => Enforced::Refuse,
+            Oversize::Truncate(ref truncate) => Enforced::Deliver(truncate(content, self.bytes)),
+            Oversize::Reroute(ref overflow) => Enforced::Reroute(overflow, content),
+        }
+    }
+}
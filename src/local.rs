@@ -0,0 +1,62 @@
+//! Queues for subscribers that live on the publishing thread.
+
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::thread::{self, ThreadId};
+
+use super::sync::{AtomicBool, Ordering};
+
+/// A queue that only the thread that created it may touch, so it needs no
+/// synchronization. Every access checks the thread first.
+pub(crate) struct LocalQueue<M> {
+    owner: ThreadId,
+    queue: UnsafeCell<VecDeque<M>>,
+    /// Set when a message had to be sent from another thread, so the owner
+    /// can be told when it next reads.
+    strayed: AtomicBool,
+}
+
+// The queue is only reachable from `owner`, whoever holds the reference.
+unsafe impl<M: Send> Sync for LocalQueue<M> {}
+
+impl<M> LocalQueue<M> {
+    pub(crate) fn new() -> Self {
+        LocalQueue {
+            owner: thread::current().id(),
+            queue: UnsafeCell::new(VecDeque::new()),
+            strayed: AtomicBool::new(false),
+        }
+    }
+
+    fn is_owner(&self) -> bool {
+        thread::current().id() == self.owner
+    }
+
+    /// Queues `message` if called on the owning thread, or hands it back to
+    /// be sent some other way.
+    pub(crate) fn push(&self, message: M) -> Result<(), M> {
+        if !self.is_owner() {
+            self.strayed.store(true, Ordering::Relaxed);
+            return Err(message);
+        }
+        // Only the owning thread gets here, and no reference into the queue
+        // outlives these calls.
+        unsafe { (*self.queue.get()).push_back(message) };
+        Ok(())
+    }
+
+    /// Takes the oldest message. Panics on any thread but the owner's, and
+    /// in debug builds if a message was pushed from another thread.
+    pub(crate) fn pop(&self) -> Option<M> {
+        assert!(self.is_owner(), "a same-thread subscriber was read from another thread");
+        debug_assert!(!self.strayed.swap(false, Ordering::Relaxed),
+                      "a same-thread subscriber was sent a message from another thread");
+        self.try_pop()
+    }
+
+    /// Like `pop()`, but returns `None` on other threads.
+    pub(crate) fn try_pop(&self) -> Option<M> {
+        if !self.is_owner() { return None; }
+        unsafe { (*self.queue.get()).pop_front() }
+    }
+}
@@ -0,0 +1,234 @@
+//! A convention for log messages carried on a network.
+//!
+//! Log records travel as `LogRecord` content on one topic per `Level`, named
+//! by `topic()`: `"log/warn"`, `"log/error"` and so on for the prefix
+//! `"log"`. A subscriber that only wants warnings and worse subscribes to
+//! `topics_from("log", Level::Warn)`. With the `log` feature, `BusLogger`
+//! installs itself as the `log` crate's logger, so the `log!` family of
+//! macros everywhere in the program publish onto the network.
+
+use std::fmt;
+use std::hash::Hash;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::Publisher;
+
+/// How severe a log record is, least first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Level {
+    /// Step-by-step detail.
+    Trace,
+    /// Detail for whoever is debugging.
+    Debug,
+    /// Normal operation worth noting.
+    Info,
+    /// Something unexpected that was coped with.
+    Warn,
+    /// Something that failed.
+    Error,
+}
+
+impl Level {
+    /// Every level, least severe first.
+    pub const ALL: [Level; 5] = [Level::Trace, Level::Debug, Level::Info, Level::Warn, Level::Error];
+
+    /// The level's name in lower case, as used in topics.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One log message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LogRecord {
+    /// How severe it is.
+    pub level: Level,
+    /// What it is about, usually the module that logged it.
+    pub target: String,
+    /// The message itself.
+    pub message: String,
+    /// The source file it was logged from, if known.
+    pub file: Option<String>,
+    /// The line it was logged from, if known.
+    pub line: Option<u32>,
+}
+
+impl LogRecord {
+    /// A record with no source location.
+    pub fn new(level: Level, target: &str, message: &str) -> Self {
+        LogRecord { level, target: target.to_owned(), message: message.to_owned(), file: None, line: None }
+    }
+}
+
+/// The topic records at `level` are published on, under `prefix`.
+pub fn topic(prefix: &str, level: Level) -> String {
+    format!("{}/{}", prefix, level)
+}
+
+/// The topics for `min` and every more severe level, under `prefix`.
+pub fn topics_from(prefix: &str, min: Level) -> Vec<String> {
+    Level::ALL.iter().filter(|&&level| level >= min).map(|&level| topic(prefix, level)).collect()
+}
+
+impl<Topic: Hash + Eq + Clone> Publisher<Topic, LogRecord> {
+    /// Publishes `record` on the topic `topic` gives for its level.
+    pub fn log_record<F: Fn(Level) -> Topic>(&self, topic: F, record: LogRecord) {
+        self.publish(topic(record.level), record);
+    }
+}
+
+#[cfg(feature = "log")]
+pub use self::bus_logger::BusLogger;
+
+#[cfg(feature = "log")]
+mod bus_logger {
+    use std::hash::Hash;
+
+    use log::{self, Log, Metadata, Record, SetLoggerError};
+
+    use super::{Level, LogRecord};
+    use Publisher;
+
+    impl From<log::Level> for Level {
+        fn from(level: log::Level) -> Self {
+            match level {
+                log::Level::Trace => Level::Trace,
+                log::Level::Debug => Level::Debug,
+                log::Level::Info => Level::Info,
+                log::Level::Warn => Level::Warn,
+                log::Level::Error => Level::Error,
+            }
+        }
+    }
+
+    fn filter(level: Level) -> log::LevelFilter {
+        match level {
+            Level::Trace => log::LevelFilter::Trace,
+            Level::Debug => log::LevelFilter::Debug,
+            Level::Info => log::LevelFilter::Info,
+            Level::Warn => log::LevelFilter::Warn,
+            Level::Error => log::LevelFilter::Error,
+        }
+    }
+
+    /// A `log::Log` that publishes every record onto a network.
+    pub struct BusLogger<Topic: Hash + Eq + Clone> {
+        publisher: Publisher<Topic, LogRecord>,
+        topic: Box<dyn Fn(Level) -> Topic + Send + Sync>,
+        min: Level,
+    }
+
+    impl<Topic> BusLogger<Topic>
+        where Topic: Hash + Eq + Clone + Send + Sync + 'static
+    {
+        /// Publishes records through `publisher`, on the topic `topic` gives
+        /// for each level. Everything from `Level::Info` up is published
+        /// unless `min_level()` says otherwise.
+        pub fn new<F>(publisher: Publisher<Topic, LogRecord>, topic: F) -> Self
+            where F: Fn(Level) -> Topic + Send + Sync + 'static
+        {
+            BusLogger { publisher, topic: Box::new(topic), min: Level::Info }
+        }
+
+        /// Publishes records at `level` and above.
+        pub fn min_level(self, level: Level) -> Self {
+            BusLogger { min: level, ..self }
+        }
+
+        /// Makes this the program's logger. Fails if a logger was already
+        /// installed.
+        pub fn install(self) -> Result<(), SetLoggerError> {
+            let max = filter(self.min);
+            log::set_boxed_logger(Box::new(self))?;
+            log::set_max_level(max);
+            Ok(())
+        }
+    }
+
+    impl<Topic> Log for BusLogger<Topic>
+        where Topic: Hash + Eq + Clone + Send + Sync + 'static
+    {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            Level::from(metadata.level()) >= self.min
+        }
+
+        fn log(&self, record: &Record) {
+            if !self.enabled(record.metadata()) { return; }
+            let level = Level::from(record.level());
+            self.publisher.publish((self.topic)(level), LogRecord {
+                level,
+                target: record.target().to_owned(),
+                message: record.args().to_string(),
+                file: record.file().map(str::to_owned),
+                line: record.line(),
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use logging::{topic, topics_from};
+
+        #[test]
+        fn records_are_published_by_level() {
+            let mut builder = Publisher::new();
+            let serious = builder.add_subscriber(&topics_from("log", Level::Warn));
+            let publisher = builder.build();
+
+            let logger = BusLogger::new(publisher, |level| topic("log", level)).min_level(Level::Debug);
+            assert!(!logger.enabled(&Metadata::builder().level(log::Level::Trace).build()));
+            for (level, text) in [(log::Level::Info, "started"), (log::Level::Error, "disk full")] {
+                logger.log(&Record::builder()
+                    .level(level)
+                    .target("storage")
+                    .args(format_args!("{}", text))
+                    .line(Some(7))
+                    .build());
+            }
+
+            let (topic, record) = serious.fetch().pop().unwrap();
+            assert_eq!(topic, "log/error");
+            assert_eq!(record.message, "disk full");
+            assert_eq!((record.target.as_str(), record.line), ("storage", Some(7)));
+            assert_eq!(serious.pending(), 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn subscribers_pick_a_minimum_level() {
+        assert_eq!(topics_from("log", Level::Warn), vec!["log/warn", "log/error"]);
+
+        let mut builder = Publisher::new();
+        let errors = builder.add_subscriber(&topics_from("log", Level::Error));
+        let publisher = builder.build();
+        publisher.log_record(|level| topic("log", level), LogRecord::new(Level::Info, "app", "hello"));
+        publisher.log_record(|level| topic("log", level), LogRecord::new(Level::Error, "app", "oops"));
+
+        let messages: Vec<String> = errors.fetch().into_iter().map(|(_, record)| record.message).collect();
+        assert_eq!(messages, vec!["oops"]);
+    }
+}
@@ -0,0 +1,136 @@
+//! Handling each message once, even when it is sent more than once.
+//!
+//! A publisher that retries a send it isn't sure arrived may deliver the same
+//! message twice. If every attempt carries the same idempotency key, as the
+//! envelope's `id`, a subscriber read through `Subscriber::effectively_once()`
+//! hands over repeats of a message only until it is acknowledged with
+//! `EffectivelyOnce::ack()`; after that, further copies are dropped as they
+//! are read, and reported as `DropReason::Duplicate`. A message that was
+//! read but never acknowledged, because handling it failed, is still handed
+//! over when it is sent again, which is what makes retrying safe.
+
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use super::{DropReason, Subscriber};
+use super::envelope::Envelope;
+
+/// The keys acknowledged lately, oldest first.
+struct Acked {
+    order: VecDeque<u64>,
+    keys: HashSet<u64>,
+}
+
+/// A subscriber that suppresses copies of messages already acknowledged.
+/// Made by `Subscriber::effectively_once()`.
+pub struct EffectivelyOnce<Topic, Body> {
+    subscriber: Subscriber<Topic, Envelope<Topic, Body>>,
+    acked: RefCell<Acked>,
+    remember: usize,
+}
+
+impl<Topic, Body> Subscriber<Topic, Envelope<Topic, Body>> {
+    /// Suppresses envelopes whose id was already acknowledged, remembering
+    /// the last `remember` ids acknowledged. Envelopes without an id are
+    /// always handed over.
+    pub fn effectively_once(self, remember: usize) -> EffectivelyOnce<Topic, Body> {
+        let acked = Acked { order: VecDeque::new(), keys: HashSet::new() };
+        EffectivelyOnce { subscriber: self, acked: RefCell::new(acked), remember }
+    }
+}
+
+impl<Topic, Body> EffectivelyOnce<Topic, Body> {
+    /// Takes the next pending message that hasn't been acknowledged before.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&self) -> Option<(Topic, Envelope<Topic, Body>)> {
+        while let Some((topic, envelope)) = self.subscriber.next() {
+            if self.is_new(&topic, &envelope) { return Some((topic, envelope)); }
+        }
+        None
+    }
+
+    /// Like `next()`, but waits up to `timeout` for a message that hasn't
+    /// been acknowledged before. Repeats don't cut the wait short.
+    pub fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Envelope<Topic, Body>)> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let (topic, envelope) = self.subscriber.next_timeout(left)?;
+            if self.is_new(&topic, &envelope) { return Some((topic, envelope)); }
+        }
+    }
+
+    /// Returns the number of messages waiting, including any repeats that
+    /// will be dropped when they are read.
+    pub fn pending(&self) -> usize {
+        self.subscriber.pending()
+    }
+
+    /// Reports `envelope` as a duplicate if it was acknowledged before.
+    fn is_new(&self, topic: &Topic, envelope: &Envelope<Topic, Body>) -> bool {
+        let repeat = envelope.id.is_some_and(|id| self.acked.borrow().keys.contains(&id));
+        if repeat { self.subscriber.drops.notify(topic, DropReason::Duplicate); }
+        !repeat
+    }
+
+    /// Takes every pending message that hasn't been acknowledged before.
+    pub fn fetch(&self) -> Vec<(Topic, Envelope<Topic, Body>)> {
+        let mut messages = vec![];
+        while let Some(message) = self.next() { messages.push(message); }
+        messages
+    }
+
+    /// Marks `envelope` as handled, so copies of it read later are dropped.
+    /// Does nothing for envelopes without an id.
+    pub fn ack(&self, envelope: &Envelope<Topic, Body>) {
+        let id = match envelope.id {
+            Some(id) => id,
+            None => return,
+        };
+        let mut acked = self.acked.borrow_mut();
+        if !acked.keys.insert(id) { return; }
+        acked.order.push_back(id);
+        while acked.order.len() > self.remember {
+            if let Some(old) = acked.order.pop_front() { acked.keys.remove(&old); }
+        }
+    }
+
+    /// Returns the subscriber, forgetting what was acknowledged.
+    pub fn into_inner(self) -> Subscriber<Topic, Envelope<Topic, Body>> {
+        self.subscriber
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Publisher;
+
+    #[test]
+    fn acknowledged_messages_are_not_handled_again() {
+        let mut builder = Publisher::new();
+        let orders = builder.add_subscriber(&["order"]).effectively_once(2);
+        let publisher = builder.build();
+
+        publisher.publish("order", Envelope::new("apples").with_id(1));
+        publisher.publish("order", Envelope::new("pears").with_id(2));
+        let first = orders.fetch();
+        assert_eq!(first.len(), 2);
+        orders.ack(&first[0].1);
+
+        // Both are sent again: the apples were handled, the pears weren't.
+        publisher.publish("order", Envelope::new("apples").with_id(1));
+        publisher.publish("order", Envelope::new("pears").with_id(2));
+        publisher.publish("order", Envelope::new("plums"));
+        let bodies: Vec<&str> = orders.fetch().into_iter().map(|(_, e)| e.body).collect();
+        assert_eq!(bodies, vec!["pears", "plums"]);
+
+        // Only the last two acknowledgements are remembered.
+        for id in [2, 3] { orders.ack(&Envelope::new("").with_id(id)); }
+        publisher.publish("order", Envelope::new("apples").with_id(1));
+        publisher.publish("order", Envelope::new("pears").with_id(2));
+        let bodies: Vec<&str> = orders.fetch().into_iter().map(|(_, e)| e.body).collect();
+        assert_eq!(bodies, vec!["apples"]);
+    }
+}
@@ -0,0 +1,359 @@
+//! Per-subscriber delivery options.
+
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{Clock, DropReason};
+use super::wake::Wake;
+use super::sync::{AtomicBool, AtomicU64, AtomicUsize, Mutex, Ordering};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use SystemClock;
+
+    fn clock() -> Arc<dyn Clock> {
+        Arc::new(SystemClock)
+    }
+
+    #[test]
+    fn every_nth_message() {
+        let sampler = Sampler::new(Sampling::Every(3));
+        let admitted: Vec<bool> = (0 .. 7).map(|_| sampler.admit()).collect();
+        assert_eq!(admitted, vec![true, false, false, true, false, false, true]);
+    }
+
+    #[test]
+    fn chance_extremes() {
+        let never = Sampler::new(Sampling::Chance(0.0));
+        let always = Sampler::new(Sampling::Chance(1.0));
+        for _ in 0 .. 100 {
+            assert!(!never.admit());
+            assert!(always.admit());
+        }
+    }
+
+    #[test]
+    fn throttle_admits_one_per_interval() {
+        let options = SubscriptionOptions::new().throttle(Duration::from_secs(60));
+        let admission = Admission::new(&options, &clock()).unwrap();
+        assert_eq!(admission.admit(), Ok(()));
+        assert_eq!(admission.admit(), Err(Some(DropReason::Throttled)));
+    }
+
+    #[test]
+    fn debounce_keeps_latest() {
+        let options = SubscriptionOptions::new().debounce(Duration::from_millis(10));
+        let admission = Admission::new(&options, &clock()).unwrap();
+        let debouncer = Debouncer::new(&options, &admission).unwrap();
+
+        for i in 0 .. 3 {
+            admission.sent();
+            debouncer.hold(i);
+        }
+        assert_eq!(debouncer.release(), None);
+
+        ::std::thread::sleep(Duration::from_millis(15));
+        assert_eq!(debouncer.release(), Some(2));
+        assert_eq!(debouncer.release(), None);
+    }
+}
+
+/// Settings for a single subscriber, passed to `Builder::add_subscriber_with()`.
+#[derive(Clone, Debug, Default)]
+pub struct SubscriptionOptions {
+    sampling: Option<Sampling>,
+    throttle: Option<Duration>,
+    debounce: Option<Duration>,
+    shutdown_phase: Option<u32>,
+    same_thread: bool,
+    once: bool,
+    order: InboxOrder,
+    pub(crate) wake: Option<Wake>,
+}
+
+/// Which pending message a subscriber reads next. See
+/// `SubscriptionOptions::inbox_order()`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum InboxOrder {
+    /// The oldest, so messages are read in the order they arrived.
+    #[default]
+    Fifo,
+    /// The newest, for state where the latest value matters most and older
+    /// ones can wait, or be skipped.
+    Lifo,
+}
+
+impl SubscriptionOptions {
+    /// Options that behave exactly like `Builder::add_subscriber()`.
+    pub fn new() -> Self {
+        SubscriptionOptions::default()
+    }
+
+    /// Delivers only a sample of the subscriber's traffic. Sampling happens
+    /// while routing, so skipped messages are never cloned for this subscriber.
+    pub fn sample(mut self, sampling: Sampling) -> Self {
+        self.sampling = Some(sampling);
+        self
+    }
+
+    /// Delivers at most one message per `interval`: the first message gets
+    /// through, and anything else published before the interval ends is
+    /// dropped while routing.
+    pub fn throttle(mut self, interval: Duration) -> Self {
+        self.throttle = Some(interval);
+        self
+    }
+
+    /// Holds back messages until none have arrived for `interval`, then
+    /// delivers only the latest one. Superseded messages are discarded when the
+    /// subscriber reads its inbox. This applies across all of the subscriber's
+    /// topics, not to each topic separately.
+    pub fn debounce(mut self, interval: Duration) -> Self {
+        self.debounce = Some(interval);
+        self
+    }
+
+    /// Puts the subscriber in the given phase of `Publisher::shutdown()`.
+    /// Lower phases are asked to shut down first, so for example input could
+    /// be phase 0, logic 1, rendering 2 and logging 3.
+    pub fn shutdown_phase(mut self, phase: u32) -> Self {
+        self.shutdown_phase = Some(phase);
+        self
+    }
+
+    pub(crate) fn phase(&self) -> Option<u32> {
+        self.shutdown_phase
+    }
+
+    /// Declares that the subscriber will only be read on the thread that
+    /// creates it, and that messages for it are mostly published on that same
+    /// thread, as in a main loop. Those messages go into a plain queue instead
+    /// of a channel, which skips the synchronization.
+    ///
+    /// Messages published on other threads still arrive, through the usual
+    /// channel, but may overtake ones already queued; in debug builds, the
+    /// next read after such a message panics, on the subscriber's thread.
+    /// Reading the subscriber on another thread panics.
+    pub fn same_thread(mut self) -> Self {
+        self.same_thread = true;
+        self
+    }
+
+    pub(crate) fn is_same_thread(&self) -> bool {
+        self.same_thread
+    }
+
+    /// Sets which pending message is read first. Messages kept back by
+    /// `Subscriber::wait_for()` are still read before anything else.
+    pub fn inbox_order(mut self, order: InboxOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub(crate) fn is_lifo(&self) -> bool {
+        self.order == InboxOrder::Lifo
+    }
+
+    /// Delivers a single message and then stops. Used by
+    /// `Publisher::subscribe_once()`, which also cleans up the routes.
+    pub(crate) fn once(mut self) -> Self {
+        self.once = true;
+        self
+    }
+}
+
+/// How a sampling subscriber picks which messages to receive.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sampling {
+    /// Receive the first message and every `n`th one after it. Zero is
+    /// treated as one.
+    Every(usize),
+    /// Receive each message independently with the given probability, from
+    /// `0.0` (never) to `1.0` (always).
+    Chance(f64),
+}
+
+/// Routing-side state for one subscriber's options. It is shared by the
+/// subscriber's routes on every topic, in every clone of the publisher.
+pub(crate) struct Admission {
+    sampler: Option<Sampler>,
+    throttle: Option<(Duration, Mutex<Option<Instant>>)>,
+    last_sent: Option<Arc<Mutex<Instant>>>,
+    fired: Option<AtomicBool>,
+    wake: Option<Wake>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Admission {
+    /// Returns `None` when the options don't affect routing at all, so plain
+    /// subscribers pay nothing for this.
+    pub(crate) fn new(options: &SubscriptionOptions, clock: &Arc<dyn Clock>) -> Option<Self> {
+        let admission = Admission {
+            sampler: options.sampling.map(Sampler::new),
+            throttle: options.throttle.map(|d| (d, Mutex::new(None))),
+            last_sent: options.debounce.map(|_| Arc::new(Mutex::new(clock.now()))),
+            fired: if options.once { Some(AtomicBool::new(false)) } else { None },
+            wake: options.wake.clone(),
+            clock: clock.clone(),
+        };
+
+        if admission.sampler.is_none()
+            && admission.throttle.is_none()
+            && admission.last_sent.is_none()
+            && admission.fired.is_none()
+            && admission.wake.is_none()
+        {
+            None
+        } else {
+            Some(admission)
+        }
+    }
+
+    /// Decides whether a message should be delivered at all, before anything
+    /// is cloned for it. A refusal carries the reason, except when a
+    /// single-delivery subscriber already has its message, which is no loss.
+    pub(crate) fn admit(&self) -> Result<(), Option<DropReason>> {
+        if let Some(ref sampler) = self.sampler {
+            if !sampler.admit() { return Err(Some(DropReason::Sampled)); }
+        }
+
+        if let Some((interval, ref last)) = self.throttle {
+            let mut last = last.lock().unwrap_or_else(|e| e.into_inner());
+            let now = self.clock.now();
+            if last.is_some_and(|t| now.duration_since(t) < interval) {
+                return Err(Some(DropReason::Throttled));
+            }
+            *last = Some(now);
+        }
+
+        // Checked last, so a message turned away above doesn't use up the
+        // one delivery.
+        if let Some(ref fired) = self.fired {
+            if fired.swap(true, Ordering::AcqRel) { return Err(None); }
+        }
+
+        Ok(())
+    }
+
+    /// True once a single-delivery subscriber has had its message, meaning
+    /// its routes can be removed.
+    pub(crate) fn is_spent(&self) -> bool {
+        self.fired.as_ref().is_some_and(|f| f.load(Ordering::Acquire))
+    }
+
+    /// Called after a message has been put in the subscriber's empty queue.
+    pub(crate) fn woken(&self) {
+        if let Some(ref wake) = self.wake { wake.wake(); }
+    }
+
+    /// Called just before a message is handed to the subscriber's inbox.
+    pub(crate) fn sent(&self) {
+        if let Some(ref last_sent) = self.last_sent {
+            *last_sent.lock().unwrap_or_else(|e| e.into_inner()) = self.clock.now();
+        }
+    }
+}
+
+/// Subscriber-side half of debouncing. The routing side stamps the time of
+/// each delivery; this side keeps only the newest message and releases it once
+/// that stamp is old enough.
+pub(crate) struct Debouncer<M> {
+    interval: Duration,
+    last_sent: Arc<Mutex<Instant>>,
+    held: RefCell<Option<M>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<M> Debouncer<M> {
+    pub(crate) fn new(options: &SubscriptionOptions, admission: &Admission) -> Option<Self> {
+        let interval = options.debounce?;
+        let last_sent = admission.last_sent.clone()?;
+
+        Some(Debouncer {
+            interval,
+            last_sent,
+            held: RefCell::new(None),
+            clock: admission.clock.clone(),
+        })
+    }
+
+    /// Replaces any held message with a newer one, returning the old one.
+    pub(crate) fn hold(&self, message: M) -> Option<M> {
+        self.held.borrow_mut().replace(message)
+    }
+
+    pub(crate) fn is_holding(&self) -> bool {
+        self.held.borrow().is_some()
+    }
+
+    /// How long until the held message can be released, if there is one.
+    pub(crate) fn until_release(&self) -> Option<Duration> {
+        if !self.is_holding() { return None; }
+        let last_sent = *self.last_sent.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = self.clock.now().saturating_duration_since(last_sent);
+        Some(self.interval.checked_sub(elapsed).unwrap_or_default())
+    }
+
+    /// Hands over the held message, however recently it arrived.
+    pub(crate) fn take(self) -> Option<M> {
+        self.held.into_inner()
+    }
+
+    /// Hands over the held message if things have been quiet long enough.
+    pub(crate) fn release(&self) -> Option<M> {
+        match self.until_release() {
+            Some(wait) if wait.is_zero() => self.held.borrow_mut().take(),
+            _ => None,
+        }
+    }
+}
+
+/// Shared sampling state for one subscriber. Its routes on every topic, in
+/// every clone of the publisher, draw from the same counter.
+struct Sampler {
+    sampling: Sampling,
+    counter: AtomicUsize,
+    rng: AtomicU64,
+}
+
+impl Sampler {
+    fn new(sampling: Sampling) -> Self {
+        // The seed only has to differ between runs, not be unpredictable.
+        let seed = RandomState::new().build_hasher().finish() | 1;
+
+        Sampler {
+            sampling,
+            counter: AtomicUsize::new(0),
+            rng: AtomicU64::new(seed),
+        }
+    }
+
+    fn admit(&self) -> bool {
+        match self.sampling {
+            Sampling::Every(n) => {
+                let n = n.max(1);
+                self.counter.fetch_add(1, Ordering::Relaxed).is_multiple_of(n)
+            },
+
+            Sampling::Chance(p) => {
+                let roll = self.next_random() >> 11;
+                (roll as f64) < p * (1u64 << 53) as f64
+            },
+        }
+    }
+
+    /// One xorshift64 step. Concurrent callers may occasionally see the same
+    /// value, which is harmless for sampling.
+    fn next_random(&self) -> u64 {
+        let mut x = self.rng.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng.store(x, Ordering::Relaxed);
+        x
+    }
+}
@@ -0,0 +1,238 @@
+//! Publishing what the operating system tells the program.
+//!
+//! Signals, child processes finishing and lines typed on standard input each
+//! usually want a thread or handler of their own, written again for every
+//! program. The functions here publish them as `OsEvent`s instead, so the
+//! rest of the program can subscribe to them like to any other topic:
+//!
+//! ```ignore
+//! let _signals = os_events::watch_signals(&[libc::SIGINT, libc::SIGTERM], publisher.clone(), "shutdown")?;
+//! os_events::watch_child(Command::new("worker").spawn()?, publisher.clone(), "workers");
+//! os_events::stdin_lines(publisher, "console");
+//! ```
+
+use std::hash::Hash;
+use std::io::{self, BufRead};
+use std::process::Child;
+use std::thread::{self, JoinHandle};
+
+use super::Publisher;
+
+/// Something the operating system reported.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OsEvent {
+    /// The process received this signal.
+    Signal(i32),
+    /// A child process finished.
+    Exited {
+        /// Its process id.
+        pid: u32,
+        /// Its exit code, or `None` if it was killed by a signal.
+        code: Option<i32>,
+    },
+    /// One line of input, without its line ending.
+    Line(String),
+    /// The input has no more lines, or could no longer be read.
+    EndOfInput,
+}
+
+/// Waits on a thread for `child` to finish, then publishes
+/// `OsEvent::Exited` under `topic`.
+pub fn watch_child<Topic>(mut child: Child, publisher: Publisher<Topic, OsEvent>, topic: Topic) -> JoinHandle<()>
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    thread::spawn(move || {
+        let pid = child.id();
+        let code = child.wait().ok().and_then(|status| status.code());
+        publisher.publish(topic, OsEvent::Exited { pid, code });
+    })
+}
+
+/// Reads `reader` on a thread, publishing each line as `OsEvent::Line`
+/// under `topic`, then `OsEvent::EndOfInput` once it is used up.
+pub fn publish_lines<R, Topic>(reader: R, publisher: Publisher<Topic, OsEvent>, topic: Topic) -> JoinHandle<()>
+    where R: BufRead + Send + 'static,
+          Topic: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    thread::spawn(move || forward_lines(reader, &publisher, topic))
+}
+
+/// Like `publish_lines()`, reading the program's standard input.
+pub fn stdin_lines<Topic>(publisher: Publisher<Topic, OsEvent>, topic: Topic) -> JoinHandle<()>
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    thread::spawn(move || forward_lines(io::stdin().lock(), &publisher, topic))
+}
+
+fn forward_lines<R, Topic>(reader: R, publisher: &Publisher<Topic, OsEvent>, topic: Topic)
+    where R: BufRead,
+          Topic: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    for line in reader.lines() {
+        match line {
+            Ok(line) => publisher.publish(topic.clone(), OsEvent::Line(line)),
+            Err(_) => break,
+        }
+    }
+    publisher.publish(topic, OsEvent::EndOfInput);
+}
+
+#[cfg(unix)]
+pub use self::signals::{watch_signals, SignalWatcher};
+
+#[cfg(unix)]
+mod signals {
+    use std::hash::Hash;
+    use std::io;
+    use std::mem;
+    use std::sync::{Mutex, OnceLock};
+    use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+    use std::thread;
+
+    use libc::{self, c_int, c_void};
+
+    use super::OsEvent;
+    use Publisher;
+
+    type Deliver = Box<dyn Fn(c_int) + Send>;
+
+    /// Where the signal handler writes the numbers of the signals it
+    /// catches. The other end is read by a single thread that passes them on
+    /// to every watcher, since a handler can do very little safely itself.
+    static WRITE_END: AtomicI32 = AtomicI32::new(-1);
+
+    struct Dispatch {
+        watchers: Mutex<Vec<(u64, Vec<c_int>, Deliver)>>,
+        next_id: AtomicU64,
+    }
+
+    fn dispatch() -> io::Result<&'static Dispatch> {
+        static DISPATCH: OnceLock<io::Result<Dispatch>> = OnceLock::new();
+        let dispatch = DISPATCH.get_or_init(|| {
+            let mut ends = [0; 2];
+            if unsafe { libc::pipe(ends.as_mut_ptr()) } != 0 { return Err(io::Error::last_os_error()); }
+            let [read_end, write_end] = ends;
+            // A full pipe must drop signals rather than block the handler.
+            unsafe {
+                let flags = libc::fcntl(write_end, libc::F_GETFL);
+                libc::fcntl(write_end, libc::F_SETFL, flags | libc::O_NONBLOCK);
+            }
+            WRITE_END.store(write_end, Ordering::Release);
+
+            thread::Builder::new().name("alewife-signals".to_owned()).spawn(move || loop {
+                let mut signal = 0u8;
+                let read = unsafe { libc::read(read_end, &mut signal as *mut u8 as *mut c_void, 1) };
+                if read == 1 {
+                    let Ok(dispatch) = dispatch() else { return };
+                    let watchers = dispatch.watchers.lock().unwrap_or_else(|e| e.into_inner());
+                    for (_, signals, deliver) in watchers.iter() {
+                        if signals.contains(&(signal as c_int)) { deliver(signal as c_int); }
+                    }
+                } else if read < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                } else {
+                    return;
+                }
+            })?;
+
+            Ok(Dispatch { watchers: Mutex::new(vec![]), next_id: AtomicU64::new(0) })
+        });
+        dispatch.as_ref().map_err(|e| io::Error::new(e.kind(), e.to_string()))
+    }
+
+    extern "C" fn on_signal(signal: c_int) {
+        let write_end = WRITE_END.load(Ordering::Acquire);
+        if write_end >= 0 {
+            let byte = signal as u8;
+            unsafe { libc::write(write_end, &byte as *const u8 as *const c_void, 1); }
+        }
+    }
+
+    /// Keeps a `watch_signals()` registration in place. Dropping it stops
+    /// publishing, though the signals stay caught.
+    pub struct SignalWatcher {
+        id: u64,
+    }
+
+    impl Drop for SignalWatcher {
+        fn drop(&mut self) {
+            if let Ok(dispatch) = dispatch() {
+                let mut watchers = dispatch.watchers.lock().unwrap_or_else(|e| e.into_inner());
+                watchers.retain(|watcher| watcher.0 != self.id);
+            }
+        }
+    }
+
+    /// Catches each of `signals` and publishes `OsEvent::Signal` under
+    /// `topic` whenever one arrives. Catching a signal replaces whatever
+    /// handler it had before, for the rest of the program, so a caught
+    /// SIGINT no longer stops it unless a subscriber does.
+    pub fn watch_signals<Topic>(signals: &[c_int], publisher: Publisher<Topic, OsEvent>, topic: Topic)
+        -> io::Result<SignalWatcher>
+        where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+    {
+        let dispatch = dispatch()?;
+        for &signal in signals {
+            unsafe {
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = on_signal as extern "C" fn(c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+
+        let id = dispatch.next_id.fetch_add(1, Ordering::Relaxed);
+        let deliver: Deliver = Box::new(move |signal| publisher.publish(topic.clone(), OsEvent::Signal(signal)));
+        dispatch.watchers.lock().unwrap_or_else(|e| e.into_inner()).push((id, signals.to_vec(), deliver));
+        Ok(SignalWatcher { id })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn lines_and_exits_are_published() {
+        let mut builder = Publisher::new();
+        let events = builder.add_subscriber(&["console", "workers"]);
+        let publisher = builder.build();
+
+        publish_lines(Cursor::new("status\nquit\n"), publisher.clone(), "console").join().unwrap();
+        let child = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        let pid = child.id();
+        watch_child(child, publisher, "workers").join().unwrap();
+
+        assert_eq!(events.fetch(), vec![
+            ("console", OsEvent::Line("status".to_owned())),
+            ("console", OsEvent::Line("quit".to_owned())),
+            ("console", OsEvent::EndOfInput),
+            ("workers", OsEvent::Exited { pid, code: Some(3) }),
+        ]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signals_are_published() {
+        let mut builder = Publisher::new();
+        let events = builder.add_subscriber(&["signals"]);
+        let watcher = watch_signals(&[libc::SIGUSR1], builder.build(), "signals").unwrap();
+
+        unsafe { libc::raise(libc::SIGUSR1); }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let event = loop {
+            if let Some((_, event)) = events.fetch().pop() { break event; }
+            assert!(Instant::now() < deadline, "the signal wasn't published");
+            thread::sleep(Duration::from_millis(2));
+        };
+        assert_eq!(event, OsEvent::Signal(libc::SIGUSR1));
+        drop(watcher);
+    }
+}
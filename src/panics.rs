@@ -0,0 +1,89 @@
+//! Reporting panics as messages on a network.
+//!
+//! A worker thread that panics takes its work with it, and unless someone
+//! joins it the rest of the program never finds out. `install()` replaces the
+//! process's panic hook with one that first publishes a `PanicReport` on a
+//! topic of your choosing, then runs the hook that was there before, so the
+//! panic is still printed as usual. The hook runs on the panicking thread
+//! before it unwinds, so subscribers hear of the panic while the thread's
+//! state is still intact, in time to save their own or raise an alert.
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::hash::Hash;
+use std::panic::{self, PanicHookInfo};
+use std::thread;
+
+use super::Publisher;
+
+/// What is known about one panic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PanicReport {
+    /// The name of the thread that panicked, if it had one.
+    pub thread: Option<String>,
+    /// The panic's message, if it was a string.
+    pub message: Option<String>,
+    /// Where in the source it panicked, as `file:line:column`.
+    pub location: Option<String>,
+    /// The backtrace, if backtraces are enabled, usually by setting
+    /// `RUST_BACKTRACE`.
+    pub backtrace: Option<String>,
+}
+
+impl PanicReport {
+    pub(crate) fn new(info: &PanicHookInfo) -> Self {
+        let payload = info.payload();
+        let message = payload.downcast_ref::<&str>().map(|&s| s.to_owned())
+            .or_else(|| payload.downcast_ref::<String>().cloned());
+        let backtrace = Backtrace::capture();
+        PanicReport {
+            thread: thread::current().name().map(str::to_owned),
+            message,
+            location: info.location().map(|l| l.to_string()),
+            backtrace: match backtrace.status() {
+                BacktraceStatus::Captured => Some(backtrace.to_string()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Makes every panic in the process publish a `PanicReport` on `topic`
+/// through `publisher` before the previous panic hook runs. The hook stays
+/// until something else sets one; there is no uninstalling it.
+///
+/// A thread that panics while holding one of the network's locks, for
+/// instance inside a content filter, would deadlock trying to publish, so
+/// use a separate network for reports if that can happen.
+pub fn install<Topic>(publisher: Publisher<Topic, PanicReport>, topic: Topic)
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static
+{
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        publisher.publish(topic.clone(), PanicReport::new(info));
+        previous(info);
+    }));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn panicking_threads_are_reported() {
+        let mut builder = Publisher::new();
+        let crashes = builder.add_subscriber(&["panic"]);
+        install(builder.build(), "panic");
+
+        let worker = thread::Builder::new().name("doomed-worker".into())
+            .spawn(|| panic!("out of {}", "fuel"))
+            .unwrap();
+        assert!(worker.join().is_err());
+
+        // Other tests panic on purpose too, so look for this one's report.
+        let report = crashes.fetch().into_iter().map(|(_, report)| report)
+            .find(|report| report.thread.as_deref() == Some("doomed-worker"))
+            .unwrap();
+        assert_eq!(report.message.as_deref(), Some("out of fuel"));
+        assert!(report.location.unwrap().contains("panics.rs"));
+    }
+}
@@ -0,0 +1,101 @@
+//! Handling a subscriber's backlog on several threads.
+//!
+//! `Subscriber::drain()` hands messages over one at a time, in order. When
+//! each message takes real work to handle and their order doesn't matter,
+//! `par_drain()` takes everything waiting and spreads it over a few scoped
+//! threads instead. Where order matters only among related messages, such as
+//! updates to the same entity, `par_drain_by()` sends every message with the
+//! same key to the same thread, which handles them in the order they came.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::thread;
+
+use super::Subscriber;
+
+/// How many threads to use, the calling thread included.
+fn workers() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+impl<Topic: Send, Content: Send> Subscriber<Topic, Content> {
+    /// Takes every pending message and passes each to `handler`, from as
+    /// many threads as the machine has cores, in no particular order.
+    /// Returns how many were handled once they all have been. A panic in
+    /// the handler is passed on once the other threads are done.
+    pub fn par_drain<F>(&self, handler: F) -> usize
+        where F: Fn(Topic, Content) + Sync
+    {
+        let messages = self.fetch();
+        let count = messages.len();
+        let queue = Mutex::new(messages.into_iter());
+        let next = || queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+        let work = || while let Some((topic, content)) = next() { handler(topic, content) };
+
+        thread::scope(|scope| {
+            for _ in 1 .. workers().min(count) { scope.spawn(work); }
+            work();
+        });
+        count
+    }
+
+    /// Like `par_drain()`, but messages for which `key` gives the same value
+    /// are handled by the same thread, in the order they were received.
+    pub fn par_drain_by<K, H, F>(&self, key: K, handler: F) -> usize
+        where K: Fn(&Topic, &Content) -> H,
+              H: Hash,
+              F: Fn(Topic, Content) + Sync,
+    {
+        let messages = self.fetch();
+        let count = messages.len();
+        let workers = workers().min(count).max(1);
+        let mut shares: Vec<Vec<(Topic, Content)>> = (0 .. workers).map(|_| vec![]).collect();
+        for (topic, content) in messages {
+            let mut hasher = DefaultHasher::new();
+            key(&topic, &content).hash(&mut hasher);
+            shares[(hasher.finish() % workers as u64) as usize].push((topic, content));
+        }
+
+        let handler = &handler;
+        thread::scope(|scope| {
+            let mut shares = shares.into_iter();
+            let mine = shares.next().unwrap_or_default();
+            for share in shares {
+                scope.spawn(move || for (topic, content) in share { handler(topic, content) });
+            }
+            for (topic, content) in mine { handler(topic, content) }
+        });
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use Publisher;
+
+    #[test]
+    fn keyed_messages_keep_their_order() {
+        let mut builder = Publisher::new();
+        let updates = builder.add_subscriber(&["update"]);
+        let publisher = builder.build();
+        for n in 0 .. 200u32 { publisher.publish("update", (n % 7, n)); }
+
+        let seen = Mutex::new(vec![vec![]; 7]);
+        let handled = updates.par_drain_by(|_, &(entity, _)| entity, |_, (entity, n)| {
+            seen.lock().unwrap()[entity as usize].push(n);
+        });
+        assert_eq!(handled, 200);
+        for (entity, ns) in seen.into_inner().unwrap().into_iter().enumerate() {
+            assert_eq!(ns, (0 .. 200).filter(|n| n % 7 == entity as u32).collect::<Vec<_>>());
+        }
+
+        for n in 0 .. 50u32 { publisher.publish("update", (0, n)); }
+        let total = Mutex::new(0);
+        assert_eq!(updates.par_drain(|_, (_, n)| *total.lock().unwrap() += n), 50);
+        assert_eq!(total.into_inner().unwrap(), (0 .. 50).sum::<u32>());
+        assert_eq!(updates.par_drain(|_, _| unreachable!()), 0);
+    }
+}
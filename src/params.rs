@@ -0,0 +1,194 @@
+//! Tunable parameters, announced on a network as they change.
+//!
+//! A `ParamServer` holds a fixed set of named parameters, each declared up
+//! front with a default value, which sets its type, and optionally a check
+//! every new value must pass. Systems that can be tuned at runtime subscribe
+//! to the server's topic and receive a `ChangeSet` whenever parameters
+//! change. Several parameters can be changed at once with `update()`: either
+//! every change is valid and they are all applied and announced together, or
+//! none is.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::Publisher;
+
+/// A parameter's value. A parameter keeps the kind of value it was declared
+/// with.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ParamValue {
+    /// A flag.
+    Bool(bool),
+    /// A whole number.
+    Int(i64),
+    /// A real number.
+    Float(f64),
+    /// Text.
+    Text(String),
+}
+
+impl ParamValue {
+    fn same_kind(&self, other: &ParamValue) -> bool {
+        matches!((self, other),
+            (ParamValue::Bool(_), ParamValue::Bool(_)) | (ParamValue::Int(_), ParamValue::Int(_))
+            | (ParamValue::Float(_), ParamValue::Float(_)) | (ParamValue::Text(_), ParamValue::Text(_)))
+    }
+}
+
+/// Parameters that changed together, as published by a `ParamServer`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChangeSet {
+    /// Each parameter that changed, with its new value, in the order given.
+    pub changes: Vec<(String, ParamValue)>,
+}
+
+/// Why a change to parameters was refused. Nothing was changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParamError {
+    /// No parameter has this name.
+    Undeclared(String),
+    /// The value is a different kind from the parameter's default.
+    WrongKind(String),
+    /// The parameter's check refused the value.
+    Invalid {
+        /// The parameter.
+        name: String,
+        /// What the check said was wrong.
+        reason: String,
+    },
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParamError::Undeclared(ref name) => write!(f, "no parameter is called {}", name),
+            ParamError::WrongKind(ref name) => write!(f, "wrong kind of value for parameter {}", name),
+            ParamError::Invalid { ref name, ref reason } => {
+                write!(f, "invalid value for parameter {}: {}", name, reason)
+            },
+        }
+    }
+}
+
+impl Error for ParamError {}
+
+type Check = Box<dyn Fn(&ParamValue) -> Result<(), String> + Send + Sync>;
+
+struct Param {
+    value: ParamValue,
+    check: Option<Check>,
+}
+
+/// A set of parameters that publishes a `ChangeSet` on its topic whenever
+/// some of them change. Clones share the same parameters.
+pub struct ParamServer<Topic: Hash + Eq + Clone> {
+    publisher: Publisher<Topic, ChangeSet>,
+    topic: Topic,
+    params: Arc<RwLock<HashMap<String, Param>>>,
+}
+
+impl<Topic: Hash + Eq + Clone> Clone for ParamServer<Topic> {
+    fn clone(&self) -> Self {
+        ParamServer { publisher: self.publisher.clone(), topic: self.topic.clone(), params: self.params.clone() }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone> ParamServer<Topic> {
+    /// A server with no parameters, which announces changes on `topic`.
+    pub fn new(publisher: Publisher<Topic, ChangeSet>, topic: Topic) -> Self {
+        ParamServer { publisher, topic, params: Arc::new(RwLock::new(HashMap::new())) }
+    }
+
+    /// Declares a parameter starting at `default`. Declaring a name again
+    /// replaces it.
+    pub fn declare(self, name: &str, default: ParamValue) -> Self {
+        self.insert(name, default, None)
+    }
+
+    /// Like `declare()`, but every value set later must pass `check`, which
+    /// says what is wrong with one that doesn't.
+    pub fn declare_checked<F>(self, name: &str, default: ParamValue, check: F) -> Self
+        where F: Fn(&ParamValue) -> Result<(), String> + Send + Sync + 'static
+    {
+        self.insert(name, default, Some(Box::new(check)))
+    }
+
+    fn insert(self, name: &str, value: ParamValue, check: Option<Check>) -> Self {
+        self.params.write().unwrap_or_else(|e| e.into_inner()).insert(name.to_owned(), Param { value, check });
+        self
+    }
+
+    /// Returns the parameter's current value, if it was declared.
+    pub fn get(&self, name: &str) -> Option<ParamValue> {
+        self.params.read().unwrap_or_else(|e| e.into_inner()).get(name).map(|param| param.value.clone())
+    }
+
+    /// The names of every parameter, in no particular order.
+    pub fn names(&self) -> Vec<String> {
+        self.params.read().unwrap_or_else(|e| e.into_inner()).keys().cloned().collect()
+    }
+
+    /// Changes one parameter. See `update()`.
+    pub fn set(&self, name: &str, value: ParamValue) -> Result<(), ParamError> {
+        self.update(vec![(name.to_owned(), value)])
+    }
+
+    /// Changes every parameter in `changes`, and publishes them as one
+    /// `ChangeSet`, if they are all declared and every value is valid.
+    /// Otherwise changes nothing and reports the first problem. Subscribers
+    /// see change sets in the order they were applied.
+    pub fn update(&self, changes: Vec<(String, ParamValue)>) -> Result<(), ParamError> {
+        let mut params = self.params.write().unwrap_or_else(|e| e.into_inner());
+        for (name, value) in &changes {
+            let param = params.get(name).ok_or_else(|| ParamError::Undeclared(name.clone()))?;
+            if !param.value.same_kind(value) { return Err(ParamError::WrongKind(name.clone())); }
+            if let Some(ref check) = param.check {
+                check(value).map_err(|reason| ParamError::Invalid { name: name.clone(), reason })?;
+            }
+        }
+
+        for (name, value) in &changes {
+            params.get_mut(name).unwrap().value = value.clone();
+        }
+        self.publisher.publish(self.topic.clone(), ChangeSet { changes });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn change_sets_are_applied_whole_or_not_at_all() {
+        let mut builder = Publisher::new();
+        let tuner = builder.add_subscriber(&["params"]);
+        let params = ParamServer::new(builder.build(), "params")
+            .declare("armed", ParamValue::Bool(false))
+            .declare_checked("gain", ParamValue::Float(0.5), |value| match *value {
+                ParamValue::Float(gain) if !(0.0 ..= 1.0).contains(&gain) => Err("must be within 0 to 1".to_owned()),
+                _ => Ok(()),
+            });
+
+        let rejected = params.update(vec![("armed".to_owned(), ParamValue::Bool(true)),
+                                          ("gain".to_owned(), ParamValue::Float(2.0))]);
+        assert_eq!(rejected, Err(ParamError::Invalid { name: "gain".to_owned(), reason: "must be within 0 to 1".to_owned() }));
+        assert_eq!(params.set("gain", ParamValue::Int(1)), Err(ParamError::WrongKind("gain".to_owned())));
+        assert_eq!(params.set("speed", ParamValue::Int(1)), Err(ParamError::Undeclared("speed".to_owned())));
+        assert_eq!(params.get("armed"), Some(ParamValue::Bool(false)));
+        assert_eq!(tuner.pending(), 0);
+
+        let changes = vec![("armed".to_owned(), ParamValue::Bool(true)), ("gain".to_owned(), ParamValue::Float(0.8))];
+        assert_eq!(params.update(changes.clone()), Ok(()));
+        assert_eq!(params.get("gain"), Some(ParamValue::Float(0.8)));
+        assert_eq!(tuner.fetch(), vec![("params", ChangeSet { changes })]);
+    }
+}
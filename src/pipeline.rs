@@ -0,0 +1,111 @@
+//! Handing each message through a chain of subscribers in turn.
+//!
+//! Staged processing, like decode, then validate, then apply, wants every
+//! stage to see a message only once the stage before it is done with it.
+//! `Builder::pipeline()` sets up such a chain for one topic: messages arrive
+//! at the first stage only, and each stage hands a message on to the next by
+//! calling `PipelineStage::pass()` once it has dealt with it, possibly with
+//! new content. A stage that doesn't pass a message on stops it there.
+
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+
+use super::{Builder, Subscriber};
+use super::sync::{AtomicUsize, Ordering};
+
+/// A stage's channel, along with its count of unread messages.
+type Link<Topic, Content> = (Sender<(Topic, Content)>, Arc<AtomicUsize>);
+
+/// One stage of a pipeline made by `Builder::pipeline()`.
+pub struct PipelineStage<Topic, Content> {
+    subscriber: Subscriber<Topic, Content>,
+    /// The next stage, unless this is the last.
+    next: Option<Link<Topic, Content>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Sets up a chain of `stages` subscribers for `topic`, returned in
+    /// order. The first stage receives every message on the topic; each
+    /// later one receives only what the stage before it passes on.
+    pub fn pipeline(&mut self, topic: Topic, stages: usize) -> Vec<PipelineStage<Topic, Content>> {
+        if stages == 0 { return vec![]; }
+        let mut subscribers = vec![self.add_subscriber(&[topic])];
+        let mut links = vec![];
+        for _ in 1..stages {
+            let (tx, rx) = mpsc::channel();
+            let pending = Arc::new(AtomicUsize::new(0));
+            subscribers.push(Subscriber::forwarded(rx, pending.clone(), self.bus.drops.clone()));
+            links.push(Some((tx, pending)));
+        }
+        links.push(None);
+
+        subscribers.into_iter().zip(links)
+            .map(|(subscriber, next)| PipelineStage { subscriber, next })
+            .collect()
+    }
+}
+
+impl<Topic, Content> PipelineStage<Topic, Content> {
+    /// Takes the next message waiting at this stage, if there is one.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&self) -> Option<(Topic, Content)> {
+        self.subscriber.next()
+    }
+
+    /// Like `next()`, but waits up to `timeout` for a message.
+    pub fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        self.subscriber.next_timeout(timeout)
+    }
+
+    /// Returns the number of messages waiting at this stage.
+    pub fn pending(&self) -> usize {
+        self.subscriber.pending()
+    }
+
+    /// Hands a message on to the next stage, once this one is done with it.
+    /// The last stage has nobody to hand it to, so there it is dropped.
+    pub fn pass(&self, topic: Topic, content: Content) {
+        if let Some((ref tx, ref pending)) = self.next {
+            pending.fetch_add(1, Ordering::AcqRel);
+            if tx.send((topic, content)).is_err() {
+                pending.fetch_sub(1, Ordering::AcqRel);
+            }
+        }
+    }
+
+    /// Returns true for the last stage.
+    pub fn is_last(&self) -> bool {
+        self.next.is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use Publisher;
+
+    #[test]
+    fn stages_see_messages_only_once_passed_on() {
+        let mut builder = Publisher::new();
+        let stages = builder.pipeline("packet", 3);
+        let publisher = builder.build();
+        let (decode, validate, apply) = (&stages[0], &stages[1], &stages[2]);
+
+        publisher.publish("packet", "7");
+        publisher.publish("packet", "x");
+        assert_eq!((validate.pending(), apply.pending()), (0, 0));
+
+        while let Some((topic, raw)) = decode.next() {
+            decode.pass(topic, raw);
+        }
+        assert_eq!(validate.pending(), 2);
+        while let Some((topic, raw)) = validate.next() {
+            if raw.parse::<u32>().is_ok() { validate.pass(topic, raw); }
+        }
+
+        assert_eq!(apply.next(), Some(("packet", "7")));
+        assert_eq!(apply.next(), None);
+        assert!(apply.is_last() && !decode.is_last());
+    }
+}
@@ -0,0 +1,202 @@
+//! Letting sandboxed plugins, such as WASM modules, take part in a network.
+//!
+//! A plugin can't share memory with the host, so every message crosses the
+//! boundary as bytes, encoded with a `Codec` both sides agree on. The host
+//! runs a `PluginHost` for each plugin instance: it subscribes on the
+//! plugin's behalf, hands each message over through the `Guest` trait, and
+//! publishes whatever the plugin sends back, but only on topics it was
+//! allowed.
+//!
+//! `Guest` is implemented for whichever runtime embeds the plugin. With
+//! wasmtime or wasmer that usually means copying each message into memory
+//! from the guest's allocator export and calling its handler export, and
+//! collecting messages from a host function the guest imports to publish.
+//! Nothing here depends on the runtime, so the host decides how much fuel,
+//! memory and time a plugin gets.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+
+use super::{Publisher, Subscriber};
+use super::codec::Codec;
+
+/// One plugin instance, as seen from the host.
+pub trait Guest {
+    /// Hands one encoded message to the plugin.
+    fn deliver(&mut self, message: &[u8]) -> Result<(), GuestError>;
+
+    /// Returns the encoded messages the plugin has published since the last
+    /// call.
+    fn take_published(&mut self) -> Vec<Vec<u8>>;
+}
+
+/// A plugin failed, for example by trapping or running out of fuel.
+#[derive(Debug)]
+pub struct GuestError {
+    cause: Box<dyn Error + Send + Sync>,
+}
+
+impl GuestError {
+    /// Wraps the underlying error.
+    pub fn new<E>(cause: E) -> Self
+        where E: Into<Box<dyn Error + Send + Sync>>
+    {
+        GuestError { cause: cause.into() }
+    }
+}
+
+impl fmt::Display for GuestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "plugin failed: {}", self.cause)
+    }
+}
+
+impl Error for GuestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.cause)
+    }
+}
+
+/// What one call to `PluginHost::pump()` did.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PumpReport {
+    /// Messages handed to the plugin.
+    pub delivered: usize,
+    /// Messages from the plugin that were published.
+    pub published: usize,
+    /// Messages from the plugin that were thrown away, because they didn't
+    /// decode or were on a topic it may not publish.
+    pub refused: usize,
+}
+
+/// Carries messages between a network and one plugin.
+pub struct PluginHost<Topic: Hash + Eq + Clone, Content: Clone> {
+    inbox: Subscriber<Topic, Content>,
+    publisher: Publisher<Topic, Content>,
+    codec: Box<dyn Codec<Topic, Content>>,
+    may_publish: HashSet<Topic>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> PluginHost<Topic, Content> {
+    /// Connects a plugin called `name` to the network `publisher` belongs to.
+    /// It receives whatever `inbox` does, and may publish only the topics in
+    /// `may_publish`, under its own name, so `Builder::restrict_topic()`
+    /// applies to it as well.
+    pub fn new<K>(name: &str, inbox: Subscriber<Topic, Content>, publisher: &Publisher<Topic, Content>,
+                  codec: K, may_publish: &[Topic]) -> Self
+        where K: Codec<Topic, Content> + 'static
+    {
+        PluginHost {
+            inbox,
+            publisher: publisher.named(name),
+            codec: Box::new(codec),
+            may_publish: may_publish.iter().cloned().collect(),
+        }
+    }
+
+    /// Hands every pending message to `guest`, then publishes what it sent
+    /// back. Messages that fail to encode are skipped. If the guest fails,
+    /// the messages it had already published are still handled, and the
+    /// error is returned; the rest stay queued for the next call.
+    pub fn pump<G: Guest>(&self, guest: &mut G) -> Result<PumpReport, GuestError> {
+        let mut report = PumpReport::default();
+        let mut failed = None;
+
+        while let Some((topic, content)) = self.inbox.next() {
+            let message = match self.codec.encode(&topic, &content) {
+                Ok(message) => message,
+                Err(_) => continue,
+            };
+            if let Err(e) = guest.deliver(&message) {
+                failed = Some(e);
+                break;
+            }
+            report.delivered += 1;
+        }
+
+        for message in guest.take_published() {
+            match self.codec.decode(&message) {
+                Ok((topic, content)) if self.may_publish.contains(&topic) => {
+                    if self.publisher.try_publish(topic, content).is_ok() {
+                        report.published += 1;
+                    } else {
+                        report.refused += 1;
+                    }
+                },
+                _ => report.refused += 1,
+            }
+        }
+
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(report),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::CodecError;
+
+    /// Encodes messages as the topic, a space, and the content in decimal.
+    struct Text;
+
+    impl Codec<String, u32> for Text {
+        fn encode(&self, topic: &String, content: &u32) -> Result<Vec<u8>, CodecError> {
+            Ok(format!("{} {}", topic, content).into_bytes())
+        }
+
+        fn decode(&self, bytes: &[u8]) -> Result<(String, u32), CodecError> {
+            let text = String::from_utf8_lossy(bytes);
+            let mut words = text.split(' ');
+            let topic = words.next().unwrap_or_default().to_owned();
+            let content = words.next().unwrap_or_default().parse().map_err(CodecError::new)?;
+            Ok((topic, content))
+        }
+    }
+
+    /// Answers each ping with a pong, and tries to cheat now and then.
+    struct Echo {
+        outbox: Vec<Vec<u8>>,
+    }
+
+    impl Guest for Echo {
+        fn deliver(&mut self, message: &[u8]) -> Result<(), GuestError> {
+            let (_, n) = Text.decode(message).map_err(GuestError::new)?;
+            if n == 13 { return Err(GuestError::new("unlucky")); }
+            self.outbox.push(format!("pong {}", n + 1).into_bytes());
+            self.outbox.push(format!("admin {}", n).into_bytes());
+            Ok(())
+        }
+
+        fn take_published(&mut self) -> Vec<Vec<u8>> {
+            self.outbox.drain(..).chain(Some(b"garbage".to_vec())).collect()
+        }
+    }
+
+    #[test]
+    fn plugins_publish_only_what_they_may() {
+        let mut builder = Publisher::new();
+        let inbox = builder.add_subscriber(&["ping".to_owned()]);
+        let watcher = builder.add_subscriber(&["pong".to_owned(), "admin".to_owned()]);
+        let publisher = builder.build();
+
+        let host = PluginHost::new("mod", inbox, &publisher, Text, &["pong".to_owned()]);
+        let mut guest = Echo { outbox: vec![] };
+
+        publisher.publish("ping".to_owned(), 1);
+        publisher.publish("ping".to_owned(), 2);
+        let report = host.pump(&mut guest).unwrap();
+        assert_eq!(report, PumpReport { delivered: 2, published: 2, refused: 3 });
+        assert_eq!(watcher.fetch(), vec![("pong".to_owned(), 2), ("pong".to_owned(), 3)]);
+
+        publisher.publish("ping".to_owned(), 13);
+        publisher.publish("ping".to_owned(), 4);
+        assert!(host.pump(&mut guest).is_err());
+        assert_eq!(host.pump(&mut guest).unwrap().delivered, 1);
+        assert_eq!(watcher.fetch(), vec![("pong".to_owned(), 5)]);
+    }
+}
@@ -0,0 +1,182 @@
+//! Reusing message buffers instead of allocating new ones.
+//!
+//! A media pipeline publishing hundreds of frames a second spends much of
+//! its time allocating buffers and freeing them again. A network can keep a
+//! pool of byte buffers instead: `Publisher::acquire_buffer()` checks one
+//! out, the publisher fills it and freezes it into a `PooledBuffer`, and once
+//! the last subscriber drops its copy the buffer goes back to the pool,
+//! emptied but with its allocation intact. After a short warm-up a steady
+//! stream of messages allocates nothing.
+
+use std::fmt;
+use std::hash::Hash;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use super::{Builder, ContentSize, Publisher};
+use super::sync::Mutex;
+
+/// The idle buffers of one network.
+pub(crate) struct BufferPool {
+    idle: Mutex<Vec<Vec<u8>>>,
+    settings: Mutex<(usize, usize)>,
+}
+
+impl BufferPool {
+    pub(crate) fn new() -> Self {
+        BufferPool { idle: Mutex::new(vec![]), settings: Mutex::new((0, 0)) }
+    }
+
+    fn take(&self) -> Vec<u8> {
+        let popped = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let (size, _) = *self.settings.lock().unwrap_or_else(|e| e.into_inner());
+        popped.unwrap_or_else(|| Vec::with_capacity(size))
+    }
+
+    fn give_back(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() == 0 { return; }
+        let (_, keep) = *self.settings.lock().unwrap_or_else(|e| e.into_inner());
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < keep {
+            buffer.clear();
+            idle.push(buffer);
+        }
+    }
+}
+
+/// A buffer checked out of the pool, ready to be filled. Dropping it without
+/// freezing it returns it to the pool.
+pub struct BufferMut {
+    buffer: Vec<u8>,
+    pool: Arc<BufferPool>,
+}
+
+impl BufferMut {
+    /// Finishes writing, giving the shareable content to publish.
+    pub fn freeze(mut self) -> PooledBuffer {
+        let buffer = mem::take(&mut self.buffer);
+        PooledBuffer { shared: Arc::new(Returning { buffer, pool: self.pool.clone() }) }
+    }
+}
+
+impl Deref for BufferMut {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl DerefMut for BufferMut {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+impl Drop for BufferMut {
+    fn drop(&mut self) {
+        self.pool.give_back(mem::take(&mut self.buffer));
+    }
+}
+
+/// Holds a buffer until the last copy of its `PooledBuffer` goes away.
+struct Returning {
+    buffer: Vec<u8>,
+    pool: Arc<BufferPool>,
+}
+
+impl Drop for Returning {
+    fn drop(&mut self) {
+        self.pool.give_back(mem::take(&mut self.buffer));
+    }
+}
+
+/// Bytes in a pooled buffer, cheap to clone. The buffer returns to its pool
+/// when the last clone is dropped.
+#[derive(Clone)]
+pub struct PooledBuffer {
+    shared: Arc<Returning>,
+}
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.shared.buffer
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for PooledBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("PooledBuffer").field(&&self[..]).finish()
+    }
+}
+
+impl ContentSize for PooledBuffer {
+    fn content_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Keeps up to `keep` idle buffers for `Publisher::acquire_buffer()`,
+    /// each newly allocated one starting with room for `buffer_size` bytes.
+    /// Without this, buffers are allocated fresh and freed after use.
+    pub fn buffer_pool(&mut self, buffer_size: usize, keep: usize) {
+        *self.bus.buffers.settings.lock().unwrap_or_else(|e| e.into_inner()) = (buffer_size, keep);
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Checks an empty buffer out of the network's pool, allocating one if
+    /// none is idle. See `Builder::buffer_pool()`.
+    pub fn acquire_buffer(&self) -> BufferMut {
+        let pool = self.bus().buffers.clone();
+        BufferMut { buffer: pool.take(), pool }
+    }
+
+    /// How many buffers are waiting in the pool.
+    pub fn idle_buffers(&self) -> usize {
+        self.bus().buffers.idle.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buffers_return_after_the_last_reader() {
+        let mut builder = Publisher::new();
+        builder.buffer_pool(64, 2);
+        let first = builder.add_subscriber(&["frame"]);
+        let second = builder.add_subscriber(&["frame"]);
+        let publisher = builder.build();
+
+        let mut buffer = publisher.acquire_buffer();
+        buffer.extend_from_slice(b"frame one");
+        let address = buffer.as_ptr();
+        publisher.publish("frame", buffer.freeze());
+        assert_eq!(publisher.idle_buffers(), 0);
+
+        let (_, frame) = first.next().unwrap();
+        assert_eq!(&frame[..], b"frame one");
+        drop(frame);
+        assert_eq!(publisher.idle_buffers(), 0);
+        drop(second.fetch());
+        assert_eq!(publisher.idle_buffers(), 1);
+
+        let reused = publisher.acquire_buffer();
+        assert!(reused.is_empty());
+        assert_eq!(reused.as_ptr(), address);
+        drop(reused);
+        assert_eq!(publisher.idle_buffers(), 1);
+    }
+}
@@ -0,0 +1,189 @@
+//! Materialized state built from a stream of messages.
+//!
+//! A `Projection` folds messages into a state value, the way an event-sourced
+//! application rebuilds its model from an event log. Feed it the history in a
+//! `Journal` with `replay()`, then keep it current with `follow()`, and read
+//! the result at any time with `state()`.
+
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::Subscriber;
+use super::journal::Journal;
+
+type Fold<Topic, Content, State> = Box<dyn FnMut(State, &Topic, &Content) -> State + Send>;
+
+struct Inner<Topic, Content, State> {
+    /// Only `None` while `fold` is running.
+    state: Option<State>,
+    fold: Fold<Topic, Content, State>,
+    watchers: Vec<Sender<State>>,
+}
+
+/// A state value kept up to date by folding messages into it.
+pub struct Projection<Topic, Content, State> {
+    inner: Arc<Mutex<Inner<Topic, Content, State>>>,
+    follower: Option<Follower>,
+}
+
+impl<Topic, Content, State> Projection<Topic, Content, State>
+    where State: Clone,
+{
+    /// Creates a projection starting from `init`. Each message is passed to
+    /// `fold` along with the current state, and the result becomes the new
+    /// state.
+    pub fn new<F>(init: State, fold: F) -> Self
+        where F: FnMut(State, &Topic, &Content) -> State + Send + 'static
+    {
+        Projection {
+            inner: Arc::new(Mutex::new(Inner {
+                state: Some(init),
+                fold: Box::new(fold),
+                watchers: Vec::new(),
+            })),
+            follower: None,
+        }
+    }
+
+    /// Folds in every entry of `journal`, oldest first, and returns how many
+    /// there were. Watchers are told about the final state only, not each
+    /// step along the way.
+    pub fn replay(&self, journal: &Journal<Topic, Content>) -> io::Result<usize> {
+        let mut inner = lock(&self.inner);
+        let mut count = 0;
+        for entry in journal.query(|_| true, ..)? {
+            let entry = entry?;
+            inner.fold(&entry.topic, &entry.content);
+            count += 1;
+        }
+        if count > 0 { inner.notify(); }
+        Ok(count)
+    }
+
+    /// Folds in a single message.
+    pub fn apply(&self, topic: &Topic, content: &Content) {
+        let mut inner = lock(&self.inner);
+        inner.fold(topic, content);
+        inner.notify();
+    }
+
+    /// Returns a copy of the current state.
+    pub fn state(&self) -> State {
+        lock(&self.inner).state.clone().expect("projection fold panicked")
+    }
+
+    /// Returns a channel that receives a copy of the state after every
+    /// change. Dropping the receiver unsubscribes.
+    pub fn watch(&self) -> Receiver<State> {
+        let (tx, rx) = mpsc::channel();
+        lock(&self.inner).watchers.push(tx);
+        rx
+    }
+}
+
+impl<Topic, Content, State> Projection<Topic, Content, State>
+    where Topic: Send + 'static,
+          Content: Send + 'static,
+          State: Clone + Send + 'static,
+{
+    /// Folds in everything `source` receives from now on, on a background
+    /// thread that runs until the projection is dropped. Following a new
+    /// source stops following the old one.
+    pub fn follow(&mut self, source: Subscriber<Topic, Content>) {
+        self.follower = None;
+
+        let inner = self.inner.clone();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = stopped.clone();
+
+        let worker = thread::spawn(move || {
+            while !flag.load(Ordering::Acquire) {
+                if let Some((topic, content)) = source.next_timeout(POLL_INTERVAL) {
+                    let mut inner = lock(&inner);
+                    inner.fold(&topic, &content);
+                    inner.notify();
+                }
+            }
+        });
+
+        self.follower = Some(Follower { stopped, worker: Some(worker) });
+    }
+}
+
+impl<Topic, Content, State: Clone> Inner<Topic, Content, State> {
+    fn fold(&mut self, topic: &Topic, content: &Content) {
+        let state = self.state.take().expect("projection fold panicked");
+        self.state = Some((self.fold)(state, topic, content));
+    }
+
+    fn notify(&mut self) {
+        let state = match self.state {
+            Some(ref state) => state,
+            None => return,
+        };
+        self.watchers.retain(|watcher| watcher.send(state.clone()).is_ok());
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// How long the follower may block before checking whether it was stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+struct Follower {
+    stopped: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Drop for Follower {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(worker) = self.worker.take() {
+            worker.join().unwrap_or(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::Publisher;
+    use super::super::journal::test::{Plain, journal_path};
+    use std::fs;
+    use std::time::Instant;
+
+    #[test]
+    fn replay_then_follow() {
+        let path = journal_path("projection");
+        let journal = Journal::open(&path, Plain).unwrap();
+        for i in 1 .. 4 { journal.append(&"deposit".to_owned(), &i).unwrap(); }
+
+        let mut balance = Projection::new(0, |total, topic: &String, amount: &u32| {
+            if topic == "deposit" { total + amount } else { total - amount }
+        });
+        assert_eq!(balance.replay(&journal).unwrap(), 3);
+        assert_eq!(balance.state(), 6);
+
+        let changes = balance.watch();
+        let mut builder = Publisher::new();
+        let live = builder.add_subscriber(&["withdrawal".to_owned()]);
+        let publisher = builder.build();
+        balance.follow(live);
+
+        publisher.publish("withdrawal".to_owned(), 2);
+        assert_eq!(changes.recv_timeout(Duration::from_secs(5)), Ok(4));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while balance.state() != 4 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(balance.state(), 4);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+//! Publishing without caring what to.
+//!
+//! `Publish` is the publishing half of a `Publisher`, as a trait, so code
+//! that only publishes can take a `P: Publish<Topic, Content>`, or a
+//! `&dyn Publish<Topic, Content>`, and be handed a whole network, a tenant's
+//! view of one, a federation, or a `testkit::MockPublisher` in tests.
+
+use std::hash::Hash;
+use std::sync::Arc;
+
+use super::{PublishError, Publisher};
+
+/// Something messages can be published to.
+pub trait Publish<Topic, Content> {
+    /// Publishes a message, dropping it if it is refused, as with
+    /// `Publisher::publish()`.
+    fn publish(&self, topic: Topic, content: Content) {
+        self.try_publish(topic, content).unwrap_or(());
+    }
+
+    /// Publishes a message, or reports why it was refused, as with
+    /// `Publisher::try_publish()`.
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError>;
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publish<Topic, Content> for Publisher<Topic, Content> {
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        Publisher::try_publish(self, topic, content)
+    }
+}
+
+impl<Topic, Content, P: Publish<Topic, Content> + ?Sized> Publish<Topic, Content> for &P {
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        (**self).try_publish(topic, content)
+    }
+}
+
+impl<Topic, Content, P: Publish<Topic, Content> + ?Sized> Publish<Topic, Content> for Box<P> {
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        (**self).try_publish(topic, content)
+    }
+}
+
+impl<Topic, Content, P: Publish<Topic, Content> + ?Sized> Publish<Topic, Content> for Arc<P> {
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        (**self).try_publish(topic, content)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testkit::MockPublisher;
+
+    #[test]
+    fn implementations_can_be_swapped_behind_dyn() {
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&["acme/orders".to_owned(), "orders".to_owned()]);
+        let network = builder.build();
+        let mock = MockPublisher::new();
+
+        let outlets: Vec<Box<dyn Publish<String, u32>>> = vec![
+            Box::new(network.clone()),
+            Box::new(network.tenant("acme")),
+            Box::new(Arc::new(mock.clone())),
+        ];
+        for (n, outlet) in outlets.iter().enumerate() {
+            outlet.publish("orders".to_owned(), n as u32);
+        }
+
+        assert_eq!(subscriber.fetch(), vec![("orders".to_owned(), 0), ("acme/orders".to_owned(), 1)]);
+        assert_eq!(mock.take(), vec![("orders".to_owned(), 2)]);
+    }
+}
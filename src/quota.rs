@@ -0,0 +1,142 @@
+//! Per-publisher limits on how fast messages can be published.
+//!
+//! Each limit is a token bucket that holds one second's worth of its rate,
+//! so a publisher can burst up to that much at once and is then held to the
+//! rate on average. Limits are looked up by the publisher's name, as given to
+//! `Publisher::named()`, and are shared by every handle with that name.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::Clock;
+use super::sync::Mutex;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {ManualClock, PublishError, Publisher};
+
+    #[test]
+    fn publishers_are_held_to_their_quota() {
+        let clock = ManualClock::new();
+        let mut builder = Publisher::new();
+        builder.clock(clock.clone());
+        builder.limit_publish_rate("plugin", 2, OverQuota::Refuse);
+        builder.limit_publish_bandwidth("uploader", 20_000, |s: &String| s.len(), OverQuota::Wait);
+        let subscriber = builder.add_subscriber(&["chat"]);
+        let publisher = builder.build();
+
+        let plugin = publisher.named("plugin");
+        assert_eq!(plugin.try_publish("chat", "a".to_owned()), Ok(()));
+        assert_eq!(plugin.try_publish("chat", "b".to_owned()), Ok(()));
+        assert_eq!(plugin.try_publish("chat", "c".to_owned()), Err(PublishError::OverQuota));
+        assert_eq!(publisher.try_publish("chat", "d".to_owned()), Ok(()));
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(plugin.try_publish("chat", "e".to_owned()), Ok(()));
+        assert_eq!(subscriber.fetch().len(), 4);
+
+        let uploader = publisher.named("uploader");
+        let started = Instant::now();
+        assert_eq!(uploader.try_publish("chat", "x".repeat(20_200)), Ok(()));
+        assert!(started.elapsed() >= Duration::from_millis(10));
+        assert_eq!(subscriber.fetch().len(), 1);
+    }
+}
+
+/// What happens to a message a publisher has no quota left for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverQuota {
+    /// It is dropped, and `Publisher::try_publish()` returns
+    /// `PublishError::OverQuota`.
+    Refuse,
+    /// The publishing thread sleeps until the quota has recovered, then
+    /// publishes it.
+    Wait,
+}
+
+type Sizer<Content> = Box<dyn Fn(&Content) -> usize + Send + Sync>;
+
+struct Rate {
+    per_second: f64,
+    over: OverQuota,
+}
+
+/// How much of each rate is left.
+struct Levels {
+    messages: f64,
+    bytes: f64,
+    /// When the levels were last topped up.
+    at: Option<Instant>,
+}
+
+/// The limits on one publisher name.
+pub(crate) struct Quota<Content> {
+    messages: Option<Rate>,
+    bytes: Option<(Rate, Sizer<Content>)>,
+    levels: Mutex<Levels>,
+}
+
+impl<Content> Quota<Content> {
+    pub(crate) fn new() -> Self {
+        Quota {
+            messages: None,
+            bytes: None,
+            levels: Mutex::new(Levels { messages: 0.0, bytes: 0.0, at: None }),
+        }
+    }
+
+    pub(crate) fn limit_messages(&mut self, per_second: u32, over: OverQuota) {
+        self.messages = Some(Rate { per_second: per_second.into(), over });
+    }
+
+    pub(crate) fn limit_bytes<F>(&mut self, per_second: u64, sizer: F, over: OverQuota)
+        where F: Fn(&Content) -> usize + Send + Sync + 'static
+    {
+        self.bytes = Some((Rate { per_second: per_second as f64, over }, Box::new(sizer)));
+    }
+
+    /// Charges `content` against the quota, first waiting for it to recover
+    /// if a limit says to. Returns false if a limit refuses the message, in
+    /// which case nothing is charged.
+    pub(crate) fn admit(&self, content: &Content, clock: &dyn Clock) -> bool {
+        let wait = {
+            let mut levels = self.levels.lock().unwrap_or_else(|e| e.into_inner());
+            let now = clock.now();
+            let elapsed = levels.at.map_or(1.0, |at| now.saturating_duration_since(at).as_secs_f64());
+            levels.at = Some(now);
+
+            if let Some(ref rate) = self.messages {
+                levels.messages = refill(levels.messages, rate, elapsed);
+            }
+            if let Some((ref rate, _)) = self.bytes {
+                levels.bytes = refill(levels.bytes, rate, elapsed);
+            }
+
+            let Levels { ref mut messages, ref mut bytes, .. } = *levels;
+            let mut buckets: Vec<(&mut f64, &Rate, f64)> = vec![];
+            if let Some(ref rate) = self.messages { buckets.push((messages, rate, 1.0)); }
+            if let Some((ref rate, ref sizer)) = self.bytes {
+                buckets.push((bytes, rate, sizer(content) as f64));
+            }
+
+            let refused = buckets.iter()
+                .any(|&(ref level, rate, cost)| rate.over == OverQuota::Refuse && **level < cost);
+            if refused { return false; }
+
+            let mut wait = 0.0f64;
+            for (level, rate, cost) in buckets {
+                *level -= cost;
+                if *level < 0.0 { wait = wait.max(-*level / rate.per_second); }
+            }
+            wait
+        };
+
+        if wait > 0.0 { thread::sleep(Duration::from_secs_f64(wait)); }
+        true
+    }
+}
+
+/// Tops `level` up for `elapsed` seconds, to at most a second's worth.
+fn refill(level: f64, rate: &Rate, elapsed: f64) -> f64 {
+    (level + rate.per_second * elapsed).min(rate.per_second)
+}
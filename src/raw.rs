@@ -0,0 +1,97 @@
+//! Networks that carry undecoded bytes.
+//!
+//! Pipelines that move serialized blobs around, such as network packets or
+//! audio frames, gain nothing from decoding them into typed content on the
+//! way. A raw network's content is an `Arc<[u8]>`, so each subscriber's copy
+//! is a reference count rather than a fresh buffer; with the `bytes`
+//! feature, `bytes::Bytes` works just as well. A subscriber that does want
+//! typed values can read through `Subscriber::decoded()`, which decodes each
+//! message only as it is read.
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::{Builder, Publisher, Subscriber};
+use super::codec::CodecError;
+
+/// Sets up a network whose content is shared bytes.
+pub type RawBuilder<Topic> = Builder<Topic, Arc<[u8]>>;
+
+/// Publishes shared bytes.
+pub type RawPublisher<Topic> = Publisher<Topic, Arc<[u8]>>;
+
+/// Receives shared bytes.
+pub type RawSubscriber<Topic> = Subscriber<Topic, Arc<[u8]>>;
+
+/// A function turning a message's bytes into a value.
+type Decoder<T> = Box<dyn Fn(&[u8]) -> Result<T, CodecError> + Send>;
+
+/// A subscriber whose byte content is decoded as it is read. Made by
+/// `Subscriber::decoded()`.
+pub struct Decoded<Topic, Content, T> {
+    subscriber: Subscriber<Topic, Content>,
+    decode: Decoder<T>,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<Topic, Content: AsRef<[u8]>> Subscriber<Topic, Content> {
+    /// Reads this subscriber's messages as values of type `T`, decoded from
+    /// their bytes by `decode`.
+    pub fn decoded<T, F>(self, decode: F) -> Decoded<Topic, Content, T>
+        where F: Fn(&[u8]) -> Result<T, CodecError> + Send + 'static
+    {
+        Decoded { subscriber: self, decode: Box::new(decode), _value: PhantomData }
+    }
+}
+
+impl<Topic, Content: AsRef<[u8]>, T> Decoded<Topic, Content, T> {
+    /// Decodes the next pending message, if there is one. A message that
+    /// doesn't decode is still consumed, and reported as an error.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&self) -> Option<(Topic, Result<T, CodecError>)> {
+        let (topic, content) = self.subscriber.next()?;
+        let value = (self.decode)(content.as_ref());
+        Some((topic, value))
+    }
+
+    /// Decodes every pending message.
+    pub fn fetch(&self) -> Vec<(Topic, Result<T, CodecError>)> {
+        let mut messages = vec![];
+        while let Some(message) = self.next() { messages.push(message); }
+        messages
+    }
+
+    /// Returns the subscriber, to read raw bytes again.
+    pub fn into_raw(self) -> Subscriber<Topic, Content> {
+        self.subscriber
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str;
+
+    use super::*;
+
+    fn number(bytes: &[u8]) -> Result<u32, CodecError> {
+        str::from_utf8(bytes).map_err(CodecError::new)?.parse().map_err(CodecError::new)
+    }
+
+    #[test]
+    fn subscribers_share_one_buffer() {
+        let mut builder: RawBuilder<&str> = Publisher::new();
+        let raw = builder.add_subscriber(&["packet"]);
+        let typed = builder.add_subscriber(&["packet"]).decoded(number);
+        let publisher = builder.build();
+
+        let packet: Arc<[u8]> = Arc::from(&b"42"[..]);
+        publisher.publish("packet", packet.clone());
+        publisher.publish("packet", Arc::from(&b"junk"[..]));
+
+        let (_, received) = raw.next().unwrap();
+        assert!(Arc::ptr_eq(&received, &packet));
+        let decoded = typed.fetch();
+        assert_eq!(decoded[0].1.as_ref().ok(), Some(&42));
+        assert!(decoded[1].1.is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Waiting for a network's critical subscribers before publishing.
+//!
+//! Components that subscribe from their own threads as they start up race
+//! whoever publishes first, and anything published before a subscriber is in
+//! place never reaches it. `Builder::expect_subscribers()` names the
+//! subscribers that matter; each checks in by subscribing under its name with
+//! `Publisher::subscribe_named()`, and `Publisher::wait_ready()` blocks until
+//! every one of them has.
+
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{Builder, NotReady, Publisher, Subscriber, SubscriptionGuard, SubscriptionOptions};
+
+/// Which named subscribers a network is waiting for, and which have arrived.
+pub(crate) struct Readiness {
+    expected: Vec<String>,
+    arrived: Mutex<HashSet<String>>,
+    changed: Condvar,
+}
+
+impl Readiness {
+    pub(crate) fn new() -> Self {
+        Readiness { expected: vec![], arrived: Mutex::new(HashSet::new()), changed: Condvar::new() }
+    }
+
+    pub(crate) fn arrive(&self, name: &str) {
+        let mut arrived = self.arrived.lock().unwrap_or_else(|e| e.into_inner());
+        arrived.insert(name.to_owned());
+        self.changed.notify_all();
+    }
+
+    fn missing(&self, arrived: &HashSet<String>) -> Vec<String> {
+        self.expected.iter().filter(|name| !arrived.contains(*name)).cloned().collect()
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Makes `Publisher::wait_ready()` wait for subscribers with each of
+    /// these names.
+    pub fn expect_subscribers(&mut self, names: &[&str]) {
+        let readiness = &mut self.bus.readiness;
+        for name in names {
+            if !readiness.expected.iter().any(|expected| expected == name) {
+                readiness.expected.push((*name).to_owned());
+            }
+        }
+    }
+
+    /// Like `add_subscriber()`, but counts as the subscriber called `name`
+    /// for `Publisher::wait_ready()`.
+    pub fn add_named_subscriber(&mut self, name: &str, topics: &[Topic]) -> Subscriber<Topic, Content> {
+        let subscriber = self.add_subscriber(topics);
+        self.bus.readiness.arrive(name);
+        subscriber
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Like `subscribe_scoped()`, but counts as the subscriber called `name`
+    /// for `wait_ready()` once its routes are in place. It goes on counting
+    /// after the guard is dropped. See `rejoin()` for subscribing under the
+    /// same name again.
+    pub fn subscribe_named(&self, name: &str, topics: &[Topic])
+        -> (Subscriber<Topic, Content>, SubscriptionGuard<Topic, Content>)
+    {
+        let bus = &self.handle.bus;
+        let (subscriber, id) = bus.add_subscriber(topics, SubscriptionOptions::new());
+        bus.rejoins.join(id, name, topics);
+        bus.readiness.arrive(name);
+        (subscriber, SubscriptionGuard { bus: bus.clone(), id })
+    }
+
+    /// Waits up to `timeout` for every subscriber named with
+    /// `Builder::expect_subscribers()` to have subscribed, and says which
+    /// are still missing if some don't in time.
+    pub fn wait_ready(&self, timeout: Duration) -> Result<(), NotReady> {
+        let readiness = &self.bus().readiness;
+        let deadline = Instant::now() + timeout;
+        let mut arrived = readiness.arrived.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let missing = readiness.missing(&arrived);
+            if missing.is_empty() { return Ok(()); }
+
+            let now = Instant::now();
+            if now >= deadline { return Err(NotReady { missing }); }
+            arrived = readiness.changed.wait_timeout(arrived, deadline - now)
+                .unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn waits_for_expected_subscribers() {
+        let mut builder = Publisher::new();
+        builder.expect_subscribers(&["audio", "ui"]);
+        let _audio = builder.add_named_subscriber("audio", &["sound"]);
+        let publisher = builder.build();
+
+        let early = Duration::from_millis(10);
+        assert_eq!(publisher.wait_ready(early), Err(NotReady { missing: vec!["ui".to_owned()] }));
+
+        let ui = {
+            let publisher = publisher.clone();
+            thread::spawn(move || publisher.subscribe_named("ui", &["frame"]))
+        };
+        assert_eq!(publisher.wait_ready(Duration::from_secs(5)), Ok(()));
+        publisher.publish("frame", 1);
+
+        let (subscriber, _guard) = ui.join().unwrap();
+        assert_eq!(subscriber.fetch(), vec![("frame", 1)]);
+    }
+}
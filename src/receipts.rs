@@ -0,0 +1,88 @@
+//! Recording who received what.
+//!
+//! For the few topics where it matters that a message was actually read,
+//! such as orders, alarms or payments, a subscriber can be made to publish a
+//! `Receipt` each time it hands one of their messages to its component.
+//! Receipts go to a system topic of their own, where an auditor can log
+//! them or a monitor can measure how long critical events take to be
+//! consumed. A receipt is sent when the message is read, not when it is
+//! queued, so a component that has fallen behind sends them late, which is
+//! what an SLA monitor wants to see.
+
+use std::hash::Hash;
+use std::time::SystemTime;
+
+use super::{Publisher, Subscriber};
+
+/// Sends a receipt for a message being read, if it needs one.
+pub(crate) type SendReceipt<Topic, Content> = Box<dyn Fn(&Topic, &Content) + Send>;
+
+/// A record that one subscriber has read one message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Receipt<Topic> {
+    /// The name the subscriber was given.
+    pub subscriber: String,
+    /// The topic the message was published on.
+    pub topic: Topic,
+    /// The message's id.
+    pub id: u64,
+    /// When the subscriber handed the message to its component.
+    pub read_at: SystemTime,
+}
+
+impl<Topic, Content> Subscriber<Topic, Content>
+    where Topic: Clone + Send + Sync + 'static
+{
+    /// Publishes a `Receipt` under `subscriber`'s name on `topic` for each
+    /// message this subscriber reads that `id` gives an id, such as
+    /// `Envelope::message_id()`. Messages `id` returns `None` for, including
+    /// every message on topics that don't need receipts, are read as usual.
+    /// Setting new receipts replaces these.
+    pub fn send_receipts<OutTopic, F>(&mut self, subscriber: &str, id: F,
+                                      publisher: Publisher<OutTopic, Receipt<Topic>>, topic: OutTopic)
+        where OutTopic: Hash + Eq + Clone + Send + Sync + 'static,
+              F: Fn(&Topic, &Content) -> Option<u64> + Send + 'static,
+    {
+        let subscriber = subscriber.to_owned();
+        self.receipt = Some(Box::new(move |read, content| {
+            let Some(id) = id(read, content) else { return };
+            publisher.publish(topic.clone(), Receipt {
+                subscriber: subscriber.clone(),
+                topic: read.clone(),
+                id,
+                read_at: SystemTime::now(),
+            });
+        }));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use envelope::Envelope;
+
+    #[test]
+    fn reads_on_selected_topics_are_receipted() {
+        let mut builder = Publisher::new();
+        let mut billing = builder.add_subscriber(&["payment", "chatter"]);
+        let publisher = builder.build();
+
+        let mut audit_builder = Publisher::new();
+        let audit = audit_builder.add_subscriber(&["receipts"]);
+        let receipts = audit_builder.build();
+        billing.send_receipts("billing", |topic, envelope: &Envelope<&str, u32>| {
+            if *topic == "payment" { envelope.message_id() } else { None }
+        }, receipts, "receipts");
+
+        publisher.publish("payment", Envelope::new(10).with_id(1));
+        publisher.publish("chatter", Envelope::new(0).with_id(2));
+        publisher.publish("payment", Envelope::new(20).with_id(3));
+        assert!(audit.fetch().is_empty());
+
+        assert_eq!(billing.fetch().len(), 3);
+        let read: Vec<(String, &str, u64)> = audit.fetch().into_iter()
+            .map(|(_, receipt)| (receipt.subscriber, receipt.topic, receipt.id))
+            .collect();
+        assert_eq!(read, vec![("billing".to_owned(), "payment", 1), ("billing".to_owned(), "payment", 3)]);
+    }
+}
@@ -0,0 +1,75 @@
+//! Remembering the last few messages published anywhere on a network.
+//!
+//! After a failure, the first thing worth knowing is what the network was
+//! carrying just before. `Builder::remember_recent()` keeps the last so many
+//! messages, across every topic, in a ring that costs one clone per publish
+//! and never grows, and `Publisher::recent()` reads them back. Unlike a
+//! `Journal`, nothing is written anywhere, so it can stay on in production.
+
+use std::collections::VecDeque;
+use std::hash::Hash;
+
+use super::{Builder, Publisher};
+use super::sync::Mutex;
+
+/// The ring of recent messages.
+pub(crate) struct Recent<Topic, Content> {
+    capacity: usize,
+    messages: Mutex<VecDeque<(Topic, Content)>>,
+}
+
+impl<Topic: Clone, Content: Clone> Recent<Topic, Content> {
+    pub(crate) fn record(&self, topic: &Topic, content: &Content) {
+        let mut messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+        if messages.len() == self.capacity { messages.pop_front(); }
+        messages.push_back((topic.clone(), content.clone()));
+    }
+
+    fn last(&self, n: usize) -> Vec<(Topic, Content)> {
+        let messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+        messages.iter().skip(messages.len().saturating_sub(n)).cloned().collect()
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Keeps the last `messages` messages published on the network, on any
+    /// topic, for `Publisher::recent()`. Only messages that pass the
+    /// network's checks are kept. Zero keeps nothing.
+    pub fn remember_recent(&mut self, messages: usize) {
+        self.bus.recent = Some(messages).filter(|&n| n > 0).map(|capacity| Recent {
+            capacity,
+            messages: Mutex::new(VecDeque::with_capacity(capacity)),
+        });
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Returns up to the last `n` messages published on the network, oldest
+    /// first, as kept by `Builder::remember_recent()`. Always empty if the
+    /// network doesn't keep any.
+    pub fn recent(&self, n: usize) -> Vec<(Topic, Content)> {
+        self.bus().recent.as_ref().map_or_else(Vec::new, |recent| recent.last(n))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_messages_on_every_topic() {
+        let mut builder = Publisher::new();
+        builder.remember_recent(3);
+        builder.restrict_topic("secret", &[]);
+        let publisher = builder.build();
+
+        publisher.publish("a", 1);
+        publisher.publish("b", 2);
+        publisher.publish("secret", 0);
+        publisher.publish("a", 3);
+        publisher.publish("c", 4);
+
+        assert_eq!(publisher.recent(10), vec![("b", 2), ("a", 3), ("c", 4)]);
+        assert_eq!(publisher.recent(1), vec![("c", 4)]);
+    }
+}
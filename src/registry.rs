@@ -0,0 +1,83 @@
+//! Keeping count of a network's live publisher handles.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use super::sync::Mutex;
+
+/// A change in the set of publisher handles, as reported to
+/// `Builder::on_publisher_event()`. Handles are grouped by name; `live`
+/// counts the handles with the same name after the change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PublisherEvent {
+    /// A handle was cloned, named, or created by `Builder::build()`.
+    Created {
+        /// The handle's name, if it has one.
+        name: Option<String>,
+        /// Handles with this name that are now alive.
+        live: usize,
+    },
+    /// A handle was dropped.
+    Dropped {
+        /// The handle's name, if it had one.
+        name: Option<String>,
+        /// Handles with this name that are still alive.
+        live: usize,
+    },
+}
+
+type Hook = Box<dyn Fn(&PublisherEvent) + Send + Sync>;
+
+pub(crate) struct Registry {
+    live: Mutex<HashMap<Option<Arc<str>>, usize>>,
+    hook: OnceLock<Hook>,
+}
+
+impl Registry {
+    pub(crate) fn new() -> Self {
+        Registry { live: Mutex::new(HashMap::new()), hook: OnceLock::new() }
+    }
+
+    /// Sets the callback. Only the first call has any effect.
+    pub(crate) fn set_hook(&self, hook: Hook) {
+        let _ = self.hook.set(hook);
+    }
+
+    pub(crate) fn join(&self, name: &Option<Arc<str>>) {
+        let live = {
+            let mut counts = self.live.lock().unwrap_or_else(|e| e.into_inner());
+            let count = counts.entry(name.clone()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        self.notify(|name| PublisherEvent::Created { name, live }, name);
+    }
+
+    pub(crate) fn leave(&self, name: &Option<Arc<str>>) {
+        let live = {
+            let mut counts = self.live.lock().unwrap_or_else(|e| e.into_inner());
+            let count = counts.get_mut(name).map_or(0, |count| {
+                *count -= 1;
+                *count
+            });
+            if count == 0 { counts.remove(name); }
+            count
+        };
+        self.notify(|name| PublisherEvent::Dropped { name, live }, name);
+    }
+
+    /// The hook is called with the table unlocked, so it may look at the
+    /// registry itself.
+    fn notify<F>(&self, event: F, name: &Option<Arc<str>>)
+        where F: FnOnce(Option<String>) -> PublisherEvent
+    {
+        if let Some(hook) = self.hook.get() {
+            hook(&event(name.as_ref().map(|n| n.to_string())));
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> HashMap<Option<String>, usize> {
+        let counts = self.live.lock().unwrap_or_else(|e| e.into_inner());
+        counts.iter().map(|(name, &count)| (name.as_ref().map(|n| n.to_string()), count)).collect()
+    }
+}
@@ -0,0 +1,102 @@
+//! Catching a restarted subscriber up on what it missed.
+//!
+//! Every new subscriber is sent the messages its topics retain, which suits a
+//! component starting for the first time, but one that restarts would get
+//! again the retained messages it already had. `Publisher::rejoin()` is for
+//! that case: a subscriber made with `subscribe_named()` or `rejoin()` is
+//! remembered by name when its guard drops, and rejoining under the same name
+//! replays only the retained messages published since then, to it alone.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::{Publisher, Subscriber, SubscriptionGuard, SubscriptionOptions};
+use super::sync::Mutex;
+
+/// What a topic's retained messages have been numbered up to, by topic.
+pub(crate) type Seen<Topic> = HashMap<Topic, u64>;
+
+/// Named subscriptions, and how far the ones that left had got.
+pub(crate) struct Rejoins<Topic> {
+    /// Live named subscriptions, by route id.
+    named: Mutex<HashMap<usize, (String, Vec<Topic>)>>,
+    /// Where each departed subscription left off, by name.
+    left: Mutex<HashMap<String, Seen<Topic>>>,
+}
+
+impl<Topic: Hash + Eq + Clone> Rejoins<Topic> {
+    pub(crate) fn new() -> Self {
+        Rejoins { named: Mutex::new(HashMap::new()), left: Mutex::new(HashMap::new()) }
+    }
+
+    pub(crate) fn join(&self, id: usize, name: &str, topics: &[Topic]) {
+        let mut named = self.named.lock().unwrap_or_else(|e| e.into_inner());
+        named.insert(id, (name.to_owned(), topics.to_vec()));
+    }
+
+    /// Remembers where the route with id `id` left off, if it was named.
+    /// Called with the routing table locked, so no message can slip in
+    /// between.
+    pub(crate) fn leave<F: Fn(&Topic) -> u64>(&self, id: usize, published: F) {
+        let departed = self.named.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+        if let Some((name, topics)) = departed {
+            let seen = topics.into_iter().map(|topic| {
+                let number = published(&topic);
+                (topic, number)
+            }).collect();
+            self.left.lock().unwrap_or_else(|e| e.into_inner()).insert(name, seen);
+        }
+    }
+
+    fn take(&self, name: &str) -> Option<Seen<Topic>> {
+        self.left.lock().unwrap_or_else(|e| e.into_inner()).remove(name)
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Like `subscribe_named()`, but if a subscriber of the same name left
+    /// earlier, replays only the retained messages published on `topics`
+    /// since it did. Topics it wasn't on before are replayed in full. Nothing
+    /// is sent to anyone else.
+    ///
+    /// Messages it had been sent but hadn't read when it left aren't
+    /// replayed.
+    pub fn rejoin(&self, name: &str, topics: &[Topic])
+        -> (Subscriber<Topic, Content>, SubscriptionGuard<Topic, Content>)
+    {
+        let bus = &self.handle.bus;
+        let seen = bus.rejoins.take(name);
+        let (subscriber, route) = bus.add_subscriber_route(topics, SubscriptionOptions::new(), seen.as_ref());
+        bus.rejoins.join(route.id, name, topics);
+        bus.readiness.arrive(name);
+        (subscriber, SubscriptionGuard { bus: bus.clone(), id: route.id })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use TopicOptions;
+
+    #[test]
+    fn rejoining_replays_only_what_was_missed() {
+        let mut builder = Publisher::new();
+        builder.declare_topic("config", TopicOptions::new().retain_last(10));
+        builder.declare_topic("score", TopicOptions::new().retain_last(10));
+        let publisher = builder.build();
+
+        publisher.publish("config", 1);
+        let (ui, guard) = publisher.subscribe_named("ui", &["config"]);
+        let (other, _other_guard) = publisher.subscribe_scoped(&["config"]);
+        assert_eq!(ui.fetch(), vec![("config", 1)]);
+        drop(guard);
+
+        publisher.publish("config", 2);
+        publisher.publish("score", 7);
+        assert_eq!(other.fetch(), vec![("config", 1), ("config", 2)]);
+
+        let (ui, _guard) = publisher.rejoin("ui", &["config", "score"]);
+        assert_eq!(ui.fetch(), vec![("config", 2), ("score", 7)]);
+        assert_eq!(other.pending(), 0);
+    }
+}
@@ -0,0 +1,276 @@
+//! Broadcasting high-rate data into a ring that readers sample.
+//!
+//! Every subscriber to a topic gets a queue of its own, and every message a
+//! copy in each queue, which is a lot of memory for telemetry read by
+//! dozens of dashboards that only care about the latest few readings. A
+//! `Ring` made by `Publisher::ring()` is a single fixed-size buffer instead:
+//! publishing overwrites its oldest entry, and any number of readers copy
+//! entries out without taking a lock or getting in the publisher's way.
+//!
+//! Each slot is a sequence lock. A writer marks the slot odd while it writes
+//! and stamps it with the message's position when done; a reader copies the
+//! slot and checks the stamp is the one it expected before and after, so an
+//! entry overwritten while it was being read is skipped rather than torn.
+//! Content is stored as `u64` words, each written and read atomically, so a
+//! reader racing a writer sees some mix of old and new words, which it then
+//! throws away, and never a data race. That is why the content has to be
+//! `RingWords`: plain numbers, and tuples and arrays of them.
+
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicU64, Ordering};
+
+use super::{Outbox, Publisher, SubscriptionGuard};
+
+/// Content a `Ring` can hold, as a fixed number of `u64` words. Implemented
+/// for the primitive number types, `bool` and `char`, and for tuples and
+/// arrays of them; other plain data can implement it by packing its fields.
+pub trait RingWords: Copy {
+    /// How many words a value takes.
+    const WORDS: usize;
+
+    /// Passes each of the value's words to `push`, `WORDS` of them in all.
+    fn to_words<F: FnMut(u64)>(self, push: &mut F);
+
+    /// Rebuilds a value from `WORDS` words taken from `words`. The words may
+    /// be a mix of two values, which the ring then discards, so any bits must
+    /// give some value rather than panic.
+    fn from_words<I: Iterator<Item = u64>>(words: &mut I) -> Self;
+}
+
+macro_rules! ring_words {
+    ($($t:ty => |$v:ident| $to:expr, |$w:ident| $from:expr;)*) => {$(
+        impl RingWords for $t {
+            const WORDS: usize = 1;
+
+            fn to_words<F: FnMut(u64)>(self, push: &mut F) {
+                let $v = self;
+                push($to)
+            }
+
+            fn from_words<I: Iterator<Item = u64>>(words: &mut I) -> Self {
+                let $w = words.next().unwrap_or(0);
+                $from
+            }
+        }
+    )*};
+}
+
+ring_words! {
+    u8 => |v| v as u64, |w| w as u8;
+    u16 => |v| v as u64, |w| w as u16;
+    u32 => |v| v as u64, |w| w as u32;
+    u64 => |v| v, |w| w;
+    usize => |v| v as u64, |w| w as usize;
+    i8 => |v| v as u64, |w| w as i8;
+    i16 => |v| v as u64, |w| w as i16;
+    i32 => |v| v as u64, |w| w as i32;
+    i64 => |v| v as u64, |w| w as i64;
+    isize => |v| v as u64, |w| w as isize;
+    f32 => |v| v.to_bits() as u64, |w| f32::from_bits(w as u32);
+    f64 => |v| v.to_bits(), |w| f64::from_bits(w);
+    bool => |v| v as u64, |w| w != 0;
+    char => |v| v as u64, |w| char::from_u32(w as u32).unwrap_or('\0');
+}
+
+macro_rules! ring_tuple {
+    ($($t:ident),*) => {
+        impl<$($t: RingWords),*> RingWords for ($($t,)*) {
+            const WORDS: usize = 0 $(+ $t::WORDS)*;
+
+            #[allow(non_snake_case)]
+            fn to_words<F: FnMut(u64)>(self, push: &mut F) {
+                let ($($t,)*) = self;
+                $($t.to_words(push);)*
+            }
+
+            fn from_words<I: Iterator<Item = u64>>(words: &mut I) -> Self {
+                ($($t::from_words(words),)*)
+            }
+        }
+    };
+}
+
+ring_tuple!(A, B);
+ring_tuple!(A, B, C);
+ring_tuple!(A, B, C, D);
+
+impl<T: RingWords, const N: usize> RingWords for [T; N] {
+    const WORDS: usize = T::WORDS * N;
+
+    fn to_words<F: FnMut(u64)>(self, push: &mut F) {
+        for value in self { value.to_words(push); }
+    }
+
+    fn from_words<I: Iterator<Item = u64>>(words: &mut I) -> Self {
+        std::array::from_fn(|_| T::from_words(words))
+    }
+}
+
+struct Slot {
+    /// Twice one more than the position of the entry in the slot, or odd
+    /// while it is being written.
+    stamp: AtomicU64,
+    words: Box<[AtomicU64]>,
+}
+
+struct Slots<T> {
+    slots: Box<[Slot]>,
+    /// How many entries have been claimed.
+    next: AtomicU64,
+    content: PhantomData<fn(T) -> T>,
+}
+
+impl<T: RingWords> Slots<T> {
+    fn slot(&self, position: u64) -> &Slot {
+        &self.slots[(position % self.slots.len() as u64) as usize]
+    }
+
+    fn write(&self, value: T) {
+        let position = self.next.fetch_add(1, Ordering::AcqRel);
+        let slot = self.slot(position);
+        let writing = position * 2 + 1;
+        let mut stamp = slot.stamp.load(Ordering::Acquire);
+        loop {
+            // A writer a whole lap ahead got here first.
+            if stamp > writing { return; }
+            if stamp % 2 == 1 {
+                std::hint::spin_loop();
+                stamp = slot.stamp.load(Ordering::Acquire);
+                continue;
+            }
+            match slot.stamp.compare_exchange_weak(stamp, writing, Ordering::Acquire, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(now) => stamp = now,
+            }
+        }
+        atomic::fence(Ordering::Release);
+        let mut words = slot.words.iter();
+        value.to_words(&mut |word| if let Some(into) = words.next() { into.store(word, Ordering::Relaxed) });
+        slot.stamp.store(writing + 1, Ordering::Release);
+    }
+
+    fn read(&self, position: u64) -> Option<T> {
+        let slot = self.slot(position);
+        let written = (position + 1) * 2;
+        if slot.stamp.load(Ordering::Acquire) != written { return None; }
+        let value = T::from_words(&mut slot.words.iter().map(|word| word.load(Ordering::Relaxed)));
+        atomic::fence(Ordering::Acquire);
+        if slot.stamp.load(Ordering::Relaxed) != written { return None; }
+        Some(value)
+    }
+}
+
+/// The latest messages on a topic, shared by everyone reading them. Made by
+/// `Publisher::ring()`; clones read the same ring.
+pub struct Ring<T> {
+    slots: Arc<Slots<T>>,
+}
+
+impl<T> Clone for Ring<T> {
+    fn clone(&self) -> Self {
+        Ring { slots: self.slots.clone() }
+    }
+}
+
+impl<T: RingWords> Ring<T> {
+    fn new(capacity: usize) -> Self {
+        let slots = (0 .. capacity.max(1))
+            .map(|_| Slot {
+                stamp: AtomicU64::new(0),
+                words: (0 .. T::WORDS).map(|_| AtomicU64::new(0)).collect(),
+            })
+            .collect();
+        Ring { slots: Arc::new(Slots { slots, next: AtomicU64::new(0), content: PhantomData }) }
+    }
+
+    /// How many entries the ring holds.
+    pub fn capacity(&self) -> usize {
+        self.slots.slots.len()
+    }
+
+    /// How many messages have been published into the ring, overwritten or
+    /// not.
+    pub fn written(&self) -> u64 {
+        self.slots.next.load(Ordering::Acquire)
+    }
+
+    /// The most recent message that could be read.
+    pub fn latest(&self) -> Option<T> {
+        let written = self.written();
+        let oldest = written.saturating_sub(self.capacity() as u64);
+        (oldest .. written).rev().find_map(|position| self.slots.read(position))
+    }
+
+    /// Up to `n` of the most recent messages, oldest first. Any that are
+    /// overwritten or still being written while the ring is read are left
+    /// out.
+    pub fn recent(&self, n: usize) -> Vec<T> {
+        let written = self.written();
+        let oldest = written.saturating_sub(n.min(self.capacity()) as u64);
+        (oldest .. written).filter_map(|position| self.slots.read(position)).collect()
+    }
+}
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + 'static,
+          Content: Clone + RingWords + Send + 'static,
+{
+    /// Copies every message on `topic` into a ring of `capacity` entries,
+    /// until the guard is dropped. Publishing into the ring never allocates
+    /// or takes a lock, and reading it never holds up the publisher, so
+    /// readers that fall behind simply miss what was overwritten. Publishers
+    /// only wait for each other: one that comes round to a slot while
+    /// another is still writing it spins until that write is done.
+    pub fn ring(&self, topic: Topic, capacity: usize) -> (Ring<Content>, SubscriptionGuard<Topic, Content>) {
+        let bus = &self.handle.bus;
+        let ring = Ring::new(capacity);
+        let slots = ring.slots.clone();
+        let outbox = Outbox::Forward(Arc::new(move |_: Topic, content: Content| slots.write(content)));
+        let id = bus.subscribe(&[topic], outbox, None);
+        (ring, SubscriptionGuard { bus: bus.clone(), id })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn readers_sample_the_latest_entries() {
+        let publisher = Publisher::new().build();
+        let (ring, guard) = publisher.ring("imu", 4);
+        assert_eq!(ring.latest(), None);
+        for n in 0 .. 10u64 { publisher.publish("imu", (n, n * 2)); }
+        assert_eq!(ring.written(), 10);
+        assert_eq!(ring.latest(), Some((9, 18)));
+        assert_eq!(ring.recent(3), vec![(7, 14), (8, 16), (9, 18)]);
+        assert_eq!(ring.recent(100).len(), 4);
+
+        let reader = ring.clone();
+        let sampling = thread::spawn(move || {
+            for _ in 0 .. 10_000 {
+                if let Some((n, twice)) = reader.latest() { assert_eq!(twice, n * 2); }
+            }
+        });
+        for n in 10 .. 10_000u64 { publisher.publish("imu", (n, n * 2)); }
+        sampling.join().unwrap();
+
+        drop(guard);
+        publisher.publish("imu", (0, 0));
+        assert_eq!(ring.latest(), Some((9999, 19998)));
+    }
+
+    #[test]
+    fn content_round_trips_through_words() {
+        type Reading = (f64, [bool; 2], (char, i8));
+        let value: Reading = (-1.5, [true, false], ('é', -3));
+        let mut words = vec![];
+        value.to_words(&mut |word| words.push(word));
+        assert_eq!(words.len(), Reading::WORDS);
+        assert_eq!(Reading::from_words(&mut words.into_iter()), value);
+    }
+}
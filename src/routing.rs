@@ -0,0 +1,390 @@
+//! The routing table: which subscribers each topic is delivered to.
+//!
+//! A subscriber's route is stored once, in a numbered slot, however many
+//! topics it listens to. Each topic only keeps a bitset of the slots it goes
+//! to, and the first 64 of those bits live inline, so a network with many
+//! topics and a handful of subscribers spends a word or so per topic. Slots
+//! left empty by unsubscribing are reused, which keeps the bitsets short.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::iter;
+
+use super::{Route, SubscribeError, TopicHasher};
+use super::hasher::TopicHashing;
+use super::sync::RwLock;
+
+/// A set of slots, one bit each.
+#[derive(Default)]
+struct Recipients {
+    low: u64,
+    high: Vec<u64>,
+}
+
+impl Recipients {
+    fn word(&self, n: usize) -> u64 {
+        if n == 0 { self.low } else { self.high.get(n - 1).cloned().unwrap_or_default() }
+    }
+
+    fn word_mut(&mut self, n: usize) -> &mut u64 {
+        if n == 0 { return &mut self.low; }
+        if self.high.len() < n { self.high.resize(n, 0); }
+        &mut self.high[n - 1]
+    }
+
+    fn contains(&self, slot: usize) -> bool {
+        self.word(slot / 64) & (1 << (slot % 64)) != 0
+    }
+
+    /// Adds `slot`, returning false if it was already there.
+    fn insert(&mut self, slot: usize) -> bool {
+        let word = self.word_mut(slot / 64);
+        let added = *word & (1 << (slot % 64)) == 0;
+        *word |= 1 << (slot % 64);
+        added
+    }
+
+    /// Takes `slot` out, returning false if it wasn't there.
+    fn remove(&mut self, slot: usize) -> bool {
+        if !self.contains(slot) { return false; }
+        *self.word_mut(slot / 64) &= !(1 << (slot % 64));
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        self.low == 0 && self.high.iter().all(|&word| word == 0)
+    }
+
+    /// The slots in the set, lowest first.
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        slots(Some(self.low).into_iter().chain(self.high.iter().cloned()))
+    }
+
+    /// The slots in either set, lowest first, each once.
+    fn union<'a>(a: Option<&'a Recipients>, b: &'a Recipients) -> impl Iterator<Item = usize> + 'a {
+        let words = 1 + a.map_or(0, |a| a.high.len()).max(b.high.len());
+        slots((0 .. words).map(move |n| a.map_or(0, |a| a.word(n)) | b.word(n)))
+    }
+
+    fn shrink_to_fit(&mut self) {
+        while self.high.last() == Some(&0) { self.high.pop(); }
+        self.high.shrink_to_fit();
+    }
+}
+
+/// The slots whose bits are set in `words`, lowest first.
+fn slots(words: impl Iterator<Item = u64>) -> impl Iterator<Item = usize> {
+    words.enumerate().flat_map(|(n, mut word)| iter::from_fn(move || {
+        if word == 0 { return None; }
+        let bit = word.trailing_zeros() as usize;
+        word &= word - 1;
+        Some(n * 64 + bit)
+    }))
+}
+
+struct Slot<Topic, Content> {
+    route: Route<Topic, Content>,
+    /// How many topics the route is on, with every topic counting as one.
+    /// The slot is freed when this reaches zero.
+    topics: usize,
+}
+
+/// Every subscriber's route, and the topics each is on. Single-consumer
+/// topics aren't kept here.
+pub(crate) struct RoutingTable<Topic, Content> {
+    slots: Vec<Option<Slot<Topic, Content>>>,
+    /// Empty slots, reused before new ones are added.
+    free: Vec<usize>,
+    /// Which slot each route id is in.
+    slot_of: HashMap<usize, usize>,
+    topics: HashMap<Topic, Recipients, TopicHashing>,
+    /// Routes that get every topic, named or not.
+    everywhere: Recipients,
+    /// Goes up whenever a route is added, changed or taken away, so a copy
+    /// of a topic's recipients can tell when it is out of date.
+    generation: u64,
+}
+
+impl<Topic: Hash + Eq + Clone, Content> RoutingTable<Topic, Content> {
+    pub(crate) fn new() -> Self {
+        RoutingTable {
+            slots: vec![],
+            free: vec![],
+            slot_of: HashMap::new(),
+            topics: HashMap::with_hasher(TopicHashing::new(TopicHasher::default())),
+            everywhere: Recipients::default(),
+            generation: 0,
+        }
+    }
+
+    /// Hashes topics with `kind` from now on.
+    pub(crate) fn set_hasher(&mut self, kind: TopicHasher) {
+        let mut topics = HashMap::with_capacity_and_hasher(self.topics.len(), TopicHashing::new(kind));
+        topics.extend(self.topics.drain());
+        self.topics = topics;
+    }
+
+    /// The table's own copy of the topic `key` stands for, if any route is on
+    /// it.
+    pub(crate) fn key<Q: ?Sized + Hash + Eq>(&self, key: &Q) -> Option<&Topic>
+        where Topic: Borrow<Q>
+    {
+        self.topics.get_key_value(key).map(|(topic, _)| topic)
+    }
+
+    /// Whether the route with id `id` is on `topic`.
+    pub(crate) fn contains(&self, topic: &Topic, id: usize) -> bool {
+        match self.slot_of.get(&id) {
+            Some(&slot) => self.everywhere.contains(slot)
+                || self.topics.get(topic).is_some_and(|recipients| recipients.contains(slot)),
+            None => false,
+        }
+    }
+
+    /// Puts `route` on `topic`. A route whose id is already in the table keeps
+    /// the outbox and admission rules it has.
+    pub(crate) fn insert(&mut self, topic: Topic, route: &Route<Topic, Content>)
+        where Route<Topic, Content>: Clone
+    {
+        let slot = self.slot_for(route);
+        if self.topics.entry(topic).or_default().insert(slot) {
+            self.slots[slot].as_mut().unwrap().topics += 1;
+        }
+    }
+
+    /// Puts `route` on every topic, including ones not yet published.
+    pub(crate) fn insert_everywhere(&mut self, route: &Route<Topic, Content>)
+        where Route<Topic, Content>: Clone
+    {
+        let slot = self.slot_for(route);
+        if self.everywhere.insert(slot) {
+            self.slots[slot].as_mut().unwrap().topics += 1;
+        }
+    }
+
+    /// The slot `route` is in, giving it one if it has none yet.
+    fn slot_for(&mut self, route: &Route<Topic, Content>) -> usize
+        where Route<Topic, Content>: Clone
+    {
+        self.generation += 1;
+        match self.slot_of.get(&route.id) {
+            Some(&slot) => slot,
+            None => {
+                let slot = self.free.pop().unwrap_or(self.slots.len());
+                let filled = Some(Slot { route: route.clone(), topics: 0 });
+                if slot == self.slots.len() { self.slots.push(filled); } else { self.slots[slot] = filled; }
+                self.slot_of.insert(route.id, slot);
+                slot
+            },
+        }
+    }
+
+    /// Takes the route with id `id` off `topic`.
+    pub(crate) fn remove(&mut self, id: usize, topic: &Topic) {
+        self.generation += 1;
+        let slot = match self.slot_of.get(&id) {
+            Some(&slot) => slot,
+            None => return,
+        };
+        if self.topics.get_mut(topic).is_some_and(|recipients| recipients.remove(slot)) {
+            self.left_topic(slot);
+        }
+    }
+
+    /// Takes every route off `topic` and forgets it, returning the routes
+    /// that were on it.
+    pub(crate) fn remove_topic(&mut self, topic: &Topic) -> Vec<Route<Topic, Content>>
+        where Route<Topic, Content>: Clone
+    {
+        self.generation += 1;
+        let recipients = match self.topics.remove(topic) {
+            Some(recipients) => recipients,
+            None => return vec![],
+        };
+        let slots: Vec<usize> = recipients.iter().collect();
+        let routes = slots.iter().map(|&slot| self.route(slot).clone()).collect();
+        for slot in slots { self.left_topic(slot); }
+        routes
+    }
+
+    /// Takes the route with id `id` off every topic. Their entries stay, for
+    /// `compact()` to clear up.
+    pub(crate) fn unsubscribe(&mut self, id: usize) {
+        self.generation += 1;
+        let slot = match self.slot_of.get(&id) {
+            Some(&slot) => slot,
+            None => return,
+        };
+        for recipients in self.topics.values_mut() { recipients.remove(slot); }
+        self.everywhere.remove(slot);
+        self.free_slot(slot);
+    }
+
+    /// The route with id `id`, if it is on any topic.
+    pub(crate) fn route_mut(&mut self, id: usize) -> Option<&mut Route<Topic, Content>> {
+        self.generation += 1;
+        let slot = *self.slot_of.get(&id)?;
+        self.slots[slot].as_mut().map(|slot| &mut slot.route)
+    }
+
+    /// The routes `topic` is delivered to.
+    pub(crate) fn recipients<'a>(&'a self, topic: &Topic)
+        -> impl Iterator<Item = &'a Route<Topic, Content>> + 'a
+    {
+        Recipients::union(self.topics.get(topic), &self.everywhere).map(move |slot| self.route(slot))
+    }
+
+    /// How many times the routes have changed.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Every route in the table, once each.
+    pub(crate) fn routes(&self) -> impl Iterator<Item = &Route<Topic, Content>> + '_ {
+        self.slots.iter().flatten().map(|slot| &slot.route)
+    }
+
+    /// Every topic with an entry, including ones nobody is on any more.
+    pub(crate) fn topics(&self) -> impl Iterator<Item = &Topic> + '_ {
+        self.topics.keys()
+    }
+
+    /// Forgets topics nobody is on and gives back spare memory, returning how
+    /// many topics were forgotten.
+    pub(crate) fn compact(&mut self) -> usize {
+        let before = self.topics.len();
+        self.topics.retain(|_, recipients| !recipients.is_empty());
+        for recipients in self.topics.values_mut() { recipients.shrink_to_fit(); }
+        self.everywhere.shrink_to_fit();
+        self.topics.shrink_to_fit();
+        while let Some(None) = self.slots.last() { self.slots.pop(); }
+        let len = self.slots.len();
+        self.free.retain(|&slot| slot < len);
+        self.slots.shrink_to_fit();
+        self.free.shrink_to_fit();
+        self.slot_of.shrink_to_fit();
+        before - self.topics.len()
+    }
+
+    fn route(&self, slot: usize) -> &Route<Topic, Content> {
+        &self.slots[slot].as_ref().expect("a topic's recipient has a route").route
+    }
+
+    fn left_topic(&mut self, slot: usize) {
+        let filled = self.slots[slot].as_mut().unwrap();
+        filled.topics -= 1;
+        if filled.topics == 0 { self.free_slot(slot); }
+    }
+
+    fn free_slot(&mut self, slot: usize) {
+        if let Some(filled) = self.slots[slot].take() {
+            self.slot_of.remove(&filled.route.id);
+            self.free.push(slot);
+        }
+    }
+}
+
+/// The one route of a single-consumer topic, kept out of the table so that
+/// delivering to it never waits for the table's lock. It is cleared when its
+/// subscription goes away, so the topic can be subscribed to again.
+pub(crate) struct SingleSlot<Topic, Content> {
+    route: RwLock<Option<Route<Topic, Content>>>,
+}
+
+impl<Topic: Clone, Content: Clone> SingleSlot<Topic, Content> {
+    pub(crate) fn new(route: Option<Route<Topic, Content>>) -> Self {
+        SingleSlot { route: RwLock::new(route) }
+    }
+
+    /// A copy of the route, if the topic has a subscriber.
+    pub(crate) fn get(&self) -> Option<Route<Topic, Content>> {
+        self.route.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub(crate) fn is_taken(&self) -> bool {
+        self.route.read().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+
+    /// Calls `f` with the route, without copying it.
+    pub(crate) fn with<R, F: FnOnce(Option<&Route<Topic, Content>>) -> R>(&self, f: F) -> R {
+        f(self.route.read().unwrap_or_else(|e| e.into_inner()).as_ref())
+    }
+
+    /// Makes `route` the topic's subscriber, returning true if it wasn't
+    /// already, and calls `welcome` with it first, while no message can be
+    /// delivered to it. Refuses if another route has the topic.
+    pub(crate) fn claim<F>(&self, route: &Route<Topic, Content>, welcome: F) -> Result<bool, SubscribeError>
+        where F: FnOnce(&Route<Topic, Content>)
+    {
+        let mut slot = self.route.write().unwrap_or_else(|e| e.into_inner());
+        match *slot {
+            Some(ref current) if current.id == route.id => Ok(false),
+            Some(_) => Err(SubscribeError::SingleConsumerTaken),
+            None => {
+                welcome(route);
+                *slot = Some(route.clone());
+                Ok(true)
+            },
+        }
+    }
+
+    /// Frees the topic if the route with the given id has it.
+    pub(crate) fn release(&self, id: usize) {
+        let mut slot = self.route.write().unwrap_or_else(|e| e.into_inner());
+        if slot.as_ref().is_some_and(|route| route.id == id) { *slot = None; }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::mpsc;
+    use sync::AtomicUsize;
+    use Outbox;
+
+    fn route(id: usize) -> Route<&'static str, u32> {
+        let (tx, _) = mpsc::channel();
+        Route { id, outbox: Outbox::Inbox(tx, Arc::new(AtomicUsize::new(0))), admission: None }
+    }
+
+    fn ids(table: &RoutingTable<&'static str, u32>, topic: &'static str) -> Vec<usize> {
+        table.recipients(&topic).map(|route| route.id).collect()
+    }
+
+    #[test]
+    fn routes_are_stored_once_and_slots_reused() {
+        let mut table = RoutingTable::new();
+        for id in 0..70 {
+            table.insert("all", &route(id));
+            if id % 2 == 0 { table.insert("even", &route(id)); }
+        }
+        table.insert("all", &route(3));
+        assert_eq!(table.routes().count(), 70);
+        assert_eq!(ids(&table, "all"), (0..70).collect::<Vec<_>>());
+        assert!(table.contains(&"even", 68) && !table.contains(&"even", 69));
+
+        table.unsubscribe(2);
+        table.remove(4, &"all");
+        assert!(!table.contains(&"all", 4) && table.contains(&"even", 4));
+        table.remove(4, &"even");
+        assert_eq!(table.routes().count(), 68);
+
+        // The two freed slots go to the next two subscribers.
+        table.insert("late", &route(100));
+        table.insert("late", &route(101));
+        assert_eq!(table.slots.len(), 70);
+        assert_eq!(table.remove_topic(&"even").len(), 33);
+        assert!(ids(&table, "even").is_empty());
+        assert!(table.contains(&"all", 68));
+
+        table.unsubscribe(100);
+        table.unsubscribe(101);
+        assert_eq!(table.compact(), 1);
+        table.remove(69, &"all");
+        for id in 0..69 { table.unsubscribe(id); }
+        assert_eq!(table.compact(), 1);
+        assert_eq!(table.slots.len(), 0);
+    }
+}
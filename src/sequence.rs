@@ -0,0 +1,193 @@
+//! Noticing when topics arrive in a particular order.
+//!
+//! Some conditions only show across several messages: a login, a password
+//! change and a withdrawal inside a minute, say. A `Sequence` describes the
+//! topics to look for, in order, and how close together they must be, and
+//! `Publisher::watch_sequence()` reports each time they occur, with the
+//! messages that made up the match. Other messages may come in between.
+//! Times are taken from the network's clock as each message is published, so
+//! a slow reader doesn't stretch the window.
+
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use super::{Outbox, Publisher, SubscriptionGuard};
+use super::sync::Mutex;
+
+/// A pattern of topics to look for, in order.
+#[derive(Clone, Debug)]
+pub struct Sequence<Topic> {
+    steps: Vec<Topic>,
+    window: Option<Duration>,
+}
+
+impl<Topic: Clone> Sequence<Topic> {
+    /// Matches `steps`, in this order. There is no time limit unless one is
+    /// set with `within()`.
+    pub fn of<I: IntoIterator<Item = Topic>>(steps: I) -> Self {
+        Sequence { steps: steps.into_iter().collect(), window: None }
+    }
+
+    /// Only matches when the last step arrives no later than `window` after
+    /// the first.
+    pub fn within(self, window: Duration) -> Self {
+        Sequence { window: Some(window), ..self }
+    }
+}
+
+/// One partly matched sequence: the messages so far, and when it started.
+type Partial<Topic, Content> = (Instant, Vec<(Topic, Content)>);
+
+/// Tracks how much of a sequence has been seen.
+struct Matcher<Topic, Content> {
+    sequence: Sequence<Topic>,
+    /// `partials[i]` is the latest-starting match with `i` steps seen.
+    partials: Vec<Option<Partial<Topic, Content>>>,
+}
+
+impl<Topic: PartialEq + Clone, Content: Clone> Matcher<Topic, Content> {
+    fn new(sequence: Sequence<Topic>) -> Self {
+        let partials = (0..sequence.steps.len()).map(|_| None).collect();
+        Matcher { sequence, partials }
+    }
+
+    /// Takes note of a message, returning the completed match if it was the
+    /// last step of one. Matches don't overlap: once one completes, the
+    /// search starts over.
+    fn observe(&mut self, topic: &Topic, content: &Content, now: Instant) -> Option<Vec<(Topic, Content)>> {
+        let steps = &self.sequence.steps;
+        if steps.is_empty() { return None; }
+        if let Some(window) = self.sequence.window {
+            for partial in &mut self.partials {
+                if partial.as_ref().is_some_and(|&(start, _)| now.duration_since(start) > window) {
+                    *partial = None;
+                }
+            }
+        }
+
+        // Furthest along first, so one message advances each match one step.
+        let mut completed = None;
+        for seen in (0..steps.len()).rev() {
+            if steps[seen] != *topic { continue; }
+            let advanced = match seen {
+                0 => Some((now, vec![])),
+                _ => self.partials[seen].take(),
+            };
+            let (start, mut messages) = match advanced {
+                Some(advanced) => advanced,
+                None => continue,
+            };
+            messages.push((topic.clone(), content.clone()));
+            if seen + 1 == steps.len() {
+                completed = Some(messages);
+                break;
+            }
+            let later = match self.partials[seen + 1] {
+                Some((existing, _)) => start >= existing,
+                None => true,
+            };
+            if later { self.partials[seen + 1] = Some((start, messages)); }
+        }
+
+        if completed.is_some() {
+            for partial in &mut self.partials { *partial = None; }
+        }
+        completed
+    }
+}
+
+/// Reports occurrences of a `Sequence`, until it is dropped. Made by
+/// `Publisher::watch_sequence()`.
+pub struct SequenceWatch<Topic: Hash + Eq + Clone, Content: Clone> {
+    matches: Receiver<Vec<(Topic, Content)>>,
+    _guard: SubscriptionGuard<Topic, Content>,
+}
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + 'static,
+          Content: Clone + Send + 'static,
+{
+    /// Starts looking for `sequence` among the messages published from now
+    /// on.
+    pub fn watch_sequence(&self, sequence: Sequence<Topic>) -> SequenceWatch<Topic, Content> {
+        let bus = &self.handle.bus;
+        let topics: HashSet<Topic> = sequence.steps.iter().cloned().collect();
+        let topics: Vec<Topic> = topics.into_iter().collect();
+        let matcher = Mutex::new(Matcher::new(sequence));
+        let clock = bus.clock.clone();
+        let (tx, rx) = mpsc::channel();
+
+        let outbox = Outbox::Forward(Arc::new(move |topic: Topic, content: Content| {
+            let now = clock.now();
+            let mut matcher = matcher.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(messages) = matcher.observe(&topic, &content, now) {
+                tx.send(messages).unwrap_or(());
+            }
+        }));
+        let id = bus.subscribe(&topics, outbox, None);
+        SequenceWatch { matches: rx, _guard: SubscriptionGuard { bus: bus.clone(), id } }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> SequenceWatch<Topic, Content> {
+    /// Returns the messages of the next match, if one has occurred, without
+    /// waiting.
+    pub fn try_next(&self) -> Option<Vec<(Topic, Content)>> {
+        self.matches.try_recv().ok()
+    }
+
+    /// Waits up to `timeout` for the next match.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Vec<(Topic, Content)>> {
+        self.matches.recv_timeout(timeout).ok()
+    }
+
+    /// Returns every match that has occurred since the last call.
+    pub fn fetch(&self) -> Vec<Vec<(Topic, Content)>> {
+        self.matches.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ManualClock;
+
+    #[test]
+    fn steps_must_arrive_in_order_and_in_time() {
+        let clock = ManualClock::new();
+        let mut builder = Publisher::new();
+        builder.clock(clock.clone());
+        let publisher = builder.build();
+
+        let sequence = Sequence::of(["login", "password", "withdraw"]).within(Duration::from_secs(60));
+        let watch = publisher.watch_sequence(sequence);
+
+        publisher.publish("password", 0);
+        publisher.publish("login", 1);
+        publisher.publish("balance", 2);
+        publisher.publish("password", 3);
+        publisher.publish("withdraw", 4);
+        assert_eq!(watch.fetch(), vec![vec![("login", 1), ("password", 3), ("withdraw", 4)]]);
+
+        publisher.publish("login", 5);
+        clock.advance(Duration::from_secs(30));
+        publisher.publish("login", 6);
+        publisher.publish("password", 7);
+        clock.advance(Duration::from_secs(45));
+        publisher.publish("withdraw", 8);
+        assert_eq!(watch.try_next(), Some(vec![("login", 6), ("password", 7), ("withdraw", 8)]));
+
+        publisher.publish("login", 9);
+        publisher.publish("password", 10);
+        clock.advance(Duration::from_secs(61));
+        publisher.publish("withdraw", 11);
+        assert_eq!(watch.try_next(), None);
+        publisher.publish("login", 12);
+        publisher.publish("password", 13);
+        publisher.publish("withdraw", 14);
+        assert_eq!(watch.try_next(), Some(vec![("login", 12), ("password", 13), ("withdraw", 14)]));
+    }
+}
@@ -0,0 +1,136 @@
+//! Spreading the routing work of a busy network over several threads.
+//!
+//! A publish does its routing on the publishing thread, so a single thread
+//! publishing at full speed is limited to one core's worth of deliveries. A
+//! `Sharded` publisher hands each message to one of several broker threads
+//! instead, chosen by a hash of its topic, and returns straight away. Every
+//! message on a topic goes through the same broker, so they arrive in the
+//! order they were published; messages on different topics may not.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use super::{Publish, PublishError, Publisher};
+
+enum Job<Topic, Content> {
+    Publish(Topic, Content),
+    /// Answers once every job sent before it is done.
+    Flush(Sender<()>),
+}
+
+struct Brokers<Topic, Content> {
+    queues: Vec<Sender<Job<Topic, Content>>>,
+    threads: Vec<JoinHandle<()>>,
+    hashing: RandomState,
+}
+
+impl<Topic, Content> Drop for Brokers<Topic, Content> {
+    fn drop(&mut self) {
+        self.queues.clear();
+        for thread in self.threads.drain(..) { let _ = thread.join(); }
+    }
+}
+
+/// A publisher that routes on broker threads, made by
+/// `Publisher::sharded()`. Clones share the same brokers, which finish what
+/// they were sent and stop once the last clone is dropped.
+pub struct Sharded<Topic, Content> {
+    brokers: Arc<Brokers<Topic, Content>>,
+}
+
+impl<Topic, Content> Clone for Sharded<Topic, Content> {
+    fn clone(&self) -> Self {
+        Sharded { brokers: self.brokers.clone() }
+    }
+}
+
+impl<Topic: Hash, Content> Sharded<Topic, Content> {
+    /// How many broker threads there are.
+    pub fn brokers(&self) -> usize {
+        self.brokers.queues.len()
+    }
+
+    /// Queues a message with its topic's broker, which publishes it as
+    /// `Publisher::publish()` would. Only a stopped broker is reported here;
+    /// a message the network refuses is reported to its drop hooks.
+    pub fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        let shard = self.brokers.hashing.hash_one(&topic) as usize % self.brokers.queues.len();
+        self.send(shard, Job::Publish(topic, content))
+    }
+
+    /// Like `try_publish()`, ignoring a stopped broker.
+    pub fn publish(&self, topic: Topic, content: Content) {
+        self.try_publish(topic, content).unwrap_or(());
+    }
+
+    /// Waits until every message queued so far has been published.
+    pub fn flush(&self) {
+        let replies: Vec<_> = (0 .. self.brokers()).filter_map(|shard| {
+            let (tx, rx) = mpsc::channel();
+            self.send(shard, Job::Flush(tx)).ok().map(|()| rx)
+        }).collect();
+        for reply in replies { let _ = reply.recv(); }
+    }
+
+    /// A broker only goes away if publishing panicked on it.
+    fn send(&self, shard: usize, job: Job<Topic, Content>) -> Result<(), PublishError> {
+        self.brokers.queues[shard].send(job).map_err(|_| PublishError::BusPoisoned)
+    }
+}
+
+impl<Topic: Hash, Content> Publish<Topic, Content> for Sharded<Topic, Content> {
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        Sharded::try_publish(self, topic, content)
+    }
+}
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+{
+    /// Starts `brokers` threads that publish to this network on behalf of
+    /// the `Sharded` handle returned, under this handle's name.
+    pub fn sharded(&self, brokers: usize) -> Sharded<Topic, Content> {
+        let (queues, threads) = (0 .. brokers.max(1)).map(|shard| {
+            let (tx, rx) = mpsc::channel();
+            let publisher = self.clone();
+            let thread = thread::Builder::new()
+                .name(format!("alewife-broker-{}", shard))
+                .spawn(move || for job in rx {
+                    match job {
+                        Job::Publish(topic, content) => publisher.publish(topic, content),
+                        Job::Flush(done) => { let _ = done.send(()); },
+                    }
+                })
+                .expect("failed to start a broker thread");
+            (tx, thread)
+        }).unzip();
+        Sharded { brokers: Arc::new(Brokers { queues, threads, hashing: RandomState::new() }) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn each_topic_keeps_its_order() {
+        let mut builder = Publisher::new();
+        let subscriber = builder.add_subscriber(&[0, 1, 2, 3]);
+        let publisher = builder.build();
+
+        let sharded = publisher.sharded(3);
+        for n in 0 .. 400 { sharded.publish(n % 4, n); }
+        sharded.flush();
+
+        let received = subscriber.fetch();
+        assert_eq!(received.len(), 400);
+        for topic in 0 .. 4 {
+            let on_topic: Vec<_> = received.iter().filter(|m| m.0 == topic).map(|m| m.1).collect();
+            assert_eq!(on_topic, (0 .. 100).map(|n| n * 4 + topic).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Shutting subscribers down in phases.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use super::ShutdownTimedOut;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn phases_are_released_in_order() {
+        let shutdown = Arc::new(Shutdown::new());
+        let input = Shutdown::join(&shutdown, 0);
+        let logger = Shutdown::join(&shutdown, 9);
+        let early = Duration::from_millis(10);
+
+        assert!(!input.is_requested());
+        assert_eq!(shutdown.run(early), Err(ShutdownTimedOut { phase: 0, waiting: 1 }));
+        assert!(input.is_requested());
+        assert!(!logger.is_requested());
+
+        input.acknowledge();
+        drop(logger);
+        assert_eq!(shutdown.run(early), Ok(()));
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Members yet to acknowledge, by phase.
+    waiting: BTreeMap<u32, usize>,
+    /// The latest phase asked to shut down.
+    requested: Option<u32>,
+}
+
+/// Shared by a network and every subscriber with a shutdown phase.
+#[derive(Default)]
+pub(crate) struct Shutdown {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shutdown {
+    pub(crate) fn new() -> Self {
+        Shutdown::default()
+    }
+
+    pub(crate) fn join(shutdown: &Arc<Self>, phase: u32) -> Member {
+        let mut state = shutdown.state.lock().unwrap_or_else(|e| e.into_inner());
+        *state.waiting.entry(phase).or_insert(0) += 1;
+        Member { shutdown: shutdown.clone(), phase, acknowledged: Cell::new(false) }
+    }
+
+    /// Asks each phase in turn to shut down, waiting up to `timeout` for all
+    /// of its members to acknowledge before moving on to the next.
+    pub(crate) fn run(&self, timeout: Duration) -> Result<(), ShutdownTimedOut> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let phases: Vec<u32> = state.waiting.keys().cloned().collect();
+
+        for phase in phases {
+            state.requested = state.requested.max(Some(phase));
+            let deadline = Instant::now() + timeout;
+            loop {
+                let waiting = state.waiting.get(&phase).cloned().unwrap_or(0);
+                if waiting == 0 { break; }
+
+                let now = Instant::now();
+                if now >= deadline { return Err(ShutdownTimedOut { phase, waiting }); }
+                state = self.changed.wait_timeout(state, deadline - now)
+                    .unwrap_or_else(|e| e.into_inner()).0;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One subscriber's place in the shutdown order. Dropping it counts as
+/// acknowledging.
+pub(crate) struct Member {
+    shutdown: Arc<Shutdown>,
+    phase: u32,
+    acknowledged: Cell<bool>,
+}
+
+impl Member {
+    pub(crate) fn is_requested(&self) -> bool {
+        let state = self.shutdown.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.requested.is_some_and(|requested| requested >= self.phase)
+    }
+
+    pub(crate) fn acknowledge(&self) {
+        if self.acknowledged.replace(true) { return; }
+
+        let mut state = self.shutdown.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(waiting) = state.waiting.get_mut(&self.phase) {
+            *waiting -= 1;
+            if *waiting == 0 { state.waiting.remove(&self.phase); }
+        }
+        self.shutdown.changed.notify_all();
+    }
+}
+
+impl Drop for Member {
+    fn drop(&mut self) {
+        self.acknowledge();
+    }
+}
@@ -0,0 +1,122 @@
+//! Deterministic delivery for tests.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::Route;
+
+/// Control over a network built in simulation mode, created by
+/// `Builder::simulate()`.
+///
+/// In simulation mode, publishing doesn't deliver anything. Each message bound
+/// for a subscriber joins that subscriber's queue, and is delivered only when
+/// the simulation is stepped. Every step picks one subscriber with something
+/// queued, using a random number generator seeded by the test, and delivers the
+/// oldest message in its queue. Each subscriber still sees messages in the
+/// order they were published, but the interleaving between subscribers, and
+/// how far each one lags behind, is up to the seed. Running the same test with
+/// the same seed always produces the same interleaving, so a failure found by
+/// trying many seeds can be reproduced from the one that triggered it.
+pub struct Simulation<Topic, Content> {
+    state: Arc<Mutex<State<Topic, Content>>>,
+    seed: u64,
+}
+
+impl<Topic, Content> Simulation<Topic, Content> {
+    pub(crate) fn new(seed: u64) -> Self {
+        let state = State { rng: seed, queues: Vec::new() };
+        Simulation { state: Arc::new(Mutex::new(state)), seed }
+    }
+
+    pub(crate) fn scheduler(&self) -> Scheduler<Topic, Content> {
+        Scheduler { state: self.state.clone() }
+    }
+
+    /// Returns the seed the simulation was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of deliveries waiting to happen.
+    pub fn pending(&self) -> usize {
+        self.lock().queues.iter().map(|q| q.messages.len()).sum()
+    }
+
+    /// Performs one delivery, chosen by the random number generator. Returns
+    /// false if there was nothing to deliver.
+    pub fn step(&self) -> bool {
+        let (route, topic, content) = {
+            let mut state = self.lock();
+            if state.queues.is_empty() { return false; }
+
+            let pick = (state.next_random() % state.queues.len() as u64) as usize;
+            let message = state.queues[pick].messages.pop_front().unwrap();
+            if state.queues[pick].messages.is_empty() {
+                state.queues.remove(pick);
+            }
+            message
+        };
+
+        // Delivered with the lock released, since forwarding to another
+        // simulated network schedules more deliveries.
+        route.send(topic, content);
+        true
+    }
+
+    /// Steps until nothing is left to deliver, including anything published
+    /// by subscribers along the way, and returns how many deliveries were
+    /// made. Stops after `limit` steps, so a feedback loop can't hang a test.
+    pub fn run(&self, limit: usize) -> usize {
+        let mut steps = 0;
+        while steps < limit && self.step() { steps += 1; }
+        steps
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<Topic, Content>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The network's half of a simulation, which queues deliveries.
+pub(crate) struct Scheduler<Topic, Content> {
+    state: Arc<Mutex<State<Topic, Content>>>,
+}
+
+impl<Topic: Clone, Content: Clone> Scheduler<Topic, Content> {
+    pub(crate) fn schedule(&self, route: &Route<Topic, Content>, topic: Topic, content: Content) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let position = state.queues.iter().position(|q| q.route == route.id);
+        let queue = match position {
+            Some(i) => &mut state.queues[i],
+            None => {
+                state.queues.push(Queue { route: route.id, messages: VecDeque::new() });
+                state.queues.last_mut().unwrap()
+            },
+        };
+        queue.messages.push_back((route.clone(), topic, content));
+    }
+}
+
+struct State<Topic, Content> {
+    rng: u64,
+    /// Only subscribers with something queued have an entry, so every pick
+    /// makes progress. Kept in order of first use, not hashed, so the
+    /// interleaving depends on nothing but the seed.
+    queues: Vec<Queue<Topic, Content>>,
+}
+
+struct Queue<Topic, Content> {
+    route: usize,
+    messages: VecDeque<(Route<Topic, Content>, Topic, Content)>,
+}
+
+impl<Topic, Content> State<Topic, Content> {
+    /// One splitmix64 step, which copes with any seed, including zero.
+    fn next_random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
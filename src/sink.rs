@@ -0,0 +1,116 @@
+//! Feeding async streams onto a network.
+
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use super::{Publish, PublishError, Publisher};
+
+/// Publishes what an async pipeline produces. `forward()` drives a stream of
+/// messages onto the network, so a pipeline ending in a `PublisherSink` needs
+/// no loop of its own. Publishing never waits, so the pipeline is only held
+/// up by the stream itself.
+pub struct PublisherSink<Topic: Hash + Eq + Clone, Content: Clone> {
+    publisher: Publisher<Topic, Content>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> PublisherSink<Topic, Content> {
+    /// Publishes through `publisher`, and under its name.
+    pub fn new(publisher: Publisher<Topic, Content>) -> Self {
+        PublisherSink { publisher }
+    }
+
+    /// Publishes one message.
+    pub fn send(&self, (topic, content): (Topic, Content)) -> Result<(), PublishError> {
+        self.publisher.try_publish(topic, content)
+    }
+
+    /// Publishes every message `stream` yields, until it ends or a message
+    /// is refused. The future resolves to how many were published, or to the
+    /// reason the first refused one was refused.
+    pub fn forward<S>(self, stream: S) -> Forward<S, Topic, Content>
+        where S: Stream<Item = (Topic, Content)> + Unpin
+    {
+        Forward { sink: self, stream, published: 0 }
+    }
+
+    /// Unwraps the publisher.
+    pub fn into_inner(self) -> Publisher<Topic, Content> {
+        self.publisher
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publish<Topic, Content> for PublisherSink<Topic, Content> {
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        self.send((topic, content))
+    }
+}
+
+/// Future returned by `PublisherSink::forward()`.
+pub struct Forward<S, Topic: Hash + Eq + Clone, Content: Clone> {
+    sink: PublisherSink<Topic, Content>,
+    stream: S,
+    published: usize,
+}
+
+impl<S, Topic, Content> Future for Forward<S, Topic, Content>
+    where S: Stream<Item = (Topic, Content)> + Unpin,
+          Topic: Hash + Eq + Clone,
+          Content: Clone,
+{
+    type Output = Result<usize, PublishError>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            match Pin::new(&mut this.stream).poll_next(context) {
+                Poll::Ready(Some(message)) => {
+                    if let Err(e) = this.sink.send(message) { return Poll::Ready(Err(e)); }
+                    this.published += 1;
+                },
+                Poll::Ready(None) => return Poll::Ready(Ok(this.published)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::task::Waker;
+
+    /// Yields the messages it was given, one per poll.
+    struct Messages(Vec<(&'static str, u32)>);
+
+    impl Stream for Messages {
+        type Item = (&'static str, u32);
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(if self.0.is_empty() { None } else { Some(self.0.remove(0)) })
+        }
+    }
+
+    #[test]
+    fn streams_forward_onto_the_network() {
+        let mut builder = Publisher::new();
+        builder.restrict_topic("admin", &["root"]);
+        let subscriber = builder.add_subscriber(&["score"]);
+        let publisher = builder.build();
+
+        let mut context = Context::from_waker(Waker::noop());
+        let sink = PublisherSink::new(publisher.clone());
+        let mut forward = sink.forward(Messages(vec![("score", 1), ("score", 2)]));
+        assert_eq!(Pin::new(&mut forward).poll(&mut context), Poll::Ready(Ok(2)));
+        assert_eq!(subscriber.fetch(), vec![("score", 1), ("score", 2)]);
+
+        let sink = PublisherSink::new(publisher);
+        let mut forward = sink.forward(Messages(vec![("score", 3), ("admin", 4), ("score", 5)]));
+        assert_eq!(Pin::new(&mut forward).poll(&mut context),
+                   Poll::Ready(Err(PublishError::Forbidden)));
+        assert_eq!(subscriber.fetch(), vec![("score", 3)]);
+    }
+}
@@ -0,0 +1,116 @@
+//! Saving a network's state and restoring it later.
+//!
+//! A network holds some state of its own: the messages topics retain for new
+//! subscribers, and messages scheduled for later. `Publisher::snapshot()`
+//! copies both out, and `Builder::restore()` puts them back into a new
+//! network, whether after a reload from a save file or in another process.
+//! With the `serde` feature a `Snapshot` can be serialized like any other
+//! value. Messages already queued for subscribers belong to the subscribers
+//! and aren't included.
+
+use std::hash::Hash;
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{Builder, Publisher};
+
+/// What `Publisher::snapshot()` saw.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot<Topic, Content> {
+    /// Messages each topic was retaining, oldest first.
+    pub retained: Vec<(Topic, Vec<Content>)>,
+    /// Messages waiting to be published, earliest first.
+    pub scheduled: Vec<Scheduled<Topic, Content>>,
+}
+
+/// A message scheduled with `Publisher::publish_after()` or
+/// `Publisher::publish_every()`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Scheduled<Topic, Content> {
+    /// How long after the snapshot the message was due. Times are saved this
+    /// way because an `Instant` means nothing in another process.
+    pub due_in: Duration,
+    /// The interval it repeats at, if it does.
+    pub every: Option<Duration>,
+    /// The topic it will be published on.
+    pub topic: Topic,
+    /// What will be published.
+    pub content: Content,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Copies out the messages topics are retaining and those scheduled for
+    /// later.
+    pub fn snapshot(&self) -> Snapshot<Topic, Content> {
+        let bus = self.bus();
+        let now = bus.clock.now();
+        let retained = bus.topic_settings.iter()
+            .map(|(topic, settings)| (topic.clone(), settings.retained(now)))
+            .filter(|(_, contents)| !contents.is_empty())
+            .collect();
+        Snapshot { retained, scheduled: bus.timers.pending(bus.clock.now()) }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Puts back the state saved by `Publisher::snapshot()`. Retained
+    /// messages only go back to topics declared to retain messages, so
+    /// declare those first; scheduled messages are due as long after this
+    /// call as they were after the snapshot, by the builder's clock. Restored
+    /// timers get new `TimerId`s.
+    pub fn restore(&mut self, snapshot: Snapshot<Topic, Content>) {
+        let now = self.bus.clock.now();
+        for (topic, contents) in snapshot.retained {
+            if let Some(settings) = self.bus.topic_settings.get(&topic) {
+                for content in &contents { settings.retain(content, now); }
+            }
+        }
+        for scheduled in snapshot.scheduled {
+            self.bus.timers.schedule(now + scheduled.due_in, scheduled.every,
+                                     scheduled.topic, scheduled.content);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {ManualClock, TopicOptions};
+
+    fn network(clock: &ManualClock) -> Builder<&'static str, u32> {
+        let mut builder = Publisher::new();
+        builder.clock(clock.clone());
+        builder.declare_topic("score", TopicOptions::new().retain_last(2));
+        builder
+    }
+
+    #[test]
+    fn restored_networks_pick_up_where_they_left_off() {
+        let clock = ManualClock::new();
+        let saved = network(&clock).build();
+        saved.publish("score", 1);
+        saved.publish("score", 2);
+        saved.publish("score", 3);
+        saved.publish_after(Duration::from_secs(5), "alarm", 4);
+        saved.publish_every(Duration::from_secs(3), "tick", 0);
+        clock.advance(Duration::from_secs(1));
+
+        let snapshot = saved.snapshot();
+        assert_eq!(snapshot.retained, vec![("score", vec![2, 3])]);
+        assert_eq!(snapshot.scheduled[0].due_in, Duration::from_secs(2));
+
+        let clock = ManualClock::new();
+        let mut builder = network(&clock);
+        builder.restore(snapshot);
+        let subscriber = builder.add_subscriber(&["score", "alarm", "tick"]);
+        let restored = builder.build();
+        restored.fast_forward(&clock, Duration::from_secs(5));
+        assert_eq!(subscriber.fetch(), vec![
+            ("score", 2), ("score", 3), ("tick", 0), ("alarm", 4), ("tick", 0),
+        ]);
+    }
+}
@@ -0,0 +1,164 @@
+//! Publishing during setup.
+//!
+//! Components are often made in whatever order suits the program rather than
+//! the network, and some have something to say as soon as they exist: a
+//! config loader with the settings it read, a device with its initial state.
+//! Messages given to `Builder::stage()`, or published through a
+//! `PrePublisher` from `Builder::add_publisher()`, wait until `build()` has
+//! added every subscriber and connected every other network, and are then
+//! published in the order they were staged, before anything else can
+//! publish. Subscribers added by the same builder receive them whenever they
+//! were added.
+//!
+//! A `PrePublisher` can be handed to a component as it is made, before the
+//! subscribers it will talk to exist, and goes on working once the network
+//! is built. If the builder is dropped without being built, whatever was
+//! staged is never published.
+
+use std::hash::Hash;
+use std::mem;
+use std::sync::Arc;
+
+use super::{Builder, Publish, PublishError, Publisher};
+use super::sync::Mutex;
+
+enum Stage<Topic: Hash + Eq + Clone, Content: Clone> {
+    Setup(Vec<(Topic, Content)>),
+    Built(Publisher<Topic, Content>),
+}
+
+/// Messages published during setup, shared by a builder and its
+/// pre-publishers.
+pub(crate) struct Staged<Topic: Hash + Eq + Clone, Content: Clone> {
+    stage: Mutex<Stage<Topic, Content>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Staged<Topic, Content> {
+    pub(crate) fn new() -> Self {
+        Staged { stage: Mutex::new(Stage::Setup(vec![])) }
+    }
+
+    fn publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        let mut stage = self.stage.lock().unwrap_or_else(|e| e.into_inner());
+        let publisher = match *stage {
+            Stage::Setup(ref mut queue) => {
+                queue.push((topic, content));
+                return Ok(());
+            },
+            Stage::Built(ref publisher) => publisher.clone(),
+        };
+        drop(stage);
+        publisher.try_publish(topic, content)
+    }
+
+    /// Publishes everything staged so far through `publisher`, and from then
+    /// on passes messages straight to it. The lock isn't held while
+    /// publishing, so a subscriber that publishes through a pre-publisher
+    /// doesn't deadlock; what it publishes is queued behind the rest. The
+    /// publisher is only kept if there are pre-publishers to use it, so it
+    /// isn't counted as a live handle otherwise.
+    pub(crate) fn go_live(self: &Arc<Self>, publisher: &Publisher<Topic, Content>) {
+        loop {
+            let mut stage = self.stage.lock().unwrap_or_else(|e| e.into_inner());
+            let queue = match *stage {
+                Stage::Setup(ref mut queue) if !queue.is_empty() => mem::take(queue),
+                _ => {
+                    if Arc::strong_count(self) > 1 { *stage = Stage::Built(publisher.clone()); }
+                    return;
+                },
+            };
+            drop(stage);
+            for (topic, content) in queue { publisher.publish(topic, content); }
+        }
+    }
+}
+
+/// A handle for publishing to a network that may not have been built yet.
+/// Made by `Builder::add_publisher()`.
+pub struct PrePublisher<Topic: Hash + Eq + Clone, Content: Clone> {
+    staged: Arc<Staged<Topic, Content>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Clone for PrePublisher<Topic, Content> {
+    fn clone(&self) -> Self {
+        PrePublisher { staged: self.staged.clone() }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> PrePublisher<Topic, Content> {
+    /// Publishes as `Publisher::try_publish()` does once the network is
+    /// built. Until then, stages the message and returns `Ok`; any refusal
+    /// is reported to the network's drop hooks instead.
+    pub fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        self.staged.publish(topic, content)
+    }
+
+    /// Like `try_publish()`, ignoring refusals.
+    pub fn publish(&self, topic: Topic, content: Content) {
+        self.try_publish(topic, content).unwrap_or(());
+    }
+
+    /// The network's own publisher, once it has been built.
+    pub fn publisher(&self) -> Option<Publisher<Topic, Content>> {
+        match *self.staged.stage.lock().unwrap_or_else(|e| e.into_inner()) {
+            Stage::Setup(_) => None,
+            Stage::Built(ref publisher) => Some(publisher.clone()),
+        }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publish<Topic, Content> for PrePublisher<Topic, Content> {
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        PrePublisher::try_publish(self, topic, content)
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Publishes `content` on `topic` as soon as the network is built, as
+    /// `Publisher::publish()` would, after anything staged before it.
+    pub fn stage(&mut self, topic: Topic, content: Content) {
+        self.staged.publish(topic, content).unwrap_or(());
+    }
+
+    /// Returns a handle that can publish straight away. Messages published
+    /// before `build()` are staged, as by `stage()`.
+    pub fn add_publisher(&mut self) -> PrePublisher<Topic, Content> {
+        PrePublisher { staged: self.staged.clone() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn staged_messages_reach_later_subscribers() {
+        let mut builder = Publisher::new();
+        let early = builder.add_subscriber(&["config"]);
+        builder.stage("config", 1);
+        builder.stage("device", 2);
+        builder.stage("config", 3);
+        let late = builder.add_subscriber(&["config", "device"]);
+        let publisher = builder.build();
+
+        publisher.publish("config", 4);
+        assert_eq!(early.fetch(), vec![("config", 1), ("config", 3), ("config", 4)]);
+        assert_eq!(late.fetch(), vec![("config", 1), ("device", 2), ("config", 3), ("config", 4)]);
+    }
+
+    #[test]
+    fn pre_publishers_work_before_and_after_build() {
+        let mut builder = Publisher::new();
+        let device = builder.add_publisher();
+        device.publish("state", 1);
+        builder.stage("state", 2);
+        assert!(device.publisher().is_none());
+        let screen = builder.add_subscriber(&["state"]);
+        let publisher = builder.build();
+
+        device.publish("state", 3);
+        publisher.publish("state", 4);
+        assert_eq!(screen.fetch(), vec![("state", 1), ("state", 2), ("state", 3), ("state", 4)]);
+        assert!(device.publisher().is_some());
+    }
+}
@@ -0,0 +1,167 @@
+//! Routing fixed enum topics without hashing.
+//!
+//! A network whose topics are the variants of a fieldless enum, and whose
+//! subscribers are all known before it starts, doesn't need a routing table
+//! behind a lock. `StaticRouter` gives each variant a slot of its own, found
+//! by a `match` in `EnumTopic::index()` that compiles down to a jump table,
+//! so a publish is an array lookup followed by the sends. With the `macros`
+//! feature, `#[derive(EnumTopic)]` writes the `match`.
+//!
+//! The price is everything else a `Publisher` offers: subscribers can't be
+//! added once it is built, and there are no budgets, limits, taps or drop
+//! reports. Use it for the hot path, next to an ordinary network for the
+//! rest.
+
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender};
+
+use super::{Publish, PublishError, Subscriber};
+use super::drops::DropHook;
+use super::sync::{AtomicUsize, Ordering};
+
+/// A topic type with a fixed set of values, each numbered from zero.
+pub trait EnumTopic {
+    /// How many values there are.
+    const COUNT: usize;
+
+    /// This value's number, below `COUNT`.
+    fn index(&self) -> usize;
+}
+
+/// One subscriber's channel, along with its count of unread messages.
+type Inbox<Topic, Content> = (Sender<(Topic, Content)>, Arc<AtomicUsize>);
+
+/// Each topic's subscribers, by index.
+type Slots<Topic, Content> = Arc<[Box<[Inbox<Topic, Content>]>]>;
+
+/// Sets up a `StaticRouter`. Made by `StaticRouter::new()`.
+pub struct StaticBuilder<Topic, Content> {
+    slots: Vec<Vec<Inbox<Topic, Content>>>,
+}
+
+/// Publishes on enum topics to subscribers fixed at setup. Clone it to add
+/// publishers.
+pub struct StaticRouter<Topic, Content> {
+    slots: Slots<Topic, Content>,
+}
+
+impl<Topic, Content> Clone for StaticRouter<Topic, Content> {
+    fn clone(&self) -> Self {
+        StaticRouter { slots: self.slots.clone() }
+    }
+}
+
+impl<Topic: EnumTopic + Clone, Content: Clone> StaticRouter<Topic, Content> {
+    /// Starts setting up a router with no subscribers.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> StaticBuilder<Topic, Content> {
+        StaticBuilder { slots: (0..Topic::COUNT).map(|_| vec![]).collect() }
+    }
+
+    /// Sends `content` to every subscriber of `topic`. The last of them gets
+    /// it without a clone.
+    pub fn publish(&self, topic: Topic, content: Content) {
+        let inboxes = &self.slots[topic.index()];
+        let (last, rest) = match inboxes.split_last() {
+            Some(split) => split,
+            None => return,
+        };
+        for inbox in rest {
+            send(inbox, topic.clone(), content.clone());
+        }
+        send(last, topic, content);
+    }
+
+    /// Returns how many subscribers receive `topic`.
+    pub fn subscribers(&self, topic: &Topic) -> usize {
+        self.slots[topic.index()].len()
+    }
+}
+
+impl<Topic: EnumTopic + Clone, Content: Clone> Publish<Topic, Content> for StaticRouter<Topic, Content> {
+    /// Publishes as with `StaticRouter::publish()`, which never refuses.
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        StaticRouter::publish(self, topic, content);
+        Ok(())
+    }
+}
+
+impl<Topic: EnumTopic + Clone, Content: Clone> StaticBuilder<Topic, Content> {
+    /// Adds a subscriber to the given topics. Naming a topic twice doesn't
+    /// deliver it twice.
+    pub fn add_subscriber(&mut self, topics: &[Topic]) -> Subscriber<Topic, Content> {
+        let (tx, rx) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let mut seen = vec![false; Topic::COUNT];
+        for topic in topics {
+            let index = topic.index();
+            if seen[index] { continue; }
+            seen[index] = true;
+            self.slots[index].push((tx.clone(), pending.clone()));
+        }
+        // Nothing is ever dropped on the way, so there is nobody to tell.
+        Subscriber::forwarded(rx, pending, Arc::new(DropHook::new()))
+    }
+
+    /// Finishes setup.
+    pub fn build(self) -> StaticRouter<Topic, Content> {
+        let slots: Vec<Box<[_]>> = self.slots.into_iter().map(Vec::into_boxed_slice).collect();
+        StaticRouter { slots: slots.into() }
+    }
+}
+
+fn send<Topic, Content>(inbox: &Inbox<Topic, Content>, topic: Topic, content: Content) {
+    let (ref tx, ref pending) = *inbox;
+    pending.fetch_add(1, Ordering::AcqRel);
+    if tx.send((topic, content)).is_err() {
+        pending.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Input {
+        Key,
+        Mouse,
+        Pad,
+    }
+
+    impl EnumTopic for Input {
+        const COUNT: usize = 3;
+
+        fn index(&self) -> usize {
+            match *self { Input::Key => 0, Input::Mouse => 1, Input::Pad => 2 }
+        }
+    }
+
+    #[test]
+    fn enum_topics_route_by_index() {
+        let mut builder = StaticRouter::new();
+        let ui = builder.add_subscriber(&[Input::Key, Input::Mouse, Input::Key]);
+        let game = builder.add_subscriber(&[Input::Key, Input::Pad]);
+        let router = builder.build();
+
+        router.clone().publish(Input::Key, 'a');
+        router.publish(Input::Mouse, 'b');
+        router.publish(Input::Pad, 'c');
+
+        assert_eq!(router.subscribers(&Input::Key), 2);
+        assert_eq!(ui.fetch(), vec![(Input::Key, 'a'), (Input::Mouse, 'b')]);
+        assert_eq!(game.fetch(), vec![(Input::Key, 'a'), (Input::Pad, 'c')]);
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn derived_indices_follow_declaration_order() {
+        #[derive(Copy, Clone, Debug, PartialEq, ::alewife::EnumTopic)]
+        enum Phase { Decode, Validate, Apply }
+
+        assert_eq!(<Phase as EnumTopic>::COUNT, 3);
+        let indices: Vec<usize> = [Phase::Decode, Phase::Validate, Phase::Apply]
+            .iter().map(EnumTopic::index).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+    }
+}
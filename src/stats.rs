@@ -0,0 +1,135 @@
+//! Per-topic traffic statistics over a sliding time window.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn window_expires_old_traffic() {
+        let tracker = StatsTracker::new(Duration::from_millis(50), |s: &&str| s.len());
+
+        let start = Instant::now();
+        tracker.record(&"chatty", &"hello", start);
+        tracker.record(&"chatty", &"hi", start);
+        tracker.record(&"quiet", &"x", start);
+
+        tracker.record_clones(&"chatty", 3, start);
+        let stats = tracker.snapshot(start);
+        assert_eq!(stats[&"chatty"], TopicStats { messages: 2, bytes: 7, clones: 3 });
+        assert_eq!(stats[&"quiet"], TopicStats { messages: 1, bytes: 1, clones: 0 });
+
+        assert!(tracker.snapshot(start + Duration::from_millis(60)).is_empty());
+    }
+}
+
+/// Number of slices the window is divided into. Old traffic leaves the window
+/// one slice at a time instead of one message at a time, which bounds memory
+/// use no matter how busy a topic gets.
+const SLICES: u32 = 16;
+
+/// Traffic seen on one topic during the most recent stats window.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TopicStats {
+    /// Number of messages published.
+    pub messages: u64,
+    /// Sum of the sizer's estimates for those messages.
+    pub bytes: u64,
+    /// Number of times their content was cloned to deliver them. A topic with
+    /// many clones per message is a candidate for sharing its content behind
+    /// an `Arc`, or for `CloneStrategy::MoveToLast` or a single-consumer
+    /// declaration if it has few subscribers.
+    pub clones: u64,
+}
+
+struct Slice {
+    start: Instant,
+    stats: TopicStats,
+}
+
+pub(crate) struct StatsTracker<Topic, Content> {
+    window: Duration,
+    slice: Duration,
+    sizer: Box<dyn Fn(&Content) -> usize + Send + Sync>,
+    topics: Mutex<HashMap<Topic, VecDeque<Slice>>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content> StatsTracker<Topic, Content> {
+    pub(crate) fn new<F>(window: Duration, sizer: F) -> Self
+        where F: Fn(&Content) -> usize + Send + Sync + 'static
+    {
+        StatsTracker {
+            window,
+            slice: window / SLICES,
+            sizer: Box::new(sizer),
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub(crate) fn record(&self, topic: &Topic, content: &Content, now: Instant) {
+        let bytes = (self.sizer)(content) as u64;
+        self.update(topic, now, |stats| {
+            stats.messages += 1;
+            stats.bytes += bytes;
+        });
+    }
+
+    /// Counts copies of a message's content made while delivering it.
+    pub(crate) fn record_clones(&self, topic: &Topic, clones: u64, now: Instant) {
+        if clones == 0 { return; }
+        self.update(topic, now, |stats| stats.clones += clones);
+    }
+
+    fn update<F: FnOnce(&mut TopicStats)>(&self, topic: &Topic, now: Instant, change: F) {
+        let mut topics = self.topics.lock().unwrap_or_else(|e| e.into_inner());
+        if !topics.contains_key(topic) {
+            topics.insert(topic.clone(), VecDeque::new());
+        }
+        let slices = topics.get_mut(topic).unwrap();
+        self.expire(slices, now);
+
+        let current = match slices.back_mut() {
+            Some(s) if now.duration_since(s.start) < self.slice => s,
+            _ => {
+                slices.push_back(Slice { start: now, stats: TopicStats::default() });
+                slices.back_mut().unwrap()
+            },
+        };
+        change(&mut current.stats);
+    }
+
+    pub(crate) fn snapshot(&self, now: Instant) -> HashMap<Topic, TopicStats> {
+        let mut topics = self.topics.lock().unwrap_or_else(|e| e.into_inner());
+
+        topics.retain(|_, slices| {
+            self.expire(slices, now);
+            !slices.is_empty()
+        });
+
+        topics.iter().map(|(topic, slices)| {
+            let total = slices.iter().fold(TopicStats::default(), |acc, s| {
+                TopicStats {
+                    messages: acc.messages + s.stats.messages,
+                    bytes: acc.bytes + s.stats.bytes,
+                    clones: acc.clones + s.stats.clones,
+                }
+            });
+            (topic.clone(), total)
+        }).collect()
+    }
+
+    fn expire(&self, slices: &mut VecDeque<Slice>, now: Instant) {
+        while let Some(oldest) = slices.front() {
+            if now.duration_since(oldest.start) < self.window { break; }
+            slices.pop_front();
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Suggesting how each topic's messages should be delivered.
+//!
+//! Several settings trade generality for speed on a busy topic: putting the
+//! content behind an `Arc` so each delivery copies a pointer rather than the
+//! data, `CloneStrategy::MoveToLast`, single-consumer topics, and conflating
+//! updates so a slow reader only sees the latest. Which ones pay off depends
+//! on how many subscribers a topic has, how large and how frequent its
+//! messages are, and whether its readers keep up. With
+//! `Builder::track_topic_stats()` on, run the program under a realistic load
+//! and `Publisher::recommend_strategies()` will say, topic by topic, which
+//! of them looks worth trying and what it saw. The advice is a starting
+//! point for measuring, not a substitute for it.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use super::Publisher;
+
+/// Cloning content larger than this, on average, for several subscribers is
+/// worth avoiding.
+const LARGE_BYTES: u64 = 1024;
+/// A subscriber with this many messages queued isn't keeping up.
+const BACKLOG: usize = 256;
+
+/// A way of delivering a topic's messages. See the module documentation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeliveryStrategy {
+    /// Cloning the content for each subscriber, as now, is fine.
+    Clone,
+    /// `CloneStrategy::MoveToLast` would save a clone per publish.
+    MoveToLast,
+    /// The content is large and copied many times; sharing it behind an
+    /// `Arc` would make each copy a pointer.
+    Arc,
+    /// The topic has one subscriber, and declaring it single-consumer would
+    /// skip the routing table.
+    SingleConsumer,
+    /// Subscribers are falling behind; if only the latest message matters,
+    /// `SubscriptionOptions::debounce()` or `Subscriber::compact()` would
+    /// let them skip the rest.
+    Conflate,
+}
+
+impl fmt::Display for DeliveryStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DeliveryStrategy::Clone => "clone per subscriber",
+            DeliveryStrategy::MoveToLast => "move to the last subscriber",
+            DeliveryStrategy::Arc => "share behind an Arc",
+            DeliveryStrategy::SingleConsumer => "declare single-consumer",
+            DeliveryStrategy::Conflate => "conflate updates",
+        })
+    }
+}
+
+/// The suggested strategy for one topic, and what it was based on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recommendation {
+    /// What to try.
+    pub strategy: DeliveryStrategy,
+    /// How many subscribers the topic has now.
+    pub subscribers: usize,
+    /// How many times each message's content was cloned, on average.
+    pub clones_per_message: f64,
+    /// The sizer's average estimate for each message.
+    pub bytes_per_message: u64,
+    /// How many messages were published each second, averaged over the
+    /// stats window.
+    pub messages_per_second: f64,
+    /// The most messages waiting now for any of the topic's subscribers, on
+    /// any topic.
+    pub backlog: usize,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Suggests a delivery strategy for each topic seen during the window
+    /// set by `Builder::track_topic_stats()`, from its traffic there and its
+    /// subscribers now. Always empty if stats tracking was not enabled.
+    pub fn recommend_strategies(&self) -> HashMap<Topic, Recommendation> {
+        let bus = self.bus();
+        let Some(ref stats) = bus.stats else { return HashMap::new() };
+        let seconds = stats.window().as_secs_f64();
+        let observed = stats.snapshot(bus.clock.now());
+        let routing = bus.routing.read().unwrap_or_else(|e| e.into_inner());
+
+        observed.into_iter().filter(|(_, stats)| stats.messages > 0).map(|(topic, stats)| {
+            let single = bus.single_consumer.get(&topic).map(|slot| slot.get());
+            let (subscribers, backlog) = match single {
+                Some(ref route) => {
+                    let backlog = route.as_ref().and_then(|route| route.queued()).unwrap_or(0);
+                    (route.is_some() as usize, backlog)
+                },
+                None => routing.recipients(&topic).fold((0, 0), |(count, most), route| {
+                    (count + 1, most.max(route.queued().unwrap_or(0)))
+                }),
+            };
+            let moves = bus.topic_settings.get(&topic).is_some_and(|s| s.move_to_last);
+            let clones_per_message = stats.clones as f64 / stats.messages as f64;
+            let bytes_per_message = stats.bytes / stats.messages;
+
+            let strategy = if backlog >= BACKLOG {
+                DeliveryStrategy::Conflate
+            } else if single.is_some() {
+                DeliveryStrategy::Clone
+            } else if subscribers == 1 {
+                DeliveryStrategy::SingleConsumer
+            } else if clones_per_message >= 2.0 && bytes_per_message >= LARGE_BYTES {
+                DeliveryStrategy::Arc
+            } else if subscribers > 1 && !moves {
+                DeliveryStrategy::MoveToLast
+            } else {
+                DeliveryStrategy::Clone
+            };
+
+            (topic, Recommendation {
+                strategy,
+                subscribers,
+                clones_per_message,
+                bytes_per_message,
+                messages_per_second: stats.messages as f64 / seconds,
+                backlog,
+            })
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn topics_get_advice_that_fits_their_traffic() {
+        let mut builder = Publisher::<&str, Vec<u8>>::new();
+        builder.track_topic_stats(Duration::from_secs(60), |content| content.len());
+        let frames: Vec<_> = (0 .. 3).map(|_| builder.add_subscriber(&["frame"])).collect();
+        let _telemetry = builder.add_subscriber(&["telemetry"]);
+        let ticks: Vec<_> = (0 .. 2).map(|_| builder.add_subscriber(&["tick"])).collect();
+        let _slow = builder.add_subscriber(&["position"]);
+        let publisher = builder.build();
+        for _ in 0 .. 10 {
+            publisher.publish("frame", vec![0; 4096]);
+            publisher.publish("telemetry", vec![0; 16]);
+            publisher.publish("tick", vec![]);
+        }
+        for _ in 0 .. BACKLOG { publisher.publish("position", vec![0; 8]); }
+        for subscriber in frames.iter().chain(&ticks) { subscriber.fetch(); }
+
+        let advice = publisher.recommend_strategies();
+        let strategy = |topic| advice[topic].strategy;
+        assert_eq!(strategy("frame"), DeliveryStrategy::Arc);
+        assert_eq!(strategy("telemetry"), DeliveryStrategy::SingleConsumer);
+        assert_eq!(strategy("tick"), DeliveryStrategy::MoveToLast);
+        assert_eq!(strategy("position"), DeliveryStrategy::Conflate);
+        assert_eq!(advice["frame"].subscribers, 3);
+        assert_eq!(advice["frame"].bytes_per_message, 4096);
+        assert_eq!(advice["position"].backlog, BACKLOG);
+    }
+}
@@ -0,0 +1,233 @@
+//! Soak-testing a network under load.
+//!
+//! Before shipping a topology it helps to know how it behaves with many
+//! threads hammering it. A `StressTest` builds a network, lets extra setup
+//! such as limits or declared topics be applied to it, then runs a number of
+//! publisher and subscriber threads against it for a while. The
+//! `StressReport` it returns counts what was delivered, checks that each
+//! subscriber saw every publisher's messages in order, and gives latency
+//! percentiles from publish to read. Requires the `stress` feature.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Builder, Publisher, Unmatched};
+
+/// The content of every message a stress test publishes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Probe {
+    /// Which publisher thread sent it.
+    pub publisher: usize,
+    /// How many messages that thread had sent before it.
+    pub seq: u64,
+    /// When it was published.
+    pub sent: Instant,
+}
+
+type Setup = Box<dyn Fn(&mut Builder<usize, Probe>)>;
+
+/// A load test to run against a network whose topics are numbers.
+pub struct StressTest {
+    publishers: usize,
+    subscribers: usize,
+    topics: usize,
+    duration: Duration,
+    setup: Option<Setup>,
+}
+
+impl StressTest {
+    /// Four publishers and four subscribers on eight topics, for a second.
+    pub fn new() -> Self {
+        StressTest {
+            publishers: 4,
+            subscribers: 4,
+            topics: 8,
+            duration: Duration::from_secs(1),
+            setup: None,
+        }
+    }
+
+    /// Sets how many threads publish.
+    pub fn publishers(mut self, threads: usize) -> Self {
+        self.publishers = threads;
+        self
+    }
+
+    /// Sets how many threads read. Each subscribes to every topic.
+    pub fn subscribers(mut self, threads: usize) -> Self {
+        self.subscribers = threads;
+        self
+    }
+
+    /// Sets how many topics there are. Each publisher cycles through them.
+    pub fn topics(mut self, topics: usize) -> Self {
+        self.topics = topics.max(1);
+        self
+    }
+
+    /// Sets how long publishers keep publishing.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Applies `setup` to the network before any subscribers are added.
+    pub fn configure<F>(mut self, setup: F) -> Self
+        where F: Fn(&mut Builder<usize, Probe>) + 'static
+    {
+        self.setup = Some(Box::new(setup));
+        self
+    }
+
+    /// Runs the test. Once publishing stops, subscribers keep reading until
+    /// nothing has arrived for a tenth of a second.
+    pub fn run(&self) -> StressReport {
+        let mut builder = Publisher::new();
+        if let Some(ref setup) = self.setup { setup(&mut builder); }
+        let topics: Vec<usize> = (0..self.topics).collect();
+        let subscribers: Vec<_> = (0..self.subscribers)
+            .map(|_| builder.add_subscriber(&topics))
+            .collect();
+        let network = builder.build();
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = subscribers.into_iter().map(|subscriber| {
+            let stopped = stopped.clone();
+            let publishers = self.publishers;
+            thread::spawn(move || {
+                let mut seen = Reading { last: vec![None; publishers], ..Reading::default() };
+                let idle = Duration::from_millis(100);
+                loop {
+                    match subscriber.wait_for(|_, _| true, idle, Unmatched::Discard) {
+                        Some((_, probe)) => seen.record(probe),
+                        None if stopped.load(Ordering::Acquire) => break,
+                        None => (),
+                    }
+                }
+                seen
+            })
+        }).collect();
+
+        let start = Instant::now();
+        let writers: Vec<_> = (0..self.publishers).map(|publisher| {
+            let network = network.clone();
+            let (duration, topics) = (self.duration, self.topics);
+            thread::spawn(move || {
+                let mut seq = 0;
+                while start.elapsed() < duration {
+                    let probe = Probe { publisher, seq, sent: Instant::now() };
+                    network.publish(seq as usize % topics, probe);
+                    seq += 1;
+                }
+                seq
+            })
+        }).collect();
+
+        let published: u64 = writers.into_iter().map(|writer| writer.join().unwrap_or(0)).sum();
+        stopped.store(true, Ordering::Release);
+
+        let mut report = StressReport {
+            published,
+            expected: published * self.subscribers as u64,
+            delivered: 0,
+            out_of_order: 0,
+            latencies: vec![],
+        };
+        for reader in readers {
+            let seen = reader.join().unwrap_or_default();
+            report.delivered += seen.delivered;
+            report.out_of_order += seen.out_of_order;
+            report.latencies.extend(seen.latencies);
+        }
+        report.latencies.sort();
+        report
+    }
+}
+
+impl Default for StressTest {
+    fn default() -> Self {
+        StressTest::new()
+    }
+}
+
+/// What one subscriber thread saw.
+#[derive(Default)]
+struct Reading {
+    /// The last sequence number seen from each publisher.
+    last: Vec<Option<u64>>,
+    delivered: u64,
+    out_of_order: u64,
+    latencies: Vec<Duration>,
+}
+
+impl Reading {
+    fn record(&mut self, probe: Probe) {
+        self.delivered += 1;
+        self.latencies.push(probe.sent.elapsed());
+        let last = &mut self.last[probe.publisher];
+        if last.is_some_and(|last| probe.seq <= last) { self.out_of_order += 1; }
+        *last = Some(probe.seq);
+    }
+}
+
+/// The results of a `StressTest`.
+#[derive(Clone, Debug)]
+pub struct StressReport {
+    /// Messages published.
+    pub published: u64,
+    /// Deliveries there would have been with nothing dropped.
+    pub expected: u64,
+    /// Messages subscribers read.
+    pub delivered: u64,
+    /// Messages a subscriber read after a later one from the same publisher.
+    pub out_of_order: u64,
+    latencies: Vec<Duration>,
+}
+
+impl StressReport {
+    /// The latency that `percentile` percent of deliveries beat, from publish
+    /// to read. Zero if nothing was delivered.
+    pub fn latency(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() { return Duration::ZERO; }
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * (self.latencies.len() - 1) as f64).round();
+        self.latencies[rank as usize]
+    }
+
+    /// Panics unless every published message reached every subscriber.
+    pub fn assert_all_delivered(&self) {
+        assert_eq!(self.delivered, self.expected, "{} of {} deliveries were lost",
+                   self.expected - self.delivered.min(self.expected), self.expected);
+    }
+
+    /// Panics if any subscriber saw a publisher's messages out of order.
+    pub fn assert_ordered(&self) {
+        assert_eq!(self.out_of_order, 0, "{} messages arrived out of order", self.out_of_order);
+    }
+
+    /// Panics unless `percentile` percent of deliveries took at most `limit`.
+    pub fn assert_latency(&self, percentile: f64, limit: Duration) {
+        let latency = self.latency(percentile);
+        assert!(latency <= limit, "p{} latency was {:?}, over the limit of {:?}",
+                percentile, latency, limit);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn short_runs_deliver_everything_in_order() {
+        let report = StressTest::new()
+            .publishers(2)
+            .subscribers(3)
+            .duration(Duration::from_millis(50))
+            .run();
+        assert!(report.published > 0);
+        report.assert_all_delivered();
+        report.assert_ordered();
+        assert!(report.latency(50.0) <= report.latency(99.0));
+    }
+}
@@ -0,0 +1,67 @@
+//! Making misuse loud during development.
+//!
+//! Most ways of misusing a network fail quietly: a publish on a topic that
+//! was never declared, or on a network whose routes were poisoned, only
+//! shows up as an error from `try_publish()` that `publish()` ignores, and
+//! subscribing to such a topic makes a subscription that never hears
+//! anything. That suits production, where a mistake in one corner shouldn't
+//! take the program down, but hides the mistake while it is being written.
+//! `Builder::strictness(Strictness::Strict)` makes each of them panic in
+//! debug builds, so it is noticed at once; in release builds they still fail
+//! quietly, as in `Strictness::Lenient`.
+
+use std::hash::Hash;
+
+use super::Builder;
+
+/// How a network treats misuse. See the module documentation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// Misuse is reported as an error, or not at all.
+    #[default]
+    Lenient,
+    /// Misuse panics in debug builds, and is treated as `Lenient` in release
+    /// builds.
+    Strict,
+}
+
+impl Strictness {
+    /// Panics with `what` if misuse should panic.
+    pub(crate) fn misuse(self, what: &str) {
+        if self == Strictness::Strict && cfg!(debug_assertions) {
+            panic!("misused a network: {}", what);
+        }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Builder<Topic, Content> {
+    /// Sets how the network treats misuse. Networks are
+    /// `Strictness::Lenient` unless set otherwise.
+    pub fn strictness(&mut self, strictness: Strictness) {
+        self.bus.strictness = strictness;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+    use {Publisher, PublishError, TopicOptions};
+
+    #[test]
+    fn strict_networks_panic_on_misuse_in_debug_builds() {
+        let mut builder = Publisher::<&str, u32>::new();
+        builder.declare_topic("score", TopicOptions::new());
+        builder.seal_topics();
+        builder.strictness(Strictness::Strict);
+        let subscribed = panic::catch_unwind(AssertUnwindSafe(|| builder.add_subscriber(&["scroe"])));
+        assert_eq!(subscribed.is_err(), cfg!(debug_assertions));
+        let publisher = builder.build();
+
+        assert_eq!(publisher.try_publish("score", 1), Ok(()));
+        let published = panic::catch_unwind(AssertUnwindSafe(|| publisher.try_publish("scroe", 2)));
+        let lenient = if cfg!(debug_assertions) { None } else { Some(Err(PublishError::Undeclared)) };
+        assert_eq!(published.ok(), lenient);
+    }
+}
@@ -0,0 +1,158 @@
+//! Receiving without caring from what.
+//!
+//! `Subscribe` is the reading half of a `Subscriber`, as a trait, so code
+//! that only reads messages can take any endpoint that hands them out: a
+//! plain subscriber, a pipeline stage, a deduplicating `EffectivelyOnce`, or
+//! something wrapped around one of those with the adapters in `adapters`.
+
+use std::time::Duration;
+
+use super::{EffectivelyOnce, PipelineStage, Subscriber};
+use super::adapters::{Filtered, Mapped, Metered, Throttled};
+use super::envelope::Envelope;
+
+/// Something messages can be read from.
+pub trait Subscribe<Topic, Content> {
+    /// Takes the next message waiting, if there is one.
+    fn next(&self) -> Option<(Topic, Content)>;
+
+    /// Like `next()`, but waits up to `timeout` for a message.
+    fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Content)>;
+
+    /// Returns how many messages are waiting, or at most that many for an
+    /// endpoint that may pass some over.
+    fn pending(&self) -> usize;
+
+    /// Takes every message waiting.
+    fn fetch(&self) -> Vec<(Topic, Content)> {
+        let mut messages = vec![];
+        while let Some(message) = self.next() { messages.push(message); }
+        messages
+    }
+
+    /// Iterates over the messages waiting, stopping once there are none.
+    fn iter(&self) -> Messages<'_, Topic, Content>
+        where Self: Sized
+    {
+        Messages { subscriber: self }
+    }
+
+    /// Passes over messages for which `matching` is false.
+    fn filtered<F>(self, matching: F) -> Filtered<Self, F>
+        where Self: Sized, F: Fn(&Topic, &Content) -> bool
+    {
+        Filtered::new(self, matching)
+    }
+
+    /// Turns each message into another with `map`.
+    fn mapped<F, ToTopic, ToContent>(self, map: F) -> Mapped<Self, F, Topic, Content>
+        where Self: Sized, F: Fn(Topic, Content) -> (ToTopic, ToContent)
+    {
+        Mapped::new(self, map)
+    }
+
+    /// Hands over at most one message per `interval`.
+    fn throttled(self, interval: Duration) -> Throttled<Self>
+        where Self: Sized
+    {
+        Throttled::new(self, interval)
+    }
+
+    /// Counts the messages read.
+    fn metered(self) -> Metered<Self>
+        where Self: Sized
+    {
+        Metered::new(self)
+    }
+}
+
+/// The messages waiting on an endpoint. Made by `Subscribe::iter()`.
+pub struct Messages<'a, Topic, Content> {
+    subscriber: &'a dyn Subscribe<Topic, Content>,
+}
+
+impl<'a, Topic, Content> Iterator for Messages<'a, Topic, Content> {
+    type Item = (Topic, Content);
+
+    fn next(&mut self) -> Option<(Topic, Content)> {
+        self.subscriber.next()
+    }
+}
+
+impl<Topic, Content> Subscribe<Topic, Content> for Subscriber<Topic, Content> {
+    fn next(&self) -> Option<(Topic, Content)> {
+        Subscriber::next(self)
+    }
+
+    fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        Subscriber::next_timeout(self, timeout)
+    }
+
+    fn pending(&self) -> usize {
+        Subscriber::pending(self)
+    }
+
+    fn fetch(&self) -> Vec<(Topic, Content)> {
+        Subscriber::fetch(self)
+    }
+}
+
+impl<Topic, Content> Subscribe<Topic, Content> for PipelineStage<Topic, Content> {
+    fn next(&self) -> Option<(Topic, Content)> {
+        PipelineStage::next(self)
+    }
+
+    fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Content)> {
+        PipelineStage::next_timeout(self, timeout)
+    }
+
+    fn pending(&self) -> usize {
+        PipelineStage::pending(self)
+    }
+}
+
+impl<Topic, Body> Subscribe<Topic, Envelope<Topic, Body>> for EffectivelyOnce<Topic, Body> {
+    fn next(&self) -> Option<(Topic, Envelope<Topic, Body>)> {
+        EffectivelyOnce::next(self)
+    }
+
+    fn next_timeout(&self, timeout: Duration) -> Option<(Topic, Envelope<Topic, Body>)> {
+        EffectivelyOnce::next_timeout(self, timeout)
+    }
+
+    fn pending(&self) -> usize {
+        EffectivelyOnce::pending(self)
+    }
+
+    fn fetch(&self) -> Vec<(Topic, Envelope<Topic, Body>)> {
+        EffectivelyOnce::fetch(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Publisher;
+
+    fn total<S: Subscribe<&'static str, u32>>(source: &S) -> u32 {
+        source.iter().map(|(_, n)| n).sum()
+    }
+
+    #[test]
+    fn any_endpoint_can_be_read_generically() {
+        let mut builder = Publisher::new();
+        let plain = builder.add_subscriber(&["n"]);
+        let stages = builder.pipeline("n", 2);
+        let publisher = builder.build();
+        for n in 1 ..= 3 { publisher.publish("n", n); }
+
+        assert_eq!(total(&plain), 6);
+        assert_eq!(total(&stages[0]), 6);
+
+        let endpoint: &dyn Subscribe<&'static str, u32> = &plain;
+        assert_eq!(endpoint.next_timeout(Duration::from_millis(1)), None);
+        publisher.publish("n", 4);
+        assert_eq!(endpoint.pending(), 1);
+        assert_eq!(endpoint.fetch(), vec![("n", 4)]);
+    }
+}
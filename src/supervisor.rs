@@ -0,0 +1,257 @@
+//! Keeping the processes of a local application running.
+//!
+//! An application split across several processes needs one of them to notice
+//! when another has died or hung, and to do something about it. Each peer
+//! process gives one of its subscribers a `Subscriber::heartbeat()` and
+//! bridges the heartbeat topic to the supervising process, where a
+//! `Supervisor` watches it. Peers are reported with a `PeerEvent` when they
+//! are first heard from and whenever they fall silent for some number of
+//! intervals, whether their process crashed, got stuck, or lost its link.
+//!
+//! Each peer has a `RestartPolicy` and a restart hook, which the supervisor
+//! calls, on its own thread, when a peer goes down and the policy allows,
+//! typically to spawn the process again. A restarted peer gets a fresh
+//! allowance of intervals to come back up before it counts as down again.
+//! `examples/supervisor.rs` shows a complete setup over TCP.
+
+use std::hash::Hash;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Publisher, Subscriber};
+use super::heartbeat::Heartbeat;
+use super::worker::{self, Worker};
+
+/// A change in a peer's state, as published by a supervisor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PeerEvent {
+    /// The peer has sent a heartbeat, for the first time or after being
+    /// down.
+    Up {
+        /// Which peer.
+        peer: String,
+    },
+    /// The peer has been silent for the supervisor's whole allowance.
+    Down {
+        /// Which peer.
+        peer: String,
+        /// How long since its last heartbeat, or since it was started.
+        silent_for: Duration,
+    },
+    /// The peer's restart hook is being called.
+    Restarting {
+        /// Which peer.
+        peer: String,
+        /// How many times it has been restarted, counting this time.
+        attempt: u32,
+    },
+    /// The peer is down and its policy allows no more restarts.
+    GaveUp {
+        /// Which peer.
+        peer: String,
+        /// How many times it was restarted.
+        restarts: u32,
+    },
+}
+
+/// When a supervisor restarts a peer that has gone down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Never; the peer is only reported.
+    Never,
+    /// Every time.
+    Always,
+    /// Until it has been restarted this many times.
+    UpTo(u32),
+}
+
+impl RestartPolicy {
+    fn allows(self, restarts: u32) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::Always => true,
+            RestartPolicy::UpTo(limit) => restarts < limit,
+        }
+    }
+}
+
+type Restart = Box<dyn FnMut(u32) + Send>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Started or restarted, and not heard from since.
+    Starting,
+    Up,
+    Down,
+}
+
+/// What the supervisor knows about one peer.
+struct Peer {
+    name: String,
+    policy: RestartPolicy,
+    restart: Restart,
+    state: State,
+    /// When the peer last sent a heartbeat, or was started.
+    since: Instant,
+    restarts: u32,
+}
+
+/// The peers a supervisor is to watch, and how. Start it with `spawn()`.
+pub struct Supervision {
+    interval: Duration,
+    missed: u32,
+    peers: Vec<Peer>,
+}
+
+impl Supervision {
+    /// Watches heartbeats sent every `interval`, counting a peer as down
+    /// once it has missed `missed` of them in a row. Panics if `interval` is
+    /// zero.
+    pub fn new(interval: Duration, missed: u32) -> Self {
+        Supervision { interval: worker::nonzero(interval), missed: missed.max(1), peers: vec![] }
+    }
+
+    /// Watches the peer whose heartbeats are sent under `name`, restarting
+    /// it as `policy` allows by calling `restart` with the attempt number.
+    /// Heartbeats from peers that weren't added are ignored.
+    pub fn peer<F>(mut self, name: &str, policy: RestartPolicy, restart: F) -> Self
+        where F: FnMut(u32) + Send + 'static
+    {
+        self.peers.push(Peer {
+            name: name.to_owned(),
+            policy,
+            restart: Box::new(restart),
+            state: State::Starting,
+            since: Instant::now(),
+            restarts: 0,
+        });
+        self
+    }
+
+    /// Starts watching the heartbeats `beats` receives, and publishes a
+    /// `PeerEvent` to `report` under `topic` whenever a peer changes state.
+    /// Every peer is taken to have just been started, and is counted as down
+    /// if it isn't heard from in time.
+    pub fn spawn<Topic, OutTopic>(
+        self,
+        beats: Subscriber<Topic, Heartbeat>,
+        report: Publisher<OutTopic, PeerEvent>,
+        topic: OutTopic,
+    ) -> Supervisor
+        where Topic: Send + 'static,
+              OutTopic: Hash + Eq + Clone + Send + Sync + 'static,
+    {
+        let silence = self.interval * self.missed;
+        let check_every = worker::check_every(self.interval);
+        let mut peers = self.peers;
+
+        let worker = Worker::spawn(move |stopped| {
+            let started = Instant::now();
+            for peer in &mut peers { peer.since = started; }
+
+            while !stopped.load(Ordering::Acquire) {
+                let now = Instant::now();
+                for (_, beat) in beats.fetch() {
+                    let Some(peer) = peers.iter_mut().find(|peer| peer.name == beat.component) else {
+                        continue;
+                    };
+                    peer.since = now;
+                    if peer.state != State::Up {
+                        peer.state = State::Up;
+                        report.publish(topic.clone(), PeerEvent::Up { peer: beat.component });
+                    }
+                }
+
+                for peer in &mut peers {
+                    let silent_for = now.duration_since(peer.since);
+                    if peer.state == State::Down || silent_for < silence { continue; }
+                    peer.state = State::Down;
+                    report.publish(topic.clone(), PeerEvent::Down { peer: peer.name.clone(), silent_for });
+
+                    if peer.policy.allows(peer.restarts) {
+                        peer.restarts += 1;
+                        report.publish(topic.clone(), PeerEvent::Restarting {
+                            peer: peer.name.clone(),
+                            attempt: peer.restarts,
+                        });
+                        (peer.restart)(peer.restarts);
+                        peer.state = State::Starting;
+                        peer.since = Instant::now();
+                    } else if peer.policy != RestartPolicy::Never {
+                        report.publish(topic.clone(), PeerEvent::GaveUp {
+                            peer: peer.name.clone(),
+                            restarts: peer.restarts,
+                        });
+                    }
+                }
+
+                thread::sleep(check_every);
+            }
+        });
+
+        Supervisor { worker }
+    }
+}
+
+/// A running supervisor. Dropping it stops the thread.
+pub struct Supervisor {
+    worker: Worker,
+}
+
+impl Supervisor {
+    /// Stops the supervisor and waits for its thread to finish. Peers are
+    /// left as they are.
+    pub fn stop(self) {
+        self.worker.stop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn silent_peers_are_restarted_until_the_policy_runs_out() {
+        let interval = Duration::from_millis(10);
+        let mut builder = Publisher::new();
+        let beats = builder.add_subscriber(&["beat"]);
+        let heartbeats = builder.build();
+
+        let mut builder = Publisher::new();
+        let events = builder.add_subscriber(&["peers"]);
+        let (restarted, restarts) = mpsc::channel();
+        let _supervisor = Supervision::new(interval, 5)
+            .peer("worker", RestartPolicy::UpTo(1), move |attempt| restarted.send(attempt).unwrap())
+            .spawn(beats, builder.build(), "peers");
+
+        let mut builder = Publisher::<&str, u32>::new();
+        let mut worker = builder.add_subscriber(&["work"]);
+        worker.heartbeat("worker", interval, heartbeats, "beat");
+        let until = Instant::now() + Duration::from_millis(30);
+        while Instant::now() < until {
+            worker.fetch();
+            thread::sleep(Duration::from_millis(2));
+        }
+
+        let mut reported = vec![];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !matches!(reported.last(), Some(PeerEvent::GaveUp { .. })) {
+            assert!(Instant::now() < deadline, "the supervisor never gave up");
+            reported.extend(events.fetch().into_iter().map(|(_, event)| event));
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let kinds: Vec<&str> = reported.iter().map(|event| match *event {
+            PeerEvent::Up { .. } => "up",
+            PeerEvent::Down { .. } => "down",
+            PeerEvent::Restarting { attempt: 1, .. } => "restarting",
+            PeerEvent::GaveUp { restarts: 1, .. } => "gave up",
+            ref other => panic!("unexpected report {:?}", other),
+        }).collect();
+        assert_eq!(kinds, vec!["up", "down", "restarting", "down", "gave up"]);
+        assert_eq!(restarts.try_iter().collect::<Vec<_>>(), vec![1]);
+    }
+}
@@ -0,0 +1,122 @@
+//! Locks and atomics used by the routing core.
+//!
+//! Building with `RUSTFLAGS="--cfg loom"` swaps these for loom's versions, so
+//! the tests below can check every interleaving of the routing table's
+//! readers and writers, and of the counters subscribers keep. Each checks
+//! that once a subscription is gone, nothing published afterwards reaches
+//! it, and that the pending counts and memory budget come back to zero.
+//!
+//! Run them with `RUSTFLAGS="--cfg loom" cargo test --release --lib sync::`;
+//! the rest of the test suite only works with the real primitives. `Arc`
+//! always comes from std, since loom's can't hold trait objects, and so do
+//! channels; every send is counted in a subscriber's pending count, which
+//! loom does see.
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::{Condvar, Mutex, RwLock};
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+#[cfg(loom)]
+pub(crate) use loom::sync::{Condvar, Mutex, RwLock};
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+/// Whether a thread panicked while holding `lock` for writing. Loom's locks
+/// are never poisoned.
+#[cfg(not(loom))]
+pub(crate) fn is_poisoned<T>(lock: &RwLock<T>) -> bool {
+    lock.is_poisoned()
+}
+
+#[cfg(loom)]
+pub(crate) fn is_poisoned<T>(_lock: &RwLock<T>) -> bool {
+    false
+}
+
+#[cfg(all(test, loom))]
+mod test {
+    use loom::thread;
+
+    use super::super::Publisher;
+
+    #[test]
+    fn subscribe_while_publishing() {
+        loom::model(|| {
+            let mut builder = Publisher::new();
+            builder.memory_budget(1 << 10);
+            let fixed = builder.add_subscriber(&["x"]);
+            let publisher = builder.build();
+
+            let other = publisher.clone();
+            let publishing = thread::spawn(move || other.publish("x", 1));
+
+            let (scoped, guard) = publisher.subscribe_scoped(&["x"]);
+            drop(guard);
+            // Published after the guard is gone, so it can't reach `scoped`.
+            publisher.publish("x", 2);
+            publishing.join().unwrap();
+
+            let mut seen = fixed.fetch();
+            seen.sort();
+            assert_eq!(seen, vec![("x", 1), ("x", 2)]);
+            let late = scoped.fetch();
+            assert!(late.is_empty() || late == vec![("x", 1)], "{:?}", late);
+            assert_eq!((fixed.pending(), scoped.pending()), (0, 0));
+            assert_eq!(publisher.memory_used(), 0);
+        });
+    }
+
+    #[test]
+    fn unread_messages_are_released_on_disconnect() {
+        loom::model(|| {
+            let mut builder = Publisher::new();
+            builder.memory_budget(1 << 10);
+            builder.declare_single_consumer("x");
+            let publisher = builder.build();
+
+            let (scoped, guard) = publisher.try_subscribe_scoped(&["x"]).unwrap();
+            let other = publisher.clone();
+            let publishing = thread::spawn(move || other.publish("x", 1));
+
+            drop(guard);
+            publisher.publish("x", 2);
+            publishing.join().unwrap();
+
+            assert!(scoped.pending() <= 1);
+            assert_eq!(publisher.memory_used(), 4 * scoped.pending());
+            drop(scoped);
+            assert_eq!(publisher.memory_used(), 0);
+        });
+    }
+
+    #[test]
+    fn child_detaches_while_parent_publishes() {
+        loom::model(|| {
+            let mut builder = Publisher::new();
+            let upstairs = builder.add_subscriber(&["up"]);
+            let parent = builder.build();
+
+            let mut builder = Publisher::new();
+            let downstairs = builder.add_subscriber(&["down"]);
+            builder.attach_parent(&parent, &[("down", "up")]);
+            let child = builder.build();
+
+            let publishing = {
+                let parent = parent.clone();
+                thread::spawn(move || parent.publish("up", 1))
+            };
+
+            drop(child);
+            parent.publish("up", 2);
+            publishing.join().unwrap();
+
+            let mut seen = upstairs.fetch();
+            seen.sort();
+            assert_eq!(seen, vec![("up", 1), ("up", 2)]);
+            let late = downstairs.fetch();
+            assert!(late.is_empty() || late == vec![("down", 1)], "{:?}", late);
+            assert_eq!(downstairs.pending(), 0);
+        });
+    }
+}
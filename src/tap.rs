@@ -0,0 +1,133 @@
+//! Watching a topic's traffic without getting in its way.
+
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::mpsc;
+
+use super::{Outbox, Publisher, Sampling, Scoped, SubscribeError, Subscriber, SubscriptionGuard, SubscriptionOptions};
+use super::options::Admission;
+use super::sync::{AtomicUsize, Ordering};
+
+type Redact<Topic, Content> = Arc<dyn Fn(&Topic, Content) -> Option<Content> + Send + Sync>;
+
+/// How a tap made by `Publisher::tap()` treats what it mirrors.
+pub struct TapOptions<Topic, Content> {
+    sampling: Option<Sampling>,
+    max_pending: usize,
+    redact: Option<Redact<Topic, Content>>,
+}
+
+impl<Topic, Content> TapOptions<Topic, Content> {
+    /// A tap that sees everything, unchanged, up to 1024 unread messages.
+    pub fn new() -> Self {
+        TapOptions { sampling: None, max_pending: 1024, redact: None }
+    }
+
+    /// Mirrors only a sample of the traffic.
+    pub fn sample(mut self, sampling: Sampling) -> Self {
+        self.sampling = Some(sampling);
+        self
+    }
+
+    /// Skips messages while `messages` are already waiting to be read, so a
+    /// tap nobody is reading costs next to nothing. Zero is treated as one.
+    pub fn max_pending(mut self, messages: usize) -> Self {
+        self.max_pending = messages.max(1);
+        self
+    }
+
+    /// Passes each mirrored message's content through `redact` first, which
+    /// can truncate it, blank out anything sensitive, or return `None` to
+    /// skip the message altogether. It runs on the publishing thread.
+    pub fn redact<F>(mut self, redact: F) -> Self
+        where F: Fn(&Topic, Content) -> Option<Content> + Send + Sync + 'static
+    {
+        self.redact = Some(Arc::new(redact));
+        self
+    }
+}
+
+impl<Topic, Content> Default for TapOptions<Topic, Content> {
+    fn default() -> Self {
+        TapOptions::new()
+    }
+}
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + 'static,
+          Content: Clone + Send + 'static,
+{
+    /// Mirrors `topic` to a new subscriber meant for debugging, until the
+    /// guard is dropped. Unlike a plain subscriber, a tap never holds
+    /// anything up or counts towards the memory budget: it drops what it
+    /// can't keep, as set by `options`, and drops aren't reported.
+    ///
+    /// Single-consumer topics can't be tapped, and are refused with
+    /// `SubscribeError::SingleConsumer`.
+    pub fn tap(&self, topic: Topic, options: TapOptions<Topic, Content>)
+        -> Result<Scoped<Topic, Content>, SubscribeError>
+    {
+        let bus = &self.handle.bus;
+        if bus.single_consumer.contains_key(&topic) { return Err(SubscribeError::SingleConsumer); }
+
+        let (tx, rx) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let counted = pending.clone();
+        let TapOptions { sampling, max_pending, redact } = options;
+
+        let admission = sampling
+            .and_then(|sampling| Admission::new(&SubscriptionOptions::new().sample(sampling), &bus.clock));
+
+        let outbox = Outbox::Forward(Arc::new(move |topic: Topic, content: Content| {
+            if admission.as_ref().is_some_and(|a| a.admit().is_err()) { return; }
+            if counted.load(Ordering::Acquire) >= max_pending { return; }
+            let content = match redact {
+                Some(ref redact) => match redact(&topic, content) {
+                    Some(content) => content,
+                    None => return,
+                },
+                None => content,
+            };
+            counted.fetch_add(1, Ordering::AcqRel);
+            if tx.send((topic, content)).is_err() {
+                counted.fetch_sub(1, Ordering::AcqRel);
+            }
+        }));
+        let id = bus.subscribe(&[topic], outbox, None);
+
+        let subscriber = Subscriber::forwarded(rx, pending, bus.drops.clone());
+        Ok((subscriber, SubscriptionGuard { bus: bus.clone(), id }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn taps_sample_redact_and_cap() {
+        let mut builder = Publisher::new();
+        let worker = builder.add_subscriber(&["orders"]);
+        builder.declare_single_consumer("frames");
+        let publisher = builder.build();
+        assert_eq!(publisher.tap("frames", TapOptions::new()).err(), Some(SubscribeError::SingleConsumer));
+
+        let options = TapOptions::new()
+            .sample(Sampling::Every(2))
+            .max_pending(2)
+            .redact(|_: &&str, card: String| Some(format!("{}****", &card[.. 4])));
+        let (tap, guard) = publisher.tap("orders", options).unwrap();
+
+        for card in &["1111-2222", "3333-4444", "5555-6666", "7777-8888", "9999-0000", "1234-5678"] {
+            publisher.publish("orders", card.to_string());
+        }
+
+        assert_eq!(tap.fetch(), vec![("orders", "1111****".to_owned()),
+                                     ("orders", "5555****".to_owned())]);
+        assert_eq!(worker.pending(), 6);
+
+        drop(guard);
+        publisher.publish("orders", "2468-1357".to_owned());
+        assert!(tap.fetch().is_empty());
+    }
+}
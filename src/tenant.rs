@@ -0,0 +1,164 @@
+//! Hosting isolated sessions on one network.
+//!
+//! A server running many independent sessions, such as one per player or per
+//! match, can give each its own view of a shared network with
+//! `Publisher::tenant()`. Everything published through the view goes out
+//! under the tenant's prefix, and subscribers made through it hear only that
+//! tenant's traffic, with the prefix taken off again. Code written against a
+//! whole network can run inside a tenant unchanged. Subscribers made on the
+//! network itself still see every tenant's topics, prefix and all, which is
+//! handy for logging and moderation.
+
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::mpsc;
+
+use super::{Outbox, Publish, PublishError, Publisher, Subscriber, SubscriptionGuard};
+use super::sync::{AtomicUsize, Ordering};
+
+/// A topic type that can be scoped to a tenant.
+pub trait TenantTopic: Sized {
+    /// The topic as it appears on the shared network, inside `tenant`.
+    fn in_tenant(&self, tenant: &str) -> Self;
+
+    /// The topic as seen from inside `tenant`, or `None` if it belongs to a
+    /// different one.
+    fn strip_tenant(&self, tenant: &str) -> Option<Self>;
+}
+
+impl TenantTopic for String {
+    fn in_tenant(&self, tenant: &str) -> Self {
+        format!("{}/{}", tenant, self)
+    }
+
+    fn strip_tenant(&self, tenant: &str) -> Option<Self> {
+        self.strip_prefix(tenant)?.strip_prefix('/').map(str::to_owned)
+    }
+}
+
+impl TenantTopic for Arc<str> {
+    fn in_tenant(&self, tenant: &str) -> Self {
+        format!("{}/{}", tenant, self).into()
+    }
+
+    fn strip_tenant(&self, tenant: &str) -> Option<Self> {
+        self.strip_prefix(tenant)?.strip_prefix('/').map(Arc::from)
+    }
+}
+
+/// One tenant's view of a network, made by `Publisher::tenant()`.
+pub struct Tenant<Topic: Hash + Eq + Clone, Content: Clone> {
+    publisher: Publisher<Topic, Content>,
+    name: Arc<str>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Clone for Tenant<Topic, Content> {
+    fn clone(&self) -> Self {
+        Tenant { publisher: self.publisher.clone(), name: self.name.clone() }
+    }
+}
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + TenantTopic + Send + 'static,
+          Content: Clone + Send + 'static,
+{
+    /// Returns a view of this network in which every topic is prefixed with
+    /// `name` and a slash. The view publishes under `name` too, as with
+    /// `Publisher::named()`, so `Builder::restrict_topic()` can tell tenants
+    /// apart.
+    pub fn tenant(&self, name: &str) -> Tenant<Topic, Content> {
+        let publisher = self.named(name);
+        let name = publisher.name().unwrap_or(name).into();
+        Tenant { publisher, name }
+    }
+}
+
+impl<Topic, Content> Tenant<Topic, Content>
+    where Topic: Hash + Eq + Clone + TenantTopic + Send + 'static,
+          Content: Clone + Send + 'static,
+{
+    /// The tenant's prefix, including those of any tenants it is nested in.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns a view of a tenant nested inside this one.
+    pub fn tenant(&self, name: &str) -> Tenant<Topic, Content> {
+        self.publisher.tenant(name)
+    }
+
+    /// Publishes `content` on the tenant's `topic`.
+    pub fn publish(&self, topic: Topic, content: Content) {
+        self.try_publish(topic, content).unwrap_or(());
+    }
+
+    /// Like `publish()`, but reports an error if the message was rejected.
+    pub fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        self.publisher.try_publish(topic.in_tenant(&self.name), content)
+    }
+
+    /// Subscribes to the tenant's `topics` until the guard is dropped. The
+    /// subscriber receives topics without the tenant's prefix.
+    pub fn subscribe(&self, topics: &[Topic])
+        -> (Subscriber<Topic, Content>, SubscriptionGuard<Topic, Content>)
+    {
+        let bus = &self.publisher.handle.bus;
+        let (tx, rx) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let counted = pending.clone();
+        let name = self.name.clone();
+
+        let outbox = Outbox::Forward(Arc::new(move |topic: Topic, content: Content| {
+            let topic = match topic.strip_tenant(&name) {
+                Some(topic) => topic,
+                None => return,
+            };
+            counted.fetch_add(1, Ordering::AcqRel);
+            if tx.send((topic, content)).is_err() {
+                counted.fetch_sub(1, Ordering::AcqRel);
+            }
+        }));
+        let topics: Vec<Topic> = topics.iter().map(|topic| topic.in_tenant(&self.name)).collect();
+        let id = bus.subscribe(&topics, outbox, None);
+
+        let subscriber = Subscriber::forwarded(rx, pending, bus.drops.clone());
+        (subscriber, SubscriptionGuard { bus: bus.clone(), id })
+    }
+}
+
+impl<Topic, Content> Publish<Topic, Content> for Tenant<Topic, Content>
+    where Topic: Hash + Eq + Clone + TenantTopic + Send + 'static,
+          Content: Clone + Send + 'static,
+{
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        Tenant::try_publish(self, topic, content)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tenants_only_hear_themselves() {
+        let mut builder = Publisher::new();
+        let everything = builder.add_subscriber(&["alice/chat".to_owned(), "bob/chat".to_owned()]);
+        let publisher = builder.build();
+
+        let alice = publisher.tenant("alice");
+        let bob = publisher.tenant("bob");
+        let (alice_chat, _alice_guard) = alice.subscribe(&["chat".to_owned()]);
+        let (bob_chat, _bob_guard) = bob.subscribe(&["chat".to_owned()]);
+
+        alice.publish("chat".to_owned(), "hi");
+        bob.publish("chat".to_owned(), "hello");
+        publisher.publish("chat".to_owned(), "nobody's");
+
+        assert_eq!(alice_chat.fetch(), vec![("chat".to_owned(), "hi")]);
+        assert_eq!(bob_chat.fetch(), vec![("chat".to_owned(), "hello")]);
+        assert_eq!(everything.fetch(), vec![
+            ("alice/chat".to_owned(), "hi"), ("bob/chat".to_owned(), "hello"),
+        ]);
+        assert_eq!(alice.tenant("lobby").name(), "alice/lobby");
+    }
+}
@@ -0,0 +1,424 @@
+//! Helpers for testing code that uses a network, and the network itself.
+//!
+//! `loopback()` checks that messages make it through a network and back,
+//! and how long they take about it, which makes a quick smoke test of a
+//! configuration, or of the machine it runs on, at startup.
+//!
+//! `Expectations` says what a test should see published, and waits only as
+//! long as it takes to find out, so tests needn't sleep and hope.
+//!
+//! Components written against the `Publish` trait rather than `Publisher`
+//! can be handed a `MockPublisher` in unit tests, which records what they
+//! publish without any network behind it.
+
+use std::error::Error;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::mem;
+use std::slice;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{Publish, PublishError, Publisher, Subscriber, SubscriptionGuard};
+
+/// How often the echo thread checks whether it was stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// What a `Loopback` has measured so far.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoopbackStats {
+    /// Messages sent.
+    pub sent: u64,
+    /// Messages whose echo didn't come back in time.
+    pub dropped: u64,
+    /// The quickest round trip.
+    pub fastest: Option<Duration>,
+    /// The slowest round trip.
+    pub slowest: Option<Duration>,
+    /// Every round trip added up.
+    pub total: Duration,
+}
+
+impl LoopbackStats {
+    /// The average round trip, if any message came back.
+    pub fn mean(&self) -> Option<Duration> {
+        let returned = (self.sent - self.dropped) as u32;
+        Some(self.total / returned).filter(|_| returned > 0)
+    }
+}
+
+/// An echo on a network: everything published on the request topic is
+/// published again on the reply topic. Made by `loopback()`. Dropping it
+/// stops the echo.
+pub struct Loopback<Topic: Hash + Eq + Clone, Content: Clone> {
+    publisher: Publisher<Topic, Content>,
+    request: Topic,
+    replies: Subscriber<Topic, Content>,
+    _guard: SubscriptionGuard<Topic, Content>,
+    stats: LoopbackStats,
+    stopped: Arc<AtomicBool>,
+    echo: Option<JoinHandle<()>>,
+}
+
+/// Starts echoing messages on `request` back on `reply`, from a thread of
+/// its own, and returns a `Loopback` to time them with. The two topics
+/// should be ones nothing else uses.
+pub fn loopback<Topic, Content>(publisher: &Publisher<Topic, Content>, request: Topic, reply: Topic)
+    -> Loopback<Topic, Content>
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+{
+    let (requests, request_guard) = publisher.subscribe_scoped(slice::from_ref(&request));
+    let (replies, guard) = publisher.subscribe_scoped(slice::from_ref(&reply));
+    let stopped = Arc::new(AtomicBool::new(false));
+    let (flag, echoer) = (stopped.clone(), publisher.clone());
+
+    let echo = thread::spawn(move || {
+        let _guard = request_guard;
+        while !flag.load(Ordering::Acquire) {
+            if let Some((_, content)) = requests.next_timeout(POLL_INTERVAL) {
+                echoer.publish(reply.clone(), content);
+            }
+        }
+    });
+
+    Loopback {
+        publisher: publisher.clone(),
+        request,
+        replies,
+        _guard: guard,
+        stats: LoopbackStats::default(),
+        stopped,
+        echo: Some(echo),
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone + PartialEq> Loopback<Topic, Content> {
+    /// Sends `content` round and waits up to `timeout` for it to come back,
+    /// returning how long that took. Late echoes of earlier messages are
+    /// ignored. A message that doesn't come back in time counts as dropped.
+    pub fn round_trip(&mut self, content: Content, timeout: Duration) -> Option<Duration> {
+        let started = Instant::now();
+        let deadline = started + timeout;
+        self.stats.sent += 1;
+        self.publisher.publish(self.request.clone(), content.clone());
+
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.replies.next_timeout(left) {
+                Some((_, echoed)) if echoed == content => break,
+                Some(_) => continue,
+                None => {
+                    self.stats.dropped += 1;
+                    return None;
+                },
+            }
+        }
+
+        let took = started.elapsed();
+        self.stats.fastest = Some(self.stats.fastest.map_or(took, |fastest| fastest.min(took)));
+        self.stats.slowest = Some(self.stats.slowest.map_or(took, |slowest| slowest.max(took)));
+        self.stats.total += took;
+        Some(took)
+    }
+
+    /// Sends `messages` round one by one, each given up to `timeout`, and
+    /// returns the measurements so far.
+    pub fn probe(&mut self, messages: u64, content: Content, timeout: Duration) -> &LoopbackStats {
+        for _ in 0 .. messages { self.round_trip(content.clone(), timeout); }
+        &self.stats
+    }
+
+    /// What has been measured so far.
+    pub fn stats(&self) -> &LoopbackStats {
+        &self.stats
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Drop for Loopback<Topic, Content> {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(echo) = self.echo.take() { echo.join().unwrap_or(()); }
+    }
+}
+
+/// How many matching messages an expectation wants.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Count {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+type Matcher<Content> = Box<dyn Fn(&Content) -> bool>;
+
+struct Expectation<Topic: Hash + Eq + Clone, Content: Clone> {
+    topic: Topic,
+    count: Count,
+    matching: Option<Matcher<Content>>,
+    subscriber: Subscriber<Topic, Content>,
+    _guard: SubscriptionGuard<Topic, Content>,
+    seen: Vec<Content>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Expectation<Topic, Content> {
+    fn matches(&self) -> usize {
+        self.seen.iter()
+            .filter(|content| self.matching.as_ref().is_none_or(|matching| matching(content)))
+            .count()
+    }
+
+    /// Whether waiting longer could change the outcome.
+    fn settled(&self) -> bool {
+        match self.count {
+            Count::Exactly(n) => self.matches() > n,
+            Count::AtLeast(n) => self.matches() >= n,
+        }
+    }
+
+    fn is_met(&self) -> bool {
+        match self.count {
+            Count::Exactly(n) => self.matches() == n,
+            Count::AtLeast(n) => self.matches() >= n,
+        }
+    }
+}
+
+/// What a test expects to be published on a network, from when each
+/// expectation is added until `verify()` gives up waiting.
+///
+/// ```ignore
+/// let expectations = Expectations::new(&publisher)
+///     .exactly(1, "x", |order| order.quantity > 0)
+///     .none("y");
+/// submit_order(&publisher);
+/// expectations.verify(Duration::from_millis(100));
+/// ```
+pub struct Expectations<Topic: Hash + Eq + Clone, Content: Clone> {
+    publisher: Publisher<Topic, Content>,
+    expectations: Vec<Expectation<Topic, Content>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Expectations<Topic, Content> {
+    /// No expectations yet, of the network `publisher` belongs to.
+    pub fn new(publisher: &Publisher<Topic, Content>) -> Self {
+        Expectations { publisher: publisher.clone(), expectations: vec![] }
+    }
+
+    fn expect(mut self, count: Count, topic: Topic, matching: Option<Matcher<Content>>) -> Self {
+        let (subscriber, guard) = self.publisher.subscribe_scoped(slice::from_ref(&topic));
+        self.expectations.push(Expectation { topic, count, matching, subscriber, _guard: guard, seen: vec![] });
+        self
+    }
+
+    /// Expects exactly `n` messages on `topic` for which `matching` is true.
+    /// Others on the topic are allowed.
+    pub fn exactly<F>(self, n: usize, topic: Topic, matching: F) -> Self
+        where F: Fn(&Content) -> bool + 'static
+    {
+        self.expect(Count::Exactly(n), topic, Some(Box::new(matching)))
+    }
+
+    /// Expects `n` or more messages on `topic` for which `matching` is true.
+    pub fn at_least<F>(self, n: usize, topic: Topic, matching: F) -> Self
+        where F: Fn(&Content) -> bool + 'static
+    {
+        self.expect(Count::AtLeast(n), topic, Some(Box::new(matching)))
+    }
+
+    /// Expects nothing at all on `topic`.
+    pub fn none(self, topic: Topic) -> Self {
+        self.expect(Count::Exactly(0), topic, None)
+    }
+
+    /// Waits up to `within` for every expectation to be met, and reports
+    /// those that weren't. Returns as soon as the outcome is known: at once
+    /// if an expectation has already seen too much, or once every
+    /// expectation is of the `at_least()` kind and met. Otherwise it has to
+    /// wait the whole time to be sure nothing more arrives.
+    pub fn check(mut self, within: Duration) -> Result<(), Unmet>
+        where Topic: Debug, Content: Debug
+    {
+        let deadline = Instant::now() + within;
+        loop {
+            for expectation in &mut self.expectations {
+                expectation.seen.extend(expectation.subscriber.fetch().into_iter().map(|(_, content)| content));
+            }
+            let overdone = self.expectations.iter()
+                .any(|e| e.settled() && matches!(e.count, Count::Exactly(_)));
+            let done = self.expectations.iter().all(|e| e.settled());
+            if overdone || done || Instant::now() >= deadline { break; }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let failures: Vec<String> = self.expectations.iter().filter(|e| !e.is_met()).map(|e| {
+            let wanted = match e.count {
+                Count::Exactly(0) if e.matching.is_none() => "no messages".to_owned(),
+                Count::Exactly(n) => format!("exactly {} matching", n),
+                Count::AtLeast(n) => format!("at least {} matching", n),
+            };
+            format!("expected {} on {:?} within {:?}, saw {} matching of {:?}",
+                    wanted, e.topic, within, e.matches(), e.seen)
+        }).collect();
+        if failures.is_empty() { Ok(()) } else { Err(Unmet { failures }) }
+    }
+
+    /// Like `check()`, but panics with a description of every expectation
+    /// that wasn't met.
+    pub fn verify(self, within: Duration)
+        where Topic: Debug, Content: Debug
+    {
+        if let Err(unmet) = self.check(within) { panic!("{}", unmet); }
+    }
+}
+
+/// Expectations that weren't met, as found by `Expectations::check()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unmet {
+    /// What went wrong with each one, in the order they were added.
+    pub failures: Vec<String>,
+}
+
+impl fmt::Display for Unmet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} expectation(s) not met:", self.failures.len())?;
+        for failure in &self.failures { write!(f, "\n  {}", failure)?; }
+        Ok(())
+    }
+}
+
+impl Error for Unmet {}
+
+/// Records what is published to it, in order, instead of delivering it.
+/// Clones share the same record.
+pub struct MockPublisher<Topic, Content> {
+    published: Arc<Mutex<Vec<(Topic, Content)>>>,
+}
+
+impl<Topic, Content> Clone for MockPublisher<Topic, Content> {
+    fn clone(&self) -> Self {
+        MockPublisher { published: self.published.clone() }
+    }
+}
+
+impl<Topic, Content> Default for MockPublisher<Topic, Content> {
+    fn default() -> Self {
+        MockPublisher::new()
+    }
+}
+
+impl<Topic, Content> MockPublisher<Topic, Content> {
+    /// A mock that has recorded nothing.
+    pub fn new() -> Self {
+        MockPublisher { published: Arc::new(Mutex::new(vec![])) }
+    }
+
+    /// Takes everything recorded so far, oldest first, leaving the record
+    /// empty.
+    pub fn take(&self) -> Vec<(Topic, Content)> {
+        mem::take(&mut *self.published.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Returns how many messages have been recorded.
+    pub fn len(&self) -> usize {
+        self.published.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Returns true if nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<Topic: Clone, Content: Clone> MockPublisher<Topic, Content> {
+    /// Everything recorded so far, oldest first.
+    pub fn published(&self) -> Vec<(Topic, Content)> {
+        self.published.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The content of everything recorded on `topic`, oldest first.
+    pub fn published_on(&self, topic: &Topic) -> Vec<Content>
+        where Topic: PartialEq
+    {
+        let published = self.published.lock().unwrap_or_else(|e| e.into_inner());
+        published.iter().filter(|(t, _)| t == topic).map(|(_, content)| content.clone()).collect()
+    }
+}
+
+impl<Topic, Content> Publish<Topic, Content> for MockPublisher<Topic, Content> {
+    fn try_publish(&self, topic: Topic, content: Content) -> Result<(), PublishError> {
+        self.published.lock().unwrap_or_else(|e| e.into_inner()).push((topic, content));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn echoes_are_timed_and_losses_counted() {
+        let mut builder = Publisher::new();
+        builder.restrict_topic("blocked", &[]);
+        let publisher = builder.build();
+
+        let mut healthy = loopback(&publisher, "ping", "pong");
+        let stats = healthy.probe(3, 7, Duration::from_secs(5)).clone();
+        assert_eq!((stats.sent, stats.dropped), (3, 0));
+        assert!(stats.fastest <= stats.mean() && stats.mean() <= stats.slowest);
+
+        let mut broken = loopback(&publisher, "ping", "blocked");
+        assert_eq!(broken.round_trip(8, Duration::from_millis(30)), None);
+        assert_eq!(broken.stats().dropped, 1);
+        assert!(healthy.round_trip(9, Duration::from_secs(5)).is_some());
+    }
+
+    #[test]
+    fn expectations_describe_what_went_wrong() {
+        let publisher = Publisher::new().build();
+        let met = Expectations::new(&publisher)
+            .exactly(1, "orders", |&quantity| quantity > 0)
+            .at_least(2, "audit", |_| true)
+            .none("errors");
+        for (topic, n) in [("orders", 0), ("orders", 5), ("audit", 1), ("audit", 2)] {
+            publisher.publish(topic, n);
+        }
+        let started = Instant::now();
+        assert_eq!(met.check(Duration::from_millis(20)), Ok(()));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+
+        let unmet = Expectations::new(&publisher)
+            .at_least(1, "orders", |&quantity| quantity > 10)
+            .none("errors");
+        publisher.publish("errors", 1);
+        let started = Instant::now();
+        let failures = unmet.check(Duration::from_secs(5)).unwrap_err().failures;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(failures, vec![
+            "expected at least 1 matching on \"orders\" within 5s, saw 0 matching of []".to_owned(),
+            "expected no messages on \"errors\" within 5s, saw 1 matching of [1]".to_owned(),
+        ]);
+    }
+
+    /// Reports a reading, as a component under test might.
+    fn report_temperature<P: Publish<&'static str, i32>>(publisher: &P, celsius: i32) {
+        if celsius > 90 { publisher.publish("alarm", celsius); }
+        publisher.publish("temperature", celsius);
+    }
+
+    #[test]
+    fn mocks_record_in_order_and_networks_deliver() {
+        let mock = MockPublisher::new();
+        report_temperature(&mock, 20);
+        report_temperature(&mock.clone(), 95);
+        assert_eq!(mock.published_on(&"temperature"), vec![20, 95]);
+        assert_eq!(mock.take(), vec![("temperature", 20), ("alarm", 95), ("temperature", 95)]);
+        assert!(mock.is_empty());
+
+        let mut builder = Publisher::new();
+        let alarms = builder.add_subscriber(&["alarm"]);
+        report_temperature(&builder.build(), 99);
+        assert_eq!(alarms.fetch(), vec![("alarm", 99)]);
+    }
+}
@@ -0,0 +1,50 @@
+//! Which types may cross threads, checked when the crate is built.
+//!
+//! A `Publisher` is `Send` and `Sync`, so one handle can sit in an `Arc` or a
+//! `static` and be shared by a whole thread pool; cloning it is only needed
+//! to give a thread a handle of its own. A `Subscriber` is `Send` but not
+//! `Sync`: it can be moved to the thread that reads it, but it keeps
+//! unsynchronized bookkeeping for `wait_for()` and same-thread delivery, so
+//! two threads shouldn't read it at once. A publisher needs its topic and
+//! content types to be `Send` and `Sync`; a subscriber only needs them to be
+//! `Send`. The functions below stop the build if a change to any of these
+//! types breaks that promise.
+
+#![allow(dead_code)]
+
+use std::hash::Hash;
+
+use super::{AsyncSubscriber, Builder, OneShot, Publisher, Subscriber, SubscriptionGuard, Tenant};
+
+fn send<T: Send>() {}
+
+fn sync<T: Sync>() {}
+
+fn shared_types<Topic, Content>()
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+{
+    send::<Publisher<Topic, Content>>();
+    sync::<Publisher<Topic, Content>>();
+    send::<SubscriptionGuard<Topic, Content>>();
+    sync::<SubscriptionGuard<Topic, Content>>();
+    send::<AsyncSubscriber<Topic, Content>>();
+    sync::<AsyncSubscriber<Topic, Content>>();
+    send::<OneShot<Topic, Content>>();
+    send::<Builder<Topic, Content>>();
+}
+
+fn tenant_types<Topic, Content>()
+    where Topic: Hash + Eq + Clone + ::TenantTopic + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+{
+    send::<Tenant<Topic, Content>>();
+    sync::<Tenant<Topic, Content>>();
+}
+
+fn owned_types<Topic, Content>()
+    where Topic: Hash + Eq + Clone + Send + 'static,
+          Content: Clone + Send + 'static,
+{
+    send::<Subscriber<Topic, Content>>();
+}
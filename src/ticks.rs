@@ -0,0 +1,131 @@
+//! Shared ticks for loops that run at a fixed rate.
+//!
+//! A frame loop, a physics step and a once-a-second stats job each need a
+//! timer, and several parts of a program often want the same rate.
+//! `Publisher::ticks()` starts a repeating timer on a topic named for its
+//! rate, such as `ticks/60hz` or `ticks/1s`, unless one is already running,
+//! and returns a guard; the timer stops once every guard for its rate is
+//! dropped. Whoever runs at that rate subscribes to the topic. Ticks are
+//! timers like any other, so they are published by `fire_timers()`.
+
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{Bus, Publisher};
+
+/// How often a tick topic ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TickRate {
+    /// This many times a second.
+    Hz(u32),
+    /// Once every so often.
+    Every(Duration),
+}
+
+impl TickRate {
+    /// The time between ticks.
+    pub fn interval(&self) -> Duration {
+        match *self {
+            TickRate::Hz(hz) => Duration::from_secs(1) / hz,
+            TickRate::Every(interval) => interval,
+        }
+    }
+
+    /// The topic the ticks are published on.
+    pub fn topic(&self) -> String {
+        format!("ticks/{}", self)
+    }
+}
+
+impl fmt::Display for TickRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TickRate::Hz(hz) => write!(f, "{}hz", hz),
+            TickRate::Every(interval) if interval.subsec_nanos() == 0 => write!(f, "{}s", interval.as_secs()),
+            TickRate::Every(interval) if interval.subsec_nanos() % 1_000_000 == 0 => write!(f, "{}ms", interval.as_millis()),
+            TickRate::Every(interval) if interval.subsec_nanos() % 1_000 == 0 => write!(f, "{}us", interval.as_micros()),
+            TickRate::Every(interval) => write!(f, "{}ns", interval.as_nanos()),
+        }
+    }
+}
+
+/// Keeps a tick topic ticking. Made by `Publisher::ticks()`.
+pub struct TickGuard<Topic: Hash + Eq + Clone, Content: Clone> {
+    bus: Arc<Bus<Topic, Content>>,
+    topic: String,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> TickGuard<Topic, Content> {
+    /// The topic being ticked.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Drop for TickGuard<Topic, Content> {
+    fn drop(&mut self) {
+        let mut ticks = self.bus.timers.ticks.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = ticks.get_mut(&self.topic) {
+            entry.1 -= 1;
+            if entry.1 == 0 {
+                self.bus.timers.cancel(entry.0);
+                ticks.remove(&self.topic);
+            }
+        }
+    }
+}
+
+impl<Topic, Content> Publisher<Topic, Content>
+    where Topic: Hash + Eq + Clone + From<String>,
+          Content: Clone + Default,
+{
+    /// Publishes `Content::default()` on `rate.topic()` at `rate`, starting
+    /// one interval from now, unless that topic is ticking already, in which
+    /// case the guard shares its timer.
+    ///
+    /// Panics if `rate` is zero.
+    pub fn ticks(&self, rate: TickRate) -> TickGuard<Topic, Content> {
+        let topic = rate.topic();
+        let bus = &self.handle.bus;
+        let mut ticks = bus.timers.ticks.lock().unwrap_or_else(|e| e.into_inner());
+        match ticks.get_mut(&topic) {
+            Some(entry) => entry.1 += 1,
+            None => {
+                let id = self.publish_every(rate.interval(), Topic::from(topic.clone()), Content::default());
+                ticks.insert(topic.clone(), (id, 1));
+            },
+        }
+        TickGuard { bus: bus.clone(), topic }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ManualClock;
+
+    #[test]
+    fn identical_rates_share_a_timer() {
+        let clock = ManualClock::new();
+        let mut builder = Publisher::<String, ()>::new();
+        builder.clock(clock.clone());
+        let frames = builder.add_subscriber(&["ticks/60hz".to_owned()]);
+        let seconds = builder.add_subscriber(&["ticks/1s".to_owned()]);
+        let publisher = builder.build();
+
+        let render = publisher.ticks(TickRate::Hz(60));
+        let physics = publisher.ticks(TickRate::Hz(60));
+        let stats = publisher.ticks(TickRate::Every(Duration::from_millis(1000)));
+        assert_eq!((render.topic(), stats.topic()), ("ticks/60hz", "ticks/1s"));
+
+        assert_eq!(publisher.fast_forward(&clock, Duration::from_secs(1)), 61);
+        assert_eq!((frames.fetch().len(), seconds.fetch().len()), (60, 1));
+
+        drop(render);
+        assert_eq!(publisher.fast_forward(&clock, Duration::from_millis(100)), 6);
+        drop((physics, stats));
+        assert_eq!(publisher.next_timer(), None);
+    }
+}
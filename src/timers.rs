@@ -0,0 +1,194 @@
+//! Publishing messages later, or over and over.
+//!
+//! Scheduled messages wait on the network until their time comes, measured by
+//! the network's `Clock`, and are published by `Publisher::fire_timers()`,
+//! which a program calls from its main loop. Nothing fires on its own, so the
+//! program decides which thread publishes. With a `ManualClock`,
+//! `Publisher::fast_forward()` moves time along and fires everything due on
+//! the way, in order, without waiting.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use super::{Clock, ManualClock, Publisher};
+use super::snapshot::Scheduled;
+use super::sync::Mutex;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fast_forward_fires_in_order() {
+        let clock = ManualClock::new();
+        let mut builder = Publisher::new();
+        builder.clock(clock.clone());
+        let subscriber = builder.add_subscriber(&["tick", "alarm"]);
+        let publisher = builder.build();
+
+        let start = clock.now();
+        publisher.publish_every(Duration::from_secs(1), "tick", 0);
+        publisher.publish_after(Duration::from_millis(2500), "alarm", 1);
+        let cancelled = publisher.publish_after(Duration::from_secs(2), "alarm", 2);
+        assert!(publisher.cancel_timer(cancelled));
+        assert_eq!(publisher.fire_timers(), 0);
+        assert_eq!(publisher.next_timer(), Some(start + Duration::from_secs(1)));
+
+        assert_eq!(publisher.fast_forward(&clock, Duration::from_secs(3)), 4);
+        assert_eq!(subscriber.fetch(), vec![("tick", 0), ("tick", 0), ("alarm", 1), ("tick", 0)]);
+        assert_eq!(clock.now(), start + Duration::from_secs(3));
+        assert!(!publisher.cancel_timer(cancelled));
+    }
+}
+
+/// Identifies a scheduled message, so it can be cancelled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+struct Timer<Topic, Content> {
+    id: TimerId,
+    topic: Topic,
+    content: Content,
+    every: Option<Duration>,
+}
+
+struct State<Topic, Content> {
+    next_id: u64,
+    /// Keyed by due time, then by when the entry was queued, so messages due
+    /// at the same moment fire in the order they were scheduled.
+    queue: BTreeMap<(Instant, u64), Timer<Topic, Content>>,
+    queued: u64,
+}
+
+/// The messages waiting on one network.
+pub(crate) struct Timers<Topic, Content> {
+    state: Mutex<State<Topic, Content>>,
+    /// The timer behind each tick topic in use, and how many guards share it.
+    pub(crate) ticks: Mutex<HashMap<String, (TimerId, usize)>>,
+}
+
+impl<Topic: Clone, Content: Clone> Timers<Topic, Content> {
+    pub(crate) fn new() -> Self {
+        let state = State { next_id: 0, queue: BTreeMap::new(), queued: 0 };
+        Timers { state: Mutex::new(state), ticks: Mutex::new(HashMap::new()) }
+    }
+
+    pub(crate) fn schedule(&self, due: Instant, every: Option<Duration>, topic: Topic, content: Content)
+        -> TimerId
+    {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let id = TimerId(state.next_id);
+        state.next_id += 1;
+        state.push(due, Timer { id, topic, content, every });
+        id
+    }
+
+    /// Copies out every waiting message, earliest first, with due times
+    /// counted from `now`.
+    pub(crate) fn pending(&self, now: Instant) -> Vec<Scheduled<Topic, Content>> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.queue.iter().map(|(&(due, _), timer)| Scheduled {
+            due_in: due.saturating_duration_since(now),
+            every: timer.every,
+            topic: timer.topic.clone(),
+            content: timer.content.clone(),
+        }).collect()
+    }
+
+    pub(crate) fn cancel(&self, id: TimerId) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let before = state.queue.len();
+        state.queue.retain(|_, timer| timer.id != id);
+        state.queue.len() < before
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.queue.keys().next().map(|&(due, _)| due)
+    }
+
+    /// Takes the earliest message due by `now`, queuing its next repetition
+    /// if it has one.
+    fn pop_due(&self, now: Instant) -> Option<(Topic, Content)> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.queue.keys().next() {
+            Some(&(due, _)) if due <= now => {},
+            _ => return None,
+        }
+        let ((due, _), timer) = state.queue.pop_first()?;
+        let message = (timer.topic.clone(), timer.content.clone());
+        if let Some(every) = timer.every {
+            state.push(due + every, timer);
+        }
+        Some(message)
+    }
+}
+
+impl<Topic, Content> State<Topic, Content> {
+    fn push(&mut self, due: Instant, timer: Timer<Topic, Content>) {
+        self.queue.insert((due, self.queued), timer);
+        self.queued += 1;
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Schedules `content` to be published on `topic` once `delay` has passed.
+    /// Scheduled messages are published by whichever handle fires them, as if
+    /// it had published them itself.
+    pub fn publish_after(&self, delay: Duration, topic: Topic, content: Content) -> TimerId {
+        let due = self.bus().clock.now() + delay;
+        self.bus().timers.schedule(due, None, topic, content)
+    }
+
+    /// Schedules `content` to be published on `topic` every `interval`,
+    /// starting one interval from now, until the timer is cancelled.
+    ///
+    /// Panics if `interval` is zero.
+    pub fn publish_every(&self, interval: Duration, topic: Topic, content: Content) -> TimerId {
+        assert!(!interval.is_zero(), "a repeating timer needs a non-zero interval");
+        let due = self.bus().clock.now() + interval;
+        self.bus().timers.schedule(due, Some(interval), topic, content)
+    }
+
+    /// Unschedules a message. Returns false if it had already been published,
+    /// or cancelled.
+    pub fn cancel_timer(&self, id: TimerId) -> bool {
+        self.bus().timers.cancel(id)
+    }
+
+    /// When the next scheduled message is due, if there is one.
+    pub fn next_timer(&self) -> Option<Instant> {
+        self.bus().timers.next_due()
+    }
+
+    /// Publishes every scheduled message that is due, earliest first, and
+    /// returns how many there were. A repeating timer that has fallen behind
+    /// fires once for every interval it missed. Also forgets retained
+    /// messages that are past their age, as `expire_retained()` does.
+    pub fn fire_timers(&self) -> usize {
+        let now = self.bus().clock.now();
+        let mut fired = 0;
+        while let Some((topic, content)) = self.bus().timers.pop_due(now) {
+            self.publish(topic, content);
+            fired += 1;
+        }
+        self.expire_retained();
+        fired
+    }
+
+    /// Advances `clock`, which must be the network's clock, by `by`. The
+    /// clock stops at each scheduled message on the way, so every message is
+    /// published at the time it was due, and whatever its subscribers read
+    /// from the clock agrees. Returns how many messages were published.
+    pub fn fast_forward(&self, clock: &ManualClock, by: Duration) -> usize {
+        let until = clock.now() + by;
+        let mut fired = 0;
+        while let Some(due) = self.next_timer().filter(|&due| due <= until) {
+            clock.advance_to(due);
+            fired += self.fire_timers();
+        }
+        clock.advance_to(until);
+        fired
+    }
+}
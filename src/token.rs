@@ -0,0 +1,104 @@
+//! Publishing to one topic over and over.
+//!
+//! Every publish looks its topic's recipients up in the routing table. A
+//! `PublishToken` does that once and keeps the answer, checking only that
+//! the table hasn't changed since, so code that publishes to the same topic
+//! thousands of times a second pays for the lookup when someone subscribes
+//! or unsubscribes rather than on every message. Everything else a publish
+//! goes through, from permissions to quotas, still applies.
+
+use std::hash::Hash;
+use std::ops::Deref;
+
+use super::{PublishError, Publisher, Route};
+use super::routing::RoutingTable;
+use super::sync::Mutex;
+
+/// A topic's recipients as of some version of the routing table.
+pub(crate) struct Cached<Topic, Content> {
+    generation: Option<u64>,
+    pub(crate) routes: Vec<Route<Topic, Content>>,
+}
+
+/// The recipients a token delivers to, looked up again whenever the routing
+/// table changes.
+pub(crate) struct Resolved<Topic, Content> {
+    cached: Mutex<Cached<Topic, Content>>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Resolved<Topic, Content> {
+    fn new() -> Self {
+        Resolved { cached: Mutex::new(Cached { generation: None, routes: vec![] }) }
+    }
+
+    /// The recipients of `topic` in `routing`, which the caller keeps locked
+    /// for as long as it uses them.
+    pub(crate) fn routes<'a>(&'a self, routing: &RoutingTable<Topic, Content>, topic: &Topic)
+        -> impl Deref<Target = Cached<Topic, Content>> + 'a
+    {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        let generation = routing.generation();
+        if cached.generation != Some(generation) {
+            cached.routes = routing.recipients(topic).cloned().collect();
+            cached.generation = Some(generation);
+        }
+        cached
+    }
+}
+
+/// A handle for publishing to one topic, made by `Publisher::token()`, which
+/// remembers who the topic is delivered to.
+pub struct PublishToken<Topic: Hash + Eq + Clone, Content: Clone> {
+    publisher: Publisher<Topic, Content>,
+    topic: Topic,
+    resolved: Resolved<Topic, Content>,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> PublishToken<Topic, Content> {
+    /// The topic this token publishes to.
+    pub fn topic(&self) -> &Topic {
+        &self.topic
+    }
+
+    /// Like `Publisher::publish()`, to the token's topic.
+    pub fn publish(&self, content: Content) {
+        self.try_publish(content).unwrap_or(());
+    }
+
+    /// Like `Publisher::try_publish()`, to the token's topic.
+    pub fn try_publish(&self, content: Content) -> Result<(), PublishError> {
+        self.publisher.publish_checked(&self.topic, content, None, Some(&self.resolved))
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Makes a token for publishing to `topic` without looking up its
+    /// subscribers each time. The token publishes as this handle, under its
+    /// name. Threads publishing to the same topic should have a token each,
+    /// as a token's copy of the recipients is behind a lock.
+    pub fn token(&self, topic: Topic) -> PublishToken<Topic, Content> {
+        PublishToken { publisher: self.clone(), topic, resolved: Resolved::new() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokens_follow_subscription_changes() {
+        let mut builder = Publisher::new();
+        let fixed = builder.add_subscriber(&["physics"]);
+        let publisher = builder.build();
+
+        let token = publisher.token("physics");
+        token.publish(1);
+        let (late, guard) = publisher.subscribe_scoped(&["physics"]);
+        token.publish(2);
+        drop(guard);
+        token.publish(3);
+
+        assert_eq!(fixed.fetch(), vec![("physics", 1), ("physics", 2), ("physics", 3)]);
+        assert_eq!(late.fetch(), vec![("physics", 2)]);
+    }
+}
@@ -0,0 +1,257 @@
+//! Drawing a network's routing as a diagram.
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Write};
+use std::hash::Hash;
+use std::iter;
+
+use super::{Outbox, Publisher};
+
+/// Diagram languages `Publisher::export_topology()` can write.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// GraphViz's DOT language, for `dot -Tsvg` and friends.
+    Dot,
+    /// A Mermaid flowchart, which many wikis and code hosts render inline.
+    Mermaid,
+}
+
+/// What a subscriber node stands for.
+enum Kind {
+    Subscriber,
+    SameThread,
+    Forward,
+}
+
+/// A network's routing, reduced to labels.
+struct Graph {
+    /// Topic labels, each with the subscribers receiving it.
+    topics: BTreeMap<String, Vec<usize>>,
+    /// Extra lines for documented topics, by label.
+    docs: BTreeMap<String, Vec<String>>,
+    /// Subscriber labels by route id.
+    subscribers: BTreeMap<usize, (String, Kind)>,
+    /// Topics shared with the parent network, and their names there.
+    parent: Vec<(String, String)>,
+    /// Watched bridge links, and whether each is running.
+    links: Vec<(String, bool)>,
+}
+
+impl<Topic: Hash + Eq + Clone + Debug, Content: Clone> Publisher<Topic, Content> {
+    /// Draws the network as it is now in the given `format`: each topic, the
+    /// subscribers it is routed to, the topics shared with a parent network,
+    /// and the bridge links passed to `watch_link()`. Declared topics show
+    /// their description and schema under their name. Subscribers are labelled
+    /// with their names from `Builder::from_config()`, where they have one;
+    /// taps, bridges and other networks receiving a topic show up as
+    /// forwarders. A link appears on its own, since the network doesn't know
+    /// which subscriber feeds it.
+    pub fn export_topology(&self, format: Format) -> String {
+        let graph = self.graph();
+        match format {
+            Format::Dot => graph.dot(),
+            Format::Mermaid => graph.mermaid(),
+        }
+    }
+
+    fn graph(&self) -> Graph {
+        let bus = self.bus();
+        let names = bus.config.names();
+        let mut graph = Graph {
+            topics: BTreeMap::new(),
+            docs: BTreeMap::new(),
+            subscribers: BTreeMap::new(),
+            parent: vec![],
+            links: vec![],
+        };
+
+        let routing = bus.routing.read().unwrap_or_else(|e| e.into_inner());
+        let routed = routing.topics().map(|topic| (topic, routing.recipients(topic).collect()));
+        let single: Vec<_> = bus.single_consumer.iter().map(|(topic, slot)| (topic, slot.get())).collect();
+        let single = single.iter().map(|&(topic, ref route)| (topic, route.iter().collect()));
+
+        for (topic, routes) in routed.chain(single) {
+            let routes: Vec<_> = routes;
+            let ids = graph.topics.entry(format!("{:?}", topic)).or_default();
+            for route in routes {
+                if !ids.contains(&route.id) { ids.push(route.id); }
+                let kind = match route.outbox {
+                    Outbox::Inbox(..) => Kind::Subscriber,
+                    Outbox::Local(..) => Kind::SameThread,
+                    Outbox::Forward(_) => Kind::Forward,
+                };
+                let label = names.get(&route.id).cloned().unwrap_or_else(|| match kind {
+                    Kind::Forward => format!("forwarder #{}", route.id),
+                    _ => format!("subscriber #{}", route.id),
+                });
+                graph.subscribers.insert(route.id, (label, kind));
+            }
+        }
+        drop(routing);
+        for ids in graph.topics.values_mut() { ids.sort(); }
+
+        if let Some(ref parent) = bus.parent {
+            for (child, upstairs) in &parent.upward {
+                let child = format!("{:?}", child);
+                graph.topics.entry(child.clone()).or_default();
+                graph.parent.push((child, format!("{:?}", upstairs)));
+            }
+            graph.parent.sort();
+        }
+
+        for (topic, settings) in &bus.topic_settings {
+            let docs = &settings.docs;
+            let lines: Vec<String> = docs.description.iter().cloned()
+                .chain(docs.schema.iter().map(|schema| format!("schema: {}", schema)))
+                .collect();
+            if lines.is_empty() { continue; }
+            let label = format!("{:?}", topic);
+            graph.topics.entry(label.clone()).or_default();
+            graph.docs.insert(label, lines);
+        }
+
+        let links = bus.links.lock().unwrap_or_else(|e| e.into_inner());
+        graph.links = links.iter().map(|(name, liveness)| (name.clone(), liveness.is_running())).collect();
+        graph
+    }
+}
+
+impl Graph {
+    fn dot(&self) -> String {
+        let mut out = String::from("digraph alewife {\n    rankdir=LR;\n");
+        for (n, label) in self.topics.keys().enumerate() {
+            let _ = writeln!(out, "    t{} [label=\"{}\", shape=box];", n,
+                             self.topic_label(label, "\\\"", "\\n"));
+        }
+        for (id, (label, kind)) in &self.subscribers {
+            let style = match *kind {
+                Kind::Subscriber => "shape=ellipse",
+                Kind::SameThread => "shape=ellipse, style=bold",
+                Kind::Forward => "shape=cds",
+            };
+            let _ = writeln!(out, "    s{} [label=\"{}\", {}];", id, escape(label, "\\\""), style);
+        }
+        if !self.parent.is_empty() {
+            out.push_str("    parent [label=\"parent network\", shape=doubleoctagon];\n");
+        }
+        for (n, (name, running)) in self.links.iter().enumerate() {
+            let style = if *running { "" } else { ", style=dashed" };
+            let _ = writeln!(out, "    l{} [label=\"bridge {}\", shape=component{}];",
+                             n, escape(name, "\\\""), style);
+        }
+
+        for (n, ids) in self.topics.values().enumerate() {
+            for id in ids {
+                let _ = writeln!(out, "    t{} -> s{};", n, id);
+            }
+        }
+        for (child, upstairs) in &self.parent {
+            let _ = writeln!(out, "    t{} -> parent [label=\"{}\", dir=both];",
+                             self.topic_index(child), escape(upstairs, "\\\""));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for (n, label) in self.topics.keys().enumerate() {
+            let _ = writeln!(out, "    t{}[\"{}\"]", n, self.topic_label(label, "#quot;", "<br/>"));
+        }
+        for (id, (label, kind)) in &self.subscribers {
+            let label = escape(label, "#quot;");
+            let _ = match *kind {
+                Kind::Subscriber => writeln!(out, "    s{}([\"{}\"])", id, label),
+                Kind::SameThread => writeln!(out, "    s{}((\"{}\"))", id, label),
+                Kind::Forward => writeln!(out, "    s{}>\"{}\"]", id, label),
+            };
+        }
+        if !self.parent.is_empty() {
+            out.push_str("    parent{{\"parent network\"}}\n");
+        }
+        for (n, (name, running)) in self.links.iter().enumerate() {
+            let state = if *running { "" } else { " (stopped)" };
+            let _ = writeln!(out, "    l{}[[\"bridge {}{}\"]]", n, escape(name, "#quot;"), state);
+        }
+
+        for (n, ids) in self.topics.values().enumerate() {
+            for id in ids {
+                let _ = writeln!(out, "    t{} --> s{}", n, id);
+            }
+        }
+        for (child, upstairs) in &self.parent {
+            let _ = writeln!(out, "    t{} <-->|\"{}\"| parent",
+                             self.topic_index(child), escape(upstairs, "#quot;"));
+        }
+        out
+    }
+
+    /// A topic's label followed by its documentation, one line each.
+    fn topic_label(&self, label: &str, quote: &str, newline: &str) -> String {
+        let lines = iter::once(label).chain(self.docs.get(label).into_iter().flatten().map(String::as_str));
+        lines.map(|line| escape(line, quote)).collect::<Vec<_>>().join(newline)
+    }
+
+    fn topic_index(&self, label: &str) -> usize {
+        self.topics.keys().position(|t| t == label).unwrap_or_default()
+    }
+}
+
+/// Replaces double quotes, the one character that ends a label early in both
+/// languages.
+fn escape(label: &str, quote: &str) -> String {
+    label.replace('"', quote)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::{BusConfig, SubscriberConfig};
+    use {Builder, TopicOptions};
+
+    #[test]
+    fn diagrams_name_configured_subscribers() {
+        let mut config = BusConfig::default();
+        config.subscribers.insert("ui".to_owned(), SubscriberConfig {
+            topics: vec!["input", "state"],
+            ..SubscriberConfig::default()
+        });
+        let mut builder: Builder<&str, u32> = Builder::from_config(&config);
+        let _ui = builder.configured_subscriber("ui");
+        let _other = builder.add_subscriber(&["state"]);
+        let publisher = builder.build();
+
+        assert_eq!(publisher.export_topology(Format::Dot), "digraph alewife {
+    rankdir=LR;
+    t0 [label=\"\\\"input\\\"\", shape=box];
+    t1 [label=\"\\\"state\\\"\", shape=box];
+    s0 [label=\"ui\", shape=ellipse];
+    s1 [label=\"subscriber #1\", shape=ellipse];
+    t0 -> s0;
+    t1 -> s0;
+    t1 -> s1;
+}
+");
+        assert_eq!(publisher.export_topology(Format::Mermaid), "flowchart LR
+    t0[\"#quot;input#quot;\"]
+    t1[\"#quot;state#quot;\"]
+    s0([\"ui\"])
+    s1([\"subscriber #1\"])
+    t0 --> s0
+    t1 --> s0
+    t1 --> s1
+");
+    }
+
+    #[test]
+    fn diagrams_show_topic_docs() {
+        let mut builder: Builder<&str, u32> = Publisher::new();
+        builder.declare_topic("score", TopicOptions::new().description("Points \"scored\"").schema("u32"));
+        let publisher = builder.build();
+
+        assert!(publisher.export_topology(Format::Dot)
+            .contains("t0 [label=\"\\\"score\\\"\\nPoints \\\"scored\\\"\\nschema: u32\", shape=box];"));
+        assert!(publisher.export_topology(Format::Mermaid)
+            .contains("t0[\"#quot;score#quot;<br/>Points #quot;scored#quot;<br/>schema: u32\"]"));
+    }
+}
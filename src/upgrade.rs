@@ -0,0 +1,113 @@
+//! Converting envelopes between versions of their body's format.
+//!
+//! `Builder::upgrade_version()` registers a function turning one version
+//! into another. A subscriber made with `Publisher::subscribe_versions()`
+//! that doesn't accept an envelope's version gets it converted instead, by
+//! the shortest chain of registered functions that ends in a version it
+//! does accept, so old publishers and new subscribers can share a topic
+//! during a migration.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+use super::Builder;
+use super::envelope::Envelope;
+
+/// Turns content in one version into the next.
+type Convert<Content> = Arc<dyn Fn(Content) -> Content + Send + Sync>;
+
+/// The conversions registered on one network, by the version they start at.
+pub(crate) struct Upgrades<Content> {
+    steps: HashMap<u32, Vec<(u32, Convert<Content>)>>,
+}
+
+impl<Content> Upgrades<Content> {
+    pub(crate) fn new() -> Self {
+        Upgrades { steps: HashMap::new() }
+    }
+
+    /// Finds the fewest conversions taking `from` to a version in `accepts`,
+    /// in the order they must be applied. Conversions registered earlier are
+    /// preferred among chains of the same length.
+    pub(crate) fn path<R: RangeBounds<u32>>(&self, from: u32, accepts: &R) -> Option<Vec<Convert<Content>>> {
+        let mut came_from: HashMap<u32, (u32, &Convert<Content>)> = HashMap::new();
+        let mut seen: HashSet<u32> = HashSet::new();
+        let mut queue = VecDeque::new();
+        seen.insert(from);
+        queue.push_back(from);
+
+        while let Some(version) = queue.pop_front() {
+            if version != from && accepts.contains(&version) {
+                let mut chain = vec![];
+                let mut at = version;
+                while let Some(&(previous, convert)) = came_from.get(&at) {
+                    chain.push(convert.clone());
+                    at = previous;
+                }
+                chain.reverse();
+                return Some(chain);
+            }
+            for &(next, ref convert) in self.steps.get(&version).into_iter().flatten() {
+                if seen.insert(next) {
+                    came_from.insert(next, (version, convert));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<Topic, Body> Builder<Topic, Envelope<Topic, Body>>
+    where Topic: Hash + Eq + Clone,
+          Body: Clone,
+{
+    /// Registers `convert` as a way to turn bodies in version `from` of their
+    /// format into version `to`. Envelopes without a version count as
+    /// version 0.
+    pub fn upgrade_version<F>(&mut self, from: u32, to: u32, convert: F)
+        where F: Fn(Body) -> Body + Send + Sync + 'static
+    {
+        let upgrades = Arc::get_mut(&mut self.bus.upgrades)
+            .expect("the network's upgrades are only shared once it is built");
+        let convert: Convert<Envelope<Topic, Body>> = Arc::new(move |mut envelope: Envelope<Topic, Body>| {
+            envelope.body = convert(envelope.body);
+            envelope.version = Some(to);
+            envelope
+        });
+        upgrades.steps.entry(from).or_default().push((to, convert));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use Publisher;
+    use envelope::Envelope;
+
+    #[test]
+    fn old_versions_are_upgraded_by_the_shortest_chain() {
+        let mut builder = Publisher::new();
+        builder.upgrade_version(1, 2, |body: String| format!("{} +2", body));
+        builder.upgrade_version(2, 3, |body| format!("{} +3", body));
+        builder.upgrade_version(1, 3, |body| format!("{} 1->3", body));
+        builder.upgrade_version(5, 6, |body| body);
+        let publisher = builder.build();
+
+        let (reader, _guard) = publisher.subscribe_versions(&["save"], 3..);
+        publisher.publish("save", Envelope::new("a".to_owned()).versioned(1));
+        publisher.publish("save", Envelope::new("b".to_owned()).versioned(2));
+        publisher.publish("save", Envelope::new("c".to_owned()).versioned(3));
+        publisher.publish("save", Envelope::new("d".to_owned()));
+
+        let received: Vec<(String, Option<u32>)> = reader.fetch().into_iter()
+            .map(|(_, envelope)| (envelope.body, envelope.version))
+            .collect();
+        assert_eq!(received, vec![
+            ("a 1->3".to_owned(), Some(3)),
+            ("b +3".to_owned(), Some(3)),
+            ("c".to_owned(), Some(3)),
+        ]);
+    }
+}
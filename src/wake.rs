@@ -0,0 +1,153 @@
+//! Waking an event loop when a subscriber has messages.
+//!
+//! A reactor built on mio or epoll waits on file descriptors, not channels,
+//! so reading subscribers from one usually means a thread per subscriber just
+//! to turn messages into readiness. `SubscriptionOptions::on_wake()` instead
+//! calls a function, on the publishing thread, whenever the subscriber's
+//! queue goes from empty to non-empty; it could wake a `mio::Waker`, say. On
+//! Unix, `SubscriptionOptions::wake_fd()` makes a `WakeFd` readable instead,
+//! which can be registered with the reactor like any socket.
+//!
+//! Wakes are edge-triggered: after one, read the subscriber until it is
+//! empty, as with `fetch()`, or the next message may not wake it again.
+
+use std::fmt;
+use std::sync::Arc;
+
+#[cfg(unix)]
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+use super::SubscriptionOptions;
+
+/// A subscriber's wake function.
+#[derive(Clone)]
+pub(crate) struct Wake(Arc<dyn Fn() + Send + Sync>);
+
+impl Wake {
+    pub(crate) fn wake(&self) {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for Wake {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Wake(..)")
+    }
+}
+
+impl SubscriptionOptions {
+    /// Calls `wake` whenever the subscriber's queue goes from empty to
+    /// non-empty. It runs on the publishing thread, while the message is
+    /// being routed, so it should return quickly. Setting another replaces
+    /// this one.
+    pub fn on_wake<F>(mut self, wake: F) -> Self
+        where F: Fn() + Send + Sync + 'static
+    {
+        self.wake = Some(Wake(Arc::new(wake)));
+        self
+    }
+
+    /// Makes `fd` readable whenever the subscriber's queue goes from empty
+    /// to non-empty. Replaces any `on_wake()` function.
+    #[cfg(unix)]
+    pub fn wake_fd(self, fd: &WakeFd) -> Self {
+        let writer = fd.writer.clone();
+        self.on_wake(move || {
+            // A full socket is readable already, so the byte isn't needed.
+            let _ = (&*writer).write(&[1]);
+        })
+    }
+}
+
+/// A file descriptor that becomes readable when a subscriber has messages,
+/// for registering with epoll, kqueue or mio. See
+/// `SubscriptionOptions::wake_fd()`. One `WakeFd` can serve several
+/// subscribers.
+#[cfg(unix)]
+pub struct WakeFd {
+    reader: UnixStream,
+    writer: Arc<UnixStream>,
+}
+
+#[cfg(unix)]
+impl WakeFd {
+    /// Makes a new descriptor, which isn't readable until a subscriber using
+    /// it is woken. Both of its ends are non-blocking.
+    pub fn new() -> io::Result<Self> {
+        let (reader, writer) = UnixStream::pair()?;
+        reader.set_nonblocking(true)?;
+        writer.set_nonblocking(true)?;
+        Ok(WakeFd { reader, writer: Arc::new(writer) })
+    }
+
+    /// Makes the descriptor unreadable again, returning true if it had been
+    /// woken. Call it before reading the subscribers, so that messages
+    /// arriving meanwhile wake it anew.
+    pub fn clear(&self) -> bool {
+        let mut woken = false;
+        let mut buffer = [0; 64];
+        while let Ok(n) = (&self.reader).read(&mut buffer) {
+            if n == 0 { break; }
+            woken = true;
+        }
+        woken
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for WakeFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.reader.as_raw_fd()
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for WakeFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.reader.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use Publisher;
+
+    #[test]
+    fn subscribers_wake_when_their_queue_fills() {
+        let wakes = Arc::new(AtomicUsize::new(0));
+        let counter = wakes.clone();
+        let mut builder = Publisher::new();
+        let options = SubscriptionOptions::new().on_wake(move || { counter.fetch_add(1, Ordering::SeqCst); });
+        let events = builder.add_subscriber_with(&["input"], options);
+        let publisher = builder.build();
+
+        publisher.publish("input", 1);
+        publisher.publish("input", 2);
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+        assert_eq!(events.fetch().len(), 2);
+        publisher.publish("input", 3);
+        assert_eq!(wakes.load(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wake_fds_become_readable() {
+        let fd = WakeFd::new().unwrap();
+        let mut builder = Publisher::new();
+        let events = builder.add_subscriber_with(&["input"], SubscriptionOptions::new().wake_fd(&fd));
+        let publisher = builder.build();
+
+        assert!(!fd.clear());
+        publisher.publish("input", 1);
+        assert!(fd.clear());
+        assert!(!fd.clear());
+        assert_eq!(events.fetch(), vec![("input", 1)]);
+    }
+}
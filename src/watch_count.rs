@@ -0,0 +1,154 @@
+//! Noticing when a topic gains or loses its last subscriber.
+//!
+//! Some producers are only worth running while someone is listening, such as
+//! a screen grabber or a verbose diagnostics feed. A `CountWatcher` made by
+//! `Publisher::watch_subscriber_count()` reports each change in how many
+//! routes a topic has, and can be polled, waited on or awaited, so such a
+//! producer can start and stop without asking the network over and over.
+
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use super::{Bus, Publisher};
+use super::sync::{Condvar, Mutex};
+
+/// Told whenever any route is added or taken away.
+pub(crate) struct RouteChanges {
+    wakers: Mutex<Vec<Waker>>,
+    changed: Condvar,
+}
+
+impl RouteChanges {
+    pub(crate) fn new() -> Self {
+        RouteChanges { wakers: Mutex::new(vec![]), changed: Condvar::new() }
+    }
+
+    /// Called with the routing table unlocked, after it has changed.
+    pub(crate) fn notify(&self) {
+        let mut wakers = self.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        for waker in wakers.drain(..) { waker.wake(); }
+        self.changed.notify_all();
+    }
+}
+
+/// Follows how many subscribers a topic has. Made by
+/// `Publisher::watch_subscriber_count()`.
+pub struct CountWatcher<Topic: Hash + Eq + Clone, Content: Clone> {
+    bus: Arc<Bus<Topic, Content>>,
+    topic: Topic,
+    last: usize,
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> CountWatcher<Topic, Content> {
+    /// How many subscribers the topic has now, taps and forwarding routes
+    /// included.
+    pub fn count(&self) -> usize {
+        let single = self.bus.single_consumer.get(&self.topic).is_some_and(|slot| slot.is_taken());
+        let routing = self.bus.routing.read().unwrap_or_else(|e| e.into_inner());
+        routing.recipients(&self.topic).count() + single as usize
+    }
+
+    /// Returns the new count if it has changed since it was last reported,
+    /// or since the watcher was made.
+    pub fn poll_changed(&mut self) -> Option<usize> {
+        let count = self.count();
+        if count == self.last { return None; }
+        self.last = count;
+        Some(count)
+    }
+
+    /// Like `poll_changed()`, but waits up to `timeout` for a change.
+    pub fn wait_changed(&mut self, timeout: Duration) -> Option<usize> {
+        let deadline = Instant::now() + timeout;
+        let bus = self.bus.clone();
+        let changes = &bus.route_changes;
+        let mut wakers = changes.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(count) = self.poll_changed() { return Some(count); }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left == Duration::ZERO { return None; }
+            wakers = changes.changed.wait_timeout(wakers, left).unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+
+    /// Resolves to the new count once it changes.
+    pub fn changed(&mut self) -> Changed<'_, Topic, Content> {
+        Changed { watcher: self }
+    }
+}
+
+/// A future for the next change in a topic's subscriber count. Made by
+/// `CountWatcher::changed()`.
+pub struct Changed<'a, Topic: Hash + Eq + Clone, Content: Clone> {
+    watcher: &'a mut CountWatcher<Topic, Content>,
+}
+
+impl<'a, Topic: Hash + Eq + Clone, Content: Clone> Future for Changed<'a, Topic, Content> {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<usize> {
+        let watcher = &mut *self.get_mut().watcher;
+        // Held while checking, so a change can't slip in before the waker
+        // is registered.
+        let bus = watcher.bus.clone();
+        let mut wakers = bus.route_changes.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        match watcher.poll_changed() {
+            Some(count) => Poll::Ready(count),
+            None => {
+                wakers.push(context.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl<Topic: Hash + Eq + Clone, Content: Clone> Publisher<Topic, Content> {
+    /// Watches how many subscribers `topic` has, starting from how many it
+    /// has now.
+    pub fn watch_subscriber_count(&self, topic: Topic) -> CountWatcher<Topic, Content> {
+        let mut watcher = CountWatcher { bus: self.handle.bus.clone(), topic, last: 0 };
+        watcher.last = watcher.count();
+        watcher
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Wake;
+    use std::thread;
+
+    use super::*;
+
+    struct Flag(Mutex<bool>);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            *self.0.lock().unwrap() = true;
+        }
+    }
+
+    #[test]
+    fn producers_hear_when_someone_listens() {
+        let publisher = Publisher::<&str, u32>::new().build();
+        let mut watcher = publisher.watch_subscriber_count("frames");
+        assert_eq!((watcher.count(), watcher.poll_changed()), (0, None));
+
+        let other = publisher.clone();
+        let viewer = thread::spawn(move || other.subscribe_scoped(&["frames"]));
+        assert_eq!(watcher.wait_changed(Duration::from_secs(5)), Some(1));
+        let (_viewer, guard) = viewer.join().unwrap();
+
+        let flag = Arc::new(Flag(Mutex::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut context = Context::from_waker(&waker);
+        let mut changed = watcher.changed();
+        assert_eq!(Pin::new(&mut changed).poll(&mut context), Poll::Pending);
+        drop(guard);
+        assert!(*flag.0.lock().unwrap());
+        assert_eq!(Pin::new(&mut changed).poll(&mut context), Poll::Ready(0));
+    }
+}
@@ -0,0 +1,178 @@
+//! Noticing subscribers that have stopped keeping up.
+//!
+//! A consumer thread that is stuck, deadlocked, or simply too slow shows up
+//! as a queue that never empties. A watchdog checks every subscriber's queue
+//! a few times per interval, and reports any that stayed non-empty for a
+//! whole interval, well before the backlog becomes a memory problem. It
+//! reports again when the queue finally drains.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Bus, Outbox, Publisher};
+use super::sync;
+use super::worker::{self, Worker};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stalls_are_reported_until_drained() {
+        let mut builder = Publisher::new();
+        let slow = builder.add_subscriber(&["work"]);
+        let publisher = builder.build();
+
+        let mut builder = Publisher::new();
+        let alerts = builder.add_subscriber(&["alerts"]);
+        let reporter = builder.build();
+
+        let watchdog = spawn(&publisher, Duration::from_millis(20), reporter, "alerts");
+        publisher.publish("work", 1);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut reported = vec![];
+        while reported.is_empty() && Instant::now() < deadline {
+            reported.extend(alerts.fetch());
+            thread::sleep(Duration::from_millis(5));
+        }
+        match reported[..] {
+            [(_, StallEvent::Stalled { queued: 1, ref subscriber, since })] => {
+                assert_eq!(subscriber, "subscriber #0");
+                assert!(since >= Duration::from_millis(20));
+            },
+            ref other => panic!("unexpected reports {:?}", other),
+        }
+
+        assert_eq!(slow.fetch(), vec![("work", 1)]);
+        let mut recovered = vec![];
+        while recovered.is_empty() && Instant::now() < deadline {
+            recovered.extend(alerts.fetch());
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(matches!(recovered[..], [(_, StallEvent::Recovered { .. })]));
+        watchdog.stop();
+    }
+}
+
+/// A change in a subscriber's progress, as published by a watchdog.
+/// Subscribers made by `Builder::from_config()` are named as in the
+/// configuration; others as `subscriber #n`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StallEvent {
+    /// The subscriber's queue hasn't been empty for at least the watchdog's
+    /// interval.
+    Stalled {
+        /// Which subscriber.
+        subscriber: String,
+        /// How many messages are waiting for it.
+        queued: usize,
+        /// How long its queue has been non-empty.
+        since: Duration,
+    },
+    /// A stalled subscriber emptied its queue, or went away.
+    Recovered {
+        /// Which subscriber.
+        subscriber: String,
+        /// Roughly how long its queue was non-empty.
+        stalled_for: Duration,
+    },
+}
+
+/// A running watchdog. Dropping it stops the thread.
+pub struct Watchdog {
+    worker: Worker,
+}
+
+impl Watchdog {
+    /// Stops the watchdog and waits for its thread to finish.
+    pub fn stop(self) {
+        self.worker.stop();
+    }
+}
+
+/// What the watchdog remembers about one subscriber.
+struct Watch {
+    /// When its queue was last seen empty.
+    empty_at: Instant,
+    stalled: bool,
+}
+
+/// Starts watching every subscriber to `watched`'s network, including those
+/// added later, and publishes a `StallEvent` to `report` under `topic` when
+/// one's queue stays non-empty for `interval`. Panics if `interval` is zero.
+pub fn spawn<Topic, Content, OutTopic>(
+    watched: &Publisher<Topic, Content>,
+    interval: Duration,
+    report: Publisher<OutTopic, StallEvent>,
+    topic: OutTopic,
+) -> Watchdog
+    where Topic: Hash + Eq + Clone + Send + Sync + 'static,
+          Content: Clone + Send + Sync + 'static,
+          OutTopic: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    let bus = watched.handle.bus.clone();
+    let check_every = worker::check_every(interval);
+
+    let worker = Worker::spawn(move |stopped| {
+        let mut watches: HashMap<usize, Watch> = HashMap::new();
+        while !stopped.load(Ordering::Acquire) {
+            let now = Instant::now();
+            let depths = queue_depths(&bus);
+            let names = bus.config.names();
+            let label = |id: usize| names.get(&id).cloned()
+                .unwrap_or_else(|| format!("subscriber #{}", id));
+
+            watches.retain(|id, watch| {
+                let gone = !depths.contains_key(id);
+                let drained = depths.get(id) == Some(&0);
+                if (gone || drained) && watch.stalled {
+                    report.publish(topic.clone(), StallEvent::Recovered {
+                        subscriber: label(*id),
+                        stalled_for: now.duration_since(watch.empty_at),
+                    });
+                    watch.stalled = false;
+                }
+                !gone
+            });
+
+            for (&id, &queued) in &depths {
+                let watch = watches.entry(id).or_insert(Watch { empty_at: now, stalled: false });
+                if queued == 0 {
+                    watch.empty_at = now;
+                    continue;
+                }
+                let since = now.duration_since(watch.empty_at);
+                if !watch.stalled && since >= interval {
+                    watch.stalled = true;
+                    report.publish(topic.clone(), StallEvent::Stalled {
+                        subscriber: label(id),
+                        queued,
+                        since,
+                    });
+                }
+            }
+
+            thread::sleep(check_every);
+        }
+    });
+
+    Watchdog { worker }
+}
+
+/// How many messages wait for each subscriber with a queue, by route id.
+fn queue_depths<Topic: Hash + Eq + Clone, Content: Clone>(bus: &Bus<Topic, Content>)
+    -> HashMap<usize, usize>
+{
+    let routing = bus.routing.read().unwrap_or_else(|e| e.into_inner());
+    let single: Vec<_> = bus.single_consumer.values().filter_map(|slot| slot.get()).collect();
+    routing.routes().chain(&single).filter_map(|route| match route.outbox {
+        Outbox::Inbox(_, ref pending) | Outbox::Local(_, _, ref pending) => {
+            Some((route.id, pending.load(sync::Ordering::Acquire)))
+        },
+        Outbox::Forward(_) => None,
+    }).collect()
+}
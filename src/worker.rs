@@ -0,0 +1,86 @@
+//! The thread behind each background handle, such as a `Monitor` or a
+//! `Watchdog`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The shortest pause between a worker's checks, however short its interval.
+const SHORTEST_CHECK: Duration = Duration::from_millis(1);
+
+/// A background thread that runs until this is stopped or dropped, either of
+/// which raises its flag and waits for it to finish.
+pub(crate) struct Worker {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Runs `work` on a new thread. It should return soon after the flag it
+    /// is given is raised.
+    pub(crate) fn spawn<F>(work: F) -> Self
+        where F: FnOnce(&AtomicBool) + Send + 'static
+    {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = stopped.clone();
+        let thread = thread::spawn(move || work(&flag));
+        Worker { stopped, thread: Some(thread) }
+    }
+
+    /// Stops the thread and waits for it.
+    pub(crate) fn stop(mut self) {
+        self.halt();
+    }
+
+    fn halt(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap_or(());
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.halt();
+    }
+}
+
+/// Returns `interval`, which a worker waits for between rounds. Panics if it
+/// is zero, which would leave the worker spinning.
+pub(crate) fn nonzero(interval: Duration) -> Duration {
+    assert!(!interval.is_zero(), "a background worker's interval can't be zero");
+    interval
+}
+
+/// How long a worker watching for something that takes `interval` to happen
+/// sleeps between checks: a quarter of it, so it notices promptly. Panics if
+/// `interval` is zero.
+pub(crate) fn check_every(interval: Duration) -> Duration {
+    (nonzero(interval) / 4).max(SHORTEST_CHECK)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stopping_joins_the_thread() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let done = finished.clone();
+        let worker = Worker::spawn(move |stopped| {
+            while !stopped.load(Ordering::Acquire) { thread::sleep(SHORTEST_CHECK); }
+            done.store(true, Ordering::Release);
+        });
+        worker.stop();
+        assert!(finished.load(Ordering::Acquire));
+        assert_eq!(check_every(Duration::from_nanos(1)), SHORTEST_CHECK);
+    }
+
+    #[test]
+    #[should_panic(expected = "interval can't be zero")]
+    fn zero_intervals_are_refused() {
+        check_every(Duration::ZERO);
+    }
+}